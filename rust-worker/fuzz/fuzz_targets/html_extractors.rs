@@ -0,0 +1,23 @@
+//! Email HTML through each standalone extractor, which parse separately
+//! from [`HtmlSummary`](bobnet::html::HtmlSummary).
+
+#![no_main]
+
+use bobnet::html::parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|html: &str| {
+    let _ = parser::extract_image_sources(html);
+    let _ = parser::extract_links(html);
+    let _ = parser::extract_links_with_rates(html, Some(0.5));
+    let _ = parser::find_wrapped_images(html);
+    let _ = parser::find_open_pixels(html);
+    let _ = parser::find_sfmc_open_pixel(html);
+    let _ = parser::find_tracking_pixels(html);
+    let _ = parser::find_global_open_rate(html);
+    let _ = parser::find_global_click_rate(html);
+    let _ = parser::find_global_open_delay(html);
+    for url in parser::extract_links(html) {
+        let _ = parser::is_open_pixel(&url);
+    }
+});
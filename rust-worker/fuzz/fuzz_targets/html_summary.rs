@@ -0,0 +1,9 @@
+//! Email HTML through the single-parse summary the worker simulates from.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|html: &str| {
+    let _ = bobnet::html::HtmlSummary::parse(html);
+});
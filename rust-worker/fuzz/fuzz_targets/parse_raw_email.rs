@@ -0,0 +1,13 @@
+//! Raw RFC 5322 emails, as Cloudflare delivers them, through the
+//! processor's email parser.
+//!
+//! The parser contains mailparse panics, but libFuzzer's panic hook still
+//! reports them as crashes.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|raw: &str| {
+    let _ = bobnet::process::parse_raw_email(raw);
+});
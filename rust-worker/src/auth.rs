@@ -0,0 +1,282 @@
+//! API-key authentication for operational routes (`/admin/*`, `/metrics`, ...).
+//!
+//! Keys come from `API_KEYS` (or `API_KEYS_FILE`) as comma-separated
+//! `name:key:scope+scope` entries, e.g. `grafana:abc123:metrics`. The name is
+//! what gets logged; `*` grants every scope. Several keys may share a scope,
+//! so a key can be rotated by adding its replacement before removing it.
+//!
+//! Clients send the key as `Authorization: Bearer <key>` or `X-Api-Key`.
+//! The key types are always available, since `API_KEYS` is part of the
+//! shared configuration; the axum middleware needs the `http` feature.
+
+use std::fmt;
+#[cfg(feature = "http")]
+use std::sync::Arc;
+
+#[cfg(feature = "http")]
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+#[cfg(feature = "http")]
+use tracing::{debug, warn};
+
+use crate::util::redact;
+
+/// Scope for Prometheus metrics and autoscaling signals.
+pub const SCOPE_METRICS: &str = "metrics";
+
+/// Scope for administrative endpoints.
+pub const SCOPE_ADMIN: &str = "admin";
+
+/// Scope for event streams.
+pub const SCOPE_EVENTS: &str = "events";
+
+/// A named API key and the scopes it grants.
+#[derive(Clone, PartialEq)]
+pub struct ApiKey {
+    pub name: String,
+    key: String,
+    pub scopes: Vec<String>,
+}
+
+impl ApiKey {
+    /// Parse an entry of the form `name:key:scope+scope`.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let mut parts = entry.trim().splitn(3, ':');
+        let name = parts.next()?.trim();
+        let key = parts.next()?.trim();
+        let scopes: Vec<String> = parts
+            .next()?
+            .split('+')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        if name.is_empty() || key.is_empty() || scopes.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            key: key.to_string(),
+            scopes,
+        })
+    }
+
+    fn grants(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == "*" || s == scope)
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKey")
+            .field("name", &self.name)
+            .field("key", &"<redacted>")
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+/// Why a request was not authorized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// No key was presented
+    Missing,
+    /// The key matches no configured key
+    Invalid,
+    /// The key is valid but lacks the route's scope
+    OutOfScope,
+}
+
+impl AuthFailure {
+    /// Stable name used in logs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuthFailure::Missing => "missing",
+            AuthFailure::Invalid => "invalid",
+            AuthFailure::OutOfScope => "out_of_scope",
+        }
+    }
+
+    #[cfg(feature = "http")]
+    fn status(self) -> StatusCode {
+        match self {
+            AuthFailure::OutOfScope => StatusCode::FORBIDDEN,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+/// The configured API keys.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApiKeys(pub Vec<ApiKey>);
+
+impl ApiKeys {
+    /// Whether no keys are configured, leaving protected routes open.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Register every key for log redaction.
+    pub fn register_secrets(&self) {
+        for key in &self.0 {
+            redact::register_secret(&key.key);
+        }
+    }
+
+    /// Check a presented key against `scope`, returning the key's name.
+    pub fn authorize(&self, presented: Option<&str>, scope: &str) -> Result<&str, AuthFailure> {
+        let presented = presented.ok_or(AuthFailure::Missing)?;
+        let key = self
+            .0
+            .iter()
+            .find(|k| constant_time_compare(&k.key, presented))
+            .ok_or(AuthFailure::Invalid)?;
+
+        if key.grants(scope) {
+            Ok(&key.name)
+        } else {
+            Err(AuthFailure::OutOfScope)
+        }
+    }
+}
+
+/// Constant-time string comparison to prevent timing attacks.
+pub(crate) fn constant_time_compare(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut result = 0u8;
+    for (x, y) in a.bytes().zip(b.bytes()) {
+        result |= x ^ y;
+    }
+    result == 0
+}
+
+/// Require an API key with `scope` on every route currently in `router`.
+///
+/// With no keys configured the router is returned unchanged, so existing
+/// deployments keep their open endpoints until keys are set.
+#[cfg(feature = "http")]
+pub fn require_scope<S>(router: Router<S>, keys: &Arc<ApiKeys>, scope: &'static str) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if keys.is_empty() {
+        return router;
+    }
+
+    let guard = ScopeGuard {
+        keys: Arc::clone(keys),
+        scope,
+    };
+    router.route_layer(middleware::from_fn_with_state(guard, check_api_key))
+}
+
+#[cfg(feature = "http")]
+#[derive(Clone)]
+struct ScopeGuard {
+    keys: Arc<ApiKeys>,
+    scope: &'static str,
+}
+
+#[cfg(feature = "http")]
+async fn check_api_key(State(guard): State<ScopeGuard>, request: Request, next: Next) -> Response {
+    let outcome = guard
+        .keys
+        .authorize(presented_key(request.headers()), guard.scope)
+        .map(str::to_string);
+
+    match outcome {
+        Ok(name) => {
+            debug!(
+                key = %name,
+                scope = guard.scope,
+                path = %request.uri().path(),
+                "api_key_authorized"
+            );
+            next.run(request).await
+        }
+        Err(failure) => {
+            warn!(
+                scope = guard.scope,
+                path = %request.uri().path(),
+                reason = failure.as_str(),
+                "api_key_rejected"
+            );
+            failure.status().into_response()
+        }
+    }
+}
+
+/// Key from `Authorization: Bearer <key>` or `X-Api-Key`.
+#[cfg(feature = "http")]
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> ApiKeys {
+        ApiKeys(vec![
+            ApiKey::parse("grafana:abc123:metrics").unwrap(),
+            ApiKey::parse("ops:s3cret:*").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_api_key_parse() {
+        let key = ApiKey::parse(" ops : k3y : admin+events ").unwrap();
+        assert_eq!(key.name, "ops");
+        assert_eq!(key.key, "k3y");
+        assert_eq!(key.scopes, vec!["admin".to_string(), "events".to_string()]);
+        assert_eq!(ApiKey::parse("ops:key:"), None);
+        assert_eq!(ApiKey::parse("ops:key"), None);
+        assert_eq!(ApiKey::parse(":key:admin"), None);
+        assert!(!format!("{:?}", key).contains("k3y"));
+    }
+
+    #[test]
+    fn test_authorize() {
+        let keys = keys();
+        assert_eq!(keys.authorize(Some("abc123"), SCOPE_METRICS), Ok("grafana"));
+        assert_eq!(keys.authorize(Some("abc123"), SCOPE_ADMIN), Err(AuthFailure::OutOfScope));
+        assert_eq!(keys.authorize(Some("s3cret"), SCOPE_ADMIN), Ok("ops"));
+        assert_eq!(keys.authorize(Some("nope"), SCOPE_METRICS), Err(AuthFailure::Invalid));
+        assert_eq!(keys.authorize(None, SCOPE_METRICS), Err(AuthFailure::Missing));
+    }
+
+    #[test]
+    fn test_constant_time_compare() {
+        assert!(constant_time_compare("abc", "abc"));
+        assert!(!constant_time_compare("abc", "abd"));
+        assert!(!constant_time_compare("abc", "abcd"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_presented_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(presented_key(&headers), None);
+
+        headers.insert("x-api-key", "abc123".parse().unwrap());
+        assert_eq!(presented_key(&headers), Some("abc123"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer s3cret".parse().unwrap());
+        assert_eq!(presented_key(&headers), Some("s3cret"));
+    }
+}
@@ -0,0 +1,756 @@
+//! BobNet CLI - Queue inspection and test publishing.
+//!
+//! Covers the queue operations otherwise done by hand in the RabbitMQ
+//! management UI:
+//! - `peek`: print messages on a queue without consuming them
+//! - `publish`: enqueue a hand-written `SimulatorJob` or `InboundWebhook`
+//! - `purge`: delete every message on a queue
+//! - `dlq`: browse dead-lettered messages and requeue them after a fix
+//! - `simulate`: simulate a local `.eml` file with the worker's simulator
+//! - `replay`: rerun a job with the seed and config recorded on its report
+//! - `stats`: summarize recent simulation outcomes across the workers
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use lapin::{
+    message::Delivery,
+    options::{
+        BasicAckOptions, BasicGetOptions, BasicNackOptions, BasicPublishOptions,
+        ConfirmSelectOptions, QueuePurgeOptions,
+    },
+    Channel, Connection,
+};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use bobnet::campaign::overrides::{self, CampaignOverrides};
+use bobnet::cli::{CommonArgs, Overrides};
+use bobnet::config::SIMULATION_VARS;
+use bobnet::content::{self, ContentCache};
+use bobnet::process::parse_raw_email;
+use bobnet::queue::dead_letter::{headers_json, requeue_properties};
+use bobnet::queue::{request_id, BrokerUrls};
+use bobnet::queue::{Death, MessageSigner, DEAD_LETTER_QUEUE};
+use bobnet::simulate::simulator::Job;
+use bobnet::stats::{fetch_summary, parse_window, StatsSummary};
+use bobnet::util::{logging, redact};
+use bobnet::{
+    tenant, CoreConfig, InboundWebhook, Publisher, Simulator, SimulatorJob, WorkerConfig,
+    INBOUND_QUEUE, SIMULATOR_QUEUE, SIMULATOR_QUEUES,
+};
+
+/// BobNet queue inspection and test publishing.
+///
+/// Flags override the matching environment variables.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print messages on a queue without consuming them
+    Peek {
+        /// Queue to read
+        #[arg(value_parser = known_queue)]
+        queue: String,
+
+        /// Maximum number of messages to print
+        #[arg(long, short = 'n', default_value_t = 10)]
+        count: u32,
+    },
+
+    /// Publish a message read from a JSON file
+    Publish {
+        #[command(subcommand)]
+        message: Message,
+    },
+
+    /// Delete every message on a queue
+    Purge {
+        /// Queue to purge
+        #[arg(value_parser = known_queue)]
+        queue: String,
+
+        /// Confirm the purge
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Browse and requeue dead-lettered messages
+    Dlq {
+        /// Dead-letter queue
+        #[arg(long, default_value = DEAD_LETTER_QUEUE)]
+        queue: String,
+
+        /// Most messages fetched when looking for selected ones
+        #[arg(long, default_value_t = 1000)]
+        scan_limit: usize,
+
+        #[command(subcommand)]
+        command: DlqCommand,
+    },
+
+    /// Simulate a local `.eml` file as the worker would and print its report
+    Simulate {
+        /// RFC 5322 message to simulate
+        file: PathBuf,
+
+        /// Recipient to simulate for; picks its preset, cohort and persona
+        #[arg(long, default_value = "simulate@example.com")]
+        to: String,
+
+        /// Make every decision but answer fetches locally
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Rerun a job with the seed, simulation settings and start time
+    /// recorded on its report, and show where the choices differ
+    Replay {
+        /// Report JSON, as published to RESULTS_QUEUE
+        report: PathBuf,
+
+        /// JSON file holding the report's `SimulatorJob`
+        job: PathBuf,
+
+        /// Wait out the open and click delays, as the worker did
+        #[arg(long)]
+        wait: bool,
+    },
+
+    /// Summarize realized open and click rates from the worker sidecars
+    Stats {
+        /// Window to summarize, e.g. 15m, 1h or 2d (at most 24h)
+        #[arg(long, default_value = "1h", value_parser = window)]
+        since: Duration,
+
+        /// Worker sidecar base URL, repeated or comma-separated for several
+        /// workers [default: http://127.0.0.1:$WORKER_METRICS_PORT]
+        #[arg(long = "worker", env = "WORKER_SIDECAR_URLS", value_delimiter = ',')]
+        workers: Vec<String>,
+
+        /// API key with the `metrics` scope, if the workers set API_KEYS
+        #[arg(long, env = "BOBNET_API_KEY", hide_env_values = true)]
+        api_key: Option<String>,
+
+        /// Number of most clicked domains to print
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum DlqCommand {
+    /// List dead-lettered messages, one page at a time
+    List {
+        /// Page to list, starting at 1
+        #[arg(long, default_value_t = 1)]
+        page: usize,
+
+        /// Messages per page
+        #[arg(long, default_value_t = 20)]
+        page_size: usize,
+    },
+
+    /// Print a dead-lettered message with its headers
+    Show {
+        /// Message ID, or position as printed by `dlq list`
+        message: String,
+    },
+
+    /// Republish messages to the queue they were dead-lettered from
+    Requeue {
+        /// Message IDs, or positions as printed by `dlq list`
+        #[arg(required_unless_present = "all")]
+        messages: Vec<String>,
+
+        /// Requeue every dead-lettered message
+        #[arg(long, conflicts_with = "messages")]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum Message {
+    /// A `SimulatorJob`, as the processor publishes it
+    Job {
+        /// JSON file holding the job
+        file: PathBuf,
+
+        /// Simulator queue to publish to
+        #[arg(long, default_value = SIMULATOR_QUEUE, value_parser = simulator_queue)]
+        queue: String,
+    },
+
+    /// An `InboundWebhook`, as the web server publishes it
+    Webhook {
+        /// JSON file holding the webhook
+        file: PathBuf,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // Command output goes to stdout, so only log warnings unless asked
+    logging::init_with_default(cli.common.log_format, "warn");
+
+    // Errors returned from main bypass the log writer, so mask them here
+    run(cli).await.map_err(redact::redact_error)
+}
+
+/// Load configuration and run the requested command.
+async fn run(cli: Cli) -> Result<()> {
+    let mut overrides = Overrides::new();
+    cli.common.apply(&mut overrides);
+    let core = || overrides.load(CoreConfig::from_vars_checked);
+
+    match cli.command {
+        Command::Peek { queue, count } => peek(&core()?, &queue, count).await,
+        Command::Publish { message } => publish(&core()?, message).await,
+        Command::Purge { queue, yes } => purge(&core()?, &queue, yes).await,
+        Command::Dlq {
+            queue,
+            scan_limit,
+            command,
+        } => dlq(&core()?, &queue, scan_limit, command).await,
+        Command::Simulate { file, to, dry_run } => {
+            let config = overrides.load(WorkerConfig::from_vars_checked)?;
+            simulate(&config, &file, &to, dry_run).await
+        }
+        Command::Replay { report, job, wait } => replay(&overrides, &report, &job, wait).await,
+        Command::Stats {
+            since,
+            mut workers,
+            api_key,
+            top,
+        } => {
+            if workers.is_empty() {
+                let config = overrides.load(WorkerConfig::from_vars_checked)?;
+                let port = config.worker_metrics_port.context(
+                    "No workers to query: pass --worker, or set WORKER_SIDECAR_URLS or WORKER_METRICS_PORT",
+                )?;
+                workers.push(format!("http://127.0.0.1:{}", port));
+            }
+            stats(&workers, since, api_key.as_deref(), top).await
+        }
+    }
+}
+
+/// Rerun the job in `job_file` as the worker did for the report in
+/// `report_file`, and print the replay's report with the decisions that
+/// differ from the recorded ones.
+///
+/// Simulation variables come from the report alone, so the worker's
+/// settings apply whatever this environment sets; everything else (TLS,
+/// `HTTP_CASSETTE`, the profile, override and content stores) comes from the
+/// environment. Fails when any decision differs.
+async fn replay(overrides: &Overrides, report_file: &Path, job_file: &Path, wait: bool) -> Result<()> {
+    let recorded: Value = read_json(report_file)?;
+    let job: SimulatorJob = read_json(job_file)?;
+    if recorded["message_id"].as_str() != Some(job.message_id.as_str()) {
+        anyhow::bail!(
+            "The report is for message {} but the job is {}",
+            recorded["message_id"],
+            job.message_id
+        );
+    }
+    let seed = recorded["seed"]
+        .as_u64()
+        .context("The report has no seed")?;
+    let started_at = recorded["started_at"]
+        .as_u64()
+        .context("The report has no start time; it comes from a worker without replays")?;
+    let vars: HashMap<String, String> =
+        serde_json::from_value(recorded["config"].clone()).unwrap_or_default();
+
+    let config = overrides.load(|var| {
+        WorkerConfig::from_vars_checked(&|name| {
+            if SIMULATION_VARS.contains(&name) {
+                vars.get(name).cloned()
+            } else {
+                var(name)
+            }
+        })
+    })?;
+
+    let mut simulator = Simulator::new(config.clone())?.with_clock(started_at);
+    if !wait {
+        simulator = simulator.without_delays();
+    }
+    if let Some(url) = &config.tenant_profiles_url {
+        let ttl = Duration::from_secs(config.tenant_profiles_ttl_secs);
+        let profiles = tenant::connect(url, ttl)
+            .await
+            .context("Failed to connect to the tenant profile store")?;
+        simulator = simulator.with_profiles(profiles);
+    }
+    if let Some(url) = &config.core.campaign_overrides_url {
+        let ttl = Duration::from_secs(config.campaign_overrides_ttl_secs);
+        let store = overrides::connect(url)
+            .await
+            .context("Failed to connect to the campaign override store")?;
+        simulator = simulator.with_campaign_overrides(CampaignOverrides::new(store, ttl));
+    }
+
+    let mut job = Job::from(job);
+    if job.html.is_none() && job.html_sha256.is_some() {
+        let url = config
+            .core
+            .content_store_url
+            .as_ref()
+            .context("The job's HTML is in the content store: set CONTENT_STORE_URL")?;
+        let store = content::connect(url, Duration::ZERO)
+            .await
+            .context("Failed to connect to the content store")?;
+        ContentCache::new(store, config.content_cache_bytes)
+            .resolve(&mut job)
+            .await
+            .context("Failed to fetch the job's HTML")?;
+    }
+
+    let replayed = serde_json::to_value(simulator.simulate_seeded(&job, seed).await)?;
+    let (recorded_decisions, replayed_decisions) = (decisions(&recorded), decisions(&replayed));
+    let differences: Vec<Value> = replayed_decisions
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(field, value)| recorded_decisions.get(field.as_str()) != Some(*value))
+        .map(|(field, value)| {
+            json!({
+                "field": field,
+                "recorded": recorded_decisions[field.as_str()],
+                "replayed": value,
+            })
+        })
+        .collect();
+
+    let output = json!({
+        "message_id": job.message_id,
+        "seed": seed,
+        "started_at": started_at,
+        "differences": differences,
+        "report": replayed,
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    if !differences.is_empty() {
+        anyhow::bail!("The replay made {} different decision(s)", differences.len());
+    }
+    Ok(())
+}
+
+/// What a report's seed, config and clock decided: the persona, delays,
+/// rolls and URLs fetched, but not what the fetches returned.
+fn decisions(report: &Value) -> Value {
+    let fetches = |kind: &str| -> Vec<Value> {
+        report[kind]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|fetch| json!({"url": fetch["url"], "delay_ms": fetch["delay_ms"]}))
+            .collect()
+    };
+    json!({
+        "status": report["status"],
+        "persona": report["persona"],
+        "open_delay_ms": report["open_delay_ms"],
+        "message_age_secs": report["message_age_secs"],
+        "calendar_factor": report["calendar_factor"],
+        "scroll_depth": report["scroll_depth"],
+        "pixels": fetches("pixels"),
+        "clicks": fetches("clicks"),
+        "scans": fetches("scans"),
+        "skipped": report["skipped"],
+    })
+}
+
+/// Accept any queue the binaries declare.
+fn known_queue(name: &str) -> Result<String, String> {
+    if name == INBOUND_QUEUE || SIMULATOR_QUEUES.contains(&name) {
+        Ok(name.to_string())
+    } else {
+        Err(format!(
+            "unknown queue (expected {} or one of {})",
+            INBOUND_QUEUE,
+            SIMULATOR_QUEUES.join(", ")
+        ))
+    }
+}
+
+/// Accept only the queues jobs are published to.
+fn simulator_queue(name: &str) -> Result<String, String> {
+    if SIMULATOR_QUEUES.contains(&name) {
+        Ok(name.to_string())
+    } else {
+        Err(format!("not a simulator queue (expected one of {})", SIMULATOR_QUEUES.join(", ")))
+    }
+}
+
+async fn connect(config: &CoreConfig) -> Result<(Connection, Channel)> {
+    let (conn, _) = BrokerUrls::from_config(config)
+        .connect()
+        .await
+        .context("Failed to connect to RabbitMQ")?;
+    let channel = conn
+        .create_channel()
+        .await
+        .context("Failed to create channel")?;
+    Ok((conn, channel))
+}
+
+/// Print up to `count` messages from `queue`, then return them all to it.
+///
+/// Messages are fetched unacknowledged and requeued together at the end, so
+/// they keep their order but come back marked as redelivered.
+async fn peek(config: &CoreConfig, queue: &str, count: u32) -> Result<()> {
+    let (conn, channel) = connect(config).await?;
+    let signer = MessageSigner::from_config(config);
+    let mut last_tag = None;
+
+    for _ in 0..count {
+        let message = channel
+            .basic_get(queue, BasicGetOptions { no_ack: false })
+            .await
+            .with_context(|| format!("Failed to read from {}", queue))?;
+        let Some(message) = message else {
+            break;
+        };
+        let remaining = message.message_count;
+        let delivery = message.delivery;
+
+        let signature = match &signer {
+            Some(signer) => match signer.verify(&delivery.properties, &delivery.data) {
+                Ok(()) => "accepted",
+                Err(e) => e.as_str(),
+            },
+            None => "unchecked",
+        };
+        let output = json!({
+            "queue": queue,
+            "message_id": message_id(&delivery),
+            "request_id": request_id::of(&delivery.properties),
+            "redelivered": delivery.redelivered,
+            "remaining": remaining,
+            "signature": signature,
+            "body": body_json(&delivery.data),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        last_tag = Some(delivery.delivery_tag);
+    }
+
+    match last_tag {
+        Some(tag) => channel
+            .basic_nack(tag, BasicNackOptions { multiple: true, requeue: true })
+            .await
+            .context("Failed to requeue peeked messages")?,
+        None => eprintln!("{} is empty", queue),
+    }
+
+    let _ = conn.close(200, "OK").await;
+    Ok(())
+}
+
+/// A message body as JSON, or as a string when it isn't JSON.
+fn body_json(data: &[u8]) -> Value {
+    serde_json::from_slice(data)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(data).into_owned()))
+}
+
+/// Publish a message read from a JSON file, signed like the binaries sign.
+async fn publish(config: &CoreConfig, message: Message) -> Result<()> {
+    let publisher = Publisher::from_brokers(BrokerUrls::from_config(config))
+        .with_signer(MessageSigner::from_config(config))
+        .with_topic_exchanges(config.topic_bindings())
+        .with_max_priority(config.simulator_max_priority());
+
+    match message {
+        Message::Job { file, queue } => {
+            let job: SimulatorJob = read_json(&file)?;
+            publisher.publish_simulator_to(&queue, &job).await?;
+            println!("Published job {} to {}", job.message_id, queue);
+        }
+        Message::Webhook { file } => {
+            let webhook: InboundWebhook = read_json(&file)?;
+            let request_id = request_id::generate();
+            publisher.publish_inbound(&webhook, Some(&request_id)).await?;
+            println!("Published webhook to {} (request ID {})", INBOUND_QUEUE, request_id);
+        }
+    }
+
+    publisher.close().await;
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("{} does not hold a valid message", path.display()))
+}
+
+/// Delete every message on `queue`.
+async fn purge(config: &CoreConfig, queue: &str, yes: bool) -> Result<()> {
+    if !yes {
+        anyhow::bail!("Refusing to purge {} without --yes", queue);
+    }
+
+    let (conn, channel) = connect(config).await?;
+    let purged = channel
+        .queue_purge(queue, QueuePurgeOptions::default())
+        .await
+        .with_context(|| format!("Failed to purge {}", queue))?;
+    println!("Purged {} messages from {}", purged, queue);
+
+    let _ = conn.close(200, "OK").await;
+    Ok(())
+}
+
+/// Run a `dlq` subcommand against `queue`.
+///
+/// Messages are fetched unacknowledged; everything not requeued is returned
+/// to the dead-letter queue when the command finishes.
+async fn dlq(config: &CoreConfig, queue: &str, scan_limit: usize, command: DlqCommand) -> Result<()> {
+    let (conn, channel) = connect(config).await?;
+
+    let result = match command {
+        DlqCommand::List { page, page_size } => {
+            let skip = page.saturating_sub(1) * page_size;
+            let deliveries = fetch(&channel, queue, skip + page_size).await?;
+            if deliveries.len() <= skip {
+                eprintln!("No dead-lettered messages on page {}", page);
+            }
+            for (index, delivery) in deliveries.iter().enumerate().skip(skip) {
+                println!("{}", summary(index + 1, delivery));
+            }
+            Ok(())
+        }
+        DlqCommand::Show { message } => {
+            let deliveries = fetch(&channel, queue, scan_limit).await?;
+            match deliveries
+                .iter()
+                .enumerate()
+                .find(|(index, delivery)| selects(&message, index + 1, delivery))
+            {
+                Some((index, delivery)) => {
+                    let mut output = summary(index + 1, delivery);
+                    output["headers"] = headers_json(&delivery.properties);
+                    output["body"] = body_json(&delivery.data);
+                    println!("{}", serde_json::to_string_pretty(&output)?);
+                    Ok(())
+                }
+                None => Err(anyhow::anyhow!("No dead-lettered message matches {}", message)),
+            }
+        }
+        DlqCommand::Requeue { messages, all } => {
+            channel
+                .confirm_select(ConfirmSelectOptions::default())
+                .await
+                .context("Failed to enable publisher confirms")?;
+            let deliveries = fetch(&channel, queue, scan_limit).await?;
+            let mut requeued = 0;
+            for (index, delivery) in deliveries.iter().enumerate() {
+                let selected = all || messages.iter().any(|m| selects(m, index + 1, delivery));
+                if selected && requeue(&channel, delivery).await? {
+                    requeued += 1;
+                }
+            }
+            println!("Requeued {} messages", requeued);
+            Ok(())
+        }
+    };
+
+    // Return whatever was fetched and not requeued (tag 0: all outstanding)
+    channel
+        .basic_nack(0, BasicNackOptions { multiple: true, requeue: true })
+        .await
+        .context("Failed to return messages to the dead-letter queue")?;
+    let _ = conn.close(200, "OK").await;
+    result
+}
+
+/// Fetch up to `limit` messages from `queue` without acknowledging them.
+async fn fetch(channel: &Channel, queue: &str, limit: usize) -> Result<Vec<Delivery>> {
+    let mut deliveries = Vec::new();
+    while deliveries.len() < limit {
+        let message = channel
+            .basic_get(queue, BasicGetOptions { no_ack: false })
+            .await
+            .with_context(|| format!("Failed to read from {}", queue))?;
+        match message {
+            Some(message) => deliveries.push(message.delivery),
+            None => break,
+        }
+    }
+    Ok(deliveries)
+}
+
+/// Whether `selector` (a message ID or 1-based position) picks `delivery`.
+fn selects(selector: &str, position: usize, delivery: &Delivery) -> bool {
+    selector.parse::<usize>().ok() == Some(position)
+        || message_id(delivery).is_some_and(|id| id == selector)
+}
+
+fn message_id(delivery: &Delivery) -> Option<&str> {
+    delivery.properties.message_id().as_ref().map(|id| id.as_str())
+}
+
+/// One-line description of a dead-lettered message.
+fn summary(position: usize, delivery: &Delivery) -> Value {
+    let death = Death::of(&delivery.properties);
+    json!({
+        "position": position,
+        "message_id": message_id(delivery),
+        "original_queue": death.as_ref().map(|d| d.queue.as_str()),
+        "reason": death.as_ref().map(|d| d.reason.as_str()),
+        "deaths": death.as_ref().map(|d| d.count),
+        "body_length": delivery.data.len(),
+    })
+}
+
+/// Republish a dead-lettered delivery to its original queue, then ack it.
+///
+/// Returns `false` when the original queue is unknown and it was skipped.
+async fn requeue(channel: &Channel, delivery: &Delivery) -> Result<bool> {
+    let Some(death) = Death::of(&delivery.properties) else {
+        eprintln!(
+            "Skipping {}: no x-death header to find its original queue",
+            message_id(delivery).unwrap_or("message without ID")
+        );
+        return Ok(false);
+    };
+
+    channel
+        .basic_publish(
+            "",
+            &death.queue,
+            BasicPublishOptions::default(),
+            &delivery.data,
+            requeue_properties(&delivery.properties),
+        )
+        .await
+        .with_context(|| format!("Failed to republish to {}", death.queue))?
+        .await
+        .context("Failed to confirm republish")?;
+    channel
+        .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+        .await
+        .context("Failed to ack requeued message")?;
+
+    println!(
+        "Requeued {} to {}",
+        message_id(delivery).unwrap_or("message without ID"),
+        death.queue
+    );
+    Ok(true)
+}
+
+/// Simulate a local `.eml` file with the worker's [`Simulator`] and print
+/// its report.
+///
+/// The email is simulated for `to` without delays; with `dry_run` every
+/// decision is made but fetches are answered locally.
+async fn simulate(config: &WorkerConfig, file: &Path, to: &str, dry_run: bool) -> Result<()> {
+    let raw = fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let email = parse_raw_email(&raw)?;
+
+    let mut job = Job {
+        message_id: email.message_id,
+        to: to.to_string(),
+        html: email.html,
+        ..Job::default()
+    };
+    job.overrides.dry_run = dry_run;
+
+    let simulator = Simulator::new(config.clone())?.without_delays();
+    let report = simulator.simulate(&job).await;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Parse a `--since` window.
+fn window(value: &str) -> Result<Duration, String> {
+    parse_window(value)
+        .ok_or_else(|| format!("invalid window `{}` (expected e.g. 90s, 15m, 1h or 2d)", value))
+}
+
+/// Collect outcome summaries from every worker and print their totals.
+///
+/// Unreachable workers are reported and left out; the command fails only if
+/// none answer.
+async fn stats(workers: &[String], since: Duration, api_key: Option<&str>, top: usize) -> Result<()> {
+    if let Some(key) = api_key {
+        redact::register_secret(key);
+    }
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let mut total = StatsSummary::default();
+    let mut answered = 0;
+    for worker in workers {
+        match fetch_summary(&client, worker, since, api_key).await {
+            Ok(summary) => {
+                total.merge(&summary);
+                answered += 1;
+            }
+            Err(e) => eprintln!("Skipping {}: {:#}", worker, e),
+        }
+    }
+    if answered == 0 {
+        anyhow::bail!("No worker returned stats");
+    }
+
+    let percent = |rate: Option<f64>| rate.map_or("-".to_string(), |r| format!("{:.1}%", r * 100.0));
+    let statuses: Vec<String> = total
+        .statuses
+        .iter()
+        .map(|(status, count)| format!("{} {}", status, count))
+        .collect();
+
+    println!(
+        "Window:        last {} ({} of {} workers)",
+        describe_window(total.window_secs),
+        answered,
+        workers.len()
+    );
+    println!("Jobs:          {} ({} rejected)", total.jobs, total.rejected);
+    println!("Open rate:     {} ({} opened)", percent(total.open_rate()), total.opened);
+    println!(
+        "Click rate:    {} ({} jobs clicked, {} clicks)",
+        percent(total.click_rate()),
+        total.clicked,
+        total.clicks
+    );
+    println!("Click errors:  {}", total.click_errors);
+    println!("Statuses:      {}", statuses.join(", "));
+    println!("Top clicked domains:");
+    for (domain, count) in total.top_domains(top) {
+        println!("  {:<40} {}", domain, count);
+    }
+    Ok(())
+}
+
+/// A window length as the shortest of `90s`, `15m`, `1h` or `2d`.
+fn describe_window(secs: u64) -> String {
+    match secs {
+        s if s > 0 && s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s > 0 && s % 3_600 == 0 => format!("{}h", s / 3_600),
+        s if s > 0 && s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
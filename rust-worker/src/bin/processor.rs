@@ -3,85 +3,161 @@
 //! This binary:
 //! 1. Consumes raw webhook payloads from the inbound_webhooks queue
 //! 2. Parses and processes them (email parsing, Message-Id extraction)
-//! 3. Publishes prepared jobs to the email_simulator queue
+//! 3. Publishes prepared jobs to the email_simulator queue, or to per-provider
+//!    queues (`email_simulator.mailgun`, ...) when `PROVIDER_QUEUES` is set,
+//!    or to their tenant's tier queue (`email_simulator.premium`, ...) when
+//!    `TENANT_TIERS` lists it; with `TOPIC_EXCHANGES` jobs instead go to the
+//!    `bobnet.jobs` exchange and `JOB_BINDINGS` decides their queues. Jobs of
+//!    a tier with a priority stay on their queue, stamped with the priority
+//!
+//! With `CONTENT_STORE_URL` set, HTML of `CONTENT_STORE_MIN_BYTES` or more is
+//! stored once under its SHA-256 and jobs carry only the hash.
+//!
+//! With `MIRROR_PERCENT` set, that share of jobs is also published, marked
+//! dry-run, to the `MIRROR_QUEUE` shadow queue (on `MIRROR_AMQP_URL` when
+//! set) for a staging worker to consume.
+//!
+//! With `MAILGUN_API_KEY` set, Mailgun webhooks from store-and-notify routes
+//! have their stored message retrieved and its full MIME parsed, escaping
+//! Mailgun's forwarding size limits; a retrieval that may succeed later is
+//! requeued, and one that never will falls back to the notification's own
+//! fields.
+//!
+//! A webhook whose processing panics is nacked without requeue, like any
+//! other malformed webhook, so it lands in `dead_letters` for inspection.
+//!
+//! Jobs carry the webhook's request ID (`x-request-id`) on to the worker;
+//! webhooks enqueued without one get a new one here.
 //!
 //! This separates the heavy lifting (parsing) from the web server,
 //! allowing the web server to remain extremely fast and responsive.
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use futures::StreamExt;
 use lapin::{
-    options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions, QueueDeclareOptions},
+    options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, QueueDeclareOptions},
     types::FieldTable,
-    Connection, ConnectionProperties,
 };
 use tokio::signal;
-use tracing::{error, info, warn};
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-
+use clap::Parser;
+use tracing::{error, info, warn, Instrument};
+
+use bobnet::cli::{CommonArgs, Overrides, ServiceCommand};
+use bobnet::content::{self, ContentWriter};
+use bobnet::{doctor, healthcheck};
+use bobnet::process::{webhook_received_at, MailgunStorage};
+use bobnet::queue::consumers::{consumer_tag, ConsumerChannels};
+use bobnet::queue::dead_letter;
+use bobnet::queue::priority::simulator_queue_args;
+use bobnet::queue::{request_id, BrokerUrls, MessageSigner, Mirror};
+use bobnet::simulate::decay::message_age_secs;
+use bobnet::simulate::latency::unix_millis;
+use bobnet::tenant::tier::{self, TenantTier};
+use bobnet::util::systemd::{self, Watchdog};
+use bobnet::util::{logging, panic, redact};
 use bobnet::{
-    process_webhook, Config, InboundWebhook, Publisher, INBOUND_QUEUE, SIMULATOR_QUEUE,
+    BobNetError, InboundWebhook, Pipeline, ProcessorConfig, Publisher, INBOUND_QUEUE, SIMULATOR_QUEUE,
+    SIMULATOR_QUEUES,
 };
 
+/// BobNet webhook processor.
+///
+/// Flags override the matching environment variables.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Publish jobs to per-provider simulator queues [overrides PROVIDER_QUEUES]
+    #[arg(long)]
+    provider_queues: bool,
+
+    /// Print the effective configuration (secrets redacted) and exit
+    #[arg(long)]
+    print_config: bool,
+
+    #[command(subcommand)]
+    command: Option<ServiceCommand>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize structured JSON logging
-    let filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let cli = Cli::parse();
 
-    tracing_subscriber::registry()
-        .with(filter)
-        .with(fmt::layer().json().flatten_event(true))
-        .init();
-
-    info!("processor_starting");
+    // Initialize structured logging (quietly for one-shot subcommands)
+    let one_shot = cli.command.is_some() || cli.print_config;
+    logging::init_with_default(cli.common.log_format, if one_shot { "warn" } else { "info" });
 
     // Load configuration
-    let config = Config::from_env();
+    let mut overrides = Overrides::new();
+    cli.common.apply(&mut overrides);
+    overrides.set("PROVIDER_QUEUES", cli.provider_queues.then_some("true"));
+    // Errors returned from main bypass the log writer, so mask them here
+    let config = overrides
+        .load(ProcessorConfig::from_vars_checked)
+        .map_err(redact::redact_error)?;
+
+    match cli.command {
+        Some(ServiceCommand::Healthcheck) => {
+            // The processor serves no HTTP, so it is healthy if the broker is
+            healthcheck::check_broker(&config.core)
+                .await
+                .map_err(redact::redact_error)?;
+            println!("healthy");
+            return Ok(());
+        }
+        Some(ServiceCommand::Doctor) => {
+            let report = doctor::processor(&config).await;
+            print!("{}", report);
+            return report.into_result();
+        }
+        None if cli.print_config => {
+            println!("{}", doctor::effective_config(&config));
+            return Ok(());
+        }
+        None => {}
+    }
+
+    info!("processor_starting");
     info!(
         concurrency = config.worker_concurrency,
+        tenant_tiers = config.tenant_tiers.as_ref().map_or(0, Vec::len),
+        simulator_max_priority = config.core.simulator_max_priority,
         "config_loaded"
     );
 
     // Run the processor
-    run(config).await?;
-
-    Ok(())
+    run(config).await.map_err(redact::redact_error)
 }
 
 /// Run the processor.
-async fn run(config: Config) -> Result<()> {
+async fn run(config: ProcessorConfig) -> Result<()> {
     let config = Arc::new(config);
 
     // Connect to RabbitMQ for consuming
-    info!(url_length = config.cloudamqp_url.len(), "rabbitmq_connecting");
+    info!(url_length = config.core.cloudamqp_url.len(), "rabbitmq_connecting");
 
-    let conn = Connection::connect(&config.cloudamqp_url, ConnectionProperties::default())
-        .await
-        .context("Failed to connect to RabbitMQ")?;
+    // Consumer channels, together prefetching enough for concurrent processing
+    let connections = ConsumerChannels::open(
+        &BrokerUrls::from_config(&config.core),
+        config.core.consumer_connections,
+        config.core.consumer_channels,
+        config.core.consumer_prefetch(config.worker_concurrency),
+    )
+    .await
+    .context("Failed to connect to RabbitMQ")?;
+    let broker = connections.broker;
 
-    info!("rabbitmq_connected");
+    info!(broker = broker, primary = broker == 0, "rabbitmq_connected");
 
-    // Create a channel for consuming
-    let channel = conn
-        .create_channel()
-        .await
-        .context("Failed to create channel")?;
+    // Declare queues and move poison messages on the first channel
+    let channel = connections.main().clone();
 
-    info!("rabbitmq_channel_created");
-
-    // Set QoS with high prefetch for concurrent processing
-    let prefetch_count = config.worker_concurrency as u16;
-    channel
-        .basic_qos(prefetch_count, BasicQosOptions::default())
-        .await
-        .context("Failed to set QoS")?;
-
-    info!(prefetch_count = prefetch_count, "rabbitmq_qos_set");
-
-    // Declare both queues
+    // Declare the inbound, simulator and tier queues
     channel
         .queue_declare(
             INBOUND_QUEUE,
@@ -94,44 +170,123 @@ async fn run(config: Config) -> Result<()> {
         .await
         .context("Failed to declare inbound queue")?;
 
-    channel
-        .queue_declare(
-            SIMULATOR_QUEUE,
-            QueueDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-        )
-        .await
-        .context("Failed to declare simulator queue")?;
+    let tier_queues = config
+        .tenant_tiers
+        .as_deref()
+        .map(tier::tier_queues)
+        .unwrap_or_default();
+    let queues = SIMULATOR_QUEUES
+        .iter()
+        .copied()
+        .chain(tier_queues.iter().map(String::as_str));
+    for queue in queues {
+        channel
+            .queue_declare(
+                queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                simulator_queue_args(config.core.simulator_max_priority()),
+            )
+            .await
+            .with_context(|| format!("Failed to declare simulator queue {}", queue))?;
+    }
 
     info!(
         inbound_queue = INBOUND_QUEUE,
-        simulator_queue = SIMULATOR_QUEUE,
+        simulator_queues = ?SIMULATOR_QUEUES,
+        tier_queues = ?tier_queues,
+        provider_queues = config.provider_queues,
         "rabbitmq_queues_declared"
     );
 
+    // Sign what we publish and verify what we consume
+    let signer = MessageSigner::from_config(&config.core);
+    info!(
+        enabled = signer.is_some(),
+        required = config.core.queue_signing_required,
+        "queue_signing_configured"
+    );
+
     // Create publisher for output queue
-    let publisher = Publisher::new(config.cloudamqp_url.clone());
+    let publisher = Publisher::from_brokers(BrokerUrls::from_config(&config.core))
+        .with_chaos(config.core.chaos.clone())
+        .with_signer(signer.clone())
+        .with_topic_exchanges(config.core.topic_bindings())
+        .with_max_priority(config.core.simulator_max_priority());
     let publisher = Arc::new(publisher);
 
-    // Start consuming from inbound queue
-    let mut consumer = channel
-        .basic_consume(
-            INBOUND_QUEUE,
-            "rust-processor",
-            BasicConsumeOptions::default(),
-            FieldTable::default(),
-        )
-        .await
-        .context("Failed to start consumer")?;
+    // A share of jobs also goes, as dry runs, to the shadow queue
+    let mirror = (config.mirror_percent > 0.0).then(|| {
+        let mirror_publisher = match &config.mirror_amqp_url {
+            Some(url) => Publisher::new(url.clone()).with_signer(signer.clone()),
+            None => (*publisher).clone(),
+        };
+        info!(
+            percent = config.mirror_percent,
+            queue = %config.mirror_queue,
+            own_broker = config.mirror_amqp_url.is_some(),
+            "job_mirroring_enabled"
+        );
+        Arc::new(Mirror::new(
+            mirror_publisher,
+            config.mirror_queue.clone(),
+            config.mirror_percent,
+        ))
+    });
+
+    // Large HTML is stored once by its hash instead of in every job
+    let content = match &config.core.content_store_url {
+        Some(url) => {
+            let ttl = Duration::from_secs(config.content_store_ttl_secs);
+            let store = content::connect(url, ttl)
+                .await
+                .context("Failed to connect to the content store")?;
+            info!(
+                min_bytes = config.content_store_min_bytes,
+                ttl_secs = ttl.as_secs(),
+                "content_store_connected"
+            );
+            Some(Arc::new(ContentWriter::new(store, config.content_store_min_bytes)))
+        }
+        None => None,
+    };
 
-    info!(queue = INBOUND_QUEUE, "rabbitmq_consumer_started");
-    info!("processor_ready");
+    // Store-and-notify routes leave the full message on Mailgun
+    let mailgun_storage = match &config.mailgun_api_key {
+        Some(key) => Some(
+            MailgunStorage::new(key.as_str()).context("Failed to build the Mailgun storage client")?,
+        ),
+        None => None,
+    };
 
-    // Clone channel for use in message handler
-    let channel = Arc::new(channel);
+    // Stages run without hooks; embedders register theirs on a `Pipeline`
+    let pipeline = Pipeline::new();
+
+    // Start consuming from inbound queue on every channel into a single stream
+    let mut consumers = Vec::with_capacity(connections.channels.len());
+    for (i, consumer_channel) in connections.channels.iter().enumerate() {
+        let consumer = consumer_channel
+            .basic_consume(
+                INBOUND_QUEUE,
+                &consumer_tag("rust-processor", i),
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .context("Failed to start consumer")?;
+        consumers.push(consumer);
+    }
+    let mut consumer = futures::stream::select_all(consumers);
+
+    info!(
+        queue = INBOUND_QUEUE,
+        channels = connections.channels.len(),
+        "rabbitmq_consumer_started"
+    );
+    info!("processor_ready");
+    systemd::ready();
 
     // Create shutdown signal future
     let shutdown = async {
@@ -161,14 +316,29 @@ async fn run(config: Config) -> Result<()> {
     // Pin the shutdown future
     tokio::pin!(shutdown);
 
+    // Poison messages moved to the DLQ since startup
+    let mut poison_messages: u64 = 0;
+
+    // Ping the systemd watchdog while the loop keeps turning
+    let watchdog = Watchdog::from_env();
+    let ping_every = watchdog.map_or(Duration::from_secs(3600), |w| w.period());
+    let mut ping =
+        tokio::time::interval_at(tokio::time::Instant::now() + ping_every, ping_every);
+
     // Process messages until shutdown
     loop {
         tokio::select! {
             // Check for shutdown signal
             _ = &mut shutdown => {
                 info!("processor_stopping");
+                systemd::stopping();
                 break;
             }
+            _ = ping.tick(), if watchdog.is_some() => {
+                if let Some(watchdog) = &watchdog {
+                    watchdog.ping();
+                }
+            }
             // Process next message
             delivery = consumer.next() => {
                 match delivery {
@@ -180,58 +350,214 @@ async fn run(config: Config) -> Result<()> {
                             .as_ref()
                             .map(|s| s.to_string())
                             .unwrap_or_else(|| "unknown".to_string());
+                        let request_id = request_id::of(&delivery.properties)
+                            .unwrap_or_else(request_id::generate);
 
                         info!(
                             queue = INBOUND_QUEUE,
                             message_id = %message_id,
+                            request_id = %request_id,
                             delivery_tag = delivery_tag,
                             body_length = delivery.data.len(),
                             "rabbitmq_webhook_received"
                         );
 
+                        if let Some(Err(e)) =
+                            signer.as_ref().map(|s| s.verify(&delivery.properties, &delivery.data))
+                        {
+                            error!(
+                                message_id = %message_id,
+                                reason = e.as_str(),
+                                "queue_signature_rejected"
+                            );
+                            // Forged or corrupted; never requeue
+                            let _ = delivery
+                                .nack(
+                                    BasicNackOptions {
+                                        requeue: false,
+                                        ..Default::default()
+                                    },
+                                )
+                                .await;
+                            continue;
+                        }
+
+                        let previous = dead_letter::previous_deliveries(&delivery.properties);
+                        if dead_letter::is_poison(previous, config.core.max_delivery_attempts) {
+                            // The processor serves no metrics, so the count goes in the log
+                            poison_messages += 1;
+                            error!(
+                                message_id = %message_id,
+                                deliveries = previous,
+                                poison_total = poison_messages,
+                                "rabbitmq_poison_message"
+                            );
+                            if let Err(e) =
+                                dead_letter::divert_poison(&channel, INBOUND_QUEUE, &delivery, previous).await
+                            {
+                                error!(message_id = %message_id, error = %e, "rabbitmq_poison_divert_failed");
+                            }
+                            continue;
+                        }
+
                         // Clone resources for the spawned task
                         let publisher = Arc::clone(&publisher);
-                        let channel = Arc::clone(&channel);
-
-                        // Spawn a task to process this message
+                        let mirror = mirror.clone();
+                        let config = Arc::clone(&config);
+                        let content = content.clone();
+                        let mailgun_storage = mailgun_storage.clone();
+                        let pipeline = pipeline.clone();
+
+                        // Spawn a task to process this message; its logs
+                        // carry the request ID
+                        let span = tracing::info_span!("webhook", request_id = %request_id);
                         tokio::spawn(async move {
                             // Parse the inbound webhook
                             let webhook: Result<InboundWebhook, _> =
                                 serde_json::from_slice(&delivery.data);
 
                             match webhook {
-                                Ok(webhook) => {
-                                    // Process the webhook into a simulator job
-                                    match process_webhook(webhook) {
-                                        Ok(job) => {
-                                            // Publish to simulator queue
-                                            if let Err(e) =
-                                                publisher.publish_simulator(&job).await
-                                            {
-                                                error!(
-                                                    message_id = %job.message_id,
-                                                    error = %e,
-                                                    "rabbitmq_publish_failed"
+                                Ok(mut webhook) => {
+                                    // Drop webhooks too old to be worth simulating
+                                    let max_age_secs = config.max_message_age_secs;
+                                    let age_secs = webhook_received_at(&webhook).map(message_age_secs);
+                                    if let Some(age_secs) =
+                                        age_secs.filter(|age| max_age_secs > 0 && *age > max_age_secs)
+                                    {
+                                        warn!(
+                                            message_id = %message_id,
+                                            provider = webhook.provider(),
+                                            age_secs = age_secs,
+                                            max_age_secs = max_age_secs,
+                                            "webhook_expired"
+                                        );
+                                        let _ = delivery
+                                            .ack(BasicAckOptions::default())
+                                            .await;
+                                        return;
+                                    }
+
+                                    // Fetch the full message of a store-and-notify route
+                                    if let InboundWebhook::Mailgun(payload) = &mut webhook {
+                                        match (&mailgun_storage, payload.message_url.is_some()) {
+                                            (Some(storage), true) => {
+                                                if let Err(e) = storage.retrieve(payload).await {
+                                                    warn!(
+                                                        message_id = %message_id,
+                                                        error = %e,
+                                                        transient = e.is_transient(),
+                                                        "mailgun_retrieve_failed"
+                                                    );
+                                                    if e.is_transient() {
+                                                        let _ = delivery
+                                                            .nack(
+                                                                BasicNackOptions {
+                                                                    requeue: true,
+                                                                    ..Default::default()
+                                                                },
+                                                            )
+                                                            .await;
+                                                        return;
+                                                    }
+                                                }
+                                            }
+                                            (None, true) => {
+                                                warn!(
+                                                    message_id = %message_id,
+                                                    "mailgun_retrieve_skipped_no_api_key"
+                                                );
+                                            }
+                                            _ => {}
+                                        }
+                                    }
+
+                                    // Route to the provider's queue when enabled
+                                    let provider = webhook.provider();
+                                    let queue = if config.provider_queues {
+                                        webhook.simulator_queue()
+                                    } else {
+                                        SIMULATOR_QUEUE
+                                    };
+
+                                    // Process the webhook into a simulator job; a
+                                    // panic in a parser or hook fails just this one
+                                    let processed = panic::contain("webhook", || pipeline.process(webhook))
+                                        .map_err(|e| BobNetError::panicked("webhook", e))
+                                        .and_then(|processed| processed);
+                                    match processed {
+                                        Ok(Some(mut job)) => {
+                                            // A tenant with a tier goes to its tier's queue,
+                                            // or is stamped with its tier's priority
+                                            let tier = config
+                                                .tenant_tiers
+                                                .as_deref()
+                                                .and_then(|tiers| tier::recipient_tier(tiers, &job.to));
+                                            let tier_queue = tier.and_then(TenantTier::own_queue);
+                                            let queue = tier_queue.as_deref().unwrap_or(queue);
+
+                                            // Shadow copies keep their HTML, so the
+                                            // staging worker needs no content store
+                                            let shadow = mirror
+                                                .as_ref()
+                                                .and_then(|mirror| mirror.shadow_of(&job));
+
+                                            // A failed store only costs the job its
+                                            // smaller size
+                                            if let Some(content) = &content {
+                                                if let Err(e) = content.offload(&mut job).await {
+                                                    warn!(
+                                                        message_id = %job.message_id,
+                                                        error = %e,
+                                                        "content_store_failed"
+                                                    );
+                                                }
+                                            }
+
+                                            // Publish to simulator queue, or by routing
+                                            // key with topic exchanges
+                                            job.enqueued_at_ms = Some(unix_millis());
+                                            job.request_id = Some(request_id);
+                                            let published = publisher
+                                                .publish_simulator_routed(queue, provider, &job, tier)
+                                                .await;
+                                            let queue = match published {
+                                                Ok(queue) => queue,
+                                                Err(e) => {
+                                                    error!(
+                                                        message_id = %job.message_id,
+                                                        error = %e,
+                                                        "rabbitmq_publish_failed"
+                                                    );
+                                                    // Nack and requeue on publish failure
+                                                    let _ = delivery
+                                                        .nack(
+                                                            BasicNackOptions {
+                                                                requeue: true,
+                                                                ..Default::default()
+                                                            },
+                                                        )
+                                                        .await;
+                                                    return;
+                                                }
+                                            };
+
+                                            // Mirroring never holds up the job
+                                            if let (Some(mirror), Some(mut shadow)) = (mirror, shadow) {
+                                                shadow.enqueued_at_ms = job.enqueued_at_ms;
+                                                shadow.request_id = job.request_id.clone();
+                                                tokio::spawn(
+                                                    async move { mirror.publish(&shadow).await }
+                                                        .in_current_span(),
                                                 );
-                                                // Nack and requeue on publish failure
-                                                let _ = channel
-                                                    .basic_nack(
-                                                        delivery_tag,
-                                                        BasicNackOptions {
-                                                            requeue: true,
-                                                            ..Default::default()
-                                                        },
-                                                    )
-                                                    .await;
-                                                return;
+                                            }
+
+                                            if let Some(chaos) = &config.core.chaos {
+                                                chaos.delay_ack().await;
                                             }
 
                                             // Acknowledge the original message
-                                            if let Err(e) = channel
-                                                .basic_ack(
-                                                    delivery_tag,
-                                                    BasicAckOptions::default(),
-                                                )
+                                            if let Err(e) = delivery
+                                                .ack(BasicAckOptions::default())
                                                 .await
                                             {
                                                 error!(
@@ -242,12 +568,19 @@ async fn run(config: Config) -> Result<()> {
                                             } else {
                                                 info!(
                                                     message_id = %job.message_id,
+                                                    queue = %queue,
                                                     to = %job.to,
                                                     has_html = job.html.is_some(),
                                                     "webhook_processed"
                                                 );
                                             }
                                         }
+                                        Ok(None) => {
+                                            // Vetoed by a hook; drop it for good
+                                            let _ = delivery
+                                                .ack(BasicAckOptions::default())
+                                                .await;
+                                        }
                                         Err(e) => {
                                             error!(
                                                 message_id = %message_id,
@@ -256,10 +589,10 @@ async fn run(config: Config) -> Result<()> {
                                             );
 
                                             // Nack and don't requeue on processing error
-                                            // (the message is likely malformed)
-                                            let _ = channel
-                                                .basic_nack(
-                                                    delivery_tag,
+                                            // (the message is likely malformed, or
+                                            // panics a parser), so it is dead-lettered
+                                            let _ = delivery
+                                                .nack(
                                                     BasicNackOptions {
                                                         requeue: false,
                                                         ..Default::default()
@@ -280,9 +613,8 @@ async fn run(config: Config) -> Result<()> {
                                     );
 
                                     // Nack and don't requeue on parse error
-                                    let _ = channel
-                                        .basic_nack(
-                                            delivery_tag,
+                                    let _ = delivery
+                                        .nack(
                                             BasicNackOptions {
                                                 requeue: false,
                                                 ..Default::default()
@@ -291,7 +623,7 @@ async fn run(config: Config) -> Result<()> {
                                         .await;
                                 }
                             }
-                        });
+                        }.instrument(span));
                     }
                     Some(Err(e)) => {
                         error!(error = %e, "rabbitmq_delivery_error");
@@ -305,8 +637,11 @@ async fn run(config: Config) -> Result<()> {
         }
     }
 
-    // Close publisher
+    // Close publishers
     publisher.close().await;
+    if let (Some(mirror), Some(_)) = (&mirror, &config.mirror_amqp_url) {
+        mirror.close().await;
+    }
 
     info!("processor_shutdown_complete");
     Ok(())
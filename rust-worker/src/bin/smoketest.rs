@@ -0,0 +1,256 @@
+//! BobNet smoke test - End-to-end check of a running deployment.
+//!
+//! Posts a synthetic Mailgun webhook to the web server, then waits for the
+//! worker's report on `RESULTS_QUEUE` and checks it. Exits 0 once the report
+//! arrives with the expected status, and 1 when the webhook is rejected, no
+//! report arrives in time or the report doesn't match, so it can gate a
+//! deploy.
+//!
+//! The synthetic email sets its open and click rates to zero and its open
+//! delay to none, so the worker finishes it at once without fetching
+//! anything.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use lapin::{
+    options::{BasicAckOptions, BasicGetOptions, BasicNackOptions},
+    Channel,
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use bobnet::cli::{CommonArgs, Overrides};
+use bobnet::queue::BrokerUrls;
+use bobnet::util::{logging, redact};
+use bobnet::web::sign_mailgun;
+use bobnet::CoreConfig;
+
+/// Sender and `From` of the synthetic email.
+const SENDER: &str = "smoketest@bobnet.invalid";
+
+/// Subject of the synthetic email.
+const SUBJECT: &str = "BobNet smoke test";
+
+/// Most reports fetched per pass over the results queue.
+const SCAN_LIMIT: usize = 1000;
+
+/// Time between passes over the results queue.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Post a synthetic Mailgun webhook and wait for the worker's report on it.
+///
+/// Flags override the matching environment variables.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// Web server base URL, including any path prefix
+    #[arg(long, env = "BOBNET_WEB_URL")]
+    url: String,
+
+    /// Recipient of the synthetic email; must be on MAILGUN_DOMAIN when the
+    /// web server sets it
+    #[arg(long, default_value = "smoketest@example.com")]
+    recipient: String,
+
+    /// Key to sign the webhook with, when the web server verifies signatures
+    #[arg(long, env = "MAILGUN_SIGNING_KEY", hide_env_values = true)]
+    signing_key: Option<String>,
+
+    /// Queue the workers publish reports to
+    #[arg(long, env = "RESULTS_QUEUE")]
+    results_queue: String,
+
+    /// Seconds to wait for the report
+    #[arg(long, default_value_t = 120)]
+    timeout_secs: u64,
+
+    /// Status the report must end with
+    #[arg(long, default_value = "completed")]
+    expect_status: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // The result goes to stdout, so only log warnings unless asked
+    logging::init_with_default(cli.common.log_format, "warn");
+
+    // Errors returned from main bypass the log writer, so mask them here
+    run(cli).await.map_err(redact::redact_error)
+}
+
+/// Post the webhook, wait for its report and check it.
+async fn run(cli: Cli) -> Result<()> {
+    let mut overrides = Overrides::new();
+    cli.common.apply(&mut overrides);
+    let config = overrides.load(CoreConfig::from_vars_checked)?;
+
+    // Connect first, so a broker problem isn't reported as a timeout
+    let (conn, _) = BrokerUrls::from_config(&config)
+        .connect()
+        .await
+        .context("Failed to connect to RabbitMQ")?;
+    let channel = conn
+        .create_channel()
+        .await
+        .context("Failed to create channel")?;
+
+    let message_id = format!("smoketest-{:016x}@bobnet.invalid", rand::random::<u64>());
+    let started = Instant::now();
+    post_webhook(&cli, &message_id).await?;
+
+    let timeout = Duration::from_secs(cli.timeout_secs);
+    let report = wait_for_report(&channel, &cli.results_queue, &message_id, timeout).await?;
+    let _ = conn.close(200, "OK").await;
+
+    let Some(report) = report else {
+        bail!(
+            "No report for {} on {} after {}s",
+            message_id,
+            cli.results_queue,
+            cli.timeout_secs
+        );
+    };
+    check(&report, &cli.recipient, &cli.expect_status)?;
+
+    let output = json!({
+        "message_id": message_id,
+        "status": report["status"],
+        "elapsed_ms": started.elapsed().as_millis() as u64,
+        "latency": report["latency"],
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+/// Post a synthetic Mailgun webhook for `message_id` to the web server.
+async fn post_webhook(cli: &Cli, message_id: &str) -> Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        .to_string();
+    let token = format!("{:032x}", rand::random::<u128>());
+    let signature = match &cli.signing_key {
+        Some(key) => sign_mailgun(key, &timestamp, &token).context("Invalid signing key")?,
+        None => String::new(),
+    };
+    let headers = json!([
+        ["Message-Id", format!("<{}>", message_id)],
+        ["Subject", SUBJECT],
+    ])
+    .to_string();
+    let html = synthetic_html(message_id);
+
+    let url = format!("{}/webhooks/mailgun", cli.url.trim_end_matches('/'));
+    let response = Client::new()
+        .post(&url)
+        .form(&[
+            ("recipient", cli.recipient.as_str()),
+            ("sender", SENDER),
+            ("from", SENDER),
+            ("subject", SUBJECT),
+            ("body-html", &html),
+            ("message-headers", &headers),
+            ("timestamp", &timestamp),
+            ("token", &token),
+            ("signature", &signature),
+        ])
+        .send()
+        .await
+        .with_context(|| format!("Failed to post to {}", url))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        bail!("{} rejected the webhook with {}: {}", url, status, body);
+    }
+    Ok(())
+}
+
+/// HTML of the synthetic email: nothing to fetch, and nothing to wait for.
+fn synthetic_html(message_id: &str) -> String {
+    format!(
+        concat!(
+            r#"<html><body><div data-scope="global" data-open-rate="0" data-click-rate="0" "#,
+            r#"data-open-delay-ms="0,0"></div><p>{} {}</p></body></html>"#
+        ),
+        SUBJECT, message_id
+    )
+}
+
+/// Wait up to `timeout` for the report on `queue` whose message ID is
+/// `message_id`, and consume it.
+///
+/// Other reports are fetched unacknowledged and requeued after each pass, as
+/// `bobnet-cli peek` does, so they stay for whatever reads the queue; a
+/// consumer on the queue may take this report first, though.
+async fn wait_for_report(
+    channel: &Channel,
+    queue: &str,
+    message_id: &str,
+    timeout: Duration,
+) -> Result<Option<Value>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut last_tag = None;
+        let mut found = None;
+        for _ in 0..SCAN_LIMIT {
+            let message = channel
+                .basic_get(queue, BasicGetOptions { no_ack: false })
+                .await
+                .with_context(|| format!("Failed to read from {}", queue))?;
+            let Some(message) = message else {
+                break;
+            };
+            let delivery = message.delivery;
+            let id = delivery.properties.message_id().as_ref().map(|id| id.as_str());
+            if id == Some(message_id) {
+                channel
+                    .basic_ack(delivery.delivery_tag, BasicAckOptions::default())
+                    .await
+                    .context("Failed to acknowledge the report")?;
+                found = Some(delivery.data);
+                break;
+            }
+            last_tag = Some(delivery.delivery_tag);
+        }
+
+        if let Some(tag) = last_tag {
+            channel
+                .basic_nack(tag, BasicNackOptions { multiple: true, requeue: true })
+                .await
+                .context("Failed to requeue other reports")?;
+        }
+        if let Some(data) = found {
+            return serde_json::from_slice(&data)
+                .map(Some)
+                .context("The report is not JSON");
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        tokio::time::sleep(POLL_INTERVAL.min(remaining)).await;
+    }
+}
+
+/// Check `report` is for `recipient` and ended with `status`.
+fn check(report: &Value, recipient: &str, status: &str) -> Result<()> {
+    let to = report["to"].as_str().unwrap_or_default();
+    if !to.eq_ignore_ascii_case(recipient) {
+        bail!("The report is for {} instead of {}", to, recipient);
+    }
+    let actual = report["status"].as_str().unwrap_or_default();
+    if actual != status {
+        bail!("The report's status is {} instead of {}: {}", actual, status, report);
+    }
+    Ok(())
+}
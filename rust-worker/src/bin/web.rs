@@ -9,57 +9,180 @@
 //! All parsing and processing happens in the background processor.
 
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
-use axum::{
-    routing::{get, post},
-    Router,
-};
 use tokio::{net::TcpListener, signal};
 use tower_http::trace::TraceLayer;
+use clap::Parser;
 use tracing::info;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-use bobnet::web::{cloudflare_webhook, health, mailgun_webhook, AppState};
-use bobnet::{Config, Publisher};
+use bobnet::campaign;
+use bobnet::cli::{CommonArgs, Overrides, ServiceCommand};
+use bobnet::{doctor, healthcheck};
+use bobnet::util::systemd::{self, Watchdog};
+use bobnet::util::{logging, redact};
+use bobnet::web::tls::{serve_tls, server_config};
+use bobnet::web::{router, AppState};
+use bobnet::queue::{BrokerUrls, MessageSigner, Outbox};
+use bobnet::tenant;
+use bobnet::{Publisher, WebConfig};
+
+/// How often webhooks left in the outbox are retried.
+const OUTBOX_REPLAY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// BobNet webhook receiver.
+///
+/// Flags override the matching environment variables.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    /// HTTP port to listen on [overrides PORT]
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Print the effective configuration (secrets redacted) and exit
+    #[arg(long)]
+    print_config: bool,
+
+    #[command(subcommand)]
+    command: Option<ServiceCommand>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize structured JSON logging
-    let filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let cli = Cli::parse();
+
+    // Initialize structured logging (quietly for one-shot subcommands)
+    let one_shot = cli.command.is_some() || cli.print_config;
+    logging::init_with_default(cli.common.log_format, if one_shot { "warn" } else { "info" });
+
+    let result = match cli.command {
+        Some(ServiceCommand::Healthcheck) => check_health(&cli).await,
+        Some(ServiceCommand::Doctor) => diagnose(&cli).await,
+        None if cli.print_config => print_config(&cli),
+        None => run(cli).await,
+    };
+
+    // Errors returned from main bypass the log writer, so mask them here
+    result.map_err(redact::redact_error)
+}
+
+/// Flags given on the command line, layered over the environment.
+fn overrides(cli: &Cli) -> Overrides {
+    let mut overrides = Overrides::new();
+    cli.common.apply(&mut overrides);
+    overrides.set("PORT", cli.port);
+    overrides
+}
+
+/// Probe the running server's `/health` endpoint.
+async fn check_health(cli: &Cli) -> Result<()> {
+    let config = overrides(cli).load(WebConfig::from_vars_checked)?;
+    let url = healthcheck::local_url(config.port, config.tls_cert_path.is_some());
+    healthcheck::check_endpoint(&url).await?;
+    println!("healthy");
+    Ok(())
+}
+
+/// Run the doctor checks for the web server.
+async fn diagnose(cli: &Cli) -> Result<()> {
+    let config = overrides(cli).load(WebConfig::from_vars_checked)?;
+    let report = doctor::web(&config).await;
+    print!("{}", report);
+    report.into_result()
+}
 
-    tracing_subscriber::registry()
-        .with(filter)
-        .with(fmt::layer().json().flatten_event(true))
-        .init();
+/// Print the configuration the server would start with.
+fn print_config(cli: &Cli) -> Result<()> {
+    let config = overrides(cli).load(WebConfig::from_vars_checked)?;
+    println!("{}", doctor::effective_config(&config));
+    Ok(())
+}
 
+/// Load configuration and serve webhooks until shutdown.
+async fn run(cli: Cli) -> Result<()> {
     info!("web_server_starting");
 
     // Load configuration
-    let config = Config::from_env();
+    let config = overrides(&cli).load(WebConfig::from_vars_checked)?;
     info!(
         port = config.port,
-        cloudflare_auth_configured = config.cloudflare_auth_token.is_some(),
+        cloudflare_auth_tokens = config.cloudflare_auth_tokens.len(),
         mailgun_signing_configured = config.mailgun_signing_key.is_some(),
         mailgun_domain = ?config.mailgun_domain,
+        auth_ban_threshold = config.auth_ban_threshold,
+        trusted_proxy_hops = config.trusted_proxy_hops,
+        queue_signing = !config.core.queue_signing_keys.is_empty(),
         "config_loaded"
     );
 
     // Create RabbitMQ publisher
-    let publisher = Publisher::new(config.cloudamqp_url.clone());
-    info!("rabbitmq_publisher_created");
+    let publisher = Publisher::from_brokers(BrokerUrls::from_config(&config.core))
+        .with_chaos(config.core.chaos.clone())
+        .with_signer(MessageSigner::from_config(&config.core))
+        .with_topic_exchanges(config.core.topic_bindings())
+        .with_max_priority(config.core.simulator_max_priority())
+        .with_connect_timeout(Duration::from_millis(config.publish_connect_timeout_ms))
+        .with_confirms(!config.publish_fire_and_forget);
+    let publisher = match &config.publish_outbox_dir {
+        Some(dir) => {
+            let outbox = Outbox::open(dir)
+                .with_context(|| format!("Failed to open the outbox in {}", dir))?
+                .with_max_bytes(config.publish_outbox_max_bytes);
+            info!(
+                dir = %dir,
+                bytes = outbox.bytes(),
+                max_bytes = config.publish_outbox_max_bytes,
+                "outbox_opened"
+            );
+            publisher.with_outbox(outbox)
+        }
+        None => publisher,
+    };
+    // Journaled webhooks from before a restart go first
+    publisher.spawn_outbox_replay(OUTBOX_REPLAY_INTERVAL);
+    info!(
+        fire_and_forget = config.publish_fire_and_forget,
+        "rabbitmq_publisher_created"
+    );
 
     // Create application state
-    let state = AppState::new(config.clone(), publisher.clone());
+    let mut state = AppState::new(config.clone(), publisher.clone());
+
+    // Campaign override admin API, backed by the store workers read
+    if let Some(url) = &config.core.campaign_overrides_url {
+        let store = campaign::overrides::connect(url)
+            .await
+            .context("Failed to connect to the campaign override store")?;
+        info!("campaign_overrides_connected");
+        state = state.with_campaign_overrides(store);
+    }
+
+    // Tenant admin API, backed by the profile store workers read
+    if let Some(url) = &config.tenant_profiles_url {
+        let store = tenant::connect_store(url)
+            .await
+            .context("Failed to connect to the tenant profile store")?;
+        info!("tenant_profiles_connected");
+        state = state.with_tenant_profiles(store);
+    }
 
     // Build the router
-    let app = Router::new()
-        .route("/health", get(health))
-        .route("/webhooks/mailgun", post(mailgun_webhook))
-        .route("/webhooks/cloudflare", post(cloudflare_webhook))
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+    let app = router(state).layer(TraceLayer::new_for_http());
+
+    // Terminate TLS ourselves when a certificate is configured
+    let tls = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert), Some(key)) => Some(server_config(
+            cert,
+            key,
+            config.tls_client_ca_path.as_deref(),
+        )?),
+        _ => None,
+    };
 
     // Bind to address
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
@@ -67,13 +190,36 @@ async fn main() -> Result<()> {
         .await
         .context("Failed to bind to address")?;
 
-    info!(address = %addr, "web_server_listening");
+    info!(
+        address = %addr,
+        tls = tls.is_some(),
+        mtls = config.tls_client_ca_path.is_some(),
+        "web_server_listening"
+    );
+    systemd::ready();
+
+    // Requests are served on their own tasks, so the watchdog checks that
+    // the runtime still schedules them
+    if let Some(watchdog) = Watchdog::from_env() {
+        tokio::spawn(async move {
+            let mut ping = tokio::time::interval(watchdog.period());
+            loop {
+                ping.tick().await;
+                watchdog.ping();
+            }
+        });
+    }
 
     // Run server with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .context("Server error")?;
+    match tls {
+        Some(tls) => serve_tls(listener, app, tls, shutdown_signal())
+            .await
+            .context("Server error")?,
+        None => axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+            .context("Server error")?,
+    }
 
     // Close publisher connection
     publisher.close().await;
@@ -108,4 +254,5 @@ async fn shutdown_signal() {
     }
 
     info!("web_server_shutting_down");
+    systemd::stopping();
 }
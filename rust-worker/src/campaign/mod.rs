@@ -0,0 +1,351 @@
+//! Per-campaign simulation funnels.
+//!
+//! SFMC stamps the send's job ID on its tracking URLs (`j=12345` on Classic
+//! open pixels and view-online links, `jb=` or `job_id=` elsewhere). Jobs
+//! carrying the same ID are one campaign, and [`CampaignStats`] keeps its
+//! rolling funnel: sends seen, opens, clicks and unique clickers. The worker
+//! sidecar serves it at `GET /campaigns/{id}`, so a test send's simulated
+//! funnel can be read back directly.
+//!
+//! [`overrides`] steers a campaign's simulation from a central store.
+
+pub mod overrides;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::html::HtmlSummary;
+use crate::simulate::report::SimulationReport;
+use crate::stats::RETENTION;
+
+/// Query parameters holding the campaign ID, most specific first.
+pub const CAMPAIGN_PARAMS: &[&str] = &["job_id", "jobid", "jb", "j"];
+
+/// Most campaigns kept at once; the least recently seen are evicted beyond it.
+const MAX_CAMPAIGNS: usize = 1_000;
+
+/// Longest campaign ID accepted, so stray parameters are not taken for one.
+const MAX_ID_LEN: usize = 64;
+
+/// The campaign ID in a tracking URL's query, if any.
+pub fn campaign_id(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+    let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    CAMPAIGN_PARAMS.iter().find_map(|param| {
+        pairs
+            .iter()
+            .find(|(name, value)| name.eq_ignore_ascii_case(param) && is_campaign_id(value))
+            .map(|(_, value)| value.clone())
+    })
+}
+
+/// The campaign ID of an email: the first found on its SFMC open pixel,
+/// images or links.
+pub fn campaign_of(summary: &HtmlSummary) -> Option<String> {
+    summary
+        .sfmc_open_pixel
+        .iter()
+        .chain(&summary.images)
+        .chain(summary.links.iter().map(|link| &link.url))
+        .find_map(|url| campaign_id(url))
+}
+
+/// Whether `value` looks like a campaign ID: 1 to 64 letters, digits, `-`
+/// or `_`.
+pub fn is_campaign_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_ID_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Funnel of one campaign.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CampaignSummary {
+    /// Campaign (SFMC job) ID
+    pub campaign_id: String,
+    /// Jobs simulated for the campaign
+    pub sends: u64,
+    /// Jobs whose open was simulated
+    pub opens: u64,
+    /// Successful clicks
+    pub clicks: u64,
+    /// Recipients with at least one successful click
+    pub unique_clickers: u64,
+    /// Unix time of the campaign's first job
+    pub first_seen: u64,
+    /// Unix time of the campaign's latest job
+    pub last_seen: u64,
+}
+
+impl CampaignSummary {
+    /// Share of sends that were opened, or `None` without sends.
+    pub fn open_rate(&self) -> Option<f64> {
+        rate(self.opens, self.sends)
+    }
+
+    /// Share of sends whose recipient clicked, or `None` without sends.
+    pub fn click_rate(&self) -> Option<f64> {
+        rate(self.unique_clickers, self.sends)
+    }
+
+    /// Add another worker's funnel of the same campaign.
+    ///
+    /// Unique clickers are summed, so a recipient whose jobs went to two
+    /// workers counts twice.
+    pub fn merge(&mut self, other: &CampaignSummary) {
+        if self.campaign_id.is_empty() {
+            self.campaign_id = other.campaign_id.clone();
+        }
+        self.sends += other.sends;
+        self.opens += other.opens;
+        self.clicks += other.clicks;
+        self.unique_clickers += other.unique_clickers;
+        self.first_seen = match self.first_seen {
+            0 => other.first_seen,
+            first => first.min(other.first_seen),
+        };
+        self.last_seen = self.last_seen.max(other.last_seen);
+    }
+}
+
+fn rate(count: u64, total: u64) -> Option<f64> {
+    if total == 0 {
+        None
+    } else {
+        Some(count as f64 / total as f64)
+    }
+}
+
+struct Campaign {
+    summary: CampaignSummary,
+    /// Hashes of the recipients who clicked
+    clickers: HashSet<u64>,
+}
+
+/// Rolling funnels of the campaigns seen in the last [`RETENTION`].
+#[derive(Default)]
+pub struct CampaignStats {
+    campaigns: Mutex<HashMap<String, Campaign>>,
+}
+
+impl CampaignStats {
+    /// Create empty statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a simulated job under its campaign; jobs without one are
+    /// ignored.
+    pub fn record(&self, report: &SimulationReport) {
+        if let Some(id) = &report.campaign_id {
+            self.record_at(now(), id, report);
+        }
+    }
+
+    /// The funnel of campaign `id`, if it was seen recently.
+    pub fn get(&self, id: &str) -> Option<CampaignSummary> {
+        let campaigns = self.campaigns.lock().unwrap_or_else(|e| e.into_inner());
+        campaigns.get(id).map(|c| c.summary.clone())
+    }
+
+    /// Every recent campaign's funnel, most recently seen first.
+    pub fn list(&self) -> Vec<CampaignSummary> {
+        let campaigns = self.campaigns.lock().unwrap_or_else(|e| e.into_inner());
+        let mut list: Vec<CampaignSummary> =
+            campaigns.values().map(|c| c.summary.clone()).collect();
+        list.sort_by(|a, b| {
+            b.last_seen
+                .cmp(&a.last_seen)
+                .then(a.campaign_id.cmp(&b.campaign_id))
+        });
+        list
+    }
+
+    fn record_at(&self, now: u64, id: &str, report: &SimulationReport) {
+        let mut campaigns = self.campaigns.lock().unwrap_or_else(|e| e.into_inner());
+        let oldest = now.saturating_sub(RETENTION.as_secs());
+        campaigns.retain(|_, c| c.summary.last_seen > oldest);
+        if campaigns.len() >= MAX_CAMPAIGNS && !campaigns.contains_key(id) {
+            let stalest = campaigns
+                .iter()
+                .min_by_key(|(_, c)| c.summary.last_seen)
+                .map(|(id, _)| id.clone());
+            if let Some(stalest) = stalest {
+                campaigns.remove(&stalest);
+            }
+        }
+
+        let campaign = campaigns.entry(id.to_string()).or_insert_with(|| Campaign {
+            summary: CampaignSummary {
+                campaign_id: id.to_string(),
+                first_seen: now,
+                ..CampaignSummary::default()
+            },
+            clickers: HashSet::new(),
+        });
+        let clicks = report.successful_clicks() as u64;
+        let summary = &mut campaign.summary;
+        summary.sends += 1;
+        summary.opens += u64::from(report.opened);
+        summary.clicks += clicks;
+        summary.last_seen = now;
+        if clicks > 0 && campaign.clickers.insert(recipient_hash(&report.to)) {
+            summary.unique_clickers += 1;
+        }
+    }
+}
+
+/// Recipients are kept as hashes; the funnel only needs to tell them apart.
+fn recipient_hash(to: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    to.to_lowercase().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::cohort::Cohort;
+    use crate::simulate::report::{FetchReport, JobStatus, Persona};
+    use crate::util::user_agent::UserAgentCategory;
+
+    fn report(to: &str, opened: bool, clicks: usize) -> SimulationReport {
+        let click = FetchReport {
+            url: "https://shop.example.com/".to_string(),
+            status: Some(200),
+            ..FetchReport::default()
+        };
+        SimulationReport {
+            message_id: "msg-1".to_string(),
+            to: to.to_string(),
+            customer_tag: None,
+            campaign_id: Some("12345".to_string()),
+            status: JobStatus::Completed,
+            persona: Persona {
+                cohort: Cohort::Stable,
+                ab_cohort: None,
+                preset: None,
+                client: None,
+                user_agent: "Mozilla/5.0".to_string(),
+                user_agent_category: UserAgentCategory::Desktop,
+                utc_offset_mins: None,
+            },
+            analysis_ms: None,
+            open_delay_ms: Some(0),
+            message_age_secs: None,
+            open_decay: None,
+            calendar_factor: None,
+            open_probability: None,
+            click_probability: None,
+            scroll_depth: None,
+            opened,
+            pixels: Vec::new(),
+            clicks: vec![click; clicks],
+            scans: Vec::new(),
+            interactions: Vec::new(),
+            skipped: Vec::new(),
+            latency: None,
+            seed: 1,
+            started_at: 0,
+            config: Default::default(),
+            dry_run: false,
+            instance_id: "worker-1",
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn test_campaign_id() {
+        assert_eq!(
+            campaign_id("https://cl.s4.exct.net/open.aspx?ffcb10-fe8b1577&d=100&j=12345")
+                .as_deref(),
+            Some("12345")
+        );
+        assert_eq!(
+            campaign_id("https://click.example.com/?j=1&job_id=abc-9").as_deref(),
+            Some("abc-9")
+        );
+        assert_eq!(campaign_id("https://example.com/?j=<script>"), None);
+        assert_eq!(campaign_id("https://example.com/?q=12345"), None);
+        assert_eq!(campaign_id("not a url"), None);
+
+        let summary = HtmlSummary::parse(
+            r#"<html><body>
+                <img src="https://cdn.example.com/logo.png">
+                <a href="https://view.example.com/?qs=x&JB=777">View online</a>
+            </body></html>"#,
+        );
+        assert_eq!(campaign_of(&summary).as_deref(), Some("777"));
+    }
+
+    #[test]
+    fn test_funnel() {
+        let stats = CampaignStats::new();
+        stats.record_at(1_000, "12345", &report("a@example.com", true, 2));
+        stats.record_at(1_010, "12345", &report("A@example.com", true, 1));
+        stats.record_at(1_020, "12345", &report("b@example.com", false, 0));
+        stats.record_at(1_030, "other", &report("a@example.com", true, 0));
+
+        let funnel = stats.get("12345").unwrap();
+        assert_eq!(funnel.sends, 3);
+        assert_eq!(funnel.opens, 2);
+        assert_eq!(funnel.clicks, 3);
+        assert_eq!(funnel.unique_clickers, 1);
+        assert_eq!((funnel.first_seen, funnel.last_seen), (1_000, 1_020));
+        assert_eq!(funnel.click_rate(), Some(1.0 / 3.0));
+
+        let ids: Vec<String> = stats.list().into_iter().map(|c| c.campaign_id).collect();
+        assert_eq!(ids, vec!["other", "12345"]);
+
+        // Campaigns idle for the retention period are dropped
+        stats.record_at(
+            1_020 + RETENTION.as_secs(),
+            "new",
+            &report("c@example.com", true, 0),
+        );
+        assert!(stats.get("12345").is_none());
+        assert!(stats.get("other").is_some());
+        assert!(stats.get("unknown").is_none());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut total = CampaignSummary::default();
+        let worker = CampaignSummary {
+            campaign_id: "12345".to_string(),
+            sends: 4,
+            opens: 3,
+            unique_clickers: 1,
+            first_seen: 100,
+            last_seen: 200,
+            ..CampaignSummary::default()
+        };
+        total.merge(&worker);
+        total.merge(&CampaignSummary {
+            first_seen: 50,
+            last_seen: 150,
+            ..worker.clone()
+        });
+        assert_eq!(total.sends, 8);
+        assert_eq!(total.open_rate(), Some(0.75));
+        assert_eq!((total.first_seen, total.last_seen), (50, 200));
+    }
+}
@@ -0,0 +1,319 @@
+//! Per-campaign simulation overrides.
+//!
+//! Test engineers register a [`CampaignOverride`] for a campaign (SFMC job)
+//! ID through the web server's admin API, e.g. "for job 12345, open every
+//! email and click only the CTA". Overrides live in a central
+//! [`OverrideStore`], Redis (feature `redis`) or Postgres (feature
+//! `postgres`), and workers consult it through [`CampaignOverrides`], which
+//! caches each campaign's override, or its absence, for a short TTL.
+//!
+//! A campaign override wins over the worker's settings, the tenant's
+//! profile, the job's overrides and the HTML's global rates, so a send can
+//! be steered without editing its template.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::WorkerConfig;
+
+/// URL schemes [`connect`] accepts.
+pub const STORE_SCHEMES: &[&str] = &["redis", "rediss", "postgres", "postgresql"];
+
+/// Most campaigns cached at once; expired entries are evicted beyond it.
+const MAX_CACHED_CAMPAIGNS: usize = 10_000;
+
+/// Error from an override store.
+#[derive(Debug, thiserror::Error)]
+pub enum OverrideError {
+    /// The URL names a store this build does not support
+    #[error(
+        "unsupported override store `{0}` (expected redis or postgres, with its feature enabled)"
+    )]
+    UnsupportedScheme(String),
+    /// The store could not be reached or queried
+    #[error("override store error: {0}")]
+    Store(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The stored override is not valid override JSON
+    #[error("invalid override for campaign `{campaign_id}`: {source}")]
+    Invalid {
+        campaign_id: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl OverrideError {
+    /// Wrap a store client error.
+    pub fn store(e: impl std::error::Error + Send + Sync + 'static) -> Self {
+        OverrideError::Store(Box::new(e))
+    }
+}
+
+/// How one campaign is simulated; unset fields keep the job's settings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CampaignOverride {
+    /// Probability of simulating an email open (0.0 - 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_probability: Option<f64>,
+    /// Probability of simulating a click (0.0 - 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub click_probability: Option<f64>,
+    /// Maximum number of links to click per email
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<usize>,
+    /// Only links whose URL contains one of these are clicked, e.g. the
+    /// CTA's path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub click_only: Option<Vec<String>>,
+}
+
+impl CampaignOverride {
+    /// Parse the override stored for `campaign_id`.
+    pub fn parse(campaign_id: &str, raw: &str) -> Result<Self, OverrideError> {
+        serde_json::from_str(raw).map_err(|source| OverrideError::Invalid {
+            campaign_id: campaign_id.to_string(),
+            source,
+        })
+    }
+
+    /// Problems that would make the override misbehave.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (name, value) in [
+            ("open_probability", self.open_probability),
+            ("click_probability", self.click_probability),
+        ] {
+            if value.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+                errors.push(format!("{}: must be between 0.0 and 1.0", name));
+            }
+        }
+        if let Some(patterns) = &self.click_only {
+            if patterns.is_empty() || patterns.iter().any(|p| p.trim().is_empty()) {
+                errors.push("click_only: must list non-empty URL fragments".to_string());
+            }
+        }
+        errors
+    }
+
+    /// `config` with this override applied.
+    pub fn apply(&self, config: &WorkerConfig) -> WorkerConfig {
+        let mut config = config.clone();
+        if let Some(p) = self.open_probability {
+            config.simulate_open_probability = p.clamp(0.0, 1.0);
+        }
+        if let Some(p) = self.click_probability {
+            config.simulate_click_probability = p.clamp(0.0, 1.0);
+        }
+        if let Some(max) = self.max_clicks {
+            config.max_clicks = max;
+        }
+        config
+    }
+
+    /// Whether the link at `url` may be clicked.
+    pub fn allows_link(&self, url: &str) -> bool {
+        match &self.click_only {
+            Some(patterns) => patterns.iter().any(|p| url.contains(p.as_str())),
+            None => true,
+        }
+    }
+}
+
+/// Central store of campaign overrides, keyed by campaign ID.
+pub trait OverrideStore: Send + Sync {
+    /// Look up the override of `campaign_id`, or `None` if it has none.
+    fn load<'a>(
+        &'a self,
+        campaign_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<CampaignOverride>, OverrideError>>;
+
+    /// Register `value` as the override of `campaign_id`, replacing any.
+    fn save<'a>(
+        &'a self,
+        campaign_id: &'a str,
+        value: &'a CampaignOverride,
+    ) -> BoxFuture<'a, Result<(), OverrideError>>;
+
+    /// Remove the override of `campaign_id`; `false` if it had none.
+    fn delete<'a>(&'a self, campaign_id: &'a str) -> BoxFuture<'a, Result<bool, OverrideError>>;
+}
+
+/// Overrides kept in process memory, for tests and single-process embedders.
+#[derive(Default)]
+pub struct MemoryOverrides {
+    overrides: Mutex<HashMap<String, CampaignOverride>>,
+}
+
+impl OverrideStore for MemoryOverrides {
+    fn load<'a>(
+        &'a self,
+        campaign_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<CampaignOverride>, OverrideError>> {
+        let value = self.overrides.lock().unwrap().get(campaign_id).cloned();
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        campaign_id: &'a str,
+        value: &'a CampaignOverride,
+    ) -> BoxFuture<'a, Result<(), OverrideError>> {
+        self.overrides
+            .lock()
+            .unwrap()
+            .insert(campaign_id.to_string(), value.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, campaign_id: &'a str) -> BoxFuture<'a, Result<bool, OverrideError>> {
+        let removed = self.overrides.lock().unwrap().remove(campaign_id).is_some();
+        Box::pin(async move { Ok(removed) })
+    }
+}
+
+struct Cached {
+    value: Option<Arc<CampaignOverride>>,
+    fetched: Instant,
+}
+
+/// In-process cache in front of an [`OverrideStore`], used by the worker.
+pub struct CampaignOverrides {
+    store: Arc<dyn OverrideStore>,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, Cached>>,
+}
+
+impl CampaignOverrides {
+    /// Cache overrides from `store` for `ttl`.
+    pub fn new(store: Arc<dyn OverrideStore>, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The override of `campaign_id`, if it has one.
+    ///
+    /// When the store fails, the last known override is used until it
+    /// answers again.
+    pub async fn get(&self, campaign_id: &str) -> Option<Arc<CampaignOverride>> {
+        let stale = {
+            let cache = self.cache.lock().unwrap();
+            match cache.get(campaign_id) {
+                Some(cached) if cached.fetched.elapsed() < self.ttl => return cached.value.clone(),
+                Some(cached) => cached.value.clone(),
+                None => None,
+            }
+        };
+
+        let value = match self.store.load(campaign_id).await {
+            Ok(value) => value.map(Arc::new),
+            Err(e) => {
+                warn!(campaign_id = %campaign_id, error = %e, "campaign_override_load_failed");
+                return stale;
+            }
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_CAMPAIGNS {
+            cache.retain(|_, cached| cached.fetched.elapsed() < self.ttl);
+        }
+        cache.insert(
+            campaign_id.to_string(),
+            Cached {
+                value: value.clone(),
+                fetched: Instant::now(),
+            },
+        );
+        value
+    }
+}
+
+/// Connect to the override store at `url`.
+///
+/// The scheme picks the store: `redis://` or `rediss://` needs the `redis`
+/// feature, `postgres://` or `postgresql://` the `postgres` feature.
+pub async fn connect(url: &str) -> Result<Arc<dyn OverrideStore>, OverrideError> {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    match scheme {
+        #[cfg(feature = "redis")]
+        "redis" | "rediss" => Ok(Arc::new(super::redis::RedisOverrides::connect(url).await?)),
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => Ok(Arc::new(
+            super::postgres::PostgresOverrides::connect(url).await?,
+        )),
+        other => Err(OverrideError::UnsupportedScheme(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cta_only() -> CampaignOverride {
+        CampaignOverride::parse(
+            "12345",
+            r#"{"open_probability": 1.0, "max_clicks": 1, "click_only": ["/cta"]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_override_apply() {
+        let campaign = cta_only();
+        let config = campaign.apply(&WorkerConfig::default());
+        assert_eq!(config.simulate_open_probability, 1.0);
+        assert_eq!(config.max_clicks, 1);
+        assert_eq!(config.simulate_click_probability, 0.3);
+
+        assert!(campaign.allows_link("https://shop.example.com/cta?utm=1"));
+        assert!(!campaign.allows_link("https://shop.example.com/about"));
+        assert!(CampaignOverride::default().allows_link("https://shop.example.com/about"));
+
+        assert!(campaign.validate().is_empty());
+        let invalid = CampaignOverride {
+            click_probability: Some(1.5),
+            click_only: Some(vec![" ".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(invalid.validate().len(), 2);
+        assert!(matches!(
+            CampaignOverride::parse("12345", r#"{"open_rate": 1.0}"#),
+            Err(OverrideError::Invalid { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_overrides_are_cached() {
+        let store = Arc::new(MemoryOverrides::default());
+        store.save("12345", &cta_only()).await.unwrap();
+        let overrides = CampaignOverrides::new(store.clone(), Duration::from_secs(60));
+
+        assert_eq!(overrides.get("12345").await.as_deref(), Some(&cta_only()));
+        assert!(overrides.get("999").await.is_none());
+
+        // Cached until the TTL passes
+        assert!(store.delete("12345").await.unwrap());
+        assert!(!store.delete("12345").await.unwrap());
+        assert!(overrides.get("12345").await.is_some());
+
+        let overrides = CampaignOverrides {
+            ttl: Duration::ZERO,
+            ..overrides
+        };
+        assert!(overrides.get("12345").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_connect_unsupported_scheme() {
+        let result = connect("mysql://localhost/overrides").await;
+        assert!(matches!(result, Err(OverrideError::UnsupportedScheme(s)) if s == "mysql"));
+    }
+}
@@ -0,0 +1,87 @@
+//! Campaign overrides stored in Postgres.
+//!
+//! Overrides are kept in a `campaign_overrides` table keyed by campaign ID:
+//!
+//! ```sql
+//! CREATE TABLE campaign_overrides (
+//!     campaign_id text PRIMARY KEY,
+//!     overrides jsonb NOT NULL,
+//!     updated_at timestamptz NOT NULL DEFAULT now()
+//! );
+//! ```
+
+use futures::future::BoxFuture;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+use super::overrides::{CampaignOverride, OverrideError, OverrideStore};
+
+const SELECT: &str = "SELECT overrides::text FROM campaign_overrides WHERE campaign_id = $1";
+
+const UPSERT: &str =
+    "INSERT INTO campaign_overrides (campaign_id, overrides) VALUES ($1, $2::jsonb) \
+     ON CONFLICT (campaign_id) DO UPDATE SET overrides = EXCLUDED.overrides, updated_at = now()";
+
+const DELETE: &str = "DELETE FROM campaign_overrides WHERE campaign_id = $1";
+
+/// Overrides kept in Postgres over a small connection pool.
+#[derive(Clone)]
+pub struct PostgresOverrides {
+    pool: PgPool,
+}
+
+impl PostgresOverrides {
+    /// Connect to the database at `url` (`postgres://` or `postgresql://`).
+    pub async fn connect(url: &str) -> Result<Self, OverrideError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .connect(url)
+            .await
+            .map_err(OverrideError::store)?;
+        Ok(Self { pool })
+    }
+}
+
+impl OverrideStore for PostgresOverrides {
+    fn load<'a>(
+        &'a self,
+        campaign_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<CampaignOverride>, OverrideError>> {
+        Box::pin(async move {
+            let raw: Option<String> = sqlx::query_scalar(SELECT)
+                .bind(campaign_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(OverrideError::store)?;
+            raw.map(|raw| CampaignOverride::parse(campaign_id, &raw))
+                .transpose()
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        campaign_id: &'a str,
+        value: &'a CampaignOverride,
+    ) -> BoxFuture<'a, Result<(), OverrideError>> {
+        Box::pin(async move {
+            let raw = serde_json::to_string(value).map_err(OverrideError::store)?;
+            sqlx::query(UPSERT)
+                .bind(campaign_id)
+                .bind(raw)
+                .execute(&self.pool)
+                .await
+                .map_err(OverrideError::store)?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, campaign_id: &'a str) -> BoxFuture<'a, Result<bool, OverrideError>> {
+        Box::pin(async move {
+            let result = sqlx::query(DELETE)
+                .bind(campaign_id)
+                .execute(&self.pool)
+                .await
+                .map_err(OverrideError::store)?;
+            Ok(result.rows_affected() > 0)
+        })
+    }
+}
@@ -0,0 +1,76 @@
+//! Campaign overrides stored in Redis.
+//!
+//! Each override is a JSON string under `bobnet:campaign:<id>`, e.g.
+//! `SET bobnet:campaign:12345 '{"open_probability": 1.0}'`.
+
+use ::redis::aio::ConnectionManager;
+use ::redis::AsyncCommands;
+use futures::future::BoxFuture;
+
+use super::overrides::{CampaignOverride, OverrideError, OverrideStore};
+
+/// Prefix of the key each campaign's override is stored under.
+pub const KEY_PREFIX: &str = "bobnet:campaign:";
+
+/// Overrides kept in Redis over a reconnecting connection.
+#[derive(Clone)]
+pub struct RedisOverrides {
+    conn: ConnectionManager,
+}
+
+impl RedisOverrides {
+    /// Connect to the Redis server at `url` (`redis://` or `rediss://`).
+    pub async fn connect(url: &str) -> Result<Self, OverrideError> {
+        let client = ::redis::Client::open(url).map_err(OverrideError::store)?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .map_err(OverrideError::store)?;
+        Ok(Self { conn })
+    }
+}
+
+fn key(campaign_id: &str) -> String {
+    format!("{}{}", KEY_PREFIX, campaign_id)
+}
+
+impl OverrideStore for RedisOverrides {
+    fn load<'a>(
+        &'a self,
+        campaign_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<CampaignOverride>, OverrideError>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let raw: Option<String> = conn
+                .get(key(campaign_id))
+                .await
+                .map_err(OverrideError::store)?;
+            raw.map(|raw| CampaignOverride::parse(campaign_id, &raw))
+                .transpose()
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        campaign_id: &'a str,
+        value: &'a CampaignOverride,
+    ) -> BoxFuture<'a, Result<(), OverrideError>> {
+        Box::pin(async move {
+            let raw = serde_json::to_string(value).map_err(OverrideError::store)?;
+            let mut conn = self.conn.clone();
+            conn.set::<_, _, ()>(key(campaign_id), raw)
+                .await
+                .map_err(OverrideError::store)
+        })
+    }
+
+    fn delete<'a>(&'a self, campaign_id: &'a str) -> BoxFuture<'a, Result<bool, OverrideError>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let removed: u64 = conn
+                .del(key(campaign_id))
+                .await
+                .map_err(OverrideError::store)?;
+            Ok(removed > 0)
+        })
+    }
+}
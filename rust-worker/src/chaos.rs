@@ -0,0 +1,172 @@
+//! Chaos / fault-injection mode.
+//!
+//! When `CHAOS_MODE` is enabled, the pipeline randomly drops publishes,
+//! delays acks, fails fetches and slows them down at configurable rates, so
+//! retry and dead-letter behavior can be exercised before it is relied on
+//! in production. Never enable this in a real deployment.
+
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::config::{env_var, parse_bool, VarSource};
+
+/// Fault rates (0.0 - 1.0) and durations for chaos mode.
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+    /// Probability that a publish fails as if the broker dropped it
+    pub publish_drop_rate: f64,
+    /// Probability that an ack is delayed
+    pub ack_delay_rate: f64,
+    /// How long a delayed ack waits, in milliseconds
+    pub ack_delay_ms: u64,
+    /// Probability that an outbound fetch fails without being sent
+    pub fetch_error_rate: f64,
+    /// Probability that an outbound fetch is slowed down
+    pub fetch_slow_rate: f64,
+    /// Extra latency added to a slowed fetch, in milliseconds
+    pub fetch_slow_ms: u64,
+}
+
+/// Fault to apply to a single fetch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchFault {
+    /// Fail the fetch without sending it
+    Error,
+    /// Delay the fetch by the given duration
+    Slow(Duration),
+}
+
+impl ChaosConfig {
+    /// Load chaos settings from the environment.
+    ///
+    /// Returns `None` unless `CHAOS_MODE` is enabled.
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(&env_var)
+    }
+
+    /// Load chaos settings from an arbitrary variable lookup.
+    pub fn from_vars(var: &VarSource<'_>) -> Option<Self> {
+        if !parse_bool(var, "CHAOS_MODE").unwrap_or(false) {
+            return None;
+        }
+
+        let rate = |name: &str| -> f64 {
+            var(name)
+                .and_then(|v| v.parse::<f64>().ok())
+                .unwrap_or(0.0)
+                .clamp(0.0, 1.0)
+        };
+        let millis = |name: &str, default: u64| -> u64 {
+            var(name)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+
+        let chaos = ChaosConfig {
+            publish_drop_rate: rate("CHAOS_PUBLISH_DROP_RATE"),
+            ack_delay_rate: rate("CHAOS_ACK_DELAY_RATE"),
+            ack_delay_ms: millis("CHAOS_ACK_DELAY_MS", 5000),
+            fetch_error_rate: rate("CHAOS_FETCH_ERROR_RATE"),
+            fetch_slow_rate: rate("CHAOS_FETCH_SLOW_RATE"),
+            fetch_slow_ms: millis("CHAOS_FETCH_SLOW_MS", 3000),
+        };
+
+        warn!(
+            publish_drop_rate = chaos.publish_drop_rate,
+            ack_delay_rate = chaos.ack_delay_rate,
+            ack_delay_ms = chaos.ack_delay_ms,
+            fetch_error_rate = chaos.fetch_error_rate,
+            fetch_slow_rate = chaos.fetch_slow_rate,
+            fetch_slow_ms = chaos.fetch_slow_ms,
+            "chaos_mode_enabled"
+        );
+
+        Some(chaos)
+    }
+
+    /// Roll whether the next publish should be dropped.
+    pub fn should_drop_publish(&self) -> bool {
+        roll(self.publish_drop_rate)
+    }
+
+    /// Roll whether the next ack should be delayed, and by how long.
+    pub fn ack_delay(&self) -> Option<Duration> {
+        roll(self.ack_delay_rate).then_some(Duration::from_millis(self.ack_delay_ms))
+    }
+
+    /// Sleep before an ack if an ack delay is rolled.
+    pub async fn delay_ack(&self) {
+        if let Some(delay) = self.ack_delay() {
+            warn!(delay_ms = delay.as_millis() as u64, "chaos_ack_delayed");
+            sleep(delay).await;
+        }
+    }
+
+    /// Roll the fault, if any, for the next outbound fetch.
+    pub fn fetch_fault(&self) -> Option<FetchFault> {
+        if roll(self.fetch_error_rate) {
+            Some(FetchFault::Error)
+        } else if roll(self.fetch_slow_rate) {
+            Some(FetchFault::Slow(Duration::from_millis(self.fetch_slow_ms)))
+        } else {
+            None
+        }
+    }
+}
+
+/// Return `true` with the given probability.
+fn roll(rate: f64) -> bool {
+    rate > 0.0 && rand::thread_rng().gen::<f64>() < rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_rates_never_fault() {
+        let chaos = ChaosConfig::default();
+        for _ in 0..100 {
+            assert!(!chaos.should_drop_publish());
+            assert!(chaos.ack_delay().is_none());
+            assert!(chaos.fetch_fault().is_none());
+        }
+    }
+
+    #[test]
+    fn test_full_rates_always_fault() {
+        let chaos = ChaosConfig {
+            publish_drop_rate: 1.0,
+            ack_delay_rate: 1.0,
+            ack_delay_ms: 10,
+            fetch_error_rate: 1.0,
+            fetch_slow_rate: 0.0,
+            fetch_slow_ms: 0,
+        };
+        assert!(chaos.should_drop_publish());
+        assert_eq!(chaos.ack_delay(), Some(Duration::from_millis(10)));
+        assert_eq!(chaos.fetch_fault(), Some(FetchFault::Error));
+    }
+
+    #[test]
+    fn test_slow_fault() {
+        let chaos = ChaosConfig {
+            fetch_slow_rate: 1.0,
+            fetch_slow_ms: 250,
+            ..Default::default()
+        };
+        assert_eq!(
+            chaos.fetch_fault(),
+            Some(FetchFault::Slow(Duration::from_millis(250)))
+        );
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        std::env::remove_var("CHAOS_MODE");
+        assert!(ChaosConfig::from_env().is_none());
+    }
+}
@@ -0,0 +1,131 @@
+//! Command-line flags shared by the binaries.
+//!
+//! Flags override the matching environment variables; anything not given on
+//! the command line still comes from the environment, so existing deployments
+//! are unaffected.
+
+use std::collections::HashMap;
+
+use clap::{Args, Subcommand};
+
+use crate::config::{env_var, ConfigError, VarSource};
+use crate::util::logging::LogFormat;
+
+/// Flags accepted by every binary.
+#[derive(Debug, Clone, Default, Args)]
+pub struct CommonArgs {
+    /// RabbitMQ connection URL [overrides CLOUDAMQP_URL]
+    #[arg(long, value_name = "URL")]
+    pub amqp_url: Option<String>,
+
+    /// Maximum number of concurrent jobs [overrides WORKER_CONCURRENCY]
+    #[arg(long, value_name = "N")]
+    pub concurrency: Option<usize>,
+
+    /// Log output format
+    #[arg(long, value_enum, env = "LOG_FORMAT", default_value_t = LogFormat::Json)]
+    pub log_format: LogFormat,
+}
+
+/// Subcommands accepted by the long-running binaries; without one they serve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Subcommand)]
+pub enum ServiceCommand {
+    /// Exit 0 if the service is healthy and 1 otherwise, for Docker HEALTHCHECK
+    Healthcheck,
+    /// Check configuration, broker, queues and DNS, and report what to fix
+    Doctor,
+}
+
+/// Simulation flags accepted by the worker.
+#[derive(Debug, Clone, Default, Args)]
+pub struct SimulationArgs {
+    /// Probability of simulating an open [overrides SIMULATE_OPEN_PROBABILITY]
+    #[arg(long, value_name = "P")]
+    pub open_probability: Option<f64>,
+
+    /// Probability of simulating clicks [overrides SIMULATE_CLICK_PROBABILITY]
+    #[arg(long, value_name = "P")]
+    pub click_probability: Option<f64>,
+
+    /// Maximum links clicked per email [overrides MAX_CLICKS]
+    #[arg(long, value_name = "N")]
+    pub max_clicks: Option<usize>,
+}
+
+/// Variables set from the command line, layered over the environment.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides(HashMap<&'static str, String>);
+
+impl Overrides {
+    /// Create an empty set of overrides.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override `name` when a flag value was given.
+    pub fn set(&mut self, name: &'static str, value: Option<impl ToString>) -> &mut Self {
+        if let Some(value) = value {
+            self.0.insert(name, value.to_string());
+        }
+        self
+    }
+
+    /// Value given on the command line for `name`, if any.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.0.get(name).cloned()
+    }
+
+    /// Look up `name`, preferring the command line over the environment.
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.get(name).or_else(|| env_var(name))
+    }
+
+    /// Load and validate a configuration with these overrides applied, e.g.
+    /// `overrides.load(WebConfig::from_vars_checked)`.
+    pub fn load<T>(
+        &self,
+        load: impl FnOnce(&VarSource<'_>) -> Result<T, ConfigError>,
+    ) -> Result<T, ConfigError> {
+        load(&|name| self.var(name))
+    }
+}
+
+impl CommonArgs {
+    /// Record the flags that were given as overrides.
+    pub fn apply(&self, overrides: &mut Overrides) {
+        overrides
+            .set("CLOUDAMQP_URL", self.amqp_url.as_ref())
+            .set("WORKER_CONCURRENCY", self.concurrency);
+    }
+}
+
+impl SimulationArgs {
+    /// Record the flags that were given as overrides.
+    pub fn apply(&self, overrides: &mut Overrides) {
+        overrides
+            .set("SIMULATE_OPEN_PROBABILITY", self.open_probability)
+            .set("SIMULATE_CLICK_PROBABILITY", self.click_probability)
+            .set("MAX_CLICKS", self.max_clicks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_take_precedence() {
+        let args = SimulationArgs {
+            open_probability: Some(0.1),
+            ..Default::default()
+        };
+        let mut overrides = Overrides::new();
+        args.apply(&mut overrides);
+
+        assert_eq!(overrides.var("SIMULATE_OPEN_PROBABILITY").as_deref(), Some("0.1"));
+        assert_eq!(overrides.var("MAX_CLICKS"), env_var("MAX_CLICKS"));
+
+        let config = overrides.load(crate::WorkerConfig::from_vars_checked).unwrap();
+        assert_eq!(config.simulate_open_probability, 0.1);
+    }
+}
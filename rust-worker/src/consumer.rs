@@ -1,101 +1,361 @@
 //! RabbitMQ consumer module using lapin.
 //!
 //! This module handles connecting to RabbitMQ, consuming messages from the
-//! email_simulator queue, and spawning async tasks to process each message
-//! concurrently.
+//! configured simulator queues (`email_simulator` by default), and spawning
+//! async tasks to process each message concurrently.
 
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
-use futures::StreamExt;
+use arc_swap::ArcSwap;
+use futures::stream::{self, Stream, StreamExt};
 use lapin::{
-    options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions, QueueDeclareOptions},
-    types::FieldTable,
-    Connection, ConnectionProperties,
+    acker::Acker,
+    message::Delivery,
+    options::{BasicAckOptions, BasicNackOptions, BasicPublishOptions, QueueDeclareOptions},
+    types::{AMQPValue, FieldTable, LongString, ShortString},
+    BasicProperties, Channel, Consumer,
 };
+use reqwest::header::CONTENT_TYPE;
 use reqwest::Client;
 use tokio::signal;
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn, Instrument};
 
-use bobnet::{Config, SIMULATOR_QUEUE};
-use crate::processor::{process_job, Job};
+use bobnet::campaign::overrides::{self, CampaignOverrides};
+use bobnet::content::{self, ContentCache, ContentError};
+use bobnet::metrics::GaugeGuard;
+use bobnet::queue::dead_letter::{self, requeue_properties};
+use bobnet::queue::consumers::ConsumerChannels;
+use bobnet::queue::prefetch::PrefetchTuner;
+use bobnet::queue::priority::simulator_queue_args;
+use bobnet::queue::{request_id, BrokerUrls, MessageSigner, SignatureError};
+use bobnet::simulate::adaptive::AdaptiveConcurrency;
+use bobnet::simulate::latency::{unix_millis, Latency};
+use bobnet::simulate::report::{JobStatus, SimulationReport, Skip};
+use bobnet::simulate::simulator::Job;
+use bobnet::stats::Outcome;
+use bobnet::tenant;
+use bobnet::tenant::drift::DriftPolicy;
+use bobnet::tenant::quota::QuotaAction;
+use bobnet::util::panic;
+use bobnet::util::instance::instance_id;
+use bobnet::util::systemd::{self, Watchdog};
+use bobnet::util::url::sanitize;
+use bobnet::{Pipeline, Publisher, Simulator, WorkerConfig};
+use crate::ordering::sharded_channels;
+use crate::reload::{self, Sources};
+use crate::sidecar::{self, WorkerMetrics};
+
+/// Resources shared by every job-processing task.
+struct WorkerContext {
+    /// Shares the HTTP client, limiter and `HTTP_CASSETTE` across jobs
+    simulator: Simulator,
+    /// Hooks around each simulation (none in the stock worker)
+    pipeline: Pipeline,
+    /// Live config; simulation parameters may be swapped by a reload
+    config: Arc<ArcSwap<WorkerConfig>>,
+    channel: Channel,
+    limiter: Option<Arc<AdaptiveConcurrency>>,
+    /// Picks the prefetch from job durations with `PREFETCH_AUTOTUNE`
+    prefetch: Option<PrefetchTuner>,
+    /// Fetches the HTML of jobs carrying only its hash
+    content: Option<ContentCache>,
+    metrics: Arc<WorkerMetrics>,
+    shutdown: CancellationToken,
+    /// Verifies queue message signatures when signing is enabled
+    signer: Option<MessageSigner>,
+    /// Publishes simulation reports when `RESULTS_QUEUE` is set
+    reports: Option<Publisher>,
+    /// Posts reports to jobs' `callback_url` overrides, or their tenant's
+    callbacks: Client,
+}
+
+/// A parsed job waiting on its recipient's shard.
+struct ShardedJob {
+    queue: String,
+    delivery: Delivery,
+    message_id: String,
+    job: Job,
+    _in_flight: GaugeGuard,
+}
 
 /// Run the RabbitMQ consumer.
 ///
 /// This function:
 /// 1. Connects to RabbitMQ using the configured URL
-/// 2. Sets up QoS with high prefetch for concurrent processing
-/// 3. Declares the queue (idempotent operation)
-/// 4. Starts consuming messages, spawning a task for each
-/// 5. Handles graceful shutdown on SIGINT/SIGTERM
-pub async fn run(config: Config) -> Result<()> {
+/// 2. Sets up QoS with high prefetch for concurrent processing, adjusted to
+///    job durations with `PREFETCH_AUTOTUNE`
+/// 3. Declares the configured queues (idempotent operation)
+/// 4. Starts consuming messages from all of them, spawning a task for each
+/// 5. Handles graceful shutdown on SIGINT/SIGTERM, giving in-flight jobs up
+///    to `shutdown_grace_secs` to wind down before the connection is closed
+pub async fn run(config: WorkerConfig, sources: Sources) -> Result<()> {
     let config = Arc::new(config);
 
     // Connect to RabbitMQ
-    info!(url_length = config.cloudamqp_url.len(), "rabbitmq_connecting");
+    info!(url_length = config.core.cloudamqp_url.len(), "rabbitmq_connecting");
 
-    let conn = Connection::connect(
-        &config.cloudamqp_url,
-        ConnectionProperties::default(),
+    // Consumer channels, together prefetching enough for concurrent processing
+    let mut prefetch = config.core.consumer_prefetch(config.worker_concurrency);
+    let connections = ConsumerChannels::open(
+        &BrokerUrls::from_config(&config.core),
+        config.core.consumer_connections,
+        config.core.consumer_channels,
+        prefetch,
     )
     .await
     .context("Failed to connect to RabbitMQ")?;
+    let broker = connections.broker;
 
-    info!("rabbitmq_connected");
+    info!(broker = broker, primary = broker == 0, "rabbitmq_connected");
 
-    // Create a channel
-    let channel = conn.create_channel().await.context("Failed to create channel")?;
+    // Declare and publish on the first channel
+    let channel = connections.main().clone();
 
-    info!("rabbitmq_channel_created");
+    // Declare the queues (durable to match Python publisher), as priority
+    // queues when enabled
+    for queue in &config.worker_queues {
+        channel
+            .queue_declare(
+                queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                simulator_queue_args(config.core.simulator_max_priority()),
+            )
+            .await
+            .with_context(|| format!("Failed to declare queue {}", queue))?;
 
-    // Set QoS with high prefetch for concurrent processing
-    let prefetch_count = config.worker_concurrency as u16;
-    channel
-        .basic_qos(prefetch_count, BasicQosOptions::default())
-        .await
-        .context("Failed to set QoS")?;
+        info!(queue = %queue, "rabbitmq_queue_declared");
+    }
 
-    info!(prefetch_count = prefetch_count, "rabbitmq_qos_set");
+    // Declare the results queue, which the report publisher does not
+    if let Some(queue) = &config.results_queue {
+        channel
+            .queue_declare(
+                queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .with_context(|| format!("Failed to declare results queue {}", queue))?;
 
-    // Declare the queue (durable to match Python publisher)
-    channel
-        .queue_declare(
-            SIMULATOR_QUEUE,
-            QueueDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-        )
-        .await
-        .context("Failed to declare queue")?;
+        info!(queue = %queue, "rabbitmq_results_queue_declared");
+    }
+
+    // Declare where jobs over their tenant's quota wait before they return
+    if config.tenant_quota_action == QuotaAction::Delay {
+        for queue in &config.worker_queues {
+            let delayed = delayed_queue(queue);
+            channel
+                .queue_declare(
+                    &delayed,
+                    QueueDeclareOptions {
+                        durable: true,
+                        ..Default::default()
+                    },
+                    delayed_queue_args(queue),
+                )
+                .await
+                .with_context(|| format!("Failed to declare delay queue {}", delayed))?;
+
+            info!(queue = %delayed, "rabbitmq_delay_queue_declared");
+        }
+    }
 
-    info!(queue = SIMULATOR_QUEUE, "rabbitmq_queue_declared");
+    // Optional AIMD limiter that shrinks concurrency when fetches degrade
+    let limiter = if config.adaptive_concurrency {
+        info!(
+            min_concurrency = config.adaptive_min_concurrency,
+            max_concurrency = config.worker_concurrency,
+            error_threshold = config.adaptive_error_threshold,
+            latency_threshold_ms = config.adaptive_latency_threshold_ms,
+            "adaptive_concurrency_enabled"
+        );
+        Some(Arc::new(AdaptiveConcurrency::new(
+            config.adaptive_min_concurrency,
+            config.worker_concurrency,
+            config.adaptive_error_threshold,
+            Duration::from_millis(config.adaptive_latency_threshold_ms),
+        )))
+    } else {
+        None
+    };
 
-    // Create a shared HTTP client for all requests
-    let client = Client::builder()
-        .pool_max_idle_per_host(100)
-        .build()
-        .context("Failed to create HTTP client")?;
+    // One HTTP client and cassette shared by every job
+    let mut simulator = Simulator::new(Arc::clone(&config))?.with_limiter(limiter.clone());
 
-    let client = Arc::new(client);
+    // Per-tenant simulation profiles, cached in-process
+    if let Some(url) = &config.tenant_profiles_url {
+        let ttl = Duration::from_secs(config.tenant_profiles_ttl_secs);
+        let profiles = tenant::connect(url, ttl)
+            .await
+            .context("Failed to connect to the tenant profile store")?;
+        info!(ttl_secs = ttl.as_secs(), "tenant_profiles_connected");
+        simulator = simulator.with_profiles(profiles);
+    }
 
-    // Start consuming messages
-    let mut consumer = channel
-        .basic_consume(
-            SIMULATOR_QUEUE,
-            "rust-worker",
-            BasicConsumeOptions::default(),
-            FieldTable::default(),
-        )
-        .await
-        .context("Failed to start consumer")?;
+    // Per-campaign overrides from the admin API, cached in-process
+    if let Some(url) = &config.core.campaign_overrides_url {
+        let ttl = Duration::from_secs(config.campaign_overrides_ttl_secs);
+        let store = overrides::connect(url)
+            .await
+            .context("Failed to connect to the campaign override store")?;
+        info!(ttl_secs = ttl.as_secs(), "campaign_overrides_connected");
+        simulator = simulator.with_campaign_overrides(CampaignOverrides::new(store, ttl));
+    }
+
+    // HTML the processor moved to the content store, cached in-process;
+    // the worker only reads, so the store's expiry does not apply
+    let content = match &config.core.content_store_url {
+        Some(url) => {
+            let store = content::connect(url, Duration::ZERO)
+                .await
+                .context("Failed to connect to the content store")?;
+            info!(cache_bytes = config.content_cache_bytes, "content_store_connected");
+            Some(ContentCache::new(store, config.content_cache_bytes))
+        }
+        None => None,
+    };
+
+    // Metrics and the optional health/metrics sidecar
+    let metrics = Arc::new(WorkerMetrics::new());
+    metrics.concurrency_limit.set(config.worker_concurrency as i64);
+    metrics.prefetch.set(i64::from(prefetch));
+
+    // Tenant usage survives restarts when it is persisted to USAGE_FILE
+    let usage_file = config.usage_file.as_ref().map(PathBuf::from);
+    if let Some(path) = &usage_file {
+        match metrics.usage.load(path) {
+            Ok(()) => info!(path = %path.display(), "tenant_usage_loaded"),
+            Err(e) => warn!(error = %e, "tenant_usage_load_failed"),
+        }
+
+        let metrics = Arc::clone(&metrics);
+        let path = path.clone();
+        let every = Duration::from_secs(config.usage_save_interval_secs);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                save_usage(&metrics, &path).await;
+            }
+        }.in_current_span());
+    }
+
+    if let Some(port) = config.worker_metrics_port {
+        let server_metrics = Arc::clone(&metrics);
+        let queues = config.worker_queues.clone();
+        let api_keys = Arc::new(config.core.api_keys.clone());
+        tokio::spawn(async move {
+            if let Err(e) = sidecar::serve(port, server_metrics, queues, api_keys).await {
+                error!(error = %e, "worker_sidecar_failed");
+            }
+        }.in_current_span());
+    }
+
+    // Start consuming messages from every queue on every channel into a
+    // single stream
+    let consumer_tags: Vec<(String, String)> = config
+        .worker_queues
+        .iter()
+        .map(|queue| (queue.clone(), format!("rust-worker-{}", queue)))
+        .collect();
+    let mut consumers =
+        Vec::with_capacity(config.worker_queues.len() * connections.channels.len());
+    for (queue, tag) in &consumer_tags {
+        consumers.extend(
+            connections
+                .consume(queue, tag)
+                .await
+                .with_context(|| format!("Failed to start consumer for {}", queue))?
+                .into_iter()
+                .map(|consumer| from_queue(queue, consumer)),
+        );
 
-    info!(queue = SIMULATOR_QUEUE, "rabbitmq_consumer_started");
+        info!(
+            queue = %queue,
+            channels = connections.channels.len(),
+            "rabbitmq_consumer_started"
+        );
+    }
+    let mut consumer = stream::select_all(consumers);
     info!("worker_ready");
+    systemd::ready();
+
+    // Share resources with the message handlers
+    let ctx = Arc::new(WorkerContext {
+        simulator,
+        pipeline: Pipeline::new(),
+        config: Arc::new(ArcSwap::new(Arc::clone(&config))),
+        channel,
+        limiter,
+        prefetch: config.prefetch_autotune.then(|| {
+            let max = config.prefetch_max.try_into().unwrap_or(u16::MAX);
+            PrefetchTuner::new(Duration::from_millis(config.prefetch_buffer_ms), max)
+        }),
+        content,
+        metrics,
+        shutdown: CancellationToken::new(),
+        signer: MessageSigner::from_config(&config.core),
+        reports: config.results_queue.as_ref().map(|_| {
+            Publisher::from_brokers(BrokerUrls::from_config(&config.core))
+                .with_chaos(config.core.chaos.clone())
+                .with_signer(MessageSigner::from_config(&config.core))
+                .with_max_priority(config.core.simulator_max_priority())
+        }),
+        callbacks: Client::builder()
+            .timeout(Duration::from_millis(config.request_timeout_ms))
+            .build()
+            .context("Failed to create callback client")?,
+    });
+
+    // Reload simulation parameters on SIGHUP, config file or remote changes
+    let live_config = Arc::clone(&ctx.config);
+    tokio::spawn(reload::watch(live_config, sources).in_current_span());
+
+    // Periodically sample queue depth for autoscaling signals
+    if config.worker_metrics_port.is_some() {
+        let ctx = Arc::clone(&ctx);
+        tokio::spawn(async move {
+            poll_scaling_signals(&ctx).await;
+        }.in_current_span());
+    }
+
+    // Optional per-recipient serial shards so each subscriber's jobs stay ordered
+    let shards = if config.recipient_ordering {
+        let shard_count = config.ordering_shards.unwrap_or(config.worker_concurrency);
+        let (sender, receivers) = sharded_channels::<ShardedJob>(shard_count);
 
-    // Clone channel for use in message handler
-    let channel = Arc::new(channel);
+        for (shard, mut rx) in receivers.into_iter().enumerate() {
+            let ctx = Arc::clone(&ctx);
+
+            tokio::spawn(async move {
+                while let Some(item) = rx.recv().await {
+                    // A panicking job fails alone; the shard goes on to the next
+                    let ShardedJob { queue, delivery, message_id, job, _in_flight } = item;
+                    let job = run_job(&ctx, &queue, &delivery, &message_id, job);
+                    if panic::contain_async("job", job).await.is_err() {
+                        reject_panicked(&delivery.acker, delivery.delivery_tag, &message_id).await;
+                    }
+                }
+                debug!(shard = shard, "recipient_shard_stopped");
+            }.in_current_span());
+        }
+
+        info!(shards = shard_count, "recipient_ordering_enabled");
+        Some(sender)
+    } else {
+        None
+    };
 
     // Create shutdown signal future
     let shutdown = async {
@@ -125,18 +385,68 @@ pub async fn run(config: Config) -> Result<()> {
     // Pin the shutdown future
     tokio::pin!(shutdown);
 
+    // Adjust the prefetch to the jobs' durations with PREFETCH_AUTOTUNE
+    let tune_every = Duration::from_secs(config.prefetch_autotune_interval_secs.max(1));
+    let mut tune =
+        tokio::time::interval_at(tokio::time::Instant::now() + tune_every, tune_every);
+    if ctx.prefetch.is_some() {
+        info!(
+            interval_secs = tune_every.as_secs(),
+            buffer_ms = config.prefetch_buffer_ms,
+            max_prefetch = config.prefetch_max,
+            "prefetch_autotune_enabled"
+        );
+    }
+
+    // Ping the systemd watchdog while the loop keeps turning
+    let watchdog = Watchdog::from_env();
+    let ping_every = watchdog.map_or(Duration::from_secs(3600), |w| w.period());
+    let mut ping =
+        tokio::time::interval_at(tokio::time::Instant::now() + ping_every, ping_every);
+
     // Process messages until shutdown
     loop {
         tokio::select! {
             // Check for shutdown signal
             _ = &mut shutdown => {
                 info!("worker_stopping");
+                systemd::stopping();
                 break;
             }
+            _ = ping.tick(), if watchdog.is_some() => {
+                if let Some(watchdog) = &watchdog {
+                    watchdog.ping();
+                }
+            }
+            // Retune the prefetch, restarting the consumers to apply it
+            _ = tune.tick(), if ctx.prefetch.is_some() => {
+                let concurrency = ctx
+                    .limiter
+                    .as_ref()
+                    .map_or(config.worker_concurrency, |limiter| limiter.limit());
+                let in_flight = ctx.metrics.in_flight.get().max(0) as usize;
+                let next = ctx.prefetch.as_ref().and_then(|tuner| {
+                    tuner.next(concurrency, in_flight, connections.channels.len(), prefetch)
+                });
+                let Some(next) = next else { continue };
+                match connections.set_prefetch(next, &consumer_tags).await {
+                    Ok(restarted) => {
+                        // One consumer per queue on each channel in turn
+                        let queues = consumer_tags.iter().map(|(queue, _)| queue).cycle();
+                        for (restarted, queue) in restarted.into_iter().zip(queues) {
+                            consumer.push(from_queue(queue, restarted));
+                        }
+                        info!(from = prefetch, to = next, in_flight = in_flight, "prefetch_tuned");
+                        prefetch = next;
+                        ctx.metrics.prefetch.set(i64::from(prefetch));
+                    }
+                    Err(e) => warn!(error = %e, prefetch = next, "prefetch_tune_failed"),
+                }
+            }
             // Process next message
             delivery = consumer.next() => {
                 match delivery {
-                    Some(Ok(delivery)) => {
+                    Some((queue, Ok(delivery))) => {
                         let delivery_tag = delivery.delivery_tag;
                         let message_id = delivery
                             .properties
@@ -146,74 +456,106 @@ pub async fn run(config: Config) -> Result<()> {
                             .unwrap_or_else(|| "unknown".to_string());
 
                         info!(
-                            queue = SIMULATOR_QUEUE,
+                            queue = %queue,
                             message_id = %message_id,
+                            request_id = ?request_id::of(&delivery.properties),
                             delivery_tag = delivery_tag,
                             "rabbitmq_job_received"
                         );
 
+                        ctx.metrics.jobs_received.inc();
+                        if let Some(published_at) = *delivery.properties.timestamp() {
+                            ctx.metrics.record_message_age(published_at, unix_now());
+                        }
+                        if let Some(Err(e)) = ctx
+                            .signer
+                            .as_ref()
+                            .map(|s| s.verify(&delivery.properties, &delivery.data))
+                        {
+                            ctx.metrics.stats.record_rejected();
+                            reject_forged(&delivery.acker, delivery_tag, &message_id, e).await;
+                            continue;
+                        }
+
+                        let previous = dead_letter::previous_deliveries(&delivery.properties);
+                        if dead_letter::is_poison(previous, config.core.max_delivery_attempts) {
+                            ctx.metrics.poison_messages.inc();
+                            divert_poison(&ctx.channel, &queue, &delivery, &message_id, previous).await;
+                            continue;
+                        }
+
+                        let in_flight = ctx.metrics.in_flight.track();
+
+                        if let Some(shards) = &shards {
+                            // Parse up front so the job can be routed by recipient
+                            match serde_json::from_slice::<Job>(&delivery.data) {
+                                Ok(job) => {
+                                    let recipient = job.to.clone();
+                                    let item = ShardedJob {
+                                        queue,
+                                        delivery,
+                                        message_id,
+                                        job,
+                                        _in_flight: in_flight,
+                                    };
+                                    if let Err(item) = shards.send(&recipient, item) {
+                                        error!(
+                                            delivery_tag = delivery_tag,
+                                            "recipient_shard_closed"
+                                        );
+                                        // Requeued rather than left holding a prefetch slot
+                                        if let Err(e) = item
+                                            .delivery
+                                            .nack(BasicNackOptions {
+                                                requeue: true,
+                                                ..Default::default()
+                                            })
+                                            .await
+                                        {
+                                            error!(
+                                                delivery_tag = delivery_tag,
+                                                error = %e,
+                                                "rabbitmq_nack_failed"
+                                            );
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    let ctx = Arc::clone(&ctx);
+                                    tokio::spawn(async move {
+                                        let _in_flight = in_flight;
+                                        ctx.metrics.stats.record_rejected();
+                                        reject_unparseable(&delivery.acker, delivery_tag, &message_id, &e)
+                                            .await;
+                                    }.in_current_span());
+                                }
+                            }
+                            continue;
+                        }
+
                         // Clone resources for the spawned task
-                        let client = Arc::clone(&client);
-                        let config = Arc::clone(&config);
-                        let channel = Arc::clone(&channel);
+                        let ctx = Arc::clone(&ctx);
 
                         // Spawn a task to process this message
                         tokio::spawn(async move {
+                            let _in_flight = in_flight;
+
                             // Parse the job JSON
                             let job: Result<Job, _> = serde_json::from_slice(&delivery.data);
 
                             match job {
                                 Ok(job) => {
-                                    // Process the job
-                                    let _result = process_job(&client, &config, &job).await;
-
-                                    // Acknowledge the message
-                                    if let Err(e) = channel
-                                        .basic_ack(delivery_tag, BasicAckOptions::default())
-                                        .await
-                                    {
-                                        error!(
-                                            delivery_tag = delivery_tag,
-                                            error = %e,
-                                            "rabbitmq_ack_failed"
-                                        );
-                                    } else {
-                                        info!(
-                                            queue = SIMULATOR_QUEUE,
-                                            message_id = %message_id,
-                                            "rabbitmq_job_completed"
-                                        );
-                                    }
+                                    run_job(&ctx, &queue, &delivery, &message_id, job).await;
                                 }
                                 Err(e) => {
-                                    error!(
-                                        message_id = %message_id,
-                                        error = %e,
-                                        "rabbitmq_job_parse_failed"
-                                    );
-
-                                    // Reject and requeue the message
-                                    if let Err(nack_err) = channel
-                                        .basic_nack(
-                                            delivery_tag,
-                                            BasicNackOptions {
-                                                requeue: true,
-                                                ..Default::default()
-                                            },
-                                        )
-                                        .await
-                                    {
-                                        error!(
-                                            delivery_tag = delivery_tag,
-                                            error = %nack_err,
-                                            "rabbitmq_nack_failed"
-                                        );
-                                    }
+                                    ctx.metrics.stats.record_rejected();
+                                    reject_unparseable(&delivery.acker, delivery_tag, &message_id, &e)
+                                        .await;
                                 }
                             }
-                        });
+                        }.in_current_span());
                     }
-                    Some(Err(e)) => {
+                    Some((_, Err(e))) => {
                         error!(error = %e, "rabbitmq_delivery_error");
                     }
                     None => {
@@ -225,6 +567,763 @@ pub async fn run(config: Config) -> Result<()> {
         }
     }
 
+    // Cancel in-flight jobs cooperatively and wait (bounded) for them to finish
+    ctx.shutdown.cancel();
+    let deadline = Instant::now() + Duration::from_secs(config.shutdown_grace_secs);
+    while ctx.metrics.in_flight.get() > 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let remaining = ctx.metrics.in_flight.get();
+    if remaining > 0 {
+        warn!(
+            in_flight = remaining,
+            grace_secs = config.shutdown_grace_secs,
+            "worker_shutdown_deadline_exceeded"
+        );
+    }
+
+    if let Some(publisher) = &ctx.reports {
+        publisher.close().await;
+    }
+
+    if let Some(path) = &usage_file {
+        save_usage(&ctx.metrics, path).await;
+    }
+
+    // Closing the connections returns any unacknowledged deliveries to the queue
+    if let Err(e) = connections.close("Normal shutdown").await {
+        warn!(error = %e, "rabbitmq_connection_close_error");
+    }
+
     info!("worker_shutdown_complete");
     Ok(())
 }
+
+/// Deliveries of `consumer` paired with the queue it consumes.
+///
+/// With topic exchanges a delivery's routing key is its job's
+/// `provider.tenant` key, not a queue, so jobs are delayed, deferred and
+/// dead-lettered by the queue carried alongside instead.
+fn from_queue(
+    queue: &str,
+    consumer: Consumer,
+) -> impl Stream<Item = (String, lapin::Result<Delivery>)> {
+    let queue = queue.to_string();
+    consumer.map(move |delivery| (queue.clone(), delivery))
+}
+
+/// Write the tenant usage ledger to `path` off the async runtime.
+async fn save_usage(metrics: &Arc<WorkerMetrics>, path: &Path) {
+    let metrics = Arc::clone(metrics);
+    let path = path.to_path_buf();
+    match tokio::task::spawn_blocking(move || metrics.usage.save(&path)).await {
+        Ok(Ok(())) => debug!("tenant_usage_saved"),
+        Ok(Err(e)) => warn!(error = %e, "tenant_usage_save_failed"),
+        Err(e) => warn!(error = %e, "tenant_usage_save_failed"),
+    }
+}
+
+/// Process a parsed job under a span carrying its request ID.
+///
+/// The ID comes from the job, else its delivery's `x-request-id` header;
+/// jobs with neither (e.g. from the Python processor) get a new one, so
+/// their report can still be matched to the worker's logs.
+async fn run_job(
+    ctx: &WorkerContext,
+    queue: &str,
+    delivery: &Delivery,
+    message_id: &str,
+    mut job: Job,
+) {
+    let request_id = job
+        .request_id
+        .take()
+        .or_else(|| request_id::of(&delivery.properties))
+        .unwrap_or_else(request_id::generate);
+    let span = job_span(&request_id);
+    job.request_id = Some(request_id);
+    process_job(ctx, queue, delivery, message_id, job).instrument(span).await;
+}
+
+/// Span for a job's logs, carrying its request ID and, as log lines show
+/// only their innermost span's fields, the instance ID of the root span.
+fn job_span(request_id: &str) -> tracing::Span {
+    tracing::info_span!("job", instance = instance_id(), request_id = %request_id)
+}
+
+/// Process a parsed job consumed from `queue` and acknowledge its delivery.
+///
+/// Jobs that have not started when shutdown begins are requeued instead,
+/// and jobs over their tenant's quota are delayed with the `delay` action.
+async fn process_job(
+    ctx: &WorkerContext,
+    queue: &str,
+    delivery: &Delivery,
+    message_id: &str,
+    mut job: Job,
+) {
+    let delivery_tag = delivery.delivery_tag;
+    if ctx.shutdown.is_cancelled() {
+        requeue_not_started(&delivery.acker, delivery_tag, message_id).await;
+        return;
+    }
+
+    // Wait for a slot under the adaptive limit
+    let _permit = match &ctx.limiter {
+        Some(limiter) => tokio::select! {
+            permit = limiter.acquire() => Some(permit),
+            _ = ctx.shutdown.cancelled() => {
+                requeue_not_started(&delivery.acker, delivery_tag, message_id).await;
+                return;
+            }
+        },
+        None => None,
+    };
+
+    // Fetch HTML kept in the content store; the job is retried later if the
+    // store failed, and dead-lettered if its HTML is gone or corrupt
+    if let Some(content) = &ctx.content {
+        if let Err(e) = content.resolve(&mut job).await {
+            if e.is_transient() {
+                retry_unfetched(ctx, queue, delivery, message_id, &e).await;
+            } else {
+                reject_unfetchable(&delivery.acker, delivery_tag, message_id, &e).await;
+            }
+            return;
+        }
+    }
+
+    // Snapshot the live config so a reload never changes a running job
+    let config = ctx.config.load_full();
+
+    // Simulate the job, unless a hook vetoes it; dry runs never call back
+    let callback_url = match &job.overrides.callback_url {
+        _ if job.overrides.dry_run => None,
+        Some(url) => Some(url.clone()),
+        None => ctx
+            .simulator
+            .tenant_profile(&job.to)
+            .await
+            .and_then(|profile| profile.callback_url.clone()),
+    };
+    let simulator = ctx.simulator.with_config(Arc::clone(&config));
+    let (received_at_ms, enqueued_at_ms) = (job.received_at_ms, job.enqueued_at_ms);
+    let started_at_ms = unix_millis();
+    let started = Instant::now();
+    let mut result = ctx
+        .pipeline
+        .simulate(&simulator, job, Some(&ctx.shutdown))
+        .await;
+    if let Some(tuner) = &ctx.prefetch {
+        tuner.record(started.elapsed());
+    }
+    if let Some(result) = &mut result {
+        result.latency = Some(Latency::measure(
+            received_at_ms,
+            enqueued_at_ms,
+            started_at_ms,
+            unix_millis(),
+        ));
+    }
+
+    if let Some(result) = &result {
+        if result.status == JobStatus::NotStarted {
+            requeue_not_started(&delivery.acker, delivery_tag, message_id).await;
+            return;
+        }
+
+        if result.status == JobStatus::OverQuota
+            && config.tenant_quota_action == QuotaAction::Delay
+            && delay_over_quota(ctx, queue, delivery, message_id, config.tenant_quota_delay_secs)
+                .await
+        {
+            return;
+        }
+
+        if let (
+            JobStatus::Deferred,
+            Some(offset),
+            Some(Skip::ActiveHours {
+                resume_after_secs, ..
+            }),
+        ) = (
+            result.status,
+            result.persona.utc_offset_mins,
+            result.skipped.first(),
+        ) {
+            if defer_to_active_hours(ctx, queue, delivery, message_id, offset, *resume_after_secs)
+                .await
+            {
+                return;
+            }
+        }
+
+        ctx.metrics.stats.record(&Outcome {
+            status: result.status.as_str(),
+            opened: result.opened,
+            clicked_domains: &result.clicked_domains(),
+            click_errors: result.click_errors(),
+        });
+        ctx.metrics.campaigns.record(result);
+        // Dry runs engaged with nothing, so they are not a tenant's usage
+        if !result.dry_run {
+            ctx.metrics.usage.record(result);
+        }
+        if let Some(policy) = DriftPolicy::from_config(&config) {
+            ctx.metrics.record_drift(result, &policy);
+        }
+        if result.status == JobStatus::Expired {
+            ctx.metrics.jobs_expired.inc();
+        }
+        if let Some(latency) = &result.latency {
+            ctx.metrics.record_latency(latency);
+        }
+        ctx.metrics.timings.record(result);
+
+        // Reports are best effort; a failed publish never fails the job
+        if let (Some(publisher), Some(queue)) = (&ctx.reports, &config.results_queue) {
+            if let Err(e) = publisher.publish_report(queue, result).await {
+                warn!(message_id = %message_id, error = %e, "results_publish_failed");
+            }
+        }
+
+        // Callbacks are best effort too
+        if let Some(url) = &callback_url {
+            if let Err(e) = post_callback(&ctx.callbacks, url, result).await {
+                warn!(message_id = %message_id, error = %format!("{:#}", e), "callback_post_failed");
+            }
+        }
+    }
+
+    if let Some(chaos) = &config.core.chaos {
+        chaos.delay_ack().await;
+    }
+
+    // Acknowledge the message
+    if let Err(e) = delivery
+        .ack(BasicAckOptions::default())
+        .await
+    {
+        error!(
+            delivery_tag = delivery_tag,
+            error = %e,
+            "rabbitmq_ack_failed"
+        );
+    } else {
+        ctx.metrics.jobs_completed.inc();
+        info!(
+            queue = queue,
+            message_id = %message_id,
+            "rabbitmq_job_completed"
+        );
+    }
+}
+
+/// POST `report` as JSON to a job's callback URL.
+///
+/// Errors never include the URL, which may carry a token.
+async fn post_callback(client: &Client, url: &str, report: &SimulationReport) -> Result<()> {
+    let url = sanitize(url).context("Invalid callback URL")?;
+    let body = serde_json::to_vec(report).context("Failed to serialize report")?;
+
+    client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| e.without_url())
+        .context("Callback request failed")?;
+    Ok(())
+}
+
+/// Queue where jobs delayed from `queue` wait.
+fn delayed_queue(queue: &str) -> String {
+    format!("{}.delayed", queue)
+}
+
+/// Queue where jobs from `queue` wait `delay_secs` to retry fetching their
+/// HTML, e.g. `email_simulator.delayed.retry20s`. Each delay has its own
+/// queue, as the broker only expires jobs at the head of a queue.
+fn retry_queue(queue: &str, delay_secs: u64) -> String {
+    format!("{}.delayed.retry{}s", queue, delay_secs)
+}
+
+/// Wait before retrying a job whose HTML the content store failed to
+/// return, `retries` times before: 5 seconds, doubling with each retry up
+/// to 5 minutes.
+fn content_retry_delay_secs(retries: u64) -> u64 {
+    const FIRST_SECS: u64 = 5;
+    const MAX_SECS: u64 = 300;
+    FIRST_SECS
+        .saturating_mul(1 << retries.min(16))
+        .min(MAX_SECS)
+}
+
+/// Queue where jobs from `queue` wait for the active hours of recipients at
+/// `offset_mins` from UTC, e.g. `email_simulator.delayed.utc-300`.
+///
+/// Each offset has its own queue, in which jobs come due in the order they
+/// arrive, so none waits behind a job due later: the broker only expires
+/// jobs at the head of a queue.
+fn deferred_queue(queue: &str, offset_mins: i32) -> String {
+    format!("{}.delayed.utc{:+}", queue, offset_mins)
+}
+
+/// Arguments dead-lettering expired jobs from the delay queue back to `queue`.
+///
+/// Queue arguments take precedence over the broker's dead-letter policy,
+/// which would otherwise match `email_simulator.delayed` too.
+fn delayed_queue_args(queue: &str) -> FieldTable {
+    let mut args = FieldTable::default();
+    args.insert(
+        ShortString::from("x-dead-letter-exchange"),
+        AMQPValue::LongString(LongString::from("")),
+    );
+    args.insert(
+        ShortString::from("x-dead-letter-routing-key"),
+        AMQPValue::LongString(LongString::from(queue)),
+    );
+    args
+}
+
+/// Republish a job over its tenant's quota to `queue`'s delay queue, from
+/// which it returns after `delay_secs`, and acknowledge the original.
+///
+/// Returns `false` if the job could not be republished; it is then handled
+/// like a dropped job.
+async fn delay_over_quota(
+    ctx: &WorkerContext,
+    queue: &str,
+    delivery: &Delivery,
+    message_id: &str,
+    delay_secs: u64,
+) -> bool {
+    let delayed = delayed_queue(queue);
+    let properties = requeue_properties(&delivery.properties);
+    if let Err(e) = delay_job(ctx, delivery, &delayed, delay_secs, properties).await {
+        warn!(message_id = %message_id, queue = %delayed, error = %e, "tenant_job_delay_failed");
+        return false;
+    }
+
+    info!(
+        queue = queue,
+        message_id = %message_id,
+        delay_secs = delay_secs,
+        "tenant_job_delayed"
+    );
+    true
+}
+
+/// Republish a job whose recipient, at `offset_mins` from UTC, is outside
+/// their active hours to their offset's delay queue, from which it returns
+/// after `delay_secs`, and acknowledge the original.
+///
+/// Returns `false` if the job could not be republished; it is then handled
+/// like a dropped job.
+async fn defer_to_active_hours(
+    ctx: &WorkerContext,
+    queue: &str,
+    delivery: &Delivery,
+    message_id: &str,
+    offset_mins: i32,
+    delay_secs: u64,
+) -> bool {
+    let deferred = deferred_queue(queue, offset_mins);
+    // Declared on first use, as offsets are only known once jobs arrive
+    let delayed = match declare_delay_queue(ctx, &deferred, queue).await {
+        Ok(()) => {
+            let properties = requeue_properties(&delivery.properties);
+            delay_job(ctx, delivery, &deferred, delay_secs, properties).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = delayed {
+        warn!(message_id = %message_id, queue = %deferred, error = %e, "recipient_job_defer_failed");
+        return false;
+    }
+
+    info!(
+        queue = queue,
+        message_id = %message_id,
+        utc_offset_mins = offset_mins,
+        delay_secs = delay_secs,
+        "recipient_job_deferred"
+    );
+    true
+}
+
+/// Retry a job consumed from `queue` whose HTML the content store failed to
+/// return through a delay queue, backing off with each retry, and
+/// acknowledge the original. Retries count as deliveries, so once
+/// `MAX_DELIVERY_ATTEMPTS` are used up the job is moved to the DLQ.
+async fn retry_unfetched(
+    ctx: &WorkerContext,
+    queue: &str,
+    delivery: &Delivery,
+    message_id: &str,
+    e: &ContentError,
+) {
+    let retries = dead_letter::retries(&delivery.properties);
+    let delay_secs = content_retry_delay_secs(retries);
+    let retry = retry_queue(queue, delay_secs);
+    warn!(
+        message_id = %message_id,
+        error = %e,
+        retries = retries,
+        delay_secs = delay_secs,
+        "content_fetch_failed"
+    );
+
+    // Declared on first use, like the deferral queues
+    let retried = match declare_delay_queue(ctx, &retry, queue).await {
+        Ok(()) => {
+            let properties = dead_letter::retry_properties(&delivery.properties);
+            delay_job(ctx, delivery, &retry, delay_secs, properties).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = retried {
+        // Left unacked, so it returns to the queue when the channel closes
+        error!(message_id = %message_id, queue = %retry, error = %e, "content_retry_failed");
+    }
+}
+
+/// Declare `delayed`, a delay queue whose jobs return to `queue`.
+async fn declare_delay_queue(
+    ctx: &WorkerContext,
+    delayed: &str,
+    queue: &str,
+) -> Result<(), lapin::Error> {
+    ctx.channel
+        .queue_declare(
+            delayed,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            delayed_queue_args(queue),
+        )
+        .await
+        .map(|_| ())
+}
+
+/// Republish a job with `properties` to `delayed`, from which it returns to
+/// its queue after `delay_secs`, and acknowledge the original.
+///
+/// `properties` should be the original ones without the previous delay's
+/// `x-death`, so the body and signature are republished unchanged.
+async fn delay_job(
+    ctx: &WorkerContext,
+    delivery: &Delivery,
+    delayed: &str,
+    delay_secs: u64,
+    properties: BasicProperties,
+) -> Result<(), lapin::Error> {
+    let properties =
+        properties.with_expiration(ShortString::from((delay_secs * 1000).to_string()));
+
+    ctx.channel
+        .basic_publish(
+            "",
+            delayed,
+            BasicPublishOptions::default(),
+            &delivery.data,
+            properties,
+        )
+        .await?
+        .await?;
+
+    if let Err(e) = delivery
+        .ack(BasicAckOptions::default())
+        .await
+    {
+        error!(
+            delivery_tag = delivery.delivery_tag,
+            error = %e,
+            "rabbitmq_ack_failed"
+        );
+    }
+    Ok(())
+}
+
+/// Return a job that never started back to the queue during shutdown.
+async fn requeue_not_started(acker: &Acker, delivery_tag: u64, message_id: &str) {
+    info!(message_id = %message_id, "rabbitmq_job_requeued_on_shutdown");
+
+    if let Err(e) = acker
+        .nack(
+            BasicNackOptions {
+                requeue: true,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        error!(
+            delivery_tag = delivery_tag,
+            error = %e,
+            "rabbitmq_nack_failed"
+        );
+    }
+}
+
+/// Dead-letter a job whose HTML is missing from the content store or does
+/// not match its hash; fetching it again would fail the same way.
+async fn reject_unfetchable(acker: &Acker, delivery_tag: u64, message_id: &str, e: &ContentError) {
+    error!(message_id = %message_id, error = %e, "content_fetch_failed");
+
+    if let Err(e) = acker
+        .nack(
+            BasicNackOptions {
+                requeue: false,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        error!(
+            delivery_tag = delivery_tag,
+            error = %e,
+            "rabbitmq_nack_failed"
+        );
+    }
+}
+
+/// Log a job that failed signature verification and drop it.
+async fn reject_forged(acker: &Acker, delivery_tag: u64, message_id: &str, e: SignatureError) {
+    error!(
+        message_id = %message_id,
+        reason = e.as_str(),
+        "queue_signature_rejected"
+    );
+
+    // Forged or corrupted; never requeue
+    if let Err(nack_err) = acker
+        .nack(
+            BasicNackOptions {
+                requeue: false,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        error!(
+            delivery_tag = delivery_tag,
+            error = %nack_err,
+            "rabbitmq_nack_failed"
+        );
+    }
+}
+
+/// Log a job delivered too often from `queue` and move it to the DLQ.
+async fn divert_poison(
+    channel: &Channel,
+    queue: &str,
+    delivery: &Delivery,
+    message_id: &str,
+    previous: u64,
+) {
+    error!(
+        queue = queue,
+        message_id = %message_id,
+        deliveries = previous,
+        "rabbitmq_poison_message"
+    );
+
+    if let Err(e) = dead_letter::divert_poison(channel, queue, delivery, previous).await {
+        // Left unacked, so it returns to the queue when the channel closes
+        error!(message_id = %message_id, error = %e, "rabbitmq_poison_divert_failed");
+    }
+}
+
+/// Dead-letter a job whose processing panicked; it would panic again on
+/// every redelivery.
+async fn reject_panicked(acker: &Acker, delivery_tag: u64, message_id: &str) {
+    error!(message_id = %message_id, "rabbitmq_job_panicked");
+
+    if let Err(e) = acker
+        .nack(
+            BasicNackOptions {
+                requeue: false,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        error!(
+            delivery_tag = delivery_tag,
+            error = %e,
+            "rabbitmq_nack_failed"
+        );
+    }
+}
+
+/// Log an unparseable job and dead-letter it; it would fail to parse on
+/// every redelivery.
+async fn reject_unparseable(
+    acker: &Acker,
+    delivery_tag: u64,
+    message_id: &str,
+    e: &serde_json::Error,
+) {
+    error!(
+        message_id = %message_id,
+        error = %e,
+        "rabbitmq_job_parse_failed"
+    );
+
+    // Malformed JSON never parses; classic queues would redeliver it forever
+    if let Err(nack_err) = acker
+        .nack(
+            BasicNackOptions {
+                requeue: false,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        error!(
+            delivery_tag = delivery_tag,
+            error = %nack_err,
+            "rabbitmq_nack_failed"
+        );
+    }
+}
+
+/// Sample queue depth and the concurrency limit for the autoscaling endpoint.
+async fn poll_scaling_signals(ctx: &WorkerContext) {
+    let config = ctx.config.load_full();
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(config.scaling_poll_interval_secs.max(1)));
+
+    loop {
+        interval.tick().await;
+
+        // Passive declare returns current counts without modifying the queue
+        let mut ready = 0i64;
+        let mut consumers = 0i64;
+        let mut sampled = false;
+
+        for name in &config.worker_queues {
+            match ctx
+                .channel
+                .queue_declare(
+                    name,
+                    QueueDeclareOptions {
+                        passive: true,
+                        ..Default::default()
+                    },
+                    FieldTable::default(),
+                )
+                .await
+            {
+                Ok(queue) => {
+                    ready += queue.message_count() as i64;
+                    consumers += queue.consumer_count() as i64;
+                    sampled = true;
+                }
+                Err(e) => {
+                    warn!(queue = %name, error = %e, "queue_depth_poll_failed");
+                }
+            }
+        }
+
+        if sampled {
+            ctx.metrics.record_queue_depth(ready, consumers, unix_now());
+        }
+
+        if let Some(limiter) = &ctx.limiter {
+            ctx.metrics.concurrency_limit.set(limiter.limit() as i64);
+        }
+    }
+}
+
+/// Current Unix time in seconds.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bobnet::queue::exchange;
+
+    fn dead_letter_key(args: &FieldTable) -> Option<String> {
+        match args.inner().get("x-dead-letter-routing-key") {
+            Some(AMQPValue::LongString(key)) => Some(key.to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_delayed_jobs_return_to_the_consumed_queue() {
+        // Published to the jobs exchange under its `provider.tenant` key and
+        // bound to the premium queue the worker consumes
+        let routing_key = exchange::routing_key("mailgun", "bob+acme@example.com");
+        assert_eq!(routing_key, "mailgun.acme");
+        let queue = "email_simulator.premium";
+
+        assert_eq!(delayed_queue(queue), "email_simulator.premium.delayed");
+        assert_eq!(
+            deferred_queue(queue, -300),
+            "email_simulator.premium.delayed.utc-300"
+        );
+        assert_eq!(
+            dead_letter_key(&delayed_queue_args(queue)).as_deref(),
+            Some(queue)
+        );
+    }
+
+    /// Log output captured in memory.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_job_logs_carry_instance_and_request_id() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let layer = bobnet::util::logging::json_layer(move || writer.clone());
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _worker = tracing::info_span!("worker", instance = instance_id()).entered();
+            let _job = job_span("req-42").entered();
+            info!("rabbitmq_job_acked");
+        });
+
+        let output = capture.0.lock().unwrap().clone();
+        let line: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(line["message"], "rabbitmq_job_acked");
+        assert_eq!(line["span"]["instance"], instance_id());
+        assert_eq!(line["span"]["request_id"], "req-42");
+    }
+
+    #[test]
+    fn test_content_retries_back_off() {
+        let delays: Vec<u64> = (0..8).map(content_retry_delay_secs).collect();
+        assert_eq!(delays, [5, 10, 20, 40, 80, 160, 300, 300]);
+        assert_eq!(content_retry_delay_secs(u64::MAX), 300);
+        assert_eq!(
+            retry_queue("email_simulator", 20),
+            "email_simulator.delayed.retry20s"
+        );
+    }
+}
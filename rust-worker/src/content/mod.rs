@@ -0,0 +1,377 @@
+//! Content-addressed storage of email HTML.
+//!
+//! One campaign sends the same multi-hundred-KB HTML to thousands of
+//! recipients, and each of their simulator jobs would carry a copy. With
+//! `CONTENT_STORE_URL` set, the processor stores HTML over
+//! `CONTENT_STORE_MIN_BYTES` once under its SHA-256 and publishes the job
+//! with only the hash (`html_sha256`). The worker fetches it back through a
+//! [`ContentCache`], so each body crosses the network about once per worker
+//! rather than once per recipient.
+//!
+//! Bodies are kept in a [`ContentStore`]: Redis (feature `redis`), S3 or an
+//! S3-compatible service ([`s3`]), or any embedder implementation. Redis
+//! entries expire after `CONTENT_STORE_TTL_SECS`; S3 objects are left to the
+//! bucket's lifecycle rules, which must keep them longer than jobs can wait
+//! in the queues.
+
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod s3;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+
+use crate::queue::SimulatorJob;
+use crate::simulate::simulator::Job;
+
+/// URL schemes [`connect`] accepts.
+pub const STORE_SCHEMES: &[&str] = &["redis", "rediss", "s3"];
+
+/// How long a stored body is not stored again; Redis entries have their
+/// expiry refreshed once this has passed.
+const RESTORE_AFTER: Duration = Duration::from_secs(600);
+
+/// Most hashes remembered as stored; older ones are forgotten beyond it.
+const MAX_REMEMBERED: usize = 10_000;
+
+/// Error from a content store.
+#[derive(Debug, thiserror::Error)]
+pub enum ContentError {
+    /// The URL names a store this build does not support
+    #[error("unsupported content store `{0}` (expected redis or s3, with its feature enabled)")]
+    UnsupportedScheme(String),
+    /// The store URL is missing a part
+    #[error("invalid content store URL: {0}")]
+    InvalidUrl(String),
+    /// The store could not be reached or queried
+    #[error("content store error: {0}")]
+    Store(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// Nothing is stored under the hash, e.g. it expired
+    #[error("no content stored for {0}")]
+    Missing(String),
+    /// What is stored does not hash to its key
+    #[error("stored content does not match {0}")]
+    Corrupt(String),
+}
+
+impl ContentError {
+    /// Wrap a store client error.
+    pub fn store(e: impl std::error::Error + Send + Sync + 'static) -> Self {
+        ContentError::Store(Box::new(e))
+    }
+
+    /// Whether fetching again later may succeed.
+    ///
+    /// Only store errors may pass; content that expired or does not match
+    /// its hash never will, and neither will a misconfigured store.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ContentError::Store(_))
+    }
+}
+
+/// Lowercase hex SHA-256 of `html`, its key in a [`ContentStore`].
+pub fn content_hash(html: &str) -> String {
+    hex::encode(Sha256::digest(html.as_bytes()))
+}
+
+/// Whether `value` can be a content hash: 64 lowercase hex characters.
+pub fn is_content_hash(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+}
+
+/// Storage of HTML bodies keyed by their [`content_hash`].
+pub trait ContentStore: Send + Sync {
+    /// Store `html` under `hash`, replacing (and refreshing) any copy.
+    fn put<'a>(&'a self, hash: &'a str, html: &'a str) -> BoxFuture<'a, Result<(), ContentError>>;
+
+    /// The body stored under `hash`, or `None` if there is none.
+    fn get<'a>(&'a self, hash: &'a str) -> BoxFuture<'a, Result<Option<String>, ContentError>>;
+}
+
+/// Bodies kept in process memory, for tests and single-process embedders.
+#[derive(Default)]
+pub struct MemoryContent {
+    bodies: Mutex<HashMap<String, String>>,
+}
+
+impl ContentStore for MemoryContent {
+    fn put<'a>(&'a self, hash: &'a str, html: &'a str) -> BoxFuture<'a, Result<(), ContentError>> {
+        self.bodies
+            .lock()
+            .unwrap()
+            .insert(hash.to_string(), html.to_string());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn get<'a>(&'a self, hash: &'a str) -> BoxFuture<'a, Result<Option<String>, ContentError>> {
+        let html = self.bodies.lock().unwrap().get(hash).cloned();
+        Box::pin(async move { Ok(html) })
+    }
+}
+
+/// Moves the HTML of large jobs into a [`ContentStore`], for the processor.
+pub struct ContentWriter {
+    store: Arc<dyn ContentStore>,
+    min_bytes: usize,
+    /// When each recently stored hash was stored
+    stored: Mutex<HashMap<String, Instant>>,
+}
+
+impl ContentWriter {
+    /// Store the HTML of jobs of at least `min_bytes` in `store`.
+    pub fn new(store: Arc<dyn ContentStore>, min_bytes: usize) -> Self {
+        Self {
+            store,
+            min_bytes,
+            stored: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replace the HTML of `job` with its hash if it is large enough,
+    /// storing it unless it was stored recently. Returns whether it did.
+    ///
+    /// On error the job is left as it was, so it can still be published with
+    /// its HTML inline.
+    pub async fn offload(&self, job: &mut SimulatorJob) -> Result<bool, ContentError> {
+        let Some(html) = job.html.as_deref().filter(|html| html.len() >= self.min_bytes) else {
+            return Ok(false);
+        };
+        let hash = content_hash(html);
+
+        let fresh = {
+            let stored = self.stored.lock().unwrap();
+            stored
+                .get(&hash)
+                .is_some_and(|at| at.elapsed() < RESTORE_AFTER)
+        };
+        if !fresh {
+            self.store.put(&hash, html).await?;
+            let mut stored = self.stored.lock().unwrap();
+            if stored.len() >= MAX_REMEMBERED {
+                stored.retain(|_, at| at.elapsed() < RESTORE_AFTER);
+            }
+            stored.insert(hash.clone(), Instant::now());
+        }
+
+        job.html = None;
+        job.html_sha256 = Some(hash);
+        Ok(true)
+    }
+}
+
+struct Cached {
+    html: Arc<str>,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    bodies: HashMap<String, Cached>,
+    bytes: usize,
+    clock: u64,
+}
+
+/// Fetches stored HTML for the worker, keeping the most recently used
+/// bodies in memory up to a byte budget.
+pub struct ContentCache {
+    store: Arc<dyn ContentStore>,
+    max_bytes: usize,
+    entries: Mutex<Entries>,
+}
+
+impl ContentCache {
+    /// Cache bodies from `store`, holding at most `max_bytes` of them.
+    pub fn new(store: Arc<dyn ContentStore>, max_bytes: usize) -> Self {
+        Self {
+            store,
+            max_bytes,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Fill in the HTML of a `job` that carries only its hash.
+    pub async fn resolve(&self, job: &mut Job) -> Result<(), ContentError> {
+        if job.html.is_some() {
+            return Ok(());
+        }
+        if let Some(hash) = &job.html_sha256 {
+            job.html = Some(self.get(hash).await?.to_string());
+        }
+        Ok(())
+    }
+
+    /// The body stored under `hash`, checked against it.
+    pub async fn get(&self, hash: &str) -> Result<Arc<str>, ContentError> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.clock += 1;
+            let clock = entries.clock;
+            if let Some(cached) = entries.bodies.get_mut(hash) {
+                cached.used = clock;
+                return Ok(Arc::clone(&cached.html));
+            }
+        }
+
+        let html = self
+            .store
+            .get(hash)
+            .await?
+            .ok_or_else(|| ContentError::Missing(hash.to_string()))?;
+        if content_hash(&html) != hash {
+            return Err(ContentError::Corrupt(hash.to_string()));
+        }
+        let html: Arc<str> = Arc::from(html);
+
+        // Bodies over the whole budget are used but not kept
+        if html.len() <= self.max_bytes {
+            let mut entries = self.entries.lock().unwrap();
+            while entries.bytes + html.len() > self.max_bytes {
+                let Some(oldest) = entries
+                    .bodies
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.used)
+                    .map(|(hash, _)| hash.clone())
+                else {
+                    break;
+                };
+                if let Some(evicted) = entries.bodies.remove(&oldest) {
+                    entries.bytes -= evicted.html.len();
+                }
+            }
+            let used = entries.clock;
+            let previous = entries.bodies.insert(
+                hash.to_string(),
+                Cached {
+                    html: Arc::clone(&html),
+                    used,
+                },
+            );
+            entries.bytes += html.len() - previous.map_or(0, |p| p.html.len());
+        }
+        Ok(html)
+    }
+}
+
+/// Connect to the content store at `url`. Redis entries expire after `ttl`.
+///
+/// The scheme picks the store: `redis://` or `rediss://` needs the `redis`
+/// feature; `s3://bucket/prefix` is always available (see [`s3`]).
+pub async fn connect(url: &str, ttl: Duration) -> Result<Arc<dyn ContentStore>, ContentError> {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    #[cfg(not(feature = "redis"))]
+    let _ = ttl;
+    match scheme {
+        #[cfg(feature = "redis")]
+        "redis" | "rediss" => Ok(Arc::new(self::redis::RedisContent::connect(url, ttl).await?)),
+        "s3" => Ok(Arc::new(self::s3::S3Content::from_url(url)?)),
+        other => Err(ContentError::UnsupportedScheme(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Store counting its writes and reads.
+    #[derive(Default)]
+    struct Counting {
+        inner: MemoryContent,
+        puts: AtomicUsize,
+        gets: AtomicUsize,
+    }
+
+    impl ContentStore for Counting {
+        fn put<'a>(
+            &'a self,
+            hash: &'a str,
+            html: &'a str,
+        ) -> BoxFuture<'a, Result<(), ContentError>> {
+            self.puts.fetch_add(1, Ordering::SeqCst);
+            self.inner.put(hash, html)
+        }
+
+        fn get<'a>(&'a self, hash: &'a str) -> BoxFuture<'a, Result<Option<String>, ContentError>> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(hash)
+        }
+    }
+
+    fn job(html: &str) -> SimulatorJob {
+        SimulatorJob::new("msg".to_string(), "user@example.com".to_string(), Some(html.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_large_bodies_travel_by_hash() {
+        let store = Arc::new(Counting::default());
+        let writer = ContentWriter::new(store.clone(), 10);
+        let html = "<html>campaign body</html>";
+
+        let mut small = job("<p>hi</p>");
+        assert!(!writer.offload(&mut small).await.unwrap());
+        assert_eq!(small.html.as_deref(), Some("<p>hi</p>"));
+
+        // The same body is stored once for every recipient
+        let mut first = job(html);
+        let mut second = job(html);
+        assert!(writer.offload(&mut first).await.unwrap());
+        assert!(writer.offload(&mut second).await.unwrap());
+        assert_eq!(first.html, None);
+        assert_eq!(first.html_sha256.as_deref(), Some(content_hash(html).as_str()));
+        assert!(is_content_hash(first.html_sha256.as_deref().unwrap()));
+        assert_eq!(store.puts.load(Ordering::SeqCst), 1);
+
+        // And fetched once by each worker
+        let cache = ContentCache::new(store.clone(), 1024);
+        for offloaded in [first, second] {
+            let mut job = Job::from(offloaded);
+            cache.resolve(&mut job).await.unwrap();
+            assert_eq!(job.html.as_deref(), Some(html));
+        }
+        assert_eq!(store.gets.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_checks_and_bounds_bodies() {
+        let store = Arc::new(Counting::default());
+        let cache = ContentCache::new(store.clone(), 20);
+
+        let missing = content_hash("gone");
+        assert!(matches!(cache.get(&missing).await, Err(ContentError::Missing(_))));
+        store.put(&missing, "tampered").await.unwrap();
+        assert!(matches!(cache.get(&missing).await, Err(ContentError::Corrupt(_))));
+
+        // The least recently used body is evicted to make room
+        for body in ["aaaaaaaaaa", "bbbbbbbbbb"] {
+            store.put(&content_hash(body), body).await.unwrap();
+        }
+        cache.get(&content_hash("aaaaaaaaaa")).await.unwrap();
+        cache.get(&content_hash("bbbbbbbbbb")).await.unwrap();
+        cache.get(&content_hash("aaaaaaaaaa")).await.unwrap();
+        store.put(&content_hash("cccccccccc"), "cccccccccc").await.unwrap();
+        cache.get(&content_hash("cccccccccc")).await.unwrap();
+        let gets = store.gets.load(Ordering::SeqCst);
+        cache.get(&content_hash("aaaaaaaaaa")).await.unwrap();
+        assert_eq!(store.gets.load(Ordering::SeqCst), gets);
+        cache.get(&content_hash("bbbbbbbbbb")).await.unwrap();
+        assert_eq!(store.gets.load(Ordering::SeqCst), gets + 1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_unsupported_scheme() {
+        let result = connect("ftp://files/html", Duration::from_secs(60)).await;
+        assert!(matches!(result, Err(ContentError::UnsupportedScheme(s)) if s == "ftp"));
+    }
+
+    #[test]
+    fn test_only_store_errors_are_transient() {
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert!(ContentError::store(io).is_transient());
+        assert!(!ContentError::Missing("abc".to_string()).is_transient());
+        assert!(!ContentError::Corrupt("abc".to_string()).is_transient());
+    }
+}
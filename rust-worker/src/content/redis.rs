@@ -0,0 +1,59 @@
+//! HTML bodies stored in Redis.
+//!
+//! Each body is a string under `bobnet:content:<sha256>`, set with an expiry
+//! that every store of the same body refreshes.
+
+use std::time::Duration;
+
+use ::redis::aio::ConnectionManager;
+use ::redis::AsyncCommands;
+use futures::future::BoxFuture;
+
+use super::{ContentError, ContentStore};
+
+/// Prefix of the key each body is stored under.
+pub const KEY_PREFIX: &str = "bobnet:content:";
+
+/// Bodies kept in Redis over a reconnecting connection.
+#[derive(Clone)]
+pub struct RedisContent {
+    conn: ConnectionManager,
+    ttl_secs: u64,
+}
+
+impl RedisContent {
+    /// Connect to the Redis server at `url` (`redis://` or `rediss://`),
+    /// storing bodies for `ttl`.
+    pub async fn connect(url: &str, ttl: Duration) -> Result<Self, ContentError> {
+        let client = ::redis::Client::open(url).map_err(ContentError::store)?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .map_err(ContentError::store)?;
+        Ok(Self {
+            conn,
+            ttl_secs: ttl.as_secs().max(1),
+        })
+    }
+}
+
+fn key(hash: &str) -> String {
+    format!("{}{}", KEY_PREFIX, hash)
+}
+
+impl ContentStore for RedisContent {
+    fn put<'a>(&'a self, hash: &'a str, html: &'a str) -> BoxFuture<'a, Result<(), ContentError>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            conn.set_ex::<_, _, ()>(key(hash), html, self.ttl_secs)
+                .await
+                .map_err(ContentError::store)
+        })
+    }
+
+    fn get<'a>(&'a self, hash: &'a str) -> BoxFuture<'a, Result<Option<String>, ContentError>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            conn.get(key(hash)).await.map_err(ContentError::store)
+        })
+    }
+}
@@ -0,0 +1,182 @@
+//! Error type of the library API.
+//!
+//! Embedders match on [`BobNetError`] to tell malformed input, which will
+//! never succeed, from broker and network trouble, which is worth retrying.
+//! The binaries still use `anyhow` and convert with `?`.
+
+use crate::simulate::fetch::FetchError;
+use crate::util::panic::Panicked;
+
+/// Result of a library operation.
+pub type Result<T, E = BobNetError> = std::result::Result<T, E>;
+
+/// Why a library operation failed.
+#[derive(Debug, thiserror::Error)]
+pub enum BobNetError {
+    /// Webhook or email content could not be parsed
+    #[error("failed to parse {what}: {source}")]
+    Parse {
+        /// What was being parsed, e.g. `email`
+        what: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A parser panicked on the content; retrying the same content will
+    /// panic again
+    #[error("{what} parser {source}")]
+    Panicked {
+        /// What was being parsed, e.g. `email`
+        what: &'static str,
+        source: Panicked,
+    },
+    /// The broker could not be reached, or a connection, channel or queue
+    /// could not be set up
+    #[cfg(feature = "amqp")]
+    #[error("{context}: {source}")]
+    Connect {
+        context: String,
+        source: lapin::Error,
+    },
+    /// The broker did not answer within the publisher's connect timeout, or
+    /// a connect attempt failed moments ago
+    #[cfg(feature = "amqp")]
+    #[error("RabbitMQ unavailable: {0}")]
+    Unavailable(&'static str),
+    /// A message could not be published to a queue
+    #[cfg(feature = "amqp")]
+    #[error("failed to publish to {queue}: {source}")]
+    Publish { queue: String, source: PublishError },
+    /// A simulation fetch failed
+    #[error("simulation fetch failed: {0}")]
+    Simulation(#[from] FetchError),
+    /// The simulator's HTTP client or cassette could not be set up
+    #[error("failed to set up simulator: {0}")]
+    Setup(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Why a publish failed.
+#[cfg(feature = "amqp")]
+#[derive(Debug, thiserror::Error)]
+pub enum PublishError {
+    #[error("failed to serialize message: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error(transparent)]
+    Broker(#[from] lapin::Error),
+    #[error("dropped by chaos fault injection")]
+    ChaosDropped,
+    #[error("refused by the broker")]
+    Nacked,
+}
+
+impl BobNetError {
+    /// Parse error for `what`, e.g. `email`.
+    pub fn parse(
+        what: &'static str,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        BobNetError::Parse {
+            what,
+            source: Box::new(source),
+        }
+    }
+
+    /// Error for a parser that panicked on `what`, e.g. `email`.
+    pub fn panicked(what: &'static str, source: Panicked) -> Self {
+        BobNetError::Panicked { what, source }
+    }
+
+    /// Connection error, with what was being attempted.
+    #[cfg(feature = "amqp")]
+    pub fn connect(context: impl Into<String>, source: lapin::Error) -> Self {
+        BobNetError::Connect {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Simulator setup error.
+    pub fn setup(source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        BobNetError::Setup(Box::new(source))
+    }
+
+    /// Publish error for `queue`.
+    #[cfg(feature = "amqp")]
+    pub fn publish(queue: &str, source: impl Into<PublishError>) -> Self {
+        BobNetError::Publish {
+            queue: queue.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Whether retrying the same operation later may succeed.
+    ///
+    /// Malformed input never will; broker and network failures may.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            BobNetError::Parse { .. } | BobNetError::Panicked { .. } => false,
+            #[cfg(feature = "amqp")]
+            BobNetError::Connect { .. } | BobNetError::Unavailable(_) => true,
+            #[cfg(feature = "amqp")]
+            BobNetError::Publish { source, .. } => !matches!(source, PublishError::Serialize(_)),
+            BobNetError::Simulation(_) => true,
+            BobNetError::Setup(_) => false,
+        }
+    }
+
+    /// Whether the broker could not be reached at all, as opposed to a
+    /// single publish failing.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            #[cfg(feature = "amqp")]
+            BobNetError::Connect { .. } | BobNetError::Unavailable(_) => true,
+            #[cfg(feature = "amqp")]
+            BobNetError::Publish { .. } => false,
+            BobNetError::Parse { .. }
+            | BobNetError::Panicked { .. }
+            | BobNetError::Simulation(_)
+            | BobNetError::Setup(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_and_transience() {
+        let parse = BobNetError::parse("email", std::fmt::Error);
+        assert_eq!(
+            parse.to_string(),
+            "failed to parse email: an error occurred when formatting an argument"
+        );
+        assert!(!parse.is_transient());
+
+        let panicked = BobNetError::panicked("email", Panicked("bad header".to_string()));
+        assert_eq!(panicked.to_string(), "email parser panicked: bad header");
+        assert!(!panicked.is_transient());
+    }
+
+    #[cfg(feature = "amqp")]
+    #[test]
+    fn test_publish_transience() {
+        let dropped = BobNetError::publish("email_simulator", PublishError::ChaosDropped);
+        assert_eq!(
+            dropped.to_string(),
+            "failed to publish to email_simulator: dropped by chaos fault injection"
+        );
+        assert!(dropped.is_transient());
+
+        assert!(!dropped.is_unavailable());
+
+        let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert!(!BobNetError::publish("inbound_webhooks", json).is_transient());
+
+        let unavailable = BobNetError::Unavailable("connect timed out");
+        assert_eq!(
+            unavailable.to_string(),
+            "RabbitMQ unavailable: connect timed out"
+        );
+        assert!(unavailable.is_transient());
+        assert!(unavailable.is_unavailable());
+    }
+}
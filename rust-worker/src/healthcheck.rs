@@ -0,0 +1,83 @@
+//! `healthcheck` subcommand shared by the binaries.
+//!
+//! Docker `HEALTHCHECK` and ECS container health checks run a command inside
+//! the container, so each binary can probe itself without curl in the image:
+//! `bobnet-web healthcheck` exits 0 when healthy and 1 otherwise.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+#[cfg(feature = "amqp")]
+use crate::config::CoreConfig;
+#[cfg(feature = "amqp")]
+use crate::queue::BrokerUrls;
+
+/// How long a check may take before it counts as failed.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// URL of the health endpoint this container serves on `port`.
+pub fn local_url(port: u16, tls: bool) -> String {
+    let scheme = if tls { "https" } else { "http" };
+    format!("{}://127.0.0.1:{}/health", scheme, port)
+}
+
+/// Check that `url` answers with a success status.
+///
+/// Certificates aren't verified: the check targets the container's own
+/// listener over loopback, which its certificate won't name.
+pub async fn check_endpoint(url: &str) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .danger_accept_invalid_certs(true)
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("{} is unreachable", url))?;
+    if !response.status().is_success() {
+        anyhow::bail!("{} returned {}", url, response.status());
+    }
+    Ok(())
+}
+
+/// Check that a broker accepts a connection and opens a channel, trying the
+/// failover brokers when the primary is down.
+#[cfg(feature = "amqp")]
+pub async fn check_broker(core: &CoreConfig) -> Result<()> {
+    let check = async {
+        let (conn, _) = BrokerUrls::from_config(core)
+            .connect()
+            .await
+            .context("Failed to connect to RabbitMQ")?;
+        conn.create_channel()
+            .await
+            .context("Failed to create channel")?;
+        let _ = conn.close(200, "OK").await;
+        Ok::<_, anyhow::Error>(())
+    };
+
+    tokio::time::timeout(TIMEOUT, check)
+        .await
+        .context("Timed out connecting to RabbitMQ")?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_url() {
+        assert_eq!(local_url(8080, false), "http://127.0.0.1:8080/health");
+        assert_eq!(local_url(8443, true), "https://127.0.0.1:8443/health");
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoint_unreachable() {
+        // Port 1 is reserved and never listening
+        let err = check_endpoint("http://127.0.0.1:1/health").await.unwrap_err();
+        assert!(err.to_string().contains("unreachable"));
+    }
+}
@@ -1,13 +1,131 @@
 //! HTML parsing utilities for extracting images, links, and click rates.
+//!
+//! The worker parses each email once with [`HtmlSummary::parse_within`],
+//! which refuses bodies over a size cap and gives up on parses that run past
+//! a time budget, so hostile or broken content can't pin a worker CPU. A
+//! panic inside scraper ends only the job, as [`HtmlError::Panicked`]. The
+//! fuzz targets in `fuzz/` feed these extractors arbitrary input.
 
-use scraper::{Html, Selector};
+use std::time::Duration;
+
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
 use tracing::{debug, info, warn};
 
-use super::types::LinkWithRate;
+use super::types::{ContactKind, LinkWithRate, PixelHeuristic, TrackingPixel, WrappedImage};
+use crate::util::panic;
+use crate::util::url::{normalize, redact};
+
+/// Limits applied to email HTML before and during parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HtmlLimits {
+    /// Largest body parsed, in bytes (0 disables the cap)
+    pub max_bytes: usize,
+    /// Longest a parse may take before the job gives up on it (zero disables)
+    pub parse_budget: Duration,
+}
+
+/// Why email HTML was not analysed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HtmlError {
+    #[error("HTML is {size} bytes, over the {limit} byte limit")]
+    TooLarge { size: usize, limit: usize },
+    #[error("HTML parse took longer than {0:?}")]
+    ParseBudgetExceeded(Duration),
+    #[error("HTML parser panicked: {0}")]
+    Panicked(String),
+}
+
+impl HtmlError {
+    /// Stable name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HtmlError::TooLarge { .. } => "too_large",
+            HtmlError::ParseBudgetExceeded(_) => "parse_budget_exceeded",
+            HtmlError::Panicked(_) => "parse_panicked",
+        }
+    }
+}
+
+/// Everything the simulator reads from an email's HTML, from a single parse.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HtmlSummary {
+    /// Global open rate override (see [`find_global_open_rate`])
+    pub open_rate: Option<f64>,
+    /// Global click rate override (see [`find_global_click_rate`])
+    pub click_rate: Option<f64>,
+    /// Global open delay range override (see [`find_global_open_delay`])
+    pub open_delay_ms: Option<(u64, u64)>,
+    /// SFMC open pixel, if present (see [`find_sfmc_open_pixel`])
+    pub sfmc_open_pixel: Option<String>,
+    /// Every known ESP open pixel, in document order (see [`find_open_pixels`])
+    pub open_pixels: Vec<String>,
+    /// Absolute image sources
+    pub images: Vec<String>,
+    /// Images that are probably tracking pixels (see [`find_tracking_pixels`])
+    pub tracking_pixels: Vec<TrackingPixel>,
+    /// Deduplicated absolute links with their click rate overrides
+    pub links: Vec<LinkWithRate>,
+    /// Images inside links (see [`find_wrapped_images`])
+    pub wrapped_images: Vec<WrappedImage>,
+    /// Deduplicated `mailto:` and `tel:` links (see [`find_contact_links`])
+    pub contact_links: Vec<String>,
+}
+
+impl HtmlSummary {
+    /// Parse `html` once and extract everything from the same document.
+    pub fn parse(html: &str) -> Self {
+        let document = Html::parse_document(html);
+        let click_rate = global_rate_in(&document, "data-click-rate", "click");
+        Self {
+            open_rate: global_rate_in(&document, "data-open-rate", "open"),
+            click_rate,
+            open_delay_ms: global_delay_in(&document, "data-open-delay-ms"),
+            sfmc_open_pixel: sfmc_open_pixel_in(&document),
+            open_pixels: open_pixels_in(&document),
+            images: image_sources_in(&document),
+            tracking_pixels: tracking_pixels_in(&document),
+            links: links_with_rates_in(&document, click_rate),
+            wrapped_images: wrapped_images_in(&document),
+            contact_links: contact_links_in(&document),
+        }
+    }
+
+    /// Parse `html` on the blocking pool, enforcing `limits`.
+    ///
+    /// A parse that overruns its budget is abandoned rather than awaited; its
+    /// thread finishes in the background, bounded by the size cap. A parse
+    /// that panics is returned as [`HtmlError::Panicked`].
+    pub async fn parse_within(html: &str, limits: HtmlLimits) -> Result<Self, HtmlError> {
+        if limits.max_bytes > 0 && html.len() > limits.max_bytes {
+            return Err(HtmlError::TooLarge {
+                size: html.len(),
+                limit: limits.max_bytes,
+            });
+        }
+
+        let owned = html.to_string();
+        let task = tokio::task::spawn_blocking(move || Self::parse(&owned));
+        let joined = if limits.parse_budget.is_zero() {
+            task.await
+        } else {
+            tokio::time::timeout(limits.parse_budget, task)
+                .await
+                .map_err(|_| HtmlError::ParseBudgetExceeded(limits.parse_budget))?
+        };
+        joined.map_err(|e| match e.try_into_panic() {
+            Ok(payload) => HtmlError::Panicked(panic::message(payload.as_ref())),
+            Err(e) => HtmlError::Panicked(e.to_string()),
+        })
+    }
+}
 
 /// Extract all image source URLs from HTML.
 pub fn extract_image_sources(html: &str) -> Vec<String> {
-    let document = Html::parse_document(html);
+    image_sources_in(&Html::parse_document(html))
+}
+
+fn image_sources_in(document: &Html) -> Vec<String> {
     let selector = Selector::parse("img[src]").expect("Invalid selector");
 
     let urls: Vec<String> = document
@@ -21,7 +139,42 @@ pub fn extract_image_sources(html: &str) -> Vec<String> {
     urls
 }
 
-/// Extract all link URLs from HTML (deduplicated).
+/// Find images wrapped in links, e.g. `<a href="..."><img src="..."></a>`.
+///
+/// Each absolute image is paired with the `href` of its nearest enclosing
+/// `<a>`, when that is absolute too, in document order. The `href` is
+/// [normalized](normalize) like link URLs, so it matches the link's own.
+pub fn find_wrapped_images(html: &str) -> Vec<WrappedImage> {
+    wrapped_images_in(&Html::parse_document(html))
+}
+
+fn wrapped_images_in(document: &Html) -> Vec<WrappedImage> {
+    let selector = Selector::parse("img[src]").expect("Invalid selector");
+    let absolute = |url: &&str| url.starts_with("http://") || url.starts_with("https://");
+
+    let wrapped: Vec<WrappedImage> = document
+        .select(&selector)
+        .filter_map(|img| {
+            let image = img.value().attr("src").filter(absolute)?;
+            let link = img
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .find(|element| element.value().name() == "a")?
+                .value()
+                .attr("href")
+                .filter(absolute)?;
+            Some(WrappedImage {
+                image: image.to_string(),
+                link: normalize(link),
+            })
+        })
+        .collect();
+
+    debug!(count = wrapped.len(), "Extracted wrapped images");
+    wrapped
+}
+
+/// Extract all link URLs from HTML, normalized and deduplicated.
 #[allow(dead_code)] // Used in tests
 pub fn extract_links(html: &str) -> Vec<String> {
     let document = Html::parse_document(html);
@@ -32,8 +185,11 @@ pub fn extract_links(html: &str) -> Vec<String> {
 
     for a in document.select(&selector) {
         if let Some(href) = a.value().attr("href") {
-            if (href.starts_with("http://") || href.starts_with("https://")) && seen.insert(href.to_string()) {
-                urls.push(href.to_string());
+            if href.starts_with("http://") || href.starts_with("https://") {
+                let url = normalize(href);
+                if seen.insert(url.clone()) {
+                    urls.push(url);
+                }
             }
         }
     }
@@ -42,28 +198,92 @@ pub fn extract_links(html: &str) -> Vec<String> {
     urls
 }
 
+/// Find `mailto:` and `tel:` links, deduplicated, in document order.
+///
+/// Tapping one opens the reader's mail or phone app rather than a page, so
+/// they are never fetched.
+pub fn find_contact_links(html: &str) -> Vec<String> {
+    contact_links_in(&Html::parse_document(html))
+}
+
+fn contact_links_in(document: &Html) -> Vec<String> {
+    let selector = Selector::parse("a[href]").expect("Invalid selector");
+
+    let mut seen = std::collections::HashSet::new();
+    let mut links = Vec::new();
+    for a in document.select(&selector) {
+        let Some(href) = a.value().attr("href").map(str::trim) else {
+            continue;
+        };
+        if ContactKind::of(href).is_some() && seen.insert(href) {
+            links.push(href.to_string());
+        }
+    }
+    links
+}
+
+/// URL fragments of SFMC open pixels (Classic, then Advanced).
+const SFMC_OPEN_PIXEL_PATTERNS: &[&str] =
+    &["://cl.s4.exct.net/open.aspx", "tracking.e360.salesforce.com/open"];
+
+/// URL fragments of other known open pixels: Litmus Email Analytics.
+const OTHER_OPEN_PIXEL_PATTERNS: &[&str] = &[".emltrk.com/"];
+
+/// Whether `url` is a known ESP or email analytics open pixel.
+pub fn is_open_pixel(url: &str) -> bool {
+    let low = url.to_lowercase();
+    SFMC_OPEN_PIXEL_PATTERNS
+        .iter()
+        .chain(OTHER_OPEN_PIXEL_PATTERNS)
+        .any(|p| low.contains(p))
+}
+
+/// Find every known open pixel, in document order and deduplicated.
+///
+/// Emails that pass through several tracking layers carry one pixel per
+/// layer, e.g. SFMC's and a Litmus Email Analytics pixel (`*.emltrk.com`);
+/// each must be fetched for every layer to count the open.
+pub fn find_open_pixels(html: &str) -> Vec<String> {
+    open_pixels_in(&Html::parse_document(html))
+}
+
+fn open_pixels_in(document: &Html) -> Vec<String> {
+    let selector = Selector::parse("img[src]").expect("Invalid selector");
+
+    let mut pixels: Vec<String> = Vec::new();
+    for src in document.select(&selector).filter_map(|img| img.value().attr("src")) {
+        let src = src.trim();
+        if (src.starts_with("http://") || src.starts_with("https://"))
+            && is_open_pixel(src)
+            && !pixels.iter().any(|p| p == src)
+        {
+            pixels.push(src.to_string());
+        }
+    }
+    info!(open_pixels_found = pixels.len(), "Searched for open pixels");
+    pixels
+}
+
 /// Find Salesforce Marketing Cloud open pixel URL if present.
 ///
 /// Searches for an `<img>` whose src matches SFMC open pixel patterns:
 /// - ExactTarget/SFMC Classic: `://cl.s4.exct.net/open.aspx`
 /// - SFMC Advanced: `tracking.e360.salesforce.com/open`
 pub fn find_sfmc_open_pixel(html: &str) -> Option<String> {
-    let document = Html::parse_document(html);
+    sfmc_open_pixel_in(&Html::parse_document(html))
+}
+
+fn sfmc_open_pixel_in(document: &Html) -> Option<String> {
     let selector = Selector::parse("img[src]").expect("Invalid selector");
 
     let all_imgs: Vec<_> = document.select(&selector).collect();
-    
-    info!(
-        total_img_tags = all_imgs.len(),
-        html_length = html.len(),
-        "Searching for SFMC open pixel"
-    );
+
+    info!(total_img_tags = all_imgs.len(), "Searching for SFMC open pixel");
 
     for (idx, img) in all_imgs.iter().enumerate() {
         if let Some(src) = img.value().attr("src") {
             let low = src.to_lowercase();
-            let matches = low.contains("://cl.s4.exct.net/open.aspx")
-                || low.contains("tracking.e360.salesforce.com/open");
+            let matches = SFMC_OPEN_PIXEL_PATTERNS.iter().any(|p| low.contains(p));
 
             debug!(
                 img_index = idx,
@@ -73,7 +293,7 @@ pub fn find_sfmc_open_pixel(html: &str) -> Option<String> {
             );
 
             if matches {
-                info!(img_index = idx, url = src, "Found SFMC open pixel");
+                info!(img_index = idx, url = %redact(src), "Found SFMC open pixel");
                 return Some(src.to_string());
             }
         }
@@ -83,98 +303,171 @@ pub fn find_sfmc_open_pixel(html: &str) -> Option<String> {
     None
 }
 
+/// URL path fragments typical of open tracking endpoints.
+const TRACKING_PATH_FRAGMENTS: &[&str] = &["/open", "/o/", "/track"];
+
+/// Find images that are probably open tracking pixels, by heuristic:
+/// - a known ESP open pixel URL (as [`find_open_pixels`])
+/// - `width` and `height` of 1 or 0, as attributes or inline style
+/// - hidden with `display:none` or `visibility:hidden`
+/// - a URL path containing `/open`, `/o/` or `/track`
+///
+/// Each image is reported once, with the first heuristic it matched.
+pub fn find_tracking_pixels(html: &str) -> Vec<TrackingPixel> {
+    tracking_pixels_in(&Html::parse_document(html))
+}
+
+fn tracking_pixels_in(document: &Html) -> Vec<TrackingPixel> {
+    let selector = Selector::parse("img[src]").expect("Invalid selector");
+
+    let mut pixels: Vec<TrackingPixel> = Vec::new();
+    for img in document.select(&selector) {
+        let element = img.value();
+        let Some(src) = element
+            .attr("src")
+            .filter(|src| src.starts_with("http://") || src.starts_with("https://"))
+        else {
+            continue;
+        };
+        if pixels.iter().any(|p| p.url == src) {
+            continue;
+        }
+
+        let style: String = element
+            .attr("style")
+            .unwrap_or_default()
+            .to_lowercase()
+            .split_whitespace()
+            .collect();
+        let low = src.to_lowercase();
+        let path = url::Url::parse(&low)
+            .map(|url| url.path().to_string())
+            .unwrap_or_default();
+
+        let heuristic = if is_open_pixel(&low) {
+            Some(PixelHeuristic::EspPattern)
+        } else if is_tiny(element.attr("width"), &style, "width")
+            && is_tiny(element.attr("height"), &style, "height")
+        {
+            Some(PixelHeuristic::OneByOne)
+        } else if style.contains("display:none") || style.contains("visibility:hidden") {
+            Some(PixelHeuristic::Hidden)
+        } else if TRACKING_PATH_FRAGMENTS.iter().any(|f| path.contains(f)) {
+            Some(PixelHeuristic::TrackingPath)
+        } else {
+            None
+        };
+
+        if let Some(heuristic) = heuristic {
+            debug!(url = %redact(src), heuristic = heuristic.as_str(), "Found tracking pixel");
+            pixels.push(TrackingPixel {
+                url: src.to_string(),
+                heuristic,
+            });
+        }
+    }
+    pixels
+}
+
+/// Whether a dimension is at most one pixel, by its attribute or, failing
+/// that, the whitespace-free lowercase inline `style`.
+fn is_tiny(attribute: Option<&str>, style: &str, property: &str) -> bool {
+    let from_style = style
+        .split(';')
+        .find_map(|decl| decl.strip_prefix(property)?.strip_prefix(':'));
+    attribute
+        .or(from_style)
+        .map(|value| value.trim().trim_end_matches("px"))
+        .and_then(|value| value.parse::<f64>().ok())
+        .is_some_and(|value| value <= 1.0)
+}
+
 /// Find global open rate override from HTML.
 ///
 /// Searches for `<div data-scope="global" data-open-rate="...">` and returns
 /// the parsed float value (0.0-1.0).
 pub fn find_global_open_rate(html: &str) -> Option<f64> {
-    let document = Html::parse_document(html);
-    let selector = Selector::parse(r#"div[data-scope="global"]"#).expect("Invalid selector");
-
-    let global_divs: Vec<_> = document.select(&selector).collect();
+    global_rate_in(&Html::parse_document(html), "data-open-rate", "open")
+}
 
-    info!(
-        total_divs_with_scope_global = global_divs.len(),
-        html_length = html.len(),
-        "Searching for global open rate"
-    );
+/// Find global click rate override from HTML.
+///
+/// Searches for `<div data-scope="global" data-click-rate="...">` and returns
+/// the parsed float value (0.0-1.0).
+pub fn find_global_click_rate(html: &str) -> Option<f64> {
+    global_rate_in(&Html::parse_document(html), "data-click-rate", "click")
+}
 
-    for (idx, div) in global_divs.iter().enumerate() {
-        if let Some(rate_attr) = div.value().attr("data-open-rate") {
-            match rate_attr.parse::<f64>() {
-                Ok(rate) => {
-                    let clamped = rate.clamp(0.0, 1.0);
-                    
-                    if rate < 0.0 {
-                        warn!(div_index = idx, value = rate, clamped_to = 0.0, "Global open rate below zero");
-                    } else if rate > 1.0 {
-                        warn!(div_index = idx, value = rate, clamped_to = 1.0, "Global open rate above one");
-                    }
+/// Find global open delay override from HTML.
+///
+/// Searches for `<div data-scope="global" data-open-delay-ms="min,max">` and
+/// returns the range in milliseconds, put in order.
+pub fn find_global_open_delay(html: &str) -> Option<(u64, u64)> {
+    global_delay_in(&Html::parse_document(html), "data-open-delay-ms")
+}
 
-                    if idx > 0 {
-                        warn!(
-                            using_first = true,
-                            total_found = global_divs.len(),
-                            "Multiple global open rate divs found"
-                        );
-                    }
+/// Read the first valid `min,max` range `attribute` from
+/// `<div data-scope="global">` elements.
+fn global_delay_in(document: &Html, attribute: &str) -> Option<(u64, u64)> {
+    let selector = Selector::parse(r#"div[data-scope="global"]"#).expect("Invalid selector");
 
-                    info!(div_index = idx, value = clamped, raw_attribute = rate_attr, "Found global open rate");
-                    return Some(clamped);
-                }
-                Err(e) => {
-                    warn!(
-                        div_index = idx,
-                        raw_attribute = rate_attr,
-                        error = %e,
-                        "Invalid global open rate value"
-                    );
-                }
+    for (idx, div) in document.select(&selector).enumerate() {
+        let Some(raw) = div.value().attr(attribute) else {
+            continue;
+        };
+        let range = raw.split_once(',').and_then(|(min, max)| {
+            Some((min.trim().parse::<u64>().ok()?, max.trim().parse::<u64>().ok()?))
+        });
+        match range {
+            Some((min, max)) => {
+                let range = (min.min(max), min.max(max));
+                info!(div_index = idx, min_ms = range.0, max_ms = range.1, "Found global {}", attribute);
+                return Some(range);
+            }
+            None => {
+                warn!(div_index = idx, raw_attribute = raw, "Invalid global {} value", attribute);
             }
         }
     }
-
-    info!(total_divs_checked = global_divs.len(), "Global open rate not found");
     None
 }
 
-/// Find global click rate override from HTML.
+/// Read the first valid `attribute` from `<div data-scope="global">` elements.
 ///
-/// Searches for `<div data-scope="global" data-click-rate="...">` and returns
-/// the parsed float value (0.0-1.0).
-pub fn find_global_click_rate(html: &str) -> Option<f64> {
-    let document = Html::parse_document(html);
+/// `kind` ("open" or "click") labels the log messages.
+fn global_rate_in(document: &Html, attribute: &str, kind: &str) -> Option<f64> {
     let selector = Selector::parse(r#"div[data-scope="global"]"#).expect("Invalid selector");
 
     let global_divs: Vec<_> = document.select(&selector).collect();
 
     info!(
         total_divs_with_scope_global = global_divs.len(),
-        html_length = html.len(),
-        "Searching for global click rate"
+        "Searching for global {} rate",
+        kind
     );
 
     for (idx, div) in global_divs.iter().enumerate() {
-        if let Some(rate_attr) = div.value().attr("data-click-rate") {
+        if let Some(rate_attr) = div.value().attr(attribute) {
             match rate_attr.parse::<f64>() {
                 Ok(rate) => {
                     let clamped = rate.clamp(0.0, 1.0);
-                    
+
                     if rate < 0.0 {
-                        warn!(div_index = idx, value = rate, clamped_to = 0.0, "Global click rate below zero");
+                        warn!(div_index = idx, value = rate, clamped_to = 0.0, "Global {} rate below zero", kind);
                     } else if rate > 1.0 {
-                        warn!(div_index = idx, value = rate, clamped_to = 1.0, "Global click rate above one");
+                        warn!(div_index = idx, value = rate, clamped_to = 1.0, "Global {} rate above one", kind);
                     }
 
                     if idx > 0 {
                         warn!(
                             using_first = true,
                             total_found = global_divs.len(),
-                            "Multiple global click rate divs found"
+                            "Multiple global {} rate divs found",
+                            kind
                         );
                     }
 
-                    info!(div_index = idx, value = clamped, raw_attribute = rate_attr, "Found global click rate");
+                    info!(div_index = idx, value = clamped, raw_attribute = rate_attr, "Found global {} rate", kind);
                     return Some(clamped);
                 }
                 Err(e) => {
@@ -182,23 +475,29 @@ pub fn find_global_click_rate(html: &str) -> Option<f64> {
                         div_index = idx,
                         raw_attribute = rate_attr,
                         error = %e,
-                        "Invalid global click rate value"
+                        "Invalid global {} rate value",
+                        kind
                     );
                 }
             }
         }
     }
 
-    info!(total_divs_checked = global_divs.len(), "Global click rate not found");
+    info!(total_divs_checked = global_divs.len(), "Global {} rate not found", kind);
     None
 }
 
 /// Extract links with their individual click rates.
 ///
 /// Finds all `<a>` tags with http/https URLs and extracts their `data-click-rate`
-/// attributes if present.
+/// attributes if present. URLs are [normalized](normalize) before they are
+/// deduplicated, so links differing only by host case, a default port or a
+/// fragment count once, with the first one's rate.
 pub fn extract_links_with_rates(html: &str, global_rate: Option<f64>) -> Vec<LinkWithRate> {
-    let document = Html::parse_document(html);
+    links_with_rates_in(&Html::parse_document(html), global_rate)
+}
+
+fn links_with_rates_in(document: &Html, global_rate: Option<f64>) -> Vec<LinkWithRate> {
     let selector = Selector::parse("a[href]").expect("Invalid selector");
 
     let mut seen = std::collections::HashSet::new();
@@ -206,12 +505,12 @@ pub fn extract_links_with_rates(html: &str, global_rate: Option<f64>) -> Vec<Lin
 
     for a in document.select(&selector) {
         let href = match a.value().attr("href") {
-            Some(h) if h.starts_with("http://") || h.starts_with("https://") => h,
+            Some(h) if h.starts_with("http://") || h.starts_with("https://") => normalize(h),
             _ => continue,
         };
 
         // Deduplicate
-        if !seen.insert(href.to_string()) {
+        if !seen.insert(href.clone()) {
             continue;
         }
 
@@ -220,15 +519,15 @@ pub fn extract_links_with_rates(html: &str, global_rate: Option<f64>) -> Vec<Lin
                 Ok(rate) => {
                     let clamped = rate.clamp(0.0, 1.0);
                     if rate < 0.0 {
-                        warn!(url = &href[..href.len().min(100)], value = rate, clamped_to = 0.0, "Link click rate below zero");
+                        warn!(url = %redact(&href), value = rate, clamped_to = 0.0, "Link click rate below zero");
                     } else if rate > 1.0 {
-                        warn!(url = &href[..href.len().min(100)], value = rate, clamped_to = 1.0, "Link click rate above one");
+                        warn!(url = %redact(&href), value = rate, clamped_to = 1.0, "Link click rate above one");
                     }
                     Some(clamped)
                 }
                 Err(e) => {
                     warn!(
-                        url = &href[..href.len().min(100)],
+                        url = %redact(&href),
                         raw_attribute = attr,
                         error = %e,
                         "Invalid link click rate value"
@@ -239,7 +538,7 @@ pub fn extract_links_with_rates(html: &str, global_rate: Option<f64>) -> Vec<Lin
         });
 
         links.push(LinkWithRate {
-            url: href.to_string(),
+            url: href,
             click_rate,
         });
     }
@@ -323,6 +622,28 @@ mod tests {
         assert!(pixel.unwrap().contains("tracking.e360.salesforce.com/open"));
     }
 
+    #[test]
+    fn test_find_all_open_pixels() {
+        let html = r#"
+            <html>
+                <img src="https://cdn.example.com/logo.png">
+                <img src="https://cl.s4.exct.net/open.aspx?ffcb10-fe">
+                <img src="https://a1b2c3.emltrk.com/a1b2c3?d=user@example.com">
+                <img src="https://tracking.e360.salesforce.com/open?j=1">
+                <img src="https://cl.s4.exct.net/open.aspx?ffcb10-fe">
+            </html>
+        "#;
+        assert_eq!(
+            find_open_pixels(html),
+            vec![
+                "https://cl.s4.exct.net/open.aspx?ffcb10-fe",
+                "https://a1b2c3.emltrk.com/a1b2c3?d=user@example.com",
+                "https://tracking.e360.salesforce.com/open?j=1",
+            ]
+        );
+        assert!(find_open_pixels(r#"<img src="https://cdn.example.com/logo.png">"#).is_empty());
+    }
+
     #[test]
     fn test_find_sfmc_open_pixel_not_found() {
         let html = r#"
@@ -399,4 +720,169 @@ mod tests {
         assert_eq!(links[1].click_rate, Some(0.2));
         assert_eq!(links[2].click_rate, None);
     }
+
+    #[test]
+    fn test_find_contact_links() {
+        let html = r#"
+            <html>
+                <a href="mailto:support@example.com?subject=Help">Email us</a>
+                <a href=" TEL:+1-555-0100 ">Call us</a>
+                <a href="mailto:support@example.com?subject=Help">Email us again</a>
+                <a href="https://example.com/contact">Contact</a>
+                <a href="sms:+15550100">Text us</a>
+            </html>
+        "#;
+
+        assert_eq!(
+            find_contact_links(html),
+            vec!["mailto:support@example.com?subject=Help", "TEL:+1-555-0100"]
+        );
+        assert_eq!(HtmlSummary::parse(html).links.len(), 1);
+    }
+
+    #[test]
+    fn test_extract_links_normalizes_before_dedup() {
+        let html = r#"
+            <html>
+                <a href="https://Shop.Example.com/sale#hero" data-click-rate="0.8">Sale</a>
+                <a href="https://shop.example.com:443/sale#footer">Sale again</a>
+                <a href="https://shop.example.com/sale?page=2">Next page</a>
+            </html>
+        "#;
+
+        let links = extract_links_with_rates(html, None);
+        let urls: Vec<&str> = links.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec!["https://shop.example.com/sale", "https://shop.example.com/sale?page=2"]
+        );
+        assert_eq!(links[0].click_rate, Some(0.8));
+        assert_eq!(extract_links(html), urls);
+    }
+
+    #[test]
+    fn test_find_global_open_delay() {
+        let html = r#"
+            <html>
+                <div data-scope="global" data-open-delay-ms="10,oops"></div>
+                <div data-scope="global" data-open-delay-ms=" 9000, 2000 "></div>
+            </html>
+        "#;
+        assert_eq!(find_global_open_delay(html), Some((2000, 9000)));
+        assert_eq!(
+            find_global_open_delay(r#"<div data-scope="global" data-open-rate="0.5"></div>"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_wrapped_images() {
+        let html = r#"
+            <html>
+                <img src="https://cdn.example.com/logo.png">
+                <a href="https://shop.example.com/sale">
+                    <table><tr><td><img src="https://cdn.example.com/hero.png"></td></tr></table>
+                </a>
+                <a href="/relative"><img src="https://cdn.example.com/icon.png"></a>
+            </html>
+        "#;
+        assert_eq!(
+            find_wrapped_images(html),
+            vec![WrappedImage {
+                image: "https://cdn.example.com/hero.png".to_string(),
+                link: "https://shop.example.com/sale".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_wrapped_image_links_match_normalized_links() {
+        let html = r#"
+            <html>
+                <a href="https://Shop.Example.COM:443/sale#hero"><img src="https://cdn.example.com/hero.png"></a>
+            </html>
+        "#;
+        let wrapped = find_wrapped_images(html);
+        assert_eq!(wrapped[0].link, "https://shop.example.com/sale");
+        assert_eq!(extract_links(html), vec![wrapped[0].link.clone()]);
+    }
+
+    #[test]
+    fn test_summary_matches_individual_extractors() {
+        let html = r#"
+            <html>
+                <div data-scope="global" data-open-rate="0.9" data-click-rate="0.4" data-open-delay-ms="0,100"></div>
+                <img src="https://cl.s4.exct.net/open.aspx?ffcb10-fe">
+                <img src="https://example.com/logo.png">
+                <a href="https://example.com/page1" data-click-rate="0.8"><img src="https://example.com/banner.png"></a>
+            </html>
+        "#;
+
+        let summary = HtmlSummary::parse(html);
+        assert_eq!(summary.open_rate, find_global_open_rate(html));
+        assert_eq!(summary.click_rate, find_global_click_rate(html));
+        assert_eq!(summary.open_delay_ms, find_global_open_delay(html));
+        assert_eq!(summary.sfmc_open_pixel, find_sfmc_open_pixel(html));
+        assert_eq!(summary.open_pixels, find_open_pixels(html));
+        assert_eq!(summary.images, extract_image_sources(html));
+        assert_eq!(summary.tracking_pixels, find_tracking_pixels(html));
+        assert_eq!(summary.links, extract_links_with_rates(html, Some(0.4)));
+        assert_eq!(summary.wrapped_images, find_wrapped_images(html));
+    }
+
+    #[test]
+    fn test_find_tracking_pixels_by_heuristic() {
+        let html = r#"
+            <html>
+                <img src="https://cdn.example.com/hero.png" width="600" height="300">
+                <img src="https://cl.s4.exct.net/open.aspx?ffcb10-fe" width="1" height="1">
+                <img src="https://esp.example.com/p.gif" width="1" height="1px">
+                <img src="https://esp.example.com/q.gif" style="WIDTH: 0; height:0px">
+                <img src="https://esp.example.com/r.gif" style="display: none">
+                <img src="https://esp.example.com/o/abc123.gif">
+                <img src="https://esp.example.com/track?u=1">
+                <img src="https://esp.example.com/p.gif" width="1" height="1">
+                <img src="https://open.example.com/logo.png" width="1">
+            </html>
+        "#;
+
+        let heuristics: Vec<_> = find_tracking_pixels(html)
+            .into_iter()
+            .map(|p| (p.url.rsplit('/').next().unwrap().to_string(), p.heuristic))
+            .collect();
+        assert_eq!(
+            heuristics,
+            vec![
+                ("open.aspx?ffcb10-fe".to_string(), PixelHeuristic::EspPattern),
+                ("p.gif".to_string(), PixelHeuristic::OneByOne),
+                ("q.gif".to_string(), PixelHeuristic::OneByOne),
+                ("r.gif".to_string(), PixelHeuristic::Hidden),
+                ("abc123.gif".to_string(), PixelHeuristic::TrackingPath),
+                ("track?u=1".to_string(), PixelHeuristic::TrackingPath),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_within_limits() {
+        let html = r#"<a href="https://example.com/">Link</a>"#;
+        let limits = HtmlLimits {
+            max_bytes: 1024,
+            parse_budget: Duration::from_secs(5),
+        };
+        let summary = HtmlSummary::parse_within(html, limits).await.unwrap();
+        assert_eq!(summary.links.len(), 1);
+
+        let tight = HtmlLimits { max_bytes: 10, ..limits };
+        assert_eq!(
+            HtmlSummary::parse_within(html, tight).await,
+            Err(HtmlError::TooLarge { size: html.len(), limit: 10 })
+        );
+
+        let unlimited = HtmlLimits {
+            max_bytes: 0,
+            parse_budget: Duration::ZERO,
+        };
+        assert!(HtmlSummary::parse_within(html, unlimited).await.is_ok());
+    }
 }
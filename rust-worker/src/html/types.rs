@@ -1,7 +1,9 @@
 //! Type definitions for HTML parsing.
 
+use serde::Serialize;
+
 /// Represents a link URL with an optional per-link click rate.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct LinkWithRate {
     /// The URL of the link
     pub url: String,
@@ -25,3 +27,75 @@ impl LinkWithRate {
         }
     }
 }
+
+/// Why an image was taken for an open tracking pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelHeuristic {
+    /// The URL matches a known ESP open pixel (see
+    /// [`find_open_pixels`](super::find_open_pixels))
+    EspPattern,
+    /// The image is 1x1 (or 0x0) by its `width`/`height` attributes or style
+    OneByOne,
+    /// The image is hidden with `display:none` or `visibility:hidden`
+    Hidden,
+    /// The URL path contains a tracking fragment such as `/open`, `/o/` or
+    /// `/track`
+    TrackingPath,
+}
+
+impl PixelHeuristic {
+    /// Stable name used in logs and reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PixelHeuristic::EspPattern => "esp_pattern",
+            PixelHeuristic::OneByOne => "one_by_one",
+            PixelHeuristic::Hidden => "hidden",
+            PixelHeuristic::TrackingPath => "tracking_path",
+        }
+    }
+}
+
+/// An image that is probably an open tracking pixel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrackingPixel {
+    /// Image source
+    pub url: String,
+    /// First heuristic the image matched
+    pub heuristic: PixelHeuristic,
+}
+
+/// An image inside a link, so the same creative can be both opened and
+/// clicked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WrappedImage {
+    /// Image source
+    pub image: String,
+    /// `href` of the nearest enclosing `<a>`, normalized
+    pub link: String,
+}
+
+/// Kind of a link that opens an app on the reader's device instead of a
+/// page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactKind {
+    /// `mailto:` link, opening a new email
+    Mailto,
+    /// `tel:` link, dialing a number
+    Tel,
+}
+
+impl ContactKind {
+    /// Kind of `href`, if it is a contact link (schemes are case-insensitive).
+    pub fn of(href: &str) -> Option<Self> {
+        let (scheme, _) = href.trim().split_once(':')?;
+        if scheme.eq_ignore_ascii_case("mailto") {
+            Some(ContactKind::Mailto)
+        } else if scheme.eq_ignore_ascii_case("tel") {
+            Some(ContactKind::Tel)
+        } else {
+            None
+        }
+    }
+}
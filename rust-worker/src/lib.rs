@@ -1,29 +1,80 @@
 //! BobNet - High-performance email simulation system.
 //!
-//! This library provides shared modules for the three BobNet binaries:
+//! This library provides shared modules for the BobNet binaries:
 //! - `bobnet-web`: Thin web server for receiving webhooks
 //! - `bobnet-processor`: Processor for parsing and preparing jobs
 //! - `bobnet-worker`: Email simulator for opens and clicks
+//! - `bobnet-cli`: Queue inspection and test publishing
 //!
 //! ## Architecture
 //!
 //! ```text
 //! Webhooks → Web Server → inbound_webhooks → Processor → email_simulator → Worker
 //! ```
+//!
+//! ## Features
+//!
+//! Each component sits behind a Cargo feature, all enabled by default. With
+//! `default-features = false` the library is just the HTML analysis and
+//! simulation engine ([`Simulator`], [`Pipeline`]), without axum or lapin.
+//!
+//! - `amqp`: RabbitMQ publisher, message signing and broker checks (lapin)
+//! - `web`: webhook receiver (`web`) and `bobnet-web`
+//! - `processor`: webhook processing (`process`) and `bobnet-processor`
+//! - `worker`: `bobnet-worker`
+//! - `cli`: flags shared by the binaries (`cli`) and `bobnet-cli`
+//! - `http`: API-key middleware (`auth::require_scope`) for the web server
+//!   and the worker sidecar
+//!
+//! The tenant profile, campaign override and content stores are opt-in:
+//!
+//! - `redis`: profiles, campaign overrides and stored HTML in Redis
+//!   (`tenant::redis`, `campaign::redis`, `content::redis`)
+//! - `postgres`: profiles and campaign overrides from Postgres
+//!   (`tenant::postgres`, `campaign::postgres`, sqlx)
 
+pub mod auth;
+pub mod campaign;
+pub mod chaos;
+#[cfg(feature = "cli")]
+pub mod cli;
 pub mod config;
+pub mod content;
+#[cfg(feature = "amqp")]
+pub mod doctor;
+pub mod error;
+pub mod healthcheck;
 pub mod html;
+pub mod metrics;
+pub mod pipeline;
+#[cfg(feature = "processor")]
 pub mod process;
 pub mod queue;
 pub mod simulate;
+pub mod stats;
+pub mod tenant;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
 pub mod util;
+#[cfg(feature = "web")]
 pub mod web;
 
 // Re-export commonly used types
-pub use config::Config;
+pub use config::{
+    CoreConfig, ProcessorConfig, WebConfig, WorkerConfig, WorkerConfigBuilder,
+};
+pub use error::BobNetError;
+pub use pipeline::{Decision, Hook, Pipeline};
+#[cfg(feature = "processor")]
 pub use process::{process_webhook, ParsedEmail};
+#[cfg(feature = "amqp")]
+pub use queue::Publisher;
 pub use queue::{
-    CloudflareRawPayload, InboundWebhook, MailgunRawPayload, Publisher, SimulatorJob,
-    INBOUND_QUEUE, SIMULATOR_QUEUE,
+    CloudflareRawPayload, GenericRawPayload, InboundWebhook, JobOverrides, MailgunRawPayload,
+    SimulatorJob, INBOUND_QUEUE, SIMULATOR_QUEUE, SIMULATOR_QUEUES,
 };
+pub use simulate::events::SimulationEvents;
+pub use simulate::report::SimulationReport;
+pub use simulate::simulator::Simulator;
+#[cfg(feature = "web")]
 pub use web::AppState;
@@ -1,43 +1,147 @@
 //! BobNet Worker - High-performance async RabbitMQ consumer for email simulation.
 //!
-//! This worker processes email simulation jobs from the email_simulator queue,
+//! This worker processes email simulation jobs from the email_simulator queue
+//! (or the queues listed in `WORKER_QUEUES`),
 //! simulating email opens (fetching tracking pixels) and clicks (following links)
 //! with configurable probabilities and delays.
 
 mod consumer;
-mod processor;
+mod ordering;
+mod reload;
+mod sidecar;
 
 use anyhow::Result;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use clap::Parser;
+use tracing::Instrument;
 
-use bobnet::Config;
+use bobnet::cli::{CommonArgs, Overrides, ServiceCommand, SimulationArgs};
+use bobnet::simulate::calibrate;
+use bobnet::{doctor, healthcheck};
+use bobnet::util::instance::instance_id;
+use bobnet::util::{logging, redact};
+use bobnet::WorkerConfig;
+
+/// BobNet email simulation worker.
+///
+/// Flags override the matching environment variables.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+
+    #[command(flatten)]
+    simulation: SimulationArgs,
+
+    /// Comma-separated simulator queues to consume, e.g. a tier's queue
+    /// [overrides WORKER_QUEUES]
+    #[arg(long, value_name = "QUEUES")]
+    queues: Option<String>,
+
+    /// Print the effective configuration (secrets redacted) and exit
+    #[arg(long)]
+    print_config: bool,
+
+    /// Simulate N synthetic jobs without HTTP or delays, compare the
+    /// realized open, click and link selection rates with the configured
+    /// ones and exit
+    #[arg(long, value_name = "N")]
+    calibrate: Option<usize>,
+
+    #[command(subcommand)]
+    command: Option<ServiceCommand>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize structured JSON logging
-    let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("info"));
+    let cli = Cli::parse();
+
+    // Initialize structured logging (quietly for one-shot subcommands)
+    let one_shot = cli.command.is_some() || cli.print_config || cli.calibrate.is_some();
+    logging::init_with_default(cli.common.log_format, if one_shot { "warn" } else { "info" });
+
+    // Every log line carries this replica's ID via the root span
+    let span = tracing::info_span!("worker", instance = instance_id());
+
+    let result = match cli.command {
+        Some(ServiceCommand::Healthcheck) => check_health(&cli).await,
+        Some(ServiceCommand::Doctor) => diagnose(&cli).await,
+        None if cli.print_config => print_config(&cli).await,
+        None if cli.calibrate.is_some() => run_calibration(&cli).await,
+        None => run(cli).instrument(span).await,
+    };
+
+    // Errors returned from main bypass the log writer, so mask them here
+    result.map_err(redact::redact_error)
+}
 
-    tracing_subscriber::registry()
-        .with(filter)
-        .with(fmt::layer().json().flatten_event(true))
-        .init();
+/// Flags given on the command line, layered over the environment.
+fn overrides(cli: &Cli) -> Overrides {
+    let mut overrides = Overrides::new();
+    cli.common.apply(&mut overrides);
+    cli.simulation.apply(&mut overrides);
+    overrides.set("WORKER_QUEUES", cli.queues.as_ref());
+    overrides
+}
+
+/// Probe the sidecar's `/health` endpoint if it is enabled, otherwise the
+/// broker connection.
+async fn check_health(cli: &Cli) -> Result<()> {
+    let config = overrides(cli).load(WorkerConfig::from_vars_checked)?;
+    match config.worker_metrics_port {
+        Some(port) => healthcheck::check_endpoint(&healthcheck::local_url(port, false)).await?,
+        None => healthcheck::check_broker(&config.core).await?,
+    }
+    println!("healthy");
+    Ok(())
+}
+
+/// Run the doctor checks for the worker.
+async fn diagnose(cli: &Cli) -> Result<()> {
+    let config = overrides(cli).load(WorkerConfig::from_vars_checked)?;
+    let report = doctor::worker(&config).await;
+    print!("{}", report);
+    report.into_result()
+}
+
+/// Print the configuration the worker would start with, including
+/// `CONFIG_FILE` and remote parameters.
+async fn print_config(cli: &Cli) -> Result<()> {
+    let (config, _) = reload::initial(overrides(cli)).await?;
+    println!("{}", doctor::effective_config(&config));
+    Ok(())
+}
+
+/// Run the calibration self-test with the configuration the worker would
+/// start with, failing if any realized rate is off its target.
+async fn run_calibration(cli: &Cli) -> Result<()> {
+    let (config, _) = reload::initial(overrides(cli)).await?;
+    let calibration = calibrate::calibrate(&config, cli.calibrate.unwrap_or_default()).await?;
+    print!("{}", calibration);
+    if !calibration.passed() {
+        anyhow::bail!("Realized rates are off their targets");
+    }
+    Ok(())
+}
 
+/// Load configuration and run the consumer until shutdown.
+async fn run(cli: Cli) -> Result<()> {
     tracing::info!("worker_starting");
 
-    // Load configuration from environment
-    let config = Config::from_env();
+    // Load configuration from flags, environment and CONFIG_FILE (if set)
+    let (config, sources) = reload::initial(overrides(&cli)).await?;
     tracing::info!(
-        cloudamqp_url_set = !config.cloudamqp_url.is_empty(),
+        cloudamqp_url_set = !config.core.cloudamqp_url.is_empty(),
         open_probability = config.simulate_open_probability,
         click_probability = config.simulate_click_probability,
         max_clicks = config.max_clicks,
         concurrency = config.worker_concurrency,
+        queues = ?config.worker_queues,
         "config_loaded"
     );
 
     // Start the consumer
-    consumer::run(config).await?;
+    consumer::run(config, sources).await?;
 
     Ok(())
 }
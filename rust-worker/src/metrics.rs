@@ -0,0 +1,339 @@
+//! Lightweight in-process metrics.
+//!
+//! Counters, gauges and histograms are plain atomics registered under a name
+//! and help string, and rendered on demand in the Prometheus text exposition
+//! format.
+//! This keeps the binaries free of a metrics dependency while still being
+//! scrapeable by Prometheus, KEDA and friends.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Monotonically increasing counter.
+#[derive(Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Increment by one.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Increment by `n`.
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Current value.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Gauge that can go up and down.
+#[derive(Clone, Default)]
+pub struct Gauge(Arc<AtomicI64>);
+
+impl Gauge {
+    /// Set to an absolute value.
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    /// Increment by one.
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Decrement by one.
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Current value.
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Increment now and decrement when the returned guard is dropped.
+    pub fn track(&self) -> GaugeGuard {
+        self.inc();
+        GaugeGuard(self.clone())
+    }
+}
+
+/// Decrements its gauge on drop; see [`Gauge::track`].
+pub struct GaugeGuard(Gauge);
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Distribution of observations over fixed buckets.
+#[derive(Clone)]
+pub struct Histogram(Arc<HistogramInner>);
+
+struct HistogramInner {
+    /// Upper bound of each bucket, ascending
+    bounds: &'static [f64],
+    /// Observations in each bucket (not cumulative); the last one is `+Inf`
+    buckets: Vec<AtomicU64>,
+    /// Sum of all observations, as `f64` bits
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self(Arc::new(HistogramInner {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        }))
+    }
+
+    /// Record one observation.
+    pub fn observe(&self, value: f64) {
+        let bucket = self
+            .0
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.0.bounds.len());
+        self.0.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .0
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+
+    /// Number of observations.
+    pub fn count(&self) -> u64 {
+        self.0
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Sum of all observations.
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.0.sum.load(Ordering::Relaxed))
+    }
+}
+
+enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+struct Entry {
+    name: &'static str,
+    help: &'static str,
+    metric: Metric,
+}
+
+/// A set of named metrics that can be rendered together.
+#[derive(Clone, Default)]
+pub struct Registry {
+    entries: Arc<Mutex<Vec<Entry>>>,
+    labels: String,
+}
+
+impl Registry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty registry whose samples all carry the given labels,
+    /// e.g. `[("instance", "worker-1")]`.
+    pub fn with_labels(labels: &[(&str, &str)]) -> Self {
+        let rendered: Vec<String> = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+            .collect();
+
+        Self {
+            labels: if rendered.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", rendered.join(","))
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Register and return a new counter.
+    pub fn counter(&self, name: &'static str, help: &'static str) -> Counter {
+        let counter = Counter::default();
+        self.push(name, help, Metric::Counter(counter.clone()));
+        counter
+    }
+
+    /// Register and return a new gauge.
+    pub fn gauge(&self, name: &'static str, help: &'static str) -> Gauge {
+        let gauge = Gauge::default();
+        self.push(name, help, Metric::Gauge(gauge.clone()));
+        gauge
+    }
+
+    /// Register and return a new histogram with buckets up to each of
+    /// `bounds` (ascending), plus `+Inf`.
+    pub fn histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        bounds: &'static [f64],
+    ) -> Histogram {
+        let histogram = Histogram::new(bounds);
+        self.push(name, help, Metric::Histogram(histogram.clone()));
+        histogram
+    }
+
+    fn push(&self, name: &'static str, help: &'static str, metric: Metric) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push(Entry { name, help, metric });
+    }
+
+    /// Render all registered metrics in Prometheus text format.
+    pub fn render(&self) -> String {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        for entry in entries.iter() {
+            let kind = match &entry.metric {
+                Metric::Counter(_) => "counter",
+                Metric::Gauge(_) => "gauge",
+                Metric::Histogram(_) => "histogram",
+            };
+            let _ = writeln!(out, "# HELP {} {}", entry.name, entry.help);
+            let _ = writeln!(out, "# TYPE {} {}", entry.name, kind);
+            match &entry.metric {
+                Metric::Counter(c) => {
+                    let _ = writeln!(out, "{}{} {}", entry.name, self.labels, c.get());
+                }
+                Metric::Gauge(g) => {
+                    let _ = writeln!(out, "{}{} {}", entry.name, self.labels, g.get());
+                }
+                Metric::Histogram(h) => self.render_histogram(&mut out, entry.name, h),
+            }
+        }
+
+        out
+    }
+
+    /// Render a histogram's cumulative buckets, sum and count.
+    fn render_histogram(&self, out: &mut String, name: &str, histogram: &Histogram) {
+        let bounds = histogram.0.bounds.iter().map(|b| b.to_string());
+        let mut cumulative = 0;
+        for (le, bucket) in bounds
+            .chain(std::iter::once("+Inf".to_string()))
+            .zip(&histogram.0.buckets)
+        {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let labels = match self.labels.strip_suffix('}') {
+                Some(labels) => format!("{},le=\"{}\"}}", labels, le),
+                None => format!("{{le=\"{}\"}}", le),
+            };
+            let _ = writeln!(out, "{}_bucket{} {}", name, labels, cumulative);
+        }
+        let _ = writeln!(out, "{}_sum{} {}", name, self.labels, histogram.sum());
+        let _ = writeln!(out, "{}_count{} {}", name, self.labels, cumulative);
+    }
+}
+
+/// Escape a label value per the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_and_gauge() {
+        let registry = Registry::new();
+        let counter = registry.counter("test_total", "A counter");
+        let gauge = registry.gauge("test_gauge", "A gauge");
+
+        counter.inc();
+        counter.inc_by(2);
+        gauge.set(10);
+        gauge.dec();
+
+        assert_eq!(counter.get(), 3);
+        assert_eq!(gauge.get(), 9);
+    }
+
+    #[test]
+    fn test_gauge_track_guard() {
+        let gauge = Gauge::default();
+        {
+            let _a = gauge.track();
+            let _b = gauge.track();
+            assert_eq!(gauge.get(), 2);
+        }
+        assert_eq!(gauge.get(), 0);
+    }
+
+    #[test]
+    fn test_render_prometheus_format() {
+        let registry = Registry::new();
+        registry.counter("jobs_total", "Jobs processed").inc();
+        registry.gauge("in_flight", "Jobs in flight").set(4);
+
+        let text = registry.render();
+        assert!(text.contains("# HELP jobs_total Jobs processed\n"));
+        assert!(text.contains("# TYPE jobs_total counter\n"));
+        assert!(text.contains("jobs_total 1\n"));
+        assert!(text.contains("# TYPE in_flight gauge\n"));
+        assert!(text.contains("in_flight 4\n"));
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let registry = Registry::with_labels(&[("instance", "worker-1")]);
+        let histogram = registry.histogram("latency_seconds", "Latency", &[0.5, 5.0]);
+        histogram.observe(0.25);
+        histogram.observe(0.5);
+        histogram.observe(2.0);
+        histogram.observe(60.0);
+
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.sum(), 62.75);
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE latency_seconds histogram\n"));
+        assert!(text.contains("latency_seconds_bucket{instance=\"worker-1\",le=\"0.5\"} 2\n"));
+        assert!(text.contains("latency_seconds_bucket{instance=\"worker-1\",le=\"5\"} 3\n"));
+        assert!(text.contains("latency_seconds_bucket{instance=\"worker-1\",le=\"+Inf\"} 4\n"));
+        assert!(text.contains("latency_seconds_sum{instance=\"worker-1\"} 62.75\n"));
+        assert!(text.contains("latency_seconds_count{instance=\"worker-1\"} 4\n"));
+
+        let registry = Registry::new();
+        let histogram = registry.histogram("latency_seconds", "Latency", &[1.0]);
+        histogram.observe(0.5);
+        let text = registry.render();
+        assert!(text.contains("latency_seconds_bucket{le=\"1\"} 1\n"));
+    }
+
+    #[test]
+    fn test_render_with_labels() {
+        let registry = Registry::with_labels(&[("instance", "web.1-\"a\"")]);
+        registry.counter("jobs_total", "Jobs processed").inc();
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE jobs_total counter\n"));
+        assert!(text.contains("jobs_total{instance=\"web.1-\\\"a\\\"\"} 1\n"));
+    }
+}
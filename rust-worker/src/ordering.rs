@@ -0,0 +1,99 @@
+//! Per-recipient ordering via consistent-hash sharding.
+//!
+//! Jobs are hashed by recipient onto a fixed number of shards, each drained
+//! serially by its own task, so a given subscriber's engagement timeline is
+//! never interleaved or reordered while unrelated recipients still run in
+//! parallel.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Sending half of a set of per-shard serial queues.
+pub struct ShardedSender<T> {
+    senders: Vec<UnboundedSender<T>>,
+}
+
+impl<T> ShardedSender<T> {
+    /// Route an item to the shard owning `key`.
+    ///
+    /// Returns the item back if that shard's worker has stopped.
+    pub fn send(&self, key: &str, item: T) -> Result<(), T> {
+        let index = shard_index(key, self.senders.len());
+        self.senders[index].send(item).map_err(|e| e.0)
+    }
+}
+
+/// Create `count` serial queues (at least one) keyed by recipient.
+pub fn sharded_channels<T>(count: usize) -> (ShardedSender<T>, Vec<UnboundedReceiver<T>>) {
+    let (senders, receivers) = (0..count.max(1)).map(|_| mpsc::unbounded_channel()).unzip();
+    (ShardedSender { senders }, receivers)
+}
+
+/// Map a recipient address to a shard using jump consistent hashing.
+///
+/// Addresses are compared case-insensitively so `User@Example.com` and
+/// `user@example.com` share a shard.
+pub fn shard_index(key: &str, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.trim().to_lowercase().hash(&mut hasher);
+    jump_hash(hasher.finish(), shards.max(1))
+}
+
+/// Jump consistent hash (Lamping & Veach), which moves only ~1/n of keys
+/// when the shard count changes.
+fn jump_hash(mut key: u64, buckets: usize) -> usize {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+
+    b as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_index_stable_and_case_insensitive() {
+        let a = shard_index("User+tag@Example.com", 16);
+        let b = shard_index("user+tag@example.com", 16);
+        assert_eq!(a, b);
+        assert_eq!(a, shard_index("user+tag@example.com", 16));
+        assert!(a < 16);
+    }
+
+    #[test]
+    fn test_shard_index_single_shard() {
+        assert_eq!(shard_index("anyone@example.com", 1), 0);
+        assert_eq!(shard_index("anyone@example.com", 0), 0);
+    }
+
+    #[test]
+    fn test_jump_hash_spreads_keys() {
+        let mut used = std::collections::HashSet::new();
+        for i in 0..200 {
+            used.insert(shard_index(&format!("user{}@example.com", i), 8));
+        }
+        assert_eq!(used.len(), 8);
+    }
+
+    #[test]
+    fn test_sharded_channels_preserve_order_per_key() {
+        let (sender, mut receivers) = sharded_channels::<u32>(4);
+        for i in 0..5 {
+            sender.send("same@example.com", i).unwrap();
+        }
+
+        let index = shard_index("same@example.com", 4);
+        let rx = &mut receivers[index];
+        let received: Vec<u32> = (0..5).map(|_| rx.try_recv().unwrap()).collect();
+        assert_eq!(received, vec![0, 1, 2, 3, 4]);
+    }
+}
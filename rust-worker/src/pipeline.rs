@@ -0,0 +1,292 @@
+//! Webhook → job → simulation as explicit stages with hooks.
+//!
+//! A [`Pipeline`] runs the two stages the binaries run, processing a webhook
+//! into a [`SimulatorJob`] and simulating a [`Job`], and calls every
+//! registered [`Hook`] around them. Hooks can rewrite what passes between
+//! stages or veto it, so an embedder can change behavior without forking the
+//! processor or worker loop. The process stage needs the `processor` feature.
+//!
+//! ```no_run
+//! use bobnet::pipeline::{Decision, Hook, Pipeline};
+//! use bobnet::simulate::simulator::Job;
+//!
+//! /// Never simulate mail sent to the internal domain.
+//! struct SkipInternal;
+//!
+//! impl Hook for SkipInternal {
+//!     fn before_simulate(&self, job: &mut Job) -> Decision {
+//!         if job.to.ends_with("@internal.example.com") {
+//!             return Decision::veto("internal recipient");
+//!         }
+//!         Decision::Continue
+//!     }
+//! }
+//!
+//! let pipeline = Pipeline::new().with_hook(SkipInternal);
+//! ```
+
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+#[cfg(feature = "processor")]
+use crate::error::Result;
+#[cfg(feature = "processor")]
+use crate::process::process_webhook;
+use crate::queue::{InboundWebhook, SimulatorJob};
+use crate::simulate::report::SimulationReport;
+use crate::simulate::simulator::{Job, Simulator};
+
+/// What a hook decided about the item it was shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Pass the item on to the next hook or stage
+    Continue,
+    /// Drop the item; later hooks and stages do not run
+    Veto(String),
+}
+
+impl Decision {
+    /// Veto with `reason`, which is logged.
+    pub fn veto(reason: impl Into<String>) -> Self {
+        Decision::Veto(reason.into())
+    }
+}
+
+/// Point in the pipeline where a hook vetoed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    /// [`Hook::before_process`]
+    BeforeProcess,
+    /// [`Hook::after_process`]
+    AfterProcess,
+    /// [`Hook::before_simulate`]
+    BeforeSimulate,
+}
+
+impl HookPoint {
+    /// Stable name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookPoint::BeforeProcess => "before_process",
+            HookPoint::AfterProcess => "after_process",
+            HookPoint::BeforeSimulate => "before_simulate",
+        }
+    }
+}
+
+/// Customization around the pipeline stages.
+///
+/// Every method defaults to doing nothing. Hooks run inline on the task
+/// handling the message, so they should not block.
+pub trait Hook: Send + Sync {
+    /// Inspect or rewrite a webhook before it is parsed.
+    fn before_process(&self, _webhook: &mut InboundWebhook) -> Decision {
+        Decision::Continue
+    }
+
+    /// Inspect or rewrite a parsed job before it is published.
+    fn after_process(&self, _job: &mut SimulatorJob) -> Decision {
+        Decision::Continue
+    }
+
+    /// Inspect or rewrite a job before it is simulated.
+    fn before_simulate(&self, _job: &mut Job) -> Decision {
+        Decision::Continue
+    }
+
+    /// Inspect or amend a report before it is recorded and published.
+    fn after_simulate(&self, _job: &Job, _report: &mut SimulationReport) {}
+}
+
+/// Pipeline stages with their hooks, called in registration order.
+///
+/// Cheap to clone: clones share the hooks.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    hooks: Vec<Arc<dyn Hook>>,
+}
+
+impl Pipeline {
+    /// A pipeline without hooks, behaving exactly like the bare stages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `hook` after the ones already registered.
+    pub fn with_hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Process `webhook` into a simulator job.
+    ///
+    /// Returns `Ok(None)` if a hook vetoed the webhook or the job.
+    #[cfg(feature = "processor")]
+    pub fn process(&self, mut webhook: InboundWebhook) -> Result<Option<SimulatorJob>> {
+        if let Some(reason) = self.first_veto(|h| h.before_process(&mut webhook)) {
+            vetoed(HookPoint::BeforeProcess, None, &reason);
+            return Ok(None);
+        }
+
+        let mut job = process_webhook(webhook)?;
+        if let Some(reason) = self.first_veto(|h| h.after_process(&mut job)) {
+            vetoed(HookPoint::AfterProcess, Some(&job.message_id), &reason);
+            return Ok(None);
+        }
+        Ok(Some(job))
+    }
+
+    /// Simulate `job` with `simulator`, stopping early once `cancel` fires.
+    ///
+    /// Returns `None` if a hook vetoed the job.
+    pub async fn simulate(
+        &self,
+        simulator: &Simulator,
+        mut job: Job,
+        cancel: Option<&CancellationToken>,
+    ) -> Option<SimulationReport> {
+        if let Some(reason) = self.first_veto(|h| h.before_simulate(&mut job)) {
+            vetoed(
+                HookPoint::BeforeSimulate,
+                job.message_id.as_deref(),
+                &reason,
+            );
+            return None;
+        }
+
+        let mut report = simulator.run(&job, None, cancel).await;
+        for hook in &self.hooks {
+            hook.after_simulate(&job, &mut report);
+        }
+        Some(report)
+    }
+
+    /// Call `f` on each hook until one vetoes, returning its reason.
+    fn first_veto(&self, mut f: impl FnMut(&dyn Hook) -> Decision) -> Option<String> {
+        self.hooks.iter().find_map(|hook| match f(hook.as_ref()) {
+            Decision::Continue => None,
+            Decision::Veto(reason) => Some(reason),
+        })
+    }
+}
+
+fn vetoed(point: HookPoint, message_id: Option<&str>, reason: &str) {
+    info!(
+        hook_point = point.as_str(),
+        message_id = message_id.unwrap_or("unknown"),
+        reason = %reason,
+        "pipeline_vetoed"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WorkerConfig;
+    #[cfg(feature = "processor")]
+    use crate::queue::MailgunRawPayload;
+    use crate::simulate::report::Skip;
+
+    struct Rewrite;
+
+    impl Hook for Rewrite {
+        fn after_process(&self, job: &mut SimulatorJob) -> Decision {
+            job.to = job.to.replace("@old.example.com", "@new.example.com");
+            Decision::Continue
+        }
+
+        fn after_simulate(&self, _job: &Job, report: &mut SimulationReport) {
+            report.customer_tag = Some("hooked".to_string());
+        }
+    }
+
+    struct VetoDomain(&'static str);
+
+    impl Hook for VetoDomain {
+        fn after_process(&self, job: &mut SimulatorJob) -> Decision {
+            if job.to.ends_with(self.0) {
+                return Decision::veto("blocked domain");
+            }
+            Decision::Continue
+        }
+
+        fn before_simulate(&self, job: &mut Job) -> Decision {
+            if job.to.ends_with(self.0) {
+                return Decision::veto("blocked domain");
+            }
+            Decision::Continue
+        }
+    }
+
+    #[cfg(feature = "processor")]
+    fn webhook(recipient: &str) -> InboundWebhook {
+        InboundWebhook::Mailgun(MailgunRawPayload {
+            recipient: recipient.to_string(),
+            sender: "".to_string(),
+            subject: "Test".to_string(),
+            body_html: Some("<html>Test</html>".to_string()),
+            body_plain: None,
+            stripped_html: None,
+            message_headers: Some(r#"[["Message-Id", "<msg@example.com>"]]"#.to_string()),
+            from_field: "".to_string(),
+            timestamp: "".to_string(),
+            token: "".to_string(),
+            received_at_ms: None,
+            message_url: None,
+            body_mime: None,
+        })
+    }
+
+    #[cfg(feature = "processor")]
+    #[test]
+    fn test_process_hooks_run_in_order() {
+        let pipeline = Pipeline::new()
+            .with_hook(Rewrite)
+            .with_hook(VetoDomain("@new.example.com"));
+
+        let kept = pipeline.process(webhook("user@other.example.com")).unwrap();
+        assert_eq!(kept.unwrap().to, "user@other.example.com");
+
+        // Rewritten by the first hook, then vetoed by the second
+        let dropped = pipeline.process(webhook("user@old.example.com")).unwrap();
+        assert!(dropped.is_none());
+
+        assert!(Pipeline::new()
+            .process(webhook("user@old.example.com"))
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_simulate_hooks() {
+        let config = WorkerConfig::builder()
+            .simulate_open_probability(1.0)
+            .simulate_click_probability(0.0)
+            .open_delay_ms((0, 0))
+            .build();
+        let simulator = Simulator::new(config).unwrap();
+        let pipeline = Pipeline::new()
+            .with_hook(VetoDomain("@blocked.example.com"))
+            .with_hook(Rewrite);
+        let job = |to: &str| Job {
+            message_id: Some("msg-1".to_string()),
+            to: to.to_string(),
+            html: Some("<html><body>No images</body></html>".to_string()),
+            ..Default::default()
+        };
+
+        let vetoed = pipeline
+            .simulate(&simulator, job("user@blocked.example.com"), None)
+            .await;
+        assert!(vetoed.is_none());
+
+        let report = pipeline
+            .simulate(&simulator, job("user@example.com"), None)
+            .await
+            .unwrap();
+        assert_eq!(report.customer_tag.as_deref(), Some("hooked"));
+        assert!(report.skipped.contains(&Skip::NoImages));
+    }
+}
@@ -3,11 +3,12 @@
 //! This module processes raw Cloudflare JSON payloads into SimulatorJobs.
 //! Cloudflare provides raw RFC 5322 email content that needs to be parsed.
 
-use anyhow::Result;
 use sha2::{Digest, Sha256};
 use tracing::info;
 
+use crate::error::{BobNetError, Result};
 use crate::process::email_parser::{parse_raw_email, ParsedEmail};
+use crate::process::received_at;
 use crate::queue::{CloudflareRawPayload, SimulatorJob};
 
 /// Process a raw Cloudflare payload into a SimulatorJob.
@@ -28,6 +29,8 @@ pub fn process_cloudflare(payload: CloudflareRawPayload) -> Result<SimulatorJob>
     // Parse the raw email content
     let parsed: ParsedEmail = match parse_raw_email(&payload.raw_content) {
         Ok(p) => p,
+        // The email would panic the parser on every retry; quarantine it
+        Err(e @ BobNetError::Panicked { .. }) => return Err(e),
         Err(e) => {
             // If parsing fails, use fallback values
             tracing::warn!(
@@ -57,7 +60,10 @@ pub fn process_cloudflare(payload: CloudflareRawPayload) -> Result<SimulatorJob>
         "cloudflare_process_complete"
     );
 
-    Ok(SimulatorJob::new(message_id, payload.to, parsed.html))
+    let mut job = SimulatorJob::new(message_id, payload.to, parsed.html);
+    job.received_at = received_at(&payload.timestamp);
+    job.received_at_ms = payload.received_at_ms;
+    Ok(job)
 }
 
 /// Generate a fallback Message-Id using SHA256 hash.
@@ -87,6 +93,7 @@ mod tests {
             to: "recipient@example.com".to_string(),
             subject: "Test Subject".to_string(),
             timestamp: "2024-01-01T00:00:00Z".to_string(),
+            received_at_ms: Some(1_704_067_200_250),
             raw_content: r#"Message-Id: <test123@example.com>
 Content-Type: text/html
 
@@ -98,6 +105,8 @@ Content-Type: text/html
 
         assert_eq!(job.message_id, "test123@example.com");
         assert_eq!(job.to, "recipient@example.com");
+        assert_eq!(job.received_at, Some(1_704_067_200));
+        assert_eq!(job.received_at_ms, Some(1_704_067_200_250));
         assert!(job.html.is_some());
         assert!(job.html.unwrap().contains("Hello World"));
     }
@@ -109,6 +118,7 @@ Content-Type: text/html
             to: "recipient@example.com".to_string(),
             subject: "No Message ID".to_string(),
             timestamp: "2024-01-01T00:00:00Z".to_string(),
+            received_at_ms: None,
             raw_content: r#"Content-Type: text/html
 
 <html><body>Test</body></html>"#
@@ -129,6 +139,7 @@ Content-Type: text/html
             to: "recipient@example.com".to_string(),
             subject: "Multipart Test".to_string(),
             timestamp: "2024-01-01T00:00:00Z".to_string(),
+            received_at_ms: None,
             raw_content: r#"Message-Id: <multi@example.com>
 Content-Type: multipart/alternative; boundary="boundary123"
 
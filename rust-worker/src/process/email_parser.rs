@@ -3,11 +3,21 @@
 //! This module provides functions to parse raw RFC 5322 email content
 //! and extract HTML body and Message-Id headers. Used by the processor
 //! to parse Cloudflare's raw_content field.
+//!
+//! A panic inside mailparse is contained and returned as
+//! [`BobNetError::Panicked`], so the message is dead-lettered instead of
+//! taking its task down. The `parse_raw_email` fuzz target in `fuzz/`
+//! exercises this path.
 
-use anyhow::{Context, Result};
 use mailparse::{parse_mail, MailHeaderMap, ParsedMail};
 use tracing::{info, warn};
 
+use crate::error::{BobNetError, Result};
+use crate::util::panic::contain;
+
+/// Bytes of the raw email logged as `raw_content_preview`.
+const PREVIEW_BYTES: usize = 200;
+
 /// Parsed email result.
 #[derive(Debug, Default)]
 pub struct ParsedEmail {
@@ -31,11 +41,24 @@ pub struct ParsedEmail {
 pub fn parse_raw_email(raw_content: &str) -> Result<ParsedEmail> {
     info!(
         raw_content_length = raw_content.len(),
-        raw_content_preview = &raw_content[..raw_content.len().min(200)],
+        raw_content_preview = preview(raw_content),
         "email_parse_start"
     );
 
-    let mail = parse_mail(raw_content.as_bytes()).context("Failed to parse email")?;
+    contain("email", || parse(raw_content)).map_err(|e| BobNetError::panicked("email", e))?
+}
+
+/// The first [`PREVIEW_BYTES`] of `raw_content`, cut at a character boundary.
+fn preview(raw_content: &str) -> &str {
+    let mut end = raw_content.len().min(PREVIEW_BYTES);
+    while !raw_content.is_char_boundary(end) {
+        end -= 1;
+    }
+    &raw_content[..end]
+}
+
+fn parse(raw_content: &str) -> Result<ParsedEmail> {
+    let mail = parse_mail(raw_content.as_bytes()).map_err(|e| BobNetError::parse("email", e))?;
 
     // Extract Message-Id header
     let message_id = mail
@@ -287,4 +310,13 @@ Content-Type: text/html
         assert!(result.html.is_some());
         assert!(result.html.unwrap().contains("Nested HTML"));
     }
+
+    #[test]
+    fn test_preview_cuts_at_char_boundary() {
+        // A multi-byte character straddling the preview length
+        let raw = format!("Subject: {}\u{00e9}\n\n<html>Test</html>", "x".repeat(190));
+        assert_eq!(preview(&raw).len(), 199);
+        assert_eq!(preview("Subject: short"), "Subject: short");
+        assert!(parse_raw_email(&raw).is_ok());
+    }
 }
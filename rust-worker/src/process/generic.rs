@@ -0,0 +1,85 @@
+//! Generic webhook payload processing.
+//!
+//! The web server already mapped the recipient, HTML and Message-Id out of
+//! the source's JSON, so this only fills in a fallback Message-Id.
+
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::error::Result;
+use crate::queue::{GenericRawPayload, SimulatorJob};
+
+/// Process a generic webhook payload into a SimulatorJob.
+pub fn process_generic(payload: GenericRawPayload) -> Result<SimulatorJob> {
+    info!(
+        source = %payload.source,
+        to = %payload.to,
+        has_html = payload.html.is_some(),
+        has_message_id = payload.message_id.is_some(),
+        "generic_process_start"
+    );
+
+    let message_id = payload
+        .message_id
+        .map(|id| id.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| {
+            generate_fallback_id(&payload.source, &payload.to, payload.html.as_deref())
+        });
+
+    let mut job = SimulatorJob::new(message_id, payload.to, payload.html);
+    job.received_at = payload.received_at_ms.map(|ms| ms / 1000);
+    job.received_at_ms = payload.received_at_ms;
+    Ok(job)
+}
+
+/// Generate a fallback Message-Id using SHA256 hash.
+fn generate_fallback_id(source: &str, to: &str, html: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}-{}-{}", source, to, html.unwrap_or_default()).as_bytes());
+    let hash = hex::encode(hasher.finalize());
+
+    info!(
+        source = %source,
+        to = %to,
+        generated_id = %hash,
+        "generic_message_id_fallback"
+    );
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(message_id: Option<&str>) -> GenericRawPayload {
+        GenericRawPayload {
+            source: "acme".to_string(),
+            to: "user@example.com".to_string(),
+            html: Some("<html>Hi</html>".to_string()),
+            message_id: message_id.map(str::to_string),
+            received_at_ms: Some(1_704_067_200_250),
+        }
+    }
+
+    #[test]
+    fn test_process_generic() {
+        let job = process_generic(payload(Some("<abc@acme.example>"))).unwrap();
+
+        assert_eq!(job.message_id, "abc@acme.example");
+        assert_eq!(job.to, "user@example.com");
+        assert_eq!(job.html.as_deref(), Some("<html>Hi</html>"));
+        assert_eq!(job.received_at, Some(1_704_067_200));
+        assert_eq!(job.received_at_ms, Some(1_704_067_200_250));
+    }
+
+    #[test]
+    fn test_process_generic_fallback_message_id() {
+        let first = process_generic(payload(None)).unwrap();
+        let second = process_generic(payload(Some(" "))).unwrap();
+
+        assert_eq!(first.message_id.len(), 64);
+        assert_eq!(first.message_id, second.message_id);
+    }
+}
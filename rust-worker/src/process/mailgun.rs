@@ -1,12 +1,16 @@
 //! Mailgun webhook payload processing.
 //!
 //! This module processes raw Mailgun form payloads into SimulatorJobs.
-//! Mailgun provides pre-parsed email content, so no RFC 5322 parsing is needed.
+//! Mailgun provides pre-parsed email content, so no RFC 5322 parsing is
+//! needed, except for messages retrieved from a store-and-notify route's
+//! storage (see [`crate::process::mailgun_storage`]).
 
-use anyhow::Result;
 use sha2::{Digest, Sha256};
 use tracing::{info, warn};
 
+use crate::error::{BobNetError, Result};
+use crate::process::email_parser::{parse_raw_email, ParsedEmail};
+use crate::process::received_at;
 use crate::queue::{MailgunRawPayload, SimulatorJob};
 
 /// Process a raw Mailgun payload into a SimulatorJob.
@@ -15,6 +19,9 @@ use crate::queue::{MailgunRawPayload, SimulatorJob};
 /// 1. Extract Message-Id from the headers JSON
 /// 2. Get the HTML body (preferring body_html over stripped_html)
 /// 3. Build the SimulatorJob
+///
+/// A stored message retrieved into `body_mime` is parsed instead, falling
+/// back to the notification's fields for anything it lacks.
 pub fn process_mailgun(payload: MailgunRawPayload) -> Result<SimulatorJob> {
     info!(
         recipient = %payload.recipient,
@@ -22,18 +29,37 @@ pub fn process_mailgun(payload: MailgunRawPayload) -> Result<SimulatorJob> {
         body_html_length = payload.body_html.as_ref().map(|s| s.len()).unwrap_or(0),
         has_stripped_html = payload.stripped_html.is_some(),
         has_message_headers = payload.message_headers.is_some(),
+        has_body_mime = payload.body_mime.is_some(),
         "mailgun_process_start"
     );
 
-    // Extract Message-Id from headers JSON
-    let message_id = extract_message_id_from_headers(&payload.message_headers)
+    // Parse the stored message, when one was retrieved
+    let parsed = match payload.body_mime.as_deref().map(parse_raw_email) {
+        Some(Ok(parsed)) => parsed,
+        // The email would panic the parser on every retry; quarantine it
+        Some(Err(e @ BobNetError::Panicked { .. })) => return Err(e),
+        Some(Err(e)) => {
+            warn!(error = %e, "mailgun_stored_message_parse_failed");
+            ParsedEmail::default()
+        }
+        None => ParsedEmail::default(),
+    };
+
+    // Extract Message-Id from the stored message or the headers JSON
+    let message_id = parsed
+        .message_id
+        .filter(|id| !id.is_empty())
+        .or_else(|| extract_message_id_from_headers(&payload.message_headers))
         .unwrap_or_else(|| generate_fallback_id(&payload.subject, &payload.recipient));
 
     // Determine HTML source for logging
+    let body_mime_is_valid = parsed.html.as_ref().map(|s| !s.is_empty()).unwrap_or(false);
     let body_html_is_valid = payload.body_html.as_ref().map(|s| !s.is_empty()).unwrap_or(false);
     let stripped_html_is_valid = payload.stripped_html.as_ref().map(|s| !s.is_empty()).unwrap_or(false);
 
-    let html_source = if body_html_is_valid {
+    let html_source = if body_mime_is_valid {
+        "body_mime"
+    } else if body_html_is_valid {
         "body_html"
     } else if stripped_html_is_valid {
         "stripped_html"
@@ -41,10 +67,12 @@ pub fn process_mailgun(payload: MailgunRawPayload) -> Result<SimulatorJob> {
         "none"
     };
 
-    // Get HTML content - prefer body_html, fall back to stripped_html
-    let html = payload
-        .body_html
+    // Get HTML content - prefer the stored message, then body_html, then
+    // stripped_html
+    let html = parsed
+        .html
         .filter(|s| !s.is_empty())
+        .or_else(|| payload.body_html.filter(|s| !s.is_empty()))
         .or_else(|| payload.stripped_html.filter(|s| !s.is_empty()));
 
     info!(
@@ -54,7 +82,10 @@ pub fn process_mailgun(payload: MailgunRawPayload) -> Result<SimulatorJob> {
         "mailgun_process_complete"
     );
 
-    Ok(SimulatorJob::new(message_id, payload.recipient, html))
+    let mut job = SimulatorJob::new(message_id, payload.recipient, html);
+    job.received_at = received_at(&payload.timestamp);
+    job.received_at_ms = payload.received_at_ms;
+    Ok(job)
 }
 
 /// Extract Message-Id from Mailgun's message-headers JSON string.
@@ -208,6 +239,9 @@ mod tests {
             from_field: "sender@example.com".to_string(),
             timestamp: "".to_string(),
             token: "".to_string(),
+            received_at_ms: None,
+            message_url: None,
+            body_mime: None,
         };
 
         let job = process_mailgun(payload).unwrap();
@@ -230,10 +264,46 @@ mod tests {
             from_field: "".to_string(),
             timestamp: "".to_string(),
             token: "".to_string(),
+            received_at_ms: None,
+            message_url: None,
+            body_mime: None,
         };
 
         let job = process_mailgun(payload).unwrap();
 
         assert_eq!(job.html, Some("<html>Stripped</html>".to_string()));
     }
+
+    #[test]
+    fn test_process_mailgun_prefers_stored_message() {
+        let payload = MailgunRawPayload {
+            recipient: "test@example.com".to_string(),
+            sender: "sender@example.com".to_string(),
+            subject: "Test".to_string(),
+            body_html: Some("<html>Truncated".to_string()),
+            body_plain: None,
+            stripped_html: None,
+            message_headers: Some(r#"[["Message-Id", "<notify@example.com>"]]"#.to_string()),
+            from_field: "sender@example.com".to_string(),
+            timestamp: "".to_string(),
+            token: "".to_string(),
+            received_at_ms: None,
+            message_url: Some(
+                "https://se.api.mailgun.net/v3/domains/mg.example.com/messages/AbC".to_string(),
+            ),
+            body_mime: Some(
+                "Message-Id: <stored@example.com>\r\n\
+                 Subject: Test\r\n\
+                 Content-Type: text/html; charset=utf-8\r\n\
+                 \r\n\
+                 <html>Full</html>"
+                    .to_string(),
+            ),
+        };
+
+        let job = process_mailgun(payload).unwrap();
+
+        assert_eq!(job.message_id, "stored@example.com");
+        assert_eq!(job.html.as_deref().map(str::trim), Some("<html>Full</html>"));
+    }
 }
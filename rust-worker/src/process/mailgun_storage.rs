@@ -0,0 +1,155 @@
+//! Retrieval of messages stored by Mailgun store-and-notify routes.
+//!
+//! A route with `store(notify="https://.../webhook/mailgun")` keeps the
+//! message on Mailgun and posts only its parsed fields and `message-url`.
+//! Forwarded messages are cut at Mailgun's size limits; stored ones are
+//! not, so with `MAILGUN_API_KEY` set the processor fetches the full MIME
+//! from the message URL and parses it like a Cloudflare email.
+
+use std::time::Duration;
+
+use ::url::Url;
+use reqwest::header::ACCEPT;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::queue::MailgunRawPayload;
+
+/// Longest wait for Mailgun to return a stored message.
+pub const RETRIEVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Why a stored message could not be retrieved.
+#[derive(Debug, thiserror::Error)]
+pub enum RetrieveError {
+    #[error("message URL is not a Mailgun storage URL")]
+    UntrustedUrl,
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Mailgun answered {0}")]
+    Status(u16),
+    #[error("response is not a stored message: {0}")]
+    Body(#[from] serde_json::Error),
+}
+
+impl RetrieveError {
+    /// Whether asking again later may succeed.
+    ///
+    /// Network failures, rate limiting and Mailgun errors may pass; an
+    /// expired or unknown message, or a foreign URL, never will.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RetrieveError::Http(_) => true,
+            RetrieveError::Status(status) => *status == 429 || *status >= 500,
+            RetrieveError::UntrustedUrl | RetrieveError::Body(_) => false,
+        }
+    }
+}
+
+/// Stored message as returned for `Accept: message/rfc2822`.
+#[derive(Deserialize)]
+struct StoredMessage {
+    #[serde(rename = "body-mime")]
+    body_mime: String,
+}
+
+/// Client for Mailgun's stored message API.
+#[derive(Clone)]
+pub struct MailgunStorage {
+    client: Client,
+    api_key: String,
+}
+
+impl MailgunStorage {
+    /// Storage client authenticating with `api_key`.
+    pub fn new(api_key: impl Into<String>) -> Result<Self, reqwest::Error> {
+        let client = Client::builder()
+            .timeout(RETRIEVE_TIMEOUT)
+            // The API key must only ever go to the URL that was checked
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        Ok(Self {
+            client,
+            api_key: api_key.into(),
+        })
+    }
+
+    /// Fetch the stored message behind `payload`'s message URL into its
+    /// `body_mime`. Payloads without a message URL are left as they are.
+    pub async fn retrieve(&self, payload: &mut MailgunRawPayload) -> Result<(), RetrieveError> {
+        if let Some(url) = &payload.message_url {
+            payload.body_mime = Some(self.fetch_mime(url).await?);
+        }
+        Ok(())
+    }
+
+    /// Full MIME of the message stored at `message_url`.
+    pub async fn fetch_mime(&self, message_url: &str) -> Result<String, RetrieveError> {
+        if !is_storage_url(message_url) {
+            return Err(RetrieveError::UntrustedUrl);
+        }
+
+        let response = self
+            .client
+            .get(message_url)
+            .basic_auth("api", Some(&self.api_key))
+            .header(ACCEPT, "message/rfc2822")
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(RetrieveError::Status(response.status().as_u16()));
+        }
+
+        let body = response.bytes().await?;
+        let stored: StoredMessage = serde_json::from_slice(&body)?;
+        Ok(stored.body_mime)
+    }
+}
+
+/// Whether `url` points into Mailgun's API over https, the only place the
+/// API key may be sent.
+pub fn is_storage_url(url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    url.scheme() == "https"
+        && url.username().is_empty()
+        && url.password().is_none()
+        && url
+            .host_str()
+            .is_some_and(|host| host == "mailgun.net" || host.ends_with(".mailgun.net"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_storage_url() {
+        assert!(is_storage_url(
+            "https://storage-us-east4.api.mailgun.net/v3/domains/mg.example.com/messages/AbC123"
+        ));
+        assert!(is_storage_url(
+            "https://se.api.eu.mailgun.net/v3/domains/mg.example.com/messages/AbC123"
+        ));
+        assert!(!is_storage_url("http://se.api.mailgun.net/v3/domains/x/messages/y"));
+        assert!(!is_storage_url("https://mailgun.net.attacker.example/v3/messages/y"));
+        assert!(!is_storage_url("https://evilmailgun.net/v3/messages/y"));
+        assert!(!is_storage_url("https://user@se.api.mailgun.net/v3/messages/y"));
+        assert!(!is_storage_url("not a url"));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_url_is_not_fetched() {
+        let storage = MailgunStorage::new("key-secret").unwrap();
+        let error = storage
+            .fetch_mime("https://attacker.example/v3/messages/y")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RetrieveError::UntrustedUrl));
+        assert!(!error.is_transient());
+
+        assert!(RetrieveError::Status(503).is_transient());
+        assert!(RetrieveError::Status(429).is_transient());
+        assert!(!RetrieveError::Status(404).is_transient());
+    }
+}
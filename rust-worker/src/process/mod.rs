@@ -11,16 +11,21 @@
 
 pub mod cloudflare;
 pub mod email_parser;
+pub mod generic;
 pub mod mailgun;
+pub mod mailgun_storage;
 
-use anyhow::Result;
 use tracing::info;
 
+use crate::error::Result;
 use crate::queue::{InboundWebhook, SimulatorJob};
+use crate::tenant::usage::day_of;
 
 pub use cloudflare::process_cloudflare;
 pub use email_parser::{parse_raw_email, ParsedEmail};
+pub use generic::process_generic;
 pub use mailgun::process_mailgun;
+pub use mailgun_storage::MailgunStorage;
 
 /// Process an inbound webhook into a simulator job.
 ///
@@ -38,6 +43,10 @@ pub fn process_webhook(webhook: InboundWebhook) -> Result<SimulatorJob> {
             info!(provider = "cloudflare", "webhook_routing");
             process_cloudflare(payload)?
         }
+        InboundWebhook::Generic(payload) => {
+            info!(provider = "generic", source = %payload.source, "webhook_routing");
+            process_generic(payload)?
+        }
     };
 
     info!(
@@ -50,6 +59,68 @@ pub fn process_webhook(webhook: InboundWebhook) -> Result<SimulatorJob> {
     Ok(job)
 }
 
+/// Unix seconds of a webhook timestamp.
+///
+/// Mailgun sends Unix seconds; Cloudflare workers send Unix milliseconds or
+/// an RFC 3339 date such as `2024-01-01T00:00:00Z`. Returns `None` for an
+/// empty or unparseable timestamp.
+pub(crate) fn received_at(timestamp: &str) -> Option<u64> {
+    let timestamp = timestamp.trim();
+    if let Ok(secs) = timestamp.parse::<u64>() {
+        // Seconds will not reach 10^12 until the year 33658
+        return Some(if secs >= 1_000_000_000_000 {
+            secs / 1000
+        } else {
+            secs
+        });
+    }
+
+    let (date, time) = timestamp.split_once(['T', 't', ' '])?;
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(i) => time.split_at(i),
+        None => (time, "Z"),
+    };
+    let mut parts = time.splitn(3, ':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds = parts.next()?;
+    let seconds: u64 = seconds
+        .split_once('.')
+        .map_or(seconds, |(s, _)| s)
+        .parse()
+        .ok()?;
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    let offset_secs = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let (sign, offset) = offset.split_at(1);
+            let (h, m) = offset.split_once(':')?;
+            let secs = h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60;
+            if sign == "-" {
+                -secs
+            } else {
+                secs
+            }
+        }
+    };
+    let local = day_of(date)? * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    u64::try_from(local as i64 - offset_secs).ok()
+}
+
+/// Unix seconds the email behind `webhook` was received: the provider's
+/// timestamp, else the web server's `received_at_ms`.
+pub fn webhook_received_at(webhook: &InboundWebhook) -> Option<u64> {
+    let (timestamp, received_at_ms) = match webhook {
+        InboundWebhook::Mailgun(payload) => (&payload.timestamp, payload.received_at_ms),
+        InboundWebhook::Cloudflare(payload) => (&payload.timestamp, payload.received_at_ms),
+        InboundWebhook::Generic(payload) => return payload.received_at_ms.map(|ms| ms / 1000),
+    };
+    received_at(timestamp).or(received_at_ms.map(|ms| ms / 1000))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,6 +139,9 @@ mod tests {
             from_field: "".to_string(),
             timestamp: "".to_string(),
             token: "".to_string(),
+            received_at_ms: None,
+            message_url: None,
+            body_mime: None,
         });
 
         let job = process_webhook(webhook).unwrap();
@@ -83,6 +157,7 @@ mod tests {
             to: "recipient@example.com".to_string(),
             subject: "Test".to_string(),
             timestamp: "".to_string(),
+            received_at_ms: None,
             raw_content: r#"Message-Id: <cf@example.com>
 Content-Type: text/html
 
@@ -95,4 +170,48 @@ Content-Type: text/html
         assert_eq!(job.message_id, "cf@example.com");
         assert_eq!(job.to, "recipient@example.com");
     }
+
+    #[test]
+    fn test_received_at() {
+        assert_eq!(received_at("1704067200"), Some(1_704_067_200));
+        assert_eq!(received_at("1704067200123"), Some(1_704_067_200));
+        assert_eq!(received_at("2024-01-01T00:00:00Z"), Some(1_704_067_200));
+        assert_eq!(received_at("2024-01-01T00:00:00.250Z"), Some(1_704_067_200));
+        assert_eq!(
+            received_at("2024-01-01T02:30:00+02:30"),
+            Some(1_704_067_200)
+        );
+        assert_eq!(
+            received_at("2023-12-31T19:00:00-05:00"),
+            Some(1_704_067_200)
+        );
+        assert_eq!(received_at(""), None);
+        assert_eq!(received_at("yesterday"), None);
+        assert_eq!(received_at("2024-01-01T25:00:00Z"), None);
+    }
+
+    #[test]
+    fn test_webhook_received_at() {
+        let webhook = |timestamp: &str, received_at_ms| {
+            InboundWebhook::Cloudflare(CloudflareRawPayload {
+                from_field: "sender@example.com".to_string(),
+                to: "recipient@example.com".to_string(),
+                subject: "Test".to_string(),
+                timestamp: timestamp.to_string(),
+                received_at_ms,
+                raw_content: String::new(),
+            })
+        };
+
+        let stamped = Some(1_704_067_260_500);
+        assert_eq!(
+            webhook_received_at(&webhook("2024-01-01T00:00:00Z", stamped)),
+            Some(1_704_067_200)
+        );
+        assert_eq!(
+            webhook_received_at(&webhook("", stamped)),
+            Some(1_704_067_260)
+        );
+        assert_eq!(webhook_received_at(&webhook("", None)), None);
+    }
 }
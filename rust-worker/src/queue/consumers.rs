@@ -0,0 +1,160 @@
+//! Consumer channels spread over several connections.
+//!
+//! One channel tops out at a few thousand deliveries a second, since each is
+//! served by a single broker process and connection. The processor and
+//! worker can open `CONSUMER_CHANNELS` channels, spread round-robin over
+//! `CONSUMER_CONNECTIONS` connections, each consuming every queue with its
+//! own prefetch, and merge their deliveries into the one processing pool.
+//! Deliveries are acknowledged through their own [`Delivery::acker`], so it
+//! does not matter which channel they came from. The prefetch can be changed
+//! while consuming (see [`super::prefetch`]).
+//!
+//! [`Delivery::acker`]: lapin::message::Delivery
+
+use lapin::options::{BasicCancelOptions, BasicConsumeOptions, BasicQosOptions};
+use lapin::types::FieldTable;
+use lapin::{Channel, Connection, Consumer};
+use tracing::info;
+
+use super::failover::BrokerUrls;
+
+/// Open consumer connections and channels, all to one broker.
+pub struct ConsumerChannels {
+    /// Index of the broker connected to
+    pub broker: usize,
+    /// Every connection, the first one carrying the first channel
+    pub connections: Vec<Connection>,
+    /// Every consumer channel, each with its prefetch set
+    pub channels: Vec<Channel>,
+}
+
+impl ConsumerChannels {
+    /// Connect to the first broker that accepts and open `channels` channels
+    /// over `connections` connections to it, each prefetching `prefetch`
+    /// messages.
+    ///
+    /// Extra connections go to the same broker as the first, so a failover
+    /// never splits the consumers over two brokers.
+    pub async fn open(
+        brokers: &BrokerUrls,
+        connections: usize,
+        channels: usize,
+        prefetch: u16,
+    ) -> Result<Self, lapin::Error> {
+        let channels = channels.max(1);
+        let (first, broker) = brokers.connect().await?;
+        let mut opened = vec![first];
+        for _ in 1..connections.clamp(1, channels) {
+            opened.push(brokers.connect_to(&brokers.urls()[broker]).await?);
+        }
+
+        let mut consumer_channels = Vec::with_capacity(channels);
+        for i in 0..channels {
+            let channel = opened[i % opened.len()].create_channel().await?;
+            channel
+                .basic_qos(prefetch, BasicQosOptions::default())
+                .await?;
+            consumer_channels.push(channel);
+        }
+
+        info!(
+            connections = opened.len(),
+            channels = consumer_channels.len(),
+            prefetch_count = prefetch,
+            "rabbitmq_consumer_channels_opened"
+        );
+        Ok(Self {
+            broker,
+            connections: opened,
+            channels: consumer_channels,
+        })
+    }
+
+    /// The first channel, for declaring queues and publishing.
+    pub fn main(&self) -> &Channel {
+        &self.channels[0]
+    }
+
+    /// Consume `queue` on every channel, the consumer of channel `i` tagged
+    /// [`consumer_tag`]`(tag, i)`.
+    pub async fn consume(&self, queue: &str, tag: &str) -> Result<Vec<Consumer>, lapin::Error> {
+        let mut consumers = Vec::with_capacity(self.channels.len());
+        for (i, channel) in self.channels.iter().enumerate() {
+            let consumer = channel
+                .basic_consume(
+                    queue,
+                    &consumer_tag(tag, i),
+                    BasicConsumeOptions::default(),
+                    FieldTable::default(),
+                )
+                .await?;
+            consumers.push(consumer);
+        }
+        Ok(consumers)
+    }
+
+    /// Set the prefetch of every channel, restarting the consumers started
+    /// by [`consume`](Self::consume) for each `(queue, tag)` so they take it,
+    /// and return the new consumers.
+    ///
+    /// RabbitMQ only applies a prefetch to consumers started after it. The
+    /// cancelled consumers' streams still yield what they had received
+    /// before ending, and those deliveries are acknowledged as usual.
+    pub async fn set_prefetch(
+        &self,
+        prefetch: u16,
+        queues: &[(String, String)],
+    ) -> Result<Vec<Consumer>, lapin::Error> {
+        let mut consumers = Vec::with_capacity(self.channels.len() * queues.len());
+        for (i, channel) in self.channels.iter().enumerate() {
+            channel
+                .basic_qos(prefetch, BasicQosOptions::default())
+                .await?;
+            for (queue, tag) in queues {
+                let tag = consumer_tag(tag, i);
+                channel
+                    .basic_cancel(&tag, BasicCancelOptions::default())
+                    .await?;
+                let consumer = channel
+                    .basic_consume(
+                        queue,
+                        &tag,
+                        BasicConsumeOptions::default(),
+                        FieldTable::default(),
+                    )
+                    .await?;
+                consumers.push(consumer);
+            }
+        }
+        Ok(consumers)
+    }
+
+    /// Close every connection, returning unacknowledged deliveries to their
+    /// queues.
+    pub async fn close(&self, reason: &str) -> Result<(), lapin::Error> {
+        for connection in &self.connections {
+            connection.close(200, reason).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Consumer tag of channel `index`, `base` itself for the first channel,
+/// e.g. `rust-worker-email_simulator` then `rust-worker-email_simulator-1`.
+pub fn consumer_tag(base: &str, index: usize) -> String {
+    match index {
+        0 => base.to_string(),
+        _ => format!("{}-{}", base, index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consumer_tag() {
+        assert_eq!(consumer_tag("rust-processor", 0), "rust-processor");
+        assert_eq!(consumer_tag("rust-processor", 2), "rust-processor-2");
+    }
+}
@@ -0,0 +1,547 @@
+//! Dead-lettered messages.
+//!
+//! Dead-lettering is configured on the broker with a policy rather than with
+//! queue arguments, so existing queues don't need redeclaring:
+//!
+//! ```text
+//! rabbitmqctl set_policy bobnet-dlx '^(inbound_webhooks|email_simulator.*)$' \
+//!     '{"dead-letter-exchange":"","dead-letter-routing-key":"dead_letters"}' \
+//!     --apply-to queues
+//! ```
+//!
+//! Messages nacked without requeue then land in [`DEAD_LETTER_QUEUE`] with
+//! RabbitMQ's `x-death` headers recording where and why they died.
+//!
+//! Consumers also move poison messages there themselves: a message already
+//! delivered `MAX_DELIVERY_ATTEMPTS` times, counting its rejections in
+//! `x-death`, quorum queues' `x-delivery-count` and its retries through a
+//! delay queue in [`RETRIES_HEADER`], is republished to the DLQ with a
+//! [`FAILURE_HEADER`] summary instead of being retried again.
+
+use lapin::message::Delivery;
+use lapin::options::{BasicAckOptions, BasicPublishOptions};
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+use lapin::{BasicProperties, Channel};
+use serde_json::{Map, Value};
+
+/// Queue that the broker policy dead-letters into.
+pub const DEAD_LETTER_QUEUE: &str = "dead_letters";
+
+/// Summary of why a poison message was moved to the DLQ.
+pub const FAILURE_HEADER: &str = "x-bobnet-failure";
+
+/// Queue a poison message was moved to the DLQ from.
+const FAILED_QUEUE_HEADER: &str = "x-bobnet-failed-queue";
+
+/// Reason recorded for poison messages without an `x-death` header.
+const POISON_REASON: &str = "poison";
+
+/// Times a message was retried through a delay queue after a transient
+/// failure, e.g. its content store being unreachable.
+pub const RETRIES_HEADER: &str = "x-bobnet-retries";
+
+/// Header quorum queues count earlier deliveries in.
+const DELIVERY_COUNT_HEADER: &str = "x-delivery-count";
+
+/// Headers RabbitMQ adds when dead-lettering, and those added to poison and
+/// retried messages.
+const DEATH_HEADER_PREFIXES: &[&str] = &[
+    "x-death",
+    "x-first-death-",
+    "x-last-death-",
+    FAILURE_HEADER,
+    FAILED_QUEUE_HEADER,
+    RETRIES_HEADER,
+];
+
+/// Where and why a message was dead-lettered, from its `x-death` headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Death {
+    /// Queue the message failed in, to requeue it to
+    pub queue: String,
+    /// Why it was dead-lettered from there: `rejected`, `poison`, ...
+    pub reason: String,
+    /// Times it has been dead-lettered in total
+    pub count: i64,
+}
+
+impl Death {
+    /// The death recorded on a delivery, or `None` if it was never
+    /// dead-lettered.
+    ///
+    /// Jobs held in a delay queue are dead-lettered back out of it when they
+    /// expire, so those deaths are passed over for the queue they returned
+    /// to: requeued to a delay queue, a job would sit there forever, as the
+    /// broker strips its expiration when dead-lettering it.
+    pub fn of(properties: &BasicProperties) -> Option<Self> {
+        let headers = properties.headers().as_ref()?;
+        let entries = deaths(headers);
+        let count: i64 = entries
+            .iter()
+            .filter_map(|entry| header(entry, "count").and_then(as_i64))
+            .sum();
+
+        // Moved to the DLQ as poison from the queue it was consumed from
+        if let Some(queue) = header(headers, FAILED_QUEUE_HEADER).and_then(as_string) {
+            return Some(Self {
+                queue,
+                reason: POISON_REASON.to_string(),
+                count: count.max(1),
+            });
+        }
+
+        // Most recent first, led by the last death's own headers
+        let last = header(headers, "x-last-death-queue")
+            .and_then(as_string)
+            .map(|queue| {
+                let reason = header(headers, "x-last-death-reason").and_then(as_string);
+                (queue, reason)
+            });
+        let recorded = entries.iter().filter_map(|entry| {
+            let queue = header(entry, "queue").and_then(as_string)?;
+            Some((queue, header(entry, "reason").and_then(as_string)))
+        });
+        let mut candidates: Vec<(String, Option<String>)> =
+            last.into_iter().chain(recorded).collect();
+        let failed = candidates.iter().position(|(queue, _)| !is_delay_queue(queue));
+        let (queue, reason) = match failed {
+            Some(i) => candidates.swap_remove(i),
+            // Only ever expired out of a delay queue: the queue it returns to
+            None => {
+                let (queue, reason) = candidates.into_iter().next()?;
+                (delayed_from(&queue).to_string(), reason)
+            }
+        };
+
+        Some(Self {
+            queue,
+            reason: reason.unwrap_or_default(),
+            count,
+        })
+    }
+}
+
+/// Entries of a message's `x-death` header, most recent first.
+fn deaths(headers: &FieldTable) -> Vec<&FieldTable> {
+    match header(headers, "x-death") {
+        Some(AMQPValue::FieldArray(deaths)) => deaths
+            .as_slice()
+            .iter()
+            .filter_map(|death| match death {
+                AMQPValue::FieldTable(table) => Some(table),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `queue` holds delayed or deferred jobs, e.g.
+/// `email_simulator.delayed` or `email_simulator.delayed.utc-300`.
+fn is_delay_queue(queue: &str) -> bool {
+    queue.contains(".delayed")
+}
+
+/// Queue that jobs in the delay queue `queue` return to.
+fn delayed_from(queue: &str) -> &str {
+    queue.find(".delayed").map_or(queue, |end| &queue[..end])
+}
+
+/// Deliveries of a message before this one: its rejections recorded in
+/// `x-death`, the redeliveries a quorum queue counted and its [`retries`].
+///
+/// Other deaths are not failed deliveries: a job expiring out of a delay
+/// queue was held back on purpose, and never ran there.
+pub fn previous_deliveries(properties: &BasicProperties) -> u64 {
+    let Some(headers) = properties.headers() else {
+        return 0;
+    };
+    let deaths: i64 = if header(headers, FAILED_QUEUE_HEADER).is_some() {
+        0
+    } else {
+        deaths(headers)
+            .iter()
+            .filter(|entry| {
+                header(entry, "reason").and_then(as_string).as_deref() == Some("rejected")
+            })
+            .filter_map(|entry| header(entry, "count").and_then(as_i64))
+            .sum()
+    };
+    let redeliveries = header(headers, DELIVERY_COUNT_HEADER)
+        .and_then(as_i64)
+        .unwrap_or(0);
+    u64::try_from(deaths.max(0) + redeliveries.max(0)).unwrap_or(0) + retries(properties)
+}
+
+/// Times a delivery was retried through a delay queue, from its
+/// [`RETRIES_HEADER`].
+pub fn retries(properties: &BasicProperties) -> u64 {
+    properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| header(headers, RETRIES_HEADER))
+        .and_then(as_i64)
+        .and_then(|n| u64::try_from(n).ok())
+        .unwrap_or(0)
+}
+
+/// Whether a delivery attempted `previous` times before has used up
+/// `max_attempts` (`0` retries forever).
+pub fn is_poison(previous: u64, max_attempts: u64) -> bool {
+    max_attempts > 0 && previous >= max_attempts
+}
+
+/// Summary of a poison delivery's failures for the [`FAILURE_HEADER`].
+pub fn failure_summary(properties: &BasicProperties, queue: &str, previous: u64) -> String {
+    let mut summary = format!("gave up on {} after {} deliveries", queue, previous);
+    if let Some(death) = Death::of(properties) {
+        summary.push_str(&format!(
+            "; last dead-lettered from {} ({})",
+            death.queue, death.reason
+        ));
+    }
+    summary
+}
+
+/// Properties of a poison delivery consumed from `queue` as moved to the
+/// DLQ: its own, with a failure summary and `queue` to requeue it to.
+///
+/// `queue` is the consumed queue, never the delivery's routing key, which
+/// with topic exchanges is its `provider.tenant` key.
+pub fn poison_properties(properties: &BasicProperties, queue: &str, previous: u64) -> BasicProperties {
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    headers.insert(
+        ShortString::from(FAILURE_HEADER),
+        AMQPValue::LongString(LongString::from(failure_summary(properties, queue, previous))),
+    );
+    headers.insert(
+        ShortString::from(FAILED_QUEUE_HEADER),
+        AMQPValue::LongString(LongString::from(queue)),
+    );
+    properties.clone().with_headers(headers)
+}
+
+/// Move a poison delivery consumed from `queue` to the DLQ with a failure
+/// summary, keeping its headers and signature, then acknowledge it.
+pub async fn divert_poison(
+    channel: &Channel,
+    queue: &str,
+    delivery: &Delivery,
+    previous: u64,
+) -> Result<(), lapin::Error> {
+    let properties = poison_properties(&delivery.properties, queue, previous);
+
+    channel
+        .basic_publish(
+            "",
+            DEAD_LETTER_QUEUE,
+            BasicPublishOptions::default(),
+            &delivery.data,
+            properties,
+        )
+        .await?
+        .await?;
+    delivery.ack(BasicAckOptions::default()).await
+}
+
+/// Properties to retry a delivery with through a delay queue: its
+/// [`requeue_properties`] with one more of its [`retries`].
+pub fn retry_properties(properties: &BasicProperties) -> BasicProperties {
+    let retried = requeue_properties(properties);
+    let mut headers = retried.headers().clone().unwrap_or_default();
+    headers.insert(
+        ShortString::from(RETRIES_HEADER),
+        AMQPValue::LongLongInt(i64::try_from(retries(properties) + 1).unwrap_or(i64::MAX)),
+    );
+    retried.with_headers(headers)
+}
+
+/// Properties to republish a dead-lettered delivery with: the original ones
+/// without the dead-lettering headers, so the signature header survives.
+pub fn requeue_properties(properties: &BasicProperties) -> BasicProperties {
+    let Some(headers) = properties.headers() else {
+        return properties.clone();
+    };
+
+    let mut kept = FieldTable::default();
+    for (name, value) in headers.inner() {
+        let name_str = name.as_str();
+        if !DEATH_HEADER_PREFIXES.iter().any(|prefix| name_str.starts_with(prefix)) {
+            kept.insert(ShortString::from(name_str), value.clone());
+        }
+    }
+    properties.clone().with_headers(kept)
+}
+
+/// A delivery's headers as JSON, for display.
+pub fn headers_json(properties: &BasicProperties) -> Value {
+    properties
+        .headers()
+        .as_ref()
+        .map(table_json)
+        .unwrap_or(Value::Null)
+}
+
+fn header<'a>(table: &'a FieldTable, name: &str) -> Option<&'a AMQPValue> {
+    table
+        .inner()
+        .iter()
+        .find(|(key, _)| key.as_str() == name)
+        .map(|(_, value)| value)
+}
+
+fn as_string(value: &AMQPValue) -> Option<String> {
+    match value {
+        AMQPValue::LongString(s) => Some(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+        AMQPValue::ShortString(s) => Some(s.as_str().to_string()),
+        _ => None,
+    }
+}
+
+fn as_i64(value: &AMQPValue) -> Option<i64> {
+    match *value {
+        AMQPValue::ShortShortInt(n) => Some(n.into()),
+        AMQPValue::ShortShortUInt(n) => Some(n.into()),
+        AMQPValue::ShortInt(n) => Some(n.into()),
+        AMQPValue::ShortUInt(n) => Some(n.into()),
+        AMQPValue::LongInt(n) => Some(n.into()),
+        AMQPValue::LongUInt(n) => Some(n.into()),
+        AMQPValue::LongLongInt(n) => Some(n),
+        _ => None,
+    }
+}
+
+fn table_json(table: &FieldTable) -> Value {
+    let map: Map<String, Value> = table
+        .inner()
+        .iter()
+        .map(|(key, value)| (key.as_str().to_string(), value_json(value)))
+        .collect();
+    Value::Object(map)
+}
+
+fn value_json(value: &AMQPValue) -> Value {
+    if let Some(n) = as_i64(value) {
+        return n.into();
+    }
+    if let Some(s) = as_string(value) {
+        return s.into();
+    }
+    match value {
+        AMQPValue::Boolean(b) => (*b).into(),
+        AMQPValue::Float(f) => (*f).into(),
+        AMQPValue::Double(f) => (*f).into(),
+        AMQPValue::Timestamp(t) => (*t).into(),
+        AMQPValue::FieldArray(array) => array.as_slice().iter().map(value_json).collect(),
+        AMQPValue::FieldTable(table) => table_json(table),
+        AMQPValue::Void => Value::Null,
+        other => format!("{:?}", other).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lapin::types::FieldArray;
+
+    fn death(queue: &str, reason: &str, count: i64) -> AMQPValue {
+        let mut table = FieldTable::default();
+        table.insert("queue".into(), AMQPValue::LongString(LongString::from(queue)));
+        table.insert("reason".into(), AMQPValue::LongString(LongString::from(reason)));
+        table.insert("count".into(), AMQPValue::LongLongInt(count));
+        AMQPValue::FieldTable(table)
+    }
+
+    fn dead_lettered() -> BasicProperties {
+        let mut headers = FieldTable::default();
+        headers.insert(
+            "x-death".into(),
+            AMQPValue::FieldArray(FieldArray::from(vec![
+                death("email_simulator.delayed", "expired", 1),
+                death("inbound_webhooks", "rejected", 2),
+            ])),
+        );
+        headers.insert(
+            "x-first-death-queue".into(),
+            AMQPValue::LongString(LongString::from("inbound_webhooks")),
+        );
+        headers.insert(
+            "x-bobnet-signature".into(),
+            AMQPValue::LongString(LongString::from("abc123")),
+        );
+        BasicProperties::default().with_headers(headers)
+    }
+
+    #[test]
+    fn test_death_of() {
+        assert_eq!(
+            Death::of(&dead_lettered()),
+            Some(Death {
+                queue: "inbound_webhooks".to_string(),
+                reason: "rejected".to_string(),
+                count: 3,
+            })
+        );
+        assert_eq!(Death::of(&BasicProperties::default()), None);
+    }
+
+    #[test]
+    fn test_death_of_delayed_jobs() {
+        // Held by the tenant quota delay, then moved to the DLQ as poison
+        let mut headers = FieldTable::default();
+        headers.insert(
+            "x-death".into(),
+            AMQPValue::FieldArray(FieldArray::from(vec![death(
+                "email_simulator.delayed",
+                "expired",
+                1,
+            )])),
+        );
+        for name in ["x-first-death-queue", "x-last-death-queue"] {
+            headers.insert(
+                name.into(),
+                AMQPValue::LongString(LongString::from("email_simulator.delayed")),
+            );
+        }
+        let delayed = BasicProperties::default().with_headers(headers.clone());
+        let poisoned = poison_properties(&delayed, "email_simulator", 5);
+        assert_eq!(
+            Death::of(&poisoned),
+            Some(Death {
+                queue: "email_simulator".to_string(),
+                reason: "poison".to_string(),
+                count: 1,
+            })
+        );
+
+        // Deferred, then rejected by the queue it returned to
+        headers.insert(
+            "x-death".into(),
+            AMQPValue::FieldArray(FieldArray::from(vec![
+                death("email_simulator", "rejected", 1),
+                death("email_simulator.delayed.utc-300", "expired", 1),
+            ])),
+        );
+        headers.insert(
+            "x-last-death-queue".into(),
+            AMQPValue::LongString(LongString::from("email_simulator")),
+        );
+        headers.insert(
+            "x-last-death-reason".into(),
+            AMQPValue::LongString(LongString::from("rejected")),
+        );
+        let rejected = BasicProperties::default().with_headers(headers);
+        assert_eq!(
+            Death::of(&rejected),
+            Some(Death {
+                queue: "email_simulator".to_string(),
+                reason: "rejected".to_string(),
+                count: 2,
+            })
+        );
+
+        // Only ever delayed: the queue it returns to
+        assert_eq!(Death::of(&delayed).unwrap().queue, "email_simulator");
+    }
+
+    #[test]
+    fn test_previous_deliveries() {
+        assert_eq!(previous_deliveries(&BasicProperties::default()), 0);
+        // Rejected twice; expiring out of the delay queue is no delivery
+        assert_eq!(previous_deliveries(&dead_lettered()), 2);
+
+        let mut headers = dead_lettered().headers().clone().unwrap();
+        headers.insert(DELIVERY_COUNT_HEADER.into(), AMQPValue::LongLongInt(2));
+        let properties = BasicProperties::default().with_headers(headers);
+        assert_eq!(previous_deliveries(&properties), 4);
+
+        // Delayed and deferred, but never run, with MAX_DELIVERY_ATTEMPTS=1
+        let mut headers = FieldTable::default();
+        headers.insert(
+            "x-death".into(),
+            AMQPValue::FieldArray(FieldArray::from(vec![
+                death("email_simulator.delayed.utc-300", "expired", 1),
+                death("email_simulator.delayed", "expired", 3),
+            ])),
+        );
+        let delayed = BasicProperties::default().with_headers(headers);
+        assert_eq!(previous_deliveries(&delayed), 0);
+        assert!(!is_poison(previous_deliveries(&delayed), 1));
+
+        assert!(is_poison(5, 5));
+        assert!(!is_poison(4, 5));
+        assert!(!is_poison(100, 0));
+    }
+
+    #[test]
+    fn test_failure_summary() {
+        assert_eq!(
+            failure_summary(&dead_lettered(), "email_simulator", 3),
+            "gave up on email_simulator after 3 deliveries; last dead-lettered from inbound_webhooks (rejected)"
+        );
+
+        let mut headers = FieldTable::default();
+        headers.insert(
+            FAILED_QUEUE_HEADER.into(),
+            AMQPValue::LongString(LongString::from("email_simulator")),
+        );
+        let poisoned = BasicProperties::default().with_headers(headers);
+        assert_eq!(
+            Death::of(&poisoned),
+            Some(Death {
+                queue: "email_simulator".to_string(),
+                reason: "poison".to_string(),
+                count: 1,
+            })
+        );
+        assert_eq!(previous_deliveries(&poisoned), 0);
+    }
+
+    #[test]
+    fn test_poison_properties_name_the_consumed_queue() {
+        // Consumed from the premium queue, published as `mailgun.acme`
+        let properties = poison_properties(&dead_lettered(), "email_simulator.premium", 5);
+        let headers = properties.headers().as_ref().unwrap();
+        assert_eq!(
+            header(headers, FAILED_QUEUE_HEADER).and_then(as_string).as_deref(),
+            Some("email_simulator.premium")
+        );
+        assert!(header(headers, FAILURE_HEADER)
+            .and_then(as_string)
+            .unwrap()
+            .starts_with("gave up on email_simulator.premium after 5 deliveries"));
+        assert!(header(headers, "x-bobnet-signature").is_some());
+    }
+
+    #[test]
+    fn test_requeue_properties_strip_death_headers() {
+        let properties = requeue_properties(&dead_lettered());
+        let headers = properties.headers().as_ref().unwrap();
+        assert!(header(headers, "x-death").is_none());
+        assert!(header(headers, "x-first-death-queue").is_none());
+        assert!(header(headers, "x-bobnet-signature").is_some());
+    }
+
+    #[test]
+    fn test_retries_count_as_deliveries() {
+        let once = retry_properties(&dead_lettered());
+        let headers = once.headers().as_ref().unwrap();
+        assert!(header(headers, "x-death").is_none());
+        assert!(header(headers, "x-bobnet-signature").is_some());
+        assert_eq!(retries(&once), 1);
+        assert_eq!(previous_deliveries(&once), 1);
+
+        let twice = retry_properties(&once);
+        assert_eq!(retries(&twice), 2);
+        assert_eq!(retries(&requeue_properties(&twice)), 0);
+    }
+
+    #[test]
+    fn test_headers_json() {
+        let json = headers_json(&dead_lettered());
+        assert_eq!(json["x-first-death-queue"], "inbound_webhooks");
+        assert_eq!(json["x-death"][1]["reason"], "rejected");
+        assert_eq!(json["x-death"][1]["count"], 2);
+        assert_eq!(headers_json(&BasicProperties::default()), Value::Null);
+    }
+}
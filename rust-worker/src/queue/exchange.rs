@@ -0,0 +1,95 @@
+//! Topic-exchange publishing.
+//!
+//! By default messages go straight to their queue through the default
+//! exchange. With `TOPIC_EXCHANGES` enabled, webhooks are published to the
+//! `bobnet.inbound` topic exchange and jobs to `bobnet.jobs`, with routing
+//! keys of the form `provider.tenant` (e.g. `mailgun.acme`, or
+//! `cloudflare.none` for recipients without a plus tag). The inbound queue is
+//! bound to every inbound key, and simulator queues are bound with the
+//! `JOB_BINDINGS` patterns, so consumers can be split per provider or per
+//! tenant by changing bindings instead of code.
+
+use crate::simulate::simulator::extract_plus_tag;
+
+use super::SIMULATOR_QUEUE;
+
+/// Topic exchange raw webhooks are published to.
+pub const INBOUND_EXCHANGE: &str = "bobnet.inbound";
+
+/// Topic exchange simulator jobs are published to.
+pub const JOBS_EXCHANGE: &str = "bobnet.jobs";
+
+/// Tenant segment of routing keys for recipients without a plus tag.
+pub const NO_TENANT: &str = "none";
+
+/// A simulator queue bound to [`JOBS_EXCHANGE`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueBinding {
+    pub queue: String,
+    /// Routing key pattern, where `*` matches one segment and `#` any number
+    pub pattern: String,
+}
+
+impl QueueBinding {
+    /// Parse an entry of the form `queue:pattern`, e.g.
+    /// `email_simulator.mailgun:mailgun.*`.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (queue, pattern) = entry.trim().split_once(':')?;
+        let queue = queue.trim();
+        let pattern = pattern.trim();
+        if queue.is_empty() || pattern.is_empty() || pattern.split('.').any(str::is_empty) {
+            return None;
+        }
+        Some(Self {
+            queue: queue.to_string(),
+            pattern: pattern.to_string(),
+        })
+    }
+}
+
+/// Bindings used when `JOB_BINDINGS` is unset: every job to `email_simulator`.
+pub fn default_bindings() -> Vec<QueueBinding> {
+    vec![QueueBinding {
+        queue: SIMULATOR_QUEUE.to_string(),
+        pattern: "#".to_string(),
+    }]
+}
+
+/// Routing key of a message from `provider` to the recipient `to`.
+///
+/// The tenant is the recipient's lowercase plus tag, with dots replaced so
+/// it stays one segment.
+pub fn routing_key(provider: &str, to: &str) -> String {
+    let tenant = extract_plus_tag(to)
+        .map(|tag| tag.to_lowercase().replace('.', "_"))
+        .filter(|tag| !tag.is_empty())
+        .unwrap_or_else(|| NO_TENANT.to_string());
+    format!("{}.{}", provider, tenant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binding_parse() {
+        assert_eq!(
+            QueueBinding::parse(" email_simulator.mailgun : mailgun.* "),
+            Some(QueueBinding {
+                queue: "email_simulator.mailgun".to_string(),
+                pattern: "mailgun.*".to_string(),
+            })
+        );
+        assert_eq!(QueueBinding::parse("email_simulator"), None);
+        assert_eq!(QueueBinding::parse("email_simulator:"), None);
+        assert_eq!(QueueBinding::parse(":#"), None);
+        assert_eq!(QueueBinding::parse("email_simulator:mailgun..acme"), None);
+    }
+
+    #[test]
+    fn test_routing_key() {
+        assert_eq!(routing_key("mailgun", "user+Acme@example.com"), "mailgun.acme");
+        assert_eq!(routing_key("cloudflare", "user@example.com"), "cloudflare.none");
+        assert_eq!(routing_key("generic", "user+acme.eu@example.com"), "generic.acme_eu");
+    }
+}
@@ -0,0 +1,318 @@
+//! Broker failover across several AMQP URLs.
+//!
+//! `CLOUDAMQP_URL` is the primary broker and `CLOUDAMQP_FAILOVER_URLS` lists
+//! others (CloudAMQP cluster nodes, or a DR broker) in order of preference.
+//! Connecting tries them in that order, skipping to the next on failure. A
+//! broker that failed is tried last until `BROKER_FAILBACK_SECS` have passed,
+//! so a dead primary does not hold up every reconnect, yet the primary is
+//! preferred again once it may have recovered.
+//!
+//! Every connection gets the `AMQP_*` heartbeat, timeout and frame settings,
+//! overriding any in the URL's query string.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lapin::uri::AMQPUri;
+use lapin::{Connection, ConnectionProperties};
+use tracing::{info, warn};
+
+use crate::config::CoreConfig;
+use crate::util::redact::redact_url;
+
+/// Ordered broker URLs, the first being the primary.
+///
+/// Clones share which brokers recently failed.
+#[derive(Clone)]
+pub struct BrokerUrls {
+    inner: Arc<BrokerInner>,
+    tuning: Tuning,
+}
+
+/// Connection settings applied to every broker; unset ones are left to the
+/// URL or the broker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tuning {
+    /// Heartbeat interval in seconds (`0` disables heartbeats)
+    pub heartbeat_secs: Option<u16>,
+    /// Milliseconds to wait for the TCP connection
+    pub connection_timeout_ms: Option<u64>,
+    /// Largest frame in bytes
+    pub frame_max: Option<u32>,
+    /// Most channels per connection
+    pub channel_max: Option<u16>,
+}
+
+impl Tuning {
+    /// The `AMQP_*` settings, clamped to what the protocol can carry.
+    pub fn from_config(core: &CoreConfig) -> Self {
+        Self {
+            heartbeat_secs: core
+                .amqp_heartbeat_secs
+                .map(|secs| secs.try_into().unwrap_or(u16::MAX)),
+            connection_timeout_ms: core.amqp_connection_timeout_ms,
+            frame_max: core
+                .amqp_frame_max
+                .map(|max| max.try_into().unwrap_or(u32::MAX)),
+            channel_max: core
+                .amqp_channel_max
+                .map(|max| max.try_into().unwrap_or(u16::MAX)),
+        }
+    }
+
+    /// Set the configured values in `uri`'s query string.
+    pub fn apply(&self, uri: &mut AMQPUri) {
+        let query = &mut uri.query;
+        query.heartbeat = self.heartbeat_secs.or(query.heartbeat);
+        query.connection_timeout = self.connection_timeout_ms.or(query.connection_timeout);
+        query.frame_max = self.frame_max.or(query.frame_max);
+        query.channel_max = self.channel_max.or(query.channel_max);
+    }
+}
+
+struct BrokerInner {
+    urls: Vec<String>,
+    failback: Duration,
+    /// When each broker last failed to connect
+    failed_at: Mutex<Vec<Option<Instant>>>,
+}
+
+impl BrokerUrls {
+    /// Brokers to try in order, retrying a failed one first again after
+    /// `failback`.
+    ///
+    /// # Panics
+    ///
+    /// If `urls` is empty.
+    pub fn new(urls: Vec<String>, failback: Duration) -> Self {
+        assert!(!urls.is_empty(), "at least one broker URL is required");
+        let failed_at = vec![None; urls.len()];
+        Self {
+            inner: Arc::new(BrokerInner {
+                urls,
+                failback,
+                failed_at: Mutex::new(failed_at),
+            }),
+            tuning: Tuning::default(),
+        }
+    }
+
+    /// The brokers of `CLOUDAMQP_URL` and `CLOUDAMQP_FAILOVER_URLS`, with the
+    /// `AMQP_*` connection settings.
+    pub fn from_config(core: &CoreConfig) -> Self {
+        Self::new(
+            core.broker_urls(),
+            Duration::from_secs(core.broker_failback_secs),
+        )
+        .with_tuning(Tuning::from_config(core))
+    }
+
+    /// Apply `tuning` to every connection.
+    pub fn with_tuning(mut self, tuning: Tuning) -> Self {
+        self.tuning = tuning;
+        self
+    }
+
+    /// A single broker without failover.
+    pub fn single(url: String) -> Self {
+        Self::new(vec![url], Duration::ZERO)
+    }
+
+    /// Every broker URL, the primary first.
+    pub fn urls(&self) -> &[String] {
+        &self.inner.urls
+    }
+
+    /// How long a failed broker is tried last.
+    pub fn failback(&self) -> Duration {
+        self.inner.failback
+    }
+
+    /// Whether the primary has not failed within the failback interval.
+    pub fn primary_due(&self) -> bool {
+        let failed_at = self.failed_at();
+        self.is_due(failed_at[0])
+    }
+
+    /// Broker indices in the order to try them: those that have not failed
+    /// recently in configured order, then the rest, least recently failed
+    /// first.
+    pub fn order(&self) -> Vec<usize> {
+        let failed_at = self.failed_at();
+        let (mut order, mut recent): (Vec<usize>, Vec<usize>) =
+            (0..self.inner.urls.len()).partition(|&i| self.is_due(failed_at[i]));
+        recent.sort_by_key(|&i| failed_at[i]);
+        order.extend(recent);
+        order
+    }
+
+    /// Run `attempt` against each broker in [`order`](Self::order) until one
+    /// succeeds, returning its result and the broker's index, or the last
+    /// error.
+    pub async fn try_each<T, E, F, Fut>(&self, mut attempt: F) -> Result<(T, usize), E>
+    where
+        E: Display,
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut last_error = None;
+        for index in self.order() {
+            let url = self.inner.urls[index].clone();
+            match attempt(url).await {
+                Ok(value) => {
+                    self.mark_ok(index);
+                    if index > 0 {
+                        warn!(broker = index, url = %self.describe(index), "rabbitmq_failed_over");
+                    }
+                    return Ok((value, index));
+                }
+                Err(e) => {
+                    self.mark_failed(index);
+                    if self.inner.urls.len() > 1 {
+                        warn!(
+                            broker = index,
+                            url = %self.describe(index),
+                            error = %e,
+                            "rabbitmq_broker_connect_failed"
+                        );
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.expect("at least one broker was tried"))
+    }
+
+    /// Connect to the first broker that accepts, returning the connection
+    /// and the broker's index.
+    ///
+    /// Consumers connect through this once and stay on whichever broker they
+    /// got until restarted: moving them back to the primary would redeliver
+    /// every unacked job.
+    pub async fn connect(&self) -> Result<(Connection, usize), lapin::Error> {
+        self.try_each(|url| async move { self.connect_to(&url).await })
+            .await
+    }
+
+    /// Connect to `url` with the connection settings.
+    pub async fn connect_to(&self, url: &str) -> Result<Connection, lapin::Error> {
+        let mut uri: AMQPUri = url
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.tuning.apply(&mut uri);
+        Connection::connect_uri(uri, ConnectionProperties::default()).await
+    }
+
+    /// Record that the broker at `index` failed.
+    pub fn mark_failed(&self, index: usize) {
+        self.failed_at_mut()[index] = Some(Instant::now());
+    }
+
+    /// Record that the broker at `index` accepted a connection.
+    pub fn mark_ok(&self, index: usize) {
+        let recovered = self.failed_at_mut()[index].take().is_some();
+        if recovered && index == 0 {
+            info!("rabbitmq_primary_recovered");
+        }
+    }
+
+    /// Redacted URL of the broker at `index`, for logs.
+    pub fn describe(&self, index: usize) -> String {
+        redact_url(&self.inner.urls[index])
+    }
+
+    fn is_due(&self, failed_at: Option<Instant>) -> bool {
+        failed_at.is_none_or(|at| at.elapsed() >= self.inner.failback)
+    }
+
+    fn failed_at(&self) -> Vec<Option<Instant>> {
+        self.failed_at_mut().clone()
+    }
+
+    fn failed_at_mut(&self) -> std::sync::MutexGuard<'_, Vec<Option<Instant>>> {
+        self.inner.failed_at.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brokers(failback: Duration) -> BrokerUrls {
+        BrokerUrls::new(
+            vec![
+                "amqp://primary".to_string(),
+                "amqp://secondary".to_string(),
+                "amqp://dr".to_string(),
+            ],
+            failback,
+        )
+    }
+
+    #[test]
+    fn test_order_prefers_primary() {
+        let brokers = brokers(Duration::from_secs(60));
+        assert_eq!(brokers.order(), vec![0, 1, 2]);
+
+        brokers.mark_failed(0);
+        assert_eq!(brokers.order(), vec![1, 2, 0]);
+        assert!(!brokers.primary_due());
+
+        brokers.mark_failed(1);
+        assert_eq!(brokers.order(), vec![2, 0, 1]);
+
+        brokers.mark_ok(0);
+        assert_eq!(brokers.order(), vec![0, 2, 1]);
+        assert!(brokers.primary_due());
+    }
+
+    #[test]
+    fn test_failed_primary_is_preferred_after_failback() {
+        let brokers = brokers(Duration::ZERO);
+        brokers.mark_failed(0);
+        assert_eq!(brokers.order(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_tuning_overrides_query_string() {
+        let mut uri: AMQPUri = "amqps://u:p@host/vhost?heartbeat=60&channel_max=32"
+            .parse()
+            .unwrap();
+        let tuning = Tuning {
+            heartbeat_secs: Some(10),
+            connection_timeout_ms: Some(5000),
+            ..Tuning::default()
+        };
+        tuning.apply(&mut uri);
+
+        assert_eq!(uri.query.heartbeat, Some(10));
+        assert_eq!(uri.query.connection_timeout, Some(5000));
+        assert_eq!(uri.query.channel_max, Some(32));
+        assert_eq!(uri.query.frame_max, None);
+    }
+
+    #[tokio::test]
+    async fn test_try_each_fails_over() {
+        let brokers = brokers(Duration::from_secs(60));
+        let result = brokers
+            .try_each(|url| async move {
+                if url == "amqp://secondary" {
+                    Ok(url)
+                } else {
+                    Err(format!("{} is down", url))
+                }
+            })
+            .await;
+        assert_eq!(result, Ok(("amqp://secondary".to_string(), 1)));
+        // The primary failed, so the secondary is tried first next time
+        assert_eq!(brokers.order(), vec![1, 2, 0]);
+
+        let result: Result<((), usize), String> = brokers
+            .try_each(|url| async move { Err(format!("{} is down", url)) })
+            .await;
+        assert_eq!(result, Err("amqp://primary is down".to_string()));
+    }
+}
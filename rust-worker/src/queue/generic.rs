@@ -0,0 +1,222 @@
+//! Config-driven JSON webhook sources.
+//!
+//! `GENERIC_WEBHOOKS` maps named one-off sources onto `/webhooks/generic/{name}`
+//! without writing a provider in Rust, e.g.
+//! `acme:data.to:data.body.html:data.id:X-Acme-Token`. The web server picks
+//! the recipient, HTML and Message-Id out of the posted JSON with the
+//! entry's dotted paths (numeric segments index arrays) and enqueues them as
+//! a [`GenericRawPayload`](super::GenericRawPayload). Sources naming an auth
+//! header accept only requests carrying one of their `GENERIC_WEBHOOK_TOKENS`.
+
+use serde_json::Value;
+
+/// Field mapping of one generic webhook source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenericSource {
+    /// Lowercase source name, the last segment of its webhook path
+    pub name: String,
+    /// Dotted path of the recipient address
+    pub recipient_path: String,
+    /// Dotted path of the HTML body
+    pub html_path: String,
+    /// Dotted path of the Message-Id (a fallback is derived when unset)
+    pub message_id_path: Option<String>,
+    /// Header that must carry one of the source's tokens (no auth when unset)
+    pub auth_header: Option<String>,
+}
+
+impl GenericSource {
+    /// Parse an entry of the form
+    /// `name:recipient_path:html_path[:message_id_path[:auth_header]]`.
+    ///
+    /// Names are letters, digits, `-` or `_`. Leave `message_id_path` empty to
+    /// name an auth header without one.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let mut parts = entry.trim().split(':').map(str::trim);
+        let name = parts.next()?.to_lowercase();
+        let recipient_path = parts.next()?.to_string();
+        let html_path = parts.next()?.to_string();
+        let message_id_path = parts.next().filter(|p| !p.is_empty()).map(str::to_string);
+        let auth_header = parts.next().filter(|h| !h.is_empty()).map(str::to_string);
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        let valid_header = auth_header.as_deref().is_none_or(is_valid_header_name);
+        let valid_paths = [Some(&recipient_path), Some(&html_path), message_id_path.as_ref()]
+            .into_iter()
+            .flatten()
+            .all(|path| is_valid_path(path));
+        if parts.next().is_some() || !valid_name || !valid_header || !valid_paths {
+            return None;
+        }
+        Some(Self {
+            name,
+            recipient_path,
+            html_path,
+            message_id_path,
+            auth_header,
+        })
+    }
+
+    /// The recipient, HTML and Message-Id of a posted `body`.
+    ///
+    /// Fails with the missing path when there is no recipient; a missing
+    /// HTML body or Message-Id is simply absent.
+    pub fn extract(&self, body: &Value) -> Result<Extracted, String> {
+        let to = lookup(body, &self.recipient_path)
+            .filter(|to| !to.trim().is_empty())
+            .ok_or_else(|| format!("no recipient at `{}`", self.recipient_path))?;
+        Ok(Extracted {
+            to,
+            html: lookup(body, &self.html_path),
+            message_id: self
+                .message_id_path
+                .as_deref()
+                .and_then(|path| lookup(body, path)),
+        })
+    }
+}
+
+/// Fields picked out of a generic webhook body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extracted {
+    pub to: String,
+    pub html: Option<String>,
+    pub message_id: Option<String>,
+}
+
+/// One accepted token of a generic source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenericToken {
+    /// Lowercase source name
+    pub source: String,
+    pub token: String,
+}
+
+impl GenericToken {
+    /// Parse an entry of the form `name:token`.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (source, token) = entry.trim().split_once(':')?;
+        let source = source.trim().to_lowercase();
+        let token = token.trim();
+        if source.is_empty() || token.is_empty() {
+            return None;
+        }
+        Some(Self {
+            source,
+            token: token.to_string(),
+        })
+    }
+}
+
+/// The string at a dotted `path` in `value`.
+///
+/// Numbers and booleans are returned as their JSON text, so numeric IDs can
+/// be used as Message-Ids; objects, arrays and nulls are not strings.
+pub fn lookup(value: &Value, path: &str) -> Option<String> {
+    let found = path.split('.').try_fold(value, |value, segment| match value {
+        Value::Object(map) => map.get(segment),
+        Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })?;
+    match found {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn is_valid_path(path: &str) -> bool {
+    !path.is_empty() && path.split('.').all(|segment| !segment.is_empty())
+}
+
+/// Whether `name` is a valid HTTP header name (an RFC 9110 token).
+fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_source_parse() {
+        assert_eq!(
+            GenericSource::parse(" Acme : data.to : data.body.html : data.id : X-Acme-Token "),
+            Some(GenericSource {
+                name: "acme".to_string(),
+                recipient_path: "data.to".to_string(),
+                html_path: "data.body.html".to_string(),
+                message_id_path: Some("data.id".to_string()),
+                auth_header: Some("X-Acme-Token".to_string()),
+            })
+        );
+
+        let source = GenericSource::parse("acme:to:html::X-Token").unwrap();
+        assert_eq!(source.message_id_path, None);
+        assert_eq!(source.auth_header.as_deref(), Some("X-Token"));
+        assert_eq!(GenericSource::parse("acme:to:html").unwrap().auth_header, None);
+
+        assert_eq!(GenericSource::parse("acme:to"), None);
+        assert_eq!(GenericSource::parse("ac.me:to:html"), None);
+        assert_eq!(GenericSource::parse("acme:data..to:html"), None);
+        assert_eq!(GenericSource::parse("acme:to:html:id:X Token"), None);
+        assert_eq!(GenericSource::parse("acme:to:html:id:X-Token:extra"), None);
+    }
+
+    #[test]
+    fn test_extract() {
+        let source = GenericSource::parse("acme:data.recipients.0:data.html:data.id").unwrap();
+        let body = json!({
+            "data": {
+                "recipients": ["user@example.com", "other@example.com"],
+                "html": "<a href=\"https://example.com\">Hi</a>",
+                "id": 12345
+            }
+        });
+
+        assert_eq!(
+            source.extract(&body),
+            Ok(Extracted {
+                to: "user@example.com".to_string(),
+                html: Some("<a href=\"https://example.com\">Hi</a>".to_string()),
+                message_id: Some("12345".to_string()),
+            })
+        );
+
+        let body = json!({ "data": { "recipients": [] } });
+        assert_eq!(
+            source.extract(&body),
+            Err("no recipient at `data.recipients.0`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lookup_non_strings() {
+        let body = json!({ "a": { "b": null, "c": [1, 2], "d": true } });
+        assert_eq!(lookup(&body, "a.b"), None);
+        assert_eq!(lookup(&body, "a.c"), None);
+        assert_eq!(lookup(&body, "a.c.1"), Some("2".to_string()));
+        assert_eq!(lookup(&body, "a.d"), Some("true".to_string()));
+        assert_eq!(lookup(&body, "a.d.e"), None);
+    }
+
+    #[test]
+    fn test_token_parse() {
+        assert_eq!(
+            GenericToken::parse("Acme:s3cr:et"),
+            Some(GenericToken {
+                source: "acme".to_string(),
+                token: "s3cr:et".to_string(),
+            })
+        );
+        assert_eq!(GenericToken::parse("acme:"), None);
+        assert_eq!(GenericToken::parse("s3cret"), None);
+    }
+}
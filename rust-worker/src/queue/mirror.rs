@@ -0,0 +1,130 @@
+//! Mirroring live jobs to a shadow simulator queue.
+//!
+//! With `MIRROR_PERCENT` set, the processor also publishes that share of its
+//! jobs to `MIRROR_QUEUE`, on the `MIRROR_AMQP_URL` broker or vhost when
+//! set, so a new worker build consuming the shadow queue runs against live
+//! traffic shapes. Mirrored jobs are marked [`dry_run`](JobOverrides::dry_run):
+//! the shadow worker makes every decision but fetches nothing, so no open
+//! or click is counted twice, and they carry no callback URL.
+//!
+//! Jobs are picked by hashing their Message-Id, so a redelivered webhook is
+//! mirrored only if the first delivery was. Mirroring is best effort: a
+//! failed publish is logged as `mirror_publish_failed` and never holds up
+//! the job.
+
+use tracing::{info, warn};
+
+use crate::util::hash::stable_hash;
+
+use super::publisher::Publisher;
+use super::types::{JobOverrides, SimulatorJob};
+
+/// Publishes a share of jobs, marked dry-run, to a shadow queue.
+#[derive(Clone)]
+pub struct Mirror {
+    publisher: Publisher,
+    queue: String,
+    percent: f64,
+}
+
+impl Mirror {
+    /// Mirror `percent` (0 - 100) of jobs to `queue` through `publisher`.
+    pub fn new(publisher: Publisher, queue: impl Into<String>, percent: f64) -> Self {
+        Self {
+            publisher,
+            queue: queue.into(),
+            percent,
+        }
+    }
+
+    /// Queue mirrored jobs are published to.
+    pub fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    /// The dry-run copy of `job` to mirror, if its Message-Id is in the
+    /// mirrored share.
+    pub fn shadow_of(&self, job: &SimulatorJob) -> Option<SimulatorJob> {
+        if !selects(&job.message_id, self.percent) {
+            return None;
+        }
+        Some(SimulatorJob {
+            overrides: JobOverrides {
+                dry_run: true,
+                callback_url: None,
+                ..job.overrides.clone()
+            },
+            ..job.clone()
+        })
+    }
+
+    /// Publish `shadow` to the shadow queue, logging rather than returning
+    /// a failure.
+    pub async fn publish(&self, shadow: &SimulatorJob) {
+        match self.publisher.publish_simulator_to(&self.queue, shadow).await {
+            Ok(()) => info!(
+                message_id = %shadow.message_id,
+                queue = %self.queue,
+                "job_mirrored"
+            ),
+            Err(e) => warn!(
+                message_id = %shadow.message_id,
+                queue = %self.queue,
+                error = %e,
+                "mirror_publish_failed"
+            ),
+        }
+    }
+
+    /// Close the mirror's broker connection.
+    pub async fn close(&self) {
+        self.publisher.close().await;
+    }
+}
+
+/// Whether `message_id` falls in the mirrored `percent` of jobs.
+fn selects(message_id: &str, percent: f64) -> bool {
+    if percent <= 0.0 {
+        return false;
+    }
+    ((stable_hash(&[message_id]) % 10_000) as f64 / 100.0) < percent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selects_share_of_jobs() {
+        assert!(!selects("msg-1", 0.0));
+        assert!(selects("msg-1", 100.0));
+        // Pinned, so every processor and every release mirrors the same jobs
+        assert!(selects("msg-1@example.com", 40.0));
+        assert!(!selects("msg-0@example.com", 40.0));
+        let mirrored = (0..2000)
+            .filter(|i| selects(&format!("msg-{}@example.com", i), 10.0))
+            .count();
+        assert!((100..300).contains(&mirrored), "mirrored {} of 2000", mirrored);
+    }
+
+    #[test]
+    fn test_shadow_jobs_are_dry_runs() {
+        let mirror = Mirror::new(Publisher::new("amqp://localhost".into()), "shadow", 100.0);
+        let job = SimulatorJob::builder()
+            .message_id("msg-1")
+            .to("user+acme@example.com")
+            .html("<p>Hi</p>")
+            .max_clicks(1)
+            .callback_url("https://hooks.example.com/report")
+            .build();
+        let shadow = mirror.shadow_of(&job).unwrap();
+        assert!(shadow.overrides.dry_run);
+        assert_eq!(shadow.overrides.callback_url, None);
+        assert_eq!(shadow.overrides.max_clicks, Some(1));
+        assert_eq!(shadow.html, job.html);
+        assert!(!job.overrides.dry_run);
+
+        let mirror = Mirror::new(Publisher::new("amqp://localhost".into()), "shadow", 0.0);
+        assert!(mirror.shadow_of(&job).is_none());
+    }
+}
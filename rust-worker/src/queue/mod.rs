@@ -3,6 +3,19 @@
 //! This module provides:
 //! - Message types for the two-queue architecture
 //! - Async publisher for enqueueing messages
+//! - Failover between several brokers
+//! - Consumer channels spread over several connections
+//! - Prefetch auto-tuning from job durations
+//! - A durable local outbox for messages the broker has not confirmed
+//! - Helpers for inspecting dead-lettered messages
+//! - Priority simulator queues and tier stamping
+//! - Request IDs that trace a message across the services
+//! - Field mappings of config-driven generic webhook sources
+//! - Optional topic exchanges with `provider.tenant` routing keys
+//! - Mirroring a share of jobs, as dry runs, to a shadow simulator queue
+//!
+//! Only the message types, request ID helpers, generic mappings and routing
+//! keys are available without the `amqp` feature.
 //!
 //! ## Architecture
 //!
@@ -10,11 +23,50 @@
 //! Web Server → inbound_webhooks queue → Processor → email_simulator queue → Worker
 //! ```
 
+#[cfg(feature = "amqp")]
+pub mod consumers;
+#[cfg(feature = "amqp")]
+pub mod dead_letter;
+pub mod exchange;
+#[cfg(feature = "amqp")]
+pub mod failover;
+pub mod generic;
+#[cfg(feature = "amqp")]
+pub mod mirror;
+#[cfg(feature = "amqp")]
+pub mod outbox;
+#[cfg(feature = "amqp")]
+pub mod prefetch;
+#[cfg(feature = "amqp")]
+pub mod priority;
+#[cfg(feature = "amqp")]
 pub mod publisher;
+pub mod request_id;
+#[cfg(feature = "amqp")]
+pub mod signing;
 pub mod types;
 
+#[cfg(feature = "amqp")]
+pub use consumers::ConsumerChannels;
+#[cfg(feature = "amqp")]
+pub use dead_letter::{Death, DEAD_LETTER_QUEUE, FAILURE_HEADER};
+pub use exchange::{QueueBinding, INBOUND_EXCHANGE, JOBS_EXCHANGE};
+#[cfg(feature = "amqp")]
+pub use failover::BrokerUrls;
+pub use generic::{GenericSource, GenericToken};
+#[cfg(feature = "amqp")]
+pub use mirror::Mirror;
+#[cfg(feature = "amqp")]
+pub use outbox::Outbox;
+#[cfg(feature = "amqp")]
+pub use priority::TIER_HEADER;
+#[cfg(feature = "amqp")]
 pub use publisher::Publisher;
+pub use request_id::REQUEST_ID_HEADER;
+#[cfg(feature = "amqp")]
+pub use signing::{MessageSigner, SignatureError};
 pub use types::{
-    CloudflareRawPayload, InboundWebhook, MailgunRawPayload, SimulatorJob,
-    INBOUND_QUEUE, SIMULATOR_QUEUE,
+    CloudflareRawPayload, GenericRawPayload, InboundWebhook, JobOverrides, MailgunRawPayload,
+    SimulatorJob, SimulatorJobBuilder, CLOUDFLARE_SIMULATOR_QUEUE, GENERIC_SIMULATOR_QUEUE,
+    INBOUND_QUEUE, MAILGUN_SIMULATOR_QUEUE, MIRROR_QUEUE, SIMULATOR_QUEUE, SIMULATOR_QUEUES,
 };
@@ -0,0 +1,298 @@
+//! Durable local outbox for the publisher.
+//!
+//! With `PUBLISH_OUTBOX_DIR` set, the web server journals each message to
+//! disk before publishing it and deletes it once the broker confirms it. A
+//! message the broker could not take stays journaled and the webhook is
+//! still accepted; a background task replays the journal, oldest first,
+//! until the broker takes them, including after a restart. So webhooks
+//! accepted during a brief broker outage survive the process dying.
+//!
+//! Each message is one fsynced JSON file named by its sequence number, so a
+//! crash mid-write leaves at most a stray `.tmp` file, which is discarded.
+//! Replayed messages may be published twice if the process dies between the
+//! broker's confirm and the delete; consumers already dedupe on Message-Id.
+//! Delivery through the outbox is therefore at-least-once.
+//!
+//! A directory of files serves here instead of an embedded store such as
+//! sled or redb: each entry is written once and deleted once, with no
+//! updates or lookups by key, so a store would add a dependency and its own
+//! compaction for nothing a directory lacks, and operators can inspect or
+//! drop stuck entries with `ls` and `rm`. Nothing needs compacting, since a
+//! delete frees its entry at once; instead the journal is capped at
+//! `PUBLISH_OUTBOX_MAX_BYTES`, so a long broker outage cannot fill the disk
+//! or make each replay read an unbounded backlog. A message that doesn't fit
+//! is not journaled and is only published directly.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::tenant::tier::TenantTier;
+
+/// A journaled message, with what is needed to publish it again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Exchange to publish to (`""` for the default exchange)
+    pub exchange: String,
+    pub routing_key: String,
+    pub message_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Tier the message is stamped with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<TenantTier>,
+    /// The JSON body
+    pub body: String,
+}
+
+/// Messages journaled in a directory until the broker confirms them.
+pub struct Outbox {
+    dir: PathBuf,
+    next: AtomicU64,
+    /// Entries a publish is still waiting on, which replays skip
+    in_flight: Mutex<HashSet<u64>>,
+    /// Bytes journaled, at most `max_bytes` (`0` for no limit)
+    bytes: AtomicU64,
+    max_bytes: u64,
+    /// Messages not journaled because the outbox was full
+    rejected: AtomicU64,
+}
+
+impl Outbox {
+    /// Open the outbox in `dir`, creating it if needed and discarding
+    /// partially written entries.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut last = 0;
+        let mut bytes = 0;
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("tmp") => fs::remove_file(&path)?,
+                Some("json") => {
+                    last = last.max(sequence(&path).unwrap_or(0));
+                    bytes += entry.metadata()?.len();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            dir,
+            next: AtomicU64::new(last + 1),
+            in_flight: Mutex::new(HashSet::new()),
+            bytes: AtomicU64::new(bytes),
+            max_bytes: 0,
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    /// Journal at most `max_bytes` of messages (`0` for no limit).
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Bytes of messages journaled.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Messages not journaled since opening because the outbox was full.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Durably journal `entry`, returning its sequence number. Replays skip
+    /// it until it is [`release`](Self::release)d.
+    ///
+    /// Fails with [`io::ErrorKind::StorageFull`] when the entry would take
+    /// the outbox over its size limit.
+    pub fn record(&self, entry: &OutboxEntry) -> io::Result<u64> {
+        let json = serde_json::to_vec(entry).map_err(io::Error::other)?;
+        let size = json.len() as u64;
+        let reserved = self.bytes.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bytes| {
+            (self.max_bytes == 0 || bytes + size <= self.max_bytes).then_some(bytes + size)
+        });
+        if reserved.is_err() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!("outbox is full ({} byte limit)", self.max_bytes),
+            ));
+        }
+
+        let written = self.write(&json);
+        if written.is_err() {
+            self.bytes.fetch_sub(size, Ordering::Relaxed);
+        }
+        written
+    }
+
+    fn write(&self, json: &[u8]) -> io::Result<u64> {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let partial = self.dir.join(format!("{:020}.tmp", seq));
+        let mut file = File::create(&partial)?;
+        file.write_all(json)?;
+        file.sync_all()?;
+        fs::rename(&partial, self.path(seq))?;
+        sync_dir(&self.dir)?;
+
+        self.in_flight().insert(seq);
+        Ok(seq)
+    }
+
+    /// Delete a published entry.
+    pub fn remove(&self, seq: u64) -> io::Result<()> {
+        self.in_flight().remove(&seq);
+        let path = self.path(seq);
+        let size = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            Err(_) => Ok(()),
+            Ok(()) => {
+                let _ = self.bytes.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bytes| {
+                    Some(bytes.saturating_sub(size))
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Leave an entry that could not be published to the replays.
+    pub fn release(&self, seq: u64) {
+        self.in_flight().remove(&seq);
+    }
+
+    /// Journaled entries no publish is waiting on, oldest first.
+    ///
+    /// Unreadable entries are logged and skipped, and left on disk for
+    /// inspection.
+    pub fn pending(&self) -> io::Result<Vec<(u64, OutboxEntry)>> {
+        let in_flight = self.in_flight().clone();
+        let mut pending = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(seq) = sequence(&path) else { continue };
+            if in_flight.contains(&seq) {
+                continue;
+            }
+            let parsed = fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|json| serde_json::from_slice(&json).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(entry) => pending.push((seq, entry)),
+                Err(e) => warn!(path = %path.display(), error = %e, "outbox_entry_unreadable"),
+            }
+        }
+        pending.sort_by_key(|&(seq, _)| seq);
+        Ok(pending)
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.json", seq))
+    }
+
+    fn in_flight(&self) -> std::sync::MutexGuard<'_, HashSet<u64>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Sequence number of an entry file.
+fn sequence(path: &Path) -> Option<u64> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+/// Persist a rename into `dir`.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message_id: &str) -> OutboxEntry {
+        OutboxEntry {
+            exchange: String::new(),
+            routing_key: "inbound_webhooks".to_string(),
+            message_id: message_id.to_string(),
+            request_id: Some("req-1".to_string()),
+            tier: None,
+            body: r#"{"provider":"cloudflare"}"#.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_outbox_survives_reopening() {
+        let dir = std::env::temp_dir().join(format!("bobnet-outbox-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let outbox = Outbox::open(&dir).unwrap();
+        let first = outbox.record(&entry("a")).unwrap();
+        let second = outbox.record(&entry("b")).unwrap();
+        let third = outbox.record(&entry("c")).unwrap();
+
+        // Publishes in flight are not replayed
+        assert!(outbox.pending().unwrap().is_empty());
+        outbox.remove(first).unwrap();
+        outbox.release(second);
+        assert_eq!(outbox.pending().unwrap(), vec![(second, entry("b"))]);
+
+        // A restart replays everything still journaled, after the newest
+        fs::write(dir.join("00000000000000000099.tmp"), "{").unwrap();
+        drop(outbox);
+        let outbox = Outbox::open(&dir).unwrap();
+        assert_eq!(
+            outbox.pending().unwrap(),
+            vec![(second, entry("b")), (third, entry("c"))]
+        );
+        assert!(outbox.record(&entry("d")).unwrap() > third);
+        assert!(!dir.join("00000000000000000099.tmp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_outbox_is_capped() {
+        let dir = std::env::temp_dir().join(format!("bobnet-outbox-cap-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let size = serde_json::to_vec(&entry("a")).unwrap().len() as u64;
+        let outbox = Outbox::open(&dir).unwrap().with_max_bytes(2 * size);
+        let first = outbox.record(&entry("a")).unwrap();
+        outbox.record(&entry("b")).unwrap();
+        assert_eq!(outbox.bytes(), 2 * size);
+
+        let full = outbox.record(&entry("c")).unwrap_err();
+        assert_eq!(full.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(outbox.rejected(), 1);
+
+        // A published entry makes room again, and reopening counts what's left
+        outbox.remove(first).unwrap();
+        assert_eq!(outbox.bytes(), size);
+        outbox.record(&entry("c")).unwrap();
+        drop(outbox);
+        assert_eq!(Outbox::open(&dir).unwrap().bytes(), 2 * size);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+//! Prefetch auto-tuning from job durations.
+//!
+//! A fixed prefetch suits one kind of job. With fast jobs it leaves the
+//! worker idle while it waits on the broker for more; with slow ones every
+//! prefetched job sits unacknowledged in one worker, and all of them are
+//! redelivered at once when it restarts or trips the broker's consumer
+//! timeout. With `PREFETCH_AUTOTUNE` the worker sizes its prefetch from what
+//! it observes instead: one delivery per concurrency slot, plus the jobs it
+//! gets through in `PREFETCH_BUFFER_MS`, estimated from the jobs in flight and
+//! their average duration (Little's law).
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Smallest relative change worth restarting the consumers for.
+const MIN_CHANGE: f64 = 0.1;
+
+/// Job durations observed since the last tuning.
+#[derive(Default)]
+struct Window {
+    jobs: u32,
+    total: Duration,
+}
+
+/// Picks the prefetch of each consumer channel from recent job durations.
+pub struct PrefetchTuner {
+    buffer: Duration,
+    max: u16,
+    window: Mutex<Window>,
+}
+
+impl PrefetchTuner {
+    /// Keep `buffer` worth of jobs prefetched beyond the running ones, with
+    /// at most `max` deliveries per channel.
+    pub fn new(buffer: Duration, max: u16) -> Self {
+        Self {
+            buffer,
+            max: max.max(1),
+            window: Mutex::new(Window::default()),
+        }
+    }
+
+    /// Record how long a job took.
+    pub fn record(&self, duration: Duration) {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        window.jobs = window.jobs.saturating_add(1);
+        window.total += duration;
+    }
+
+    /// The prefetch each of `channels` channels should have, given the
+    /// `concurrency` jobs the worker may run at once and the `in_flight` it
+    /// holds, or `None` to keep `current`: when no job finished since the
+    /// last call, or the change would be under 10%.
+    pub fn next(
+        &self,
+        concurrency: usize,
+        in_flight: usize,
+        channels: usize,
+        current: u16,
+    ) -> Option<u16> {
+        let window = std::mem::take(&mut *self.window.lock().unwrap_or_else(|e| e.into_inner()));
+        if window.jobs == 0 {
+            return None;
+        }
+        let average = window.total.as_secs_f64() / f64::from(window.jobs);
+
+        // Jobs finished per second by the busy slots, so what is used up
+        // while more are on their way
+        let busy = in_flight.min(concurrency) as f64;
+        let rate = busy / average.max(0.001);
+        let buffered = (rate * self.buffer.as_secs_f64()).ceil() as usize;
+        let prefetch = concurrency
+            .saturating_add(buffered)
+            .div_ceil(channels.max(1))
+            .clamp(1, usize::from(self.max)) as u16;
+
+        let change = f64::from(prefetch.abs_diff(current)) / f64::from(current.max(1));
+        (change >= MIN_CHANGE).then_some(prefetch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuner_after(duration: Duration) -> PrefetchTuner {
+        let tuner = PrefetchTuner::new(Duration::from_secs(1), 1000);
+        for _ in 0..10 {
+            tuner.record(duration);
+        }
+        tuner
+    }
+
+    #[test]
+    fn test_prefetch_follows_job_duration() {
+        // Fast jobs: 100 slots finishing 2000 jobs a second, capped per channel
+        let fast = tuner_after(Duration::from_millis(50));
+        assert_eq!(fast.next(100, 100, 1, 100), Some(1000));
+        let fast = tuner_after(Duration::from_millis(50));
+        assert_eq!(fast.next(100, 100, 4, 25), Some(525));
+
+        // Slow jobs: barely more than the slots, so little waits unacked
+        let slow = tuner_after(Duration::from_secs(60));
+        assert_eq!(slow.next(100, 300, 1, 300), Some(102));
+
+        // An idle worker falls back to one delivery per slot
+        let idle = tuner_after(Duration::from_millis(50));
+        assert_eq!(idle.next(100, 0, 2, 200), Some(50));
+    }
+
+    #[test]
+    fn test_prefetch_kept_without_evidence() {
+        let tuner = PrefetchTuner::new(Duration::from_secs(1), 1000);
+        assert_eq!(tuner.next(100, 50, 1, 100), None);
+
+        // Small changes are not worth restarting the consumers
+        tuner.record(Duration::from_secs(60));
+        assert_eq!(tuner.next(100, 100, 1, 100), None);
+
+        // Each call starts a new window
+        assert_eq!(tuner.next(100, 100, 1, 10), None);
+    }
+}
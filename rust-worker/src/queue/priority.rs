@@ -0,0 +1,81 @@
+//! Priority simulator queues and tier stamping.
+//!
+//! With `SIMULATOR_MAX_PRIORITY` set, every simulator queue is declared as a
+//! priority queue (`x-max-priority`), and the broker delivers its
+//! higher-priority jobs first. The processor stamps each job of a tenant
+//! whose tier has a priority (see [`crate::tenant::tier`]) with it, and every
+//! job with a tier with the tier's name in the [`TIER_HEADER`].
+//!
+//! A queue's arguments cannot change once declared: a queue that already
+//! exists without them must be deleted (or drained and deleted) before the
+//! services declare it as a priority queue, and every service must use the
+//! same `SIMULATOR_MAX_PRIORITY`.
+
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+use lapin::BasicProperties;
+
+use crate::tenant::tier::TenantTier;
+
+/// Queue argument making a queue a priority queue.
+pub const MAX_PRIORITY_ARGUMENT: &str = "x-max-priority";
+
+/// AMQP header carrying the tier of a job's tenant.
+pub const TIER_HEADER: &str = "x-bobnet-tier";
+
+/// Arguments of a simulator queue, a priority queue when `max_priority` is
+/// set.
+pub fn simulator_queue_args(max_priority: Option<u8>) -> FieldTable {
+    let mut args = FieldTable::default();
+    if let Some(max) = max_priority {
+        args.insert(
+            ShortString::from(MAX_PRIORITY_ARGUMENT),
+            AMQPValue::ShortShortUInt(max),
+        );
+    }
+    args
+}
+
+/// Add the tier header of `tier` to `headers`.
+pub fn insert(headers: &mut FieldTable, tier: &TenantTier) {
+    headers.insert(
+        ShortString::from(TIER_HEADER),
+        AMQPValue::LongString(LongString::from(tier.tier.as_str())),
+    );
+}
+
+/// The tier header of a delivery, if present.
+pub fn of(properties: &BasicProperties) -> Option<String> {
+    properties
+        .headers()
+        .as_ref()?
+        .inner()
+        .iter()
+        .find(|(name, _)| name.as_str() == TIER_HEADER)
+        .and_then(|(_, value)| match value {
+            AMQPValue::LongString(s) => std::str::from_utf8(s.as_bytes()).ok(),
+            _ => None,
+        })
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_args_and_tier_header() {
+        assert!(simulator_queue_args(None).inner().is_empty());
+        assert_eq!(
+            simulator_queue_args(Some(10))
+                .inner()
+                .get(MAX_PRIORITY_ARGUMENT),
+            Some(&AMQPValue::ShortShortUInt(10))
+        );
+
+        let mut headers = FieldTable::default();
+        insert(&mut headers, &TenantTier::parse("acme:paid:9").unwrap());
+        let properties = BasicProperties::default().with_headers(headers);
+        assert_eq!(of(&properties).as_deref(), Some("paid"));
+        assert_eq!(of(&BasicProperties::default()), None);
+    }
+}
@@ -2,19 +2,43 @@
 //!
 //! This module provides a connection-pooled publisher that can be shared
 //! across multiple async tasks for high-throughput message publishing.
+//! Messages go straight to their queue, or through the topic exchanges of
+//! [`super::exchange`] when enabled. With several brokers (see
+//! [`super::failover`]) it fails over to the next on connect failure, and
+//! moves back to the primary once it accepts connections again. With an
+//! [`Outbox`], messages are journaled until the broker confirms them. Jobs
+//! of a tenant with a tier are stamped with it (see [`super::priority`]).
 
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
 use lapin::{
-    options::{BasicPublishOptions, QueueDeclareOptions},
+    options::{
+        BasicPublishOptions, ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions,
+        QueueDeclareOptions,
+    },
     types::FieldTable,
-    BasicProperties, Channel, Connection, ConnectionProperties,
+    BasicProperties, Channel, Connection, ExchangeKind,
 };
 use tokio::sync::RwLock;
-use tracing::{info, warn};
-
-use super::types::{InboundWebhook, SimulatorJob, INBOUND_QUEUE, SIMULATOR_QUEUE};
+use tracing::{debug, info, warn};
+
+use super::exchange::{routing_key, QueueBinding, INBOUND_EXCHANGE, JOBS_EXCHANGE};
+use super::failover::BrokerUrls;
+use super::outbox::{Outbox, OutboxEntry};
+use super::priority::{self, simulator_queue_args};
+use super::request_id;
+use super::signing::MessageSigner;
+use super::types::{InboundWebhook, SimulatorJob, INBOUND_QUEUE, SIMULATOR_QUEUE, SIMULATOR_QUEUES};
+use crate::chaos::ChaosConfig;
+use crate::error::{BobNetError, PublishError, Result};
+use crate::simulate::report::SimulationReport;
+use crate::tenant::tier::TenantTier;
+
+/// How long a connection replaced by failing back to the primary stays open
+/// for publishes still waiting on it.
+const FAILBACK_DRAIN: Duration = Duration::from_secs(5);
 
 /// Async RabbitMQ publisher with connection management.
 ///
@@ -23,26 +47,182 @@ use super::types::{InboundWebhook, SimulatorJob, INBOUND_QUEUE, SIMULATOR_QUEUE}
 #[derive(Clone)]
 pub struct Publisher {
     inner: Arc<PublisherInner>,
+    chaos: Option<ChaosConfig>,
+    signer: Option<MessageSigner>,
+    connect_timeout: Option<Duration>,
+    /// Simulator queue bindings when publishing through topic exchanges
+    job_bindings: Option<Arc<[QueueBinding]>>,
+    /// Whether publishes wait for the broker to confirm them
+    confirms: bool,
+    /// Journal of messages not yet confirmed
+    outbox: Option<Arc<Outbox>>,
+    /// `x-max-priority` of the simulator queues, which are priority queues
+    /// when set
+    max_priority: Option<u8>,
 }
 
 struct PublisherInner {
-    url: String,
+    brokers: BrokerUrls,
     connection: RwLock<Option<Connection>>,
     channel: RwLock<Option<Channel>>,
+    /// When the last connect attempt failed
+    failed_at: Mutex<Option<Instant>>,
+    /// Index of the broker the connection is to
+    active: AtomicUsize,
+    /// Whether a task is checking if the primary is back
+    probing: AtomicBool,
+}
+
+impl PublisherInner {
+    /// Whether the last connect attempt failed less than `window` ago.
+    fn failed_within(&self, window: Duration) -> bool {
+        let failed_at = self.failed_at.lock().unwrap_or_else(|e| e.into_inner());
+        failed_at.is_some_and(|at| at.elapsed() < window)
+    }
+
+    fn mark_failed(&self) {
+        *self.failed_at.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+    }
 }
 
 impl Publisher {
     /// Create a new publisher with the given RabbitMQ URL.
     pub fn new(url: String) -> Self {
+        Self::from_brokers(BrokerUrls::single(url))
+    }
+
+    /// Create a publisher that fails over between `brokers`.
+    pub fn from_brokers(brokers: BrokerUrls) -> Self {
         Self {
             inner: Arc::new(PublisherInner {
-                url,
+                brokers,
                 connection: RwLock::new(None),
                 channel: RwLock::new(None),
+                failed_at: Mutex::new(None),
+                active: AtomicUsize::new(0),
+                probing: AtomicBool::new(false),
             }),
+            chaos: None,
+            signer: None,
+            connect_timeout: None,
+            job_bindings: None,
+            confirms: true,
+            outbox: None,
+            max_priority: None,
         }
     }
 
+    /// Enable chaos fault injection (randomly failed publishes).
+    pub fn with_chaos(mut self, chaos: Option<ChaosConfig>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Sign every published body (see [`MessageSigner`]).
+    pub fn with_signer(mut self, signer: Option<MessageSigner>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Give up connecting to the broker after `timeout`, and for `timeout`
+    /// after a failed attempt fail publishes at once instead of retrying, so
+    /// callers are never held up while the broker is down.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Publish webhooks and routed jobs through the `bobnet.inbound` and
+    /// `bobnet.jobs` topic exchanges, binding simulator queues to the jobs
+    /// exchange with `job_bindings` (see [`super::exchange`]). `None` keeps
+    /// publishing straight to the queues.
+    pub fn with_topic_exchanges(mut self, job_bindings: Option<Vec<QueueBinding>>) -> Self {
+        self.job_bindings = job_bindings.map(Arc::from);
+        self
+    }
+
+    /// Wait for the broker to confirm each publish (the default), failing
+    /// the publish if it refuses the message. Without confirms a publish
+    /// returns once written to the connection, so a broker or network failure
+    /// right after loses the message (at-most-once) in exchange for lower
+    /// latency.
+    pub fn with_confirms(mut self, confirms: bool) -> Self {
+        self.confirms = confirms;
+        self
+    }
+
+    /// Journal every message to `outbox` before publishing it and delete it
+    /// once the broker confirms it. A message the broker can't take is
+    /// left journaled and the publish succeeds; [`replay_outbox`] publishes
+    /// it later. Needs confirms.
+    ///
+    /// A successful publish then means the message is journaled or
+    /// confirmed, not that the broker has it: journaled messages reach the
+    /// broker at least once, through replay, possibly after newer ones. A
+    /// message that doesn't fit in a full outbox is published directly, and
+    /// fails like one without an outbox.
+    ///
+    /// [`replay_outbox`]: Self::replay_outbox
+    pub fn with_outbox(mut self, outbox: Outbox) -> Self {
+        self.outbox = Some(Arc::new(outbox));
+        self
+    }
+
+    /// The outbox messages are journaled to, if any.
+    pub fn outbox(&self) -> Option<&Outbox> {
+        self.outbox.as_deref()
+    }
+
+    /// Declare the simulator queues as priority queues up to `max_priority`
+    /// (`SIMULATOR_MAX_PRIORITY`). Every service declaring them must agree,
+    /// as the broker refuses to redeclare a queue with other arguments.
+    pub fn with_max_priority(mut self, max_priority: Option<u8>) -> Self {
+        self.max_priority = max_priority;
+        self
+    }
+
+    /// Base properties for a persistent JSON message, signed when enabled
+    /// and stamped with its request ID and tier when it has them.
+    fn properties(
+        &self,
+        body: &[u8],
+        request_id: Option<&str>,
+        tier: Option<&TenantTier>,
+    ) -> BasicProperties {
+        let properties = BasicProperties::default()
+            .with_delivery_mode(2) // Persistent
+            .with_content_type("application/json".into())
+            .with_timestamp(unix_now());
+        let mut headers = match &self.signer {
+            Some(signer) => signer.headers(body),
+            None => FieldTable::default(),
+        };
+        if let Some(request_id) = request_id {
+            request_id::insert(&mut headers, request_id);
+        }
+        if let Some(tier) = tier {
+            priority::insert(&mut headers, tier);
+        }
+        let properties = match tier.and_then(|tier| tier.priority) {
+            Some(priority) => properties.with_priority(priority),
+            None => properties,
+        };
+        if headers.inner().is_empty() {
+            properties
+        } else {
+            properties.with_headers(headers)
+        }
+    }
+
+    /// Fail the publish if chaos mode rolls a dropped publish.
+    fn chaos_drop(&self, queue: &str) -> Result<()> {
+        if self.chaos.as_ref().is_some_and(|c| c.should_drop_publish()) {
+            warn!(queue = queue, "chaos_publish_dropped");
+            return Err(BobNetError::publish(queue, PublishError::ChaosDropped));
+        }
+        Ok(())
+    }
+
     /// Ensure we have a valid connection and channel.
     async fn ensure_connected(&self) -> Result<Channel> {
         // Check if we have a valid channel
@@ -50,6 +230,7 @@ impl Publisher {
             let channel = self.inner.channel.read().await;
             if let Some(ch) = channel.as_ref() {
                 if ch.status().connected() {
+                    self.fail_back_if_due();
                     return Ok(ch.clone());
                 }
             }
@@ -66,12 +247,101 @@ impl Publisher {
             }
         }
 
+        let attempt = match self.connect_timeout {
+            Some(timeout) => {
+                // Publishes that queued behind a failed attempt fail with it
+                if self.inner.failed_within(timeout) {
+                    return Err(BobNetError::Unavailable("last connect attempt failed"));
+                }
+                tokio::time::timeout(timeout, self.connect())
+                    .await
+                    .unwrap_or(Err(BobNetError::Unavailable("connect timed out")))
+            }
+            None => self.connect().await,
+        };
+        let ((conn, ch), index) = match attempt {
+            Ok(connected) => connected,
+            Err(e) => {
+                self.inner.mark_failed();
+                warn!(error = %e, "rabbitmq_publisher_connect_failed");
+                return Err(e);
+            }
+        };
+
+        *connection = Some(conn);
+        *channel = Some(ch.clone());
+        self.inner.active.store(index, Ordering::Relaxed);
+
+        Ok(ch)
+    }
+
+    /// Connect to the first broker that accepts, returning its index.
+    async fn connect(&self) -> Result<((Connection, Channel), usize)> {
+        self.inner.brokers.try_each(|url| self.open(url)).await
+    }
+
+    /// While connected to a failover broker, check in the background whether
+    /// the primary is back, at most once per failback interval, and move the
+    /// publisher to it if so.
+    fn fail_back_if_due(&self) {
+        let brokers = &self.inner.brokers;
+        if self.inner.active.load(Ordering::Relaxed) == 0
+            || !brokers.primary_due()
+            || self.inner.probing.swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+
+        let publisher = self.clone();
+        tokio::spawn(async move {
+            publisher.fail_back().await;
+            publisher.inner.probing.store(false, Ordering::Release);
+        });
+    }
+
+    async fn fail_back(&self) {
+        let brokers = &self.inner.brokers;
+        let primary = brokers.urls()[0].clone();
+        let attempt = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.open(primary))
+                .await
+                .unwrap_or(Err(BobNetError::Unavailable("connect timed out"))),
+            None => self.open(primary).await,
+        };
+        let (conn, ch) = match attempt {
+            Ok(connected) => connected,
+            Err(e) => {
+                brokers.mark_failed(0);
+                info!(error = %e, "rabbitmq_primary_still_down");
+                return;
+            }
+        };
+        brokers.mark_ok(0);
+
+        let old = {
+            let mut connection = self.inner.connection.write().await;
+            let mut channel = self.inner.channel.write().await;
+            *channel = Some(ch);
+            self.inner.active.store(0, Ordering::Relaxed);
+            connection.replace(conn)
+        };
+        info!(url = %brokers.describe(0), "rabbitmq_publisher_failed_back");
+
+        // Let publishes already on the old connection finish
+        if let Some(old) = old {
+            tokio::time::sleep(FAILBACK_DRAIN).await;
+            let _ = old.close(200, "Failed back to primary").await;
+        }
+    }
+
+    /// Open a connection to `url` and a channel, and declare the queues.
+    async fn open(&self, url: String) -> Result<(Connection, Channel)> {
         info!("rabbitmq_publisher_connecting");
 
         // Create new connection
-        let conn = Connection::connect(&self.inner.url, ConnectionProperties::default())
+        let conn = self.inner.brokers.connect_to(&url)
             .await
-            .context("Failed to connect to RabbitMQ")?;
+            .map_err(|e| BobNetError::connect("failed to connect to RabbitMQ", e))?;
 
         info!("rabbitmq_publisher_connected");
 
@@ -79,22 +349,17 @@ impl Publisher {
         let ch = conn
             .create_channel()
             .await
-            .context("Failed to create channel")?;
+            .map_err(|e| BobNetError::connect("failed to create channel", e))?;
 
-        // Declare both queues (idempotent operation)
-        ch.queue_declare(
-            INBOUND_QUEUE,
-            QueueDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            FieldTable::default(),
-        )
-        .await
-        .context("Failed to declare inbound queue")?;
+        if self.confirms {
+            ch.confirm_select(ConfirmSelectOptions::default())
+                .await
+                .map_err(|e| BobNetError::connect("failed to enable publisher confirms", e))?;
+        }
 
+        // Declare all queues (idempotent operation)
         ch.queue_declare(
-            SIMULATOR_QUEUE,
+            INBOUND_QUEUE,
             QueueDeclareOptions {
                 durable: true,
                 ..Default::default()
@@ -102,51 +367,64 @@ impl Publisher {
             FieldTable::default(),
         )
         .await
-        .context("Failed to declare simulator queue")?;
+        .map_err(|e| BobNetError::connect("failed to declare inbound queue", e))?;
+
+        for queue in SIMULATOR_QUEUES {
+            ch.queue_declare(
+                queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                simulator_queue_args(self.max_priority),
+            )
+            .await
+            .map_err(|e| {
+                BobNetError::connect(format!("failed to declare simulator queue {}", queue), e)
+            })?;
+        }
 
         info!(
             inbound_queue = INBOUND_QUEUE,
-            simulator_queue = SIMULATOR_QUEUE,
+            simulator_queues = ?SIMULATOR_QUEUES,
             "rabbitmq_queues_declared"
         );
 
-        *connection = Some(conn);
-        *channel = Some(ch.clone());
+        if let Some(bindings) = &self.job_bindings {
+            declare_exchanges(&ch, bindings, self.max_priority).await?;
+        }
 
-        Ok(ch)
+        Ok((conn, ch))
     }
 
-    /// Publish a raw inbound webhook to the inbound_webhooks queue.
-    pub async fn publish_inbound(&self, webhook: &InboundWebhook) -> Result<()> {
-        let channel = self.ensure_connected().await?;
-
-        let body = serde_json::to_vec(webhook).context("Failed to serialize webhook")?;
+    /// Publish a raw inbound webhook to the inbound_webhooks queue, with the
+    /// ID of the request that delivered it.
+    pub async fn publish_inbound(
+        &self,
+        webhook: &InboundWebhook,
+        request_id: Option<&str>,
+    ) -> Result<()> {
+        let body = serde_json::to_vec(webhook).map_err(|e| BobNetError::publish(INBOUND_QUEUE, e))?;
 
         // Generate a message ID for tracking
         let message_id = match webhook {
             InboundWebhook::Mailgun(p) => format!("mailgun-{}", &p.recipient),
             InboundWebhook::Cloudflare(p) => format!("cloudflare-{}", &p.to),
+            InboundWebhook::Generic(p) => format!("generic-{}-{}", &p.source, &p.to),
         };
 
-        channel
-            .basic_publish(
-                "",
-                INBOUND_QUEUE,
-                BasicPublishOptions::default(),
-                &body,
-                BasicProperties::default()
-                    .with_delivery_mode(2) // Persistent
-                    .with_content_type("application/json".into())
-                    .with_message_id(message_id.clone().into()),
-            )
-            .await
-            .context("Failed to publish to inbound queue")?
-            .await
-            .context("Failed to confirm publish")?;
+        let (exchange, key) = match &self.job_bindings {
+            Some(_) => (INBOUND_EXCHANGE, routing_key(webhook.provider(), webhook.recipient())),
+            None => ("", INBOUND_QUEUE.to_string()),
+        };
+        self.publish(exchange, &key, &message_id, request_id, None, &body).await?;
 
         info!(
             queue = INBOUND_QUEUE,
+            exchange = exchange,
+            routing_key = %key,
             message_id = %message_id,
+            request_id = ?request_id,
             body_length = body.len(),
             "rabbitmq_inbound_published"
         );
@@ -156,34 +434,287 @@ impl Publisher {
 
     /// Publish a parsed job to the email_simulator queue.
     pub async fn publish_simulator(&self, job: &SimulatorJob) -> Result<()> {
-        let channel = self.ensure_connected().await?;
+        self.publish_simulator_to(SIMULATOR_QUEUE, job).await
+    }
+
+    /// Publish a parsed job to a specific simulator queue (e.g. a provider queue).
+    pub async fn publish_simulator_to(&self, queue: &str, job: &SimulatorJob) -> Result<()> {
+        self.publish_job("", queue, job, None).await
+    }
+
+    /// Publish a job parsed from a `provider` webhook: through the jobs
+    /// exchange with its `provider.tenant` routing key when topic exchanges
+    /// are enabled, otherwise to `queue`. A job of a tenant with a `tier` is
+    /// stamped with it, and with its priority if it has one.
+    ///
+    /// Returns where it went: the routing key or the queue.
+    pub async fn publish_simulator_routed(
+        &self,
+        queue: &str,
+        provider: &str,
+        job: &SimulatorJob,
+        tier: Option<&TenantTier>,
+    ) -> Result<String> {
+        if self.job_bindings.is_none() {
+            self.publish_job("", queue, job, tier).await?;
+            return Ok(queue.to_string());
+        }
+
+        let key = routing_key(provider, &job.to);
+        self.publish_job(JOBS_EXCHANGE, &key, job, tier).await?;
+        Ok(key)
+    }
+
+    /// Publish `job` to `exchange` with `routing_key`.
+    async fn publish_job(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        job: &SimulatorJob,
+        tier: Option<&TenantTier>,
+    ) -> Result<()> {
+        let body = serde_json::to_vec(job).map_err(|e| BobNetError::publish(routing_key, e))?;
+        let request_id = job.request_id.as_deref();
+        self.publish(exchange, routing_key, &job.message_id, request_id, tier, &body)
+            .await?;
+
+        if exchange.is_empty() {
+            info!(
+                queue = routing_key,
+                message_id = %job.message_id,
+                request_id = ?job.request_id,
+                tier = tier.map(|t| t.tier.as_str()),
+                priority = tier.and_then(|t| t.priority),
+                body_length = body.len(),
+                "rabbitmq_simulator_published"
+            );
+        } else {
+            info!(
+                exchange = exchange,
+                routing_key = routing_key,
+                message_id = %job.message_id,
+                request_id = ?job.request_id,
+                tier = tier.map(|t| t.tier.as_str()),
+                priority = tier.and_then(|t| t.priority),
+                body_length = body.len(),
+                "rabbitmq_simulator_published"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Publish a job's simulation report to a results queue (`RESULTS_QUEUE`).
+    ///
+    /// The queue is not declared here; the worker declares it at startup.
+    pub async fn publish_report(&self, queue: &str, report: &SimulationReport) -> Result<()> {
+        let body = serde_json::to_vec(report).map_err(|e| BobNetError::publish(queue, e))?;
+        let request_id = report.request_id.as_deref();
+        self.publish("", queue, &report.message_id, request_id, None, &body)
+            .await?;
+
+        info!(
+            queue = queue,
+            message_id = %report.message_id,
+            request_id = ?report.request_id,
+            body_length = body.len(),
+            "rabbitmq_report_published"
+        );
+
+        Ok(())
+    }
+
+    /// Publish `body` through the outbox when there is one, otherwise
+    /// straight to the broker.
+    ///
+    /// Returns `Ok` once a journaled message is confirmed or left for
+    /// replay, so callers get at-least-once delivery from the outbox rather
+    /// than a broker confirm; see [`with_outbox`](Self::with_outbox).
+    async fn publish(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        message_id: &str,
+        request_id: Option<&str>,
+        tier: Option<&TenantTier>,
+        body: &[u8],
+    ) -> Result<()> {
+        let Some(outbox) = &self.outbox else {
+            return self
+                .send(exchange, routing_key, message_id, request_id, tier, body)
+                .await;
+        };
+
+        let entry = OutboxEntry {
+            exchange: exchange.to_string(),
+            routing_key: routing_key.to_string(),
+            message_id: message_id.to_string(),
+            request_id: request_id.map(str::to_string),
+            tier: tier.cloned(),
+            // Bodies are serialized JSON
+            body: String::from_utf8_lossy(body).into_owned(),
+        };
+        let journal = Arc::clone(outbox);
+        let journaled = tokio::task::spawn_blocking(move || journal.record(&entry))
+            .await
+            .map_err(std::io::Error::other)
+            .and_then(|recorded| recorded);
+        let seq = match journaled {
+            Ok(seq) => seq,
+            Err(e) if e.kind() == std::io::ErrorKind::StorageFull => {
+                // Published without the safety net, so a down broker fails
+                // the webhook and its provider retries it
+                warn!(message_id = %message_id, error = %e, "outbox_full");
+                return self
+                    .send(exchange, routing_key, message_id, request_id, tier, body)
+                    .await;
+            }
+            Err(e) => {
+                // Still worth publishing without the safety net
+                warn!(message_id = %message_id, error = %e, "outbox_write_failed");
+                return self
+                    .send(exchange, routing_key, message_id, request_id, tier, body)
+                    .await;
+            }
+        };
+
+        match self
+            .send(exchange, routing_key, message_id, request_id, tier, body)
+            .await
+        {
+            Ok(()) => {
+                if let Err(e) = outbox.remove(seq) {
+                    warn!(message_id = %message_id, error = %e, "outbox_remove_failed");
+                }
+            }
+            Err(e) => {
+                outbox.release(seq);
+                warn!(message_id = %message_id, error = %e, "outbox_publish_deferred");
+            }
+        }
+        Ok(())
+    }
+
+    /// Publish the outbox's journaled messages, oldest first, until one
+    /// fails, returning how many were published.
+    pub async fn replay_outbox(&self) -> usize {
+        let Some(outbox) = &self.outbox else {
+            return 0;
+        };
+        let pending = match outbox.pending() {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!(error = %e, "outbox_read_failed");
+                return 0;
+            }
+        };
+
+        let mut replayed = 0;
+        for (seq, entry) in pending {
+            let sent = self
+                .send(
+                    &entry.exchange,
+                    &entry.routing_key,
+                    &entry.message_id,
+                    entry.request_id.as_deref(),
+                    entry.tier.as_ref(),
+                    entry.body.as_bytes(),
+                )
+                .await;
+            if let Err(e) = sent {
+                debug!(message_id = %entry.message_id, error = %e, "outbox_replay_paused");
+                break;
+            }
+            if let Err(e) = outbox.remove(seq) {
+                warn!(message_id = %entry.message_id, error = %e, "outbox_remove_failed");
+            }
+            replayed += 1;
+        }
+        replayed
+    }
 
-        let body = serde_json::to_vec(job).context("Failed to serialize job")?;
+    /// Replay the outbox now and then every `every`, in the background.
+    pub fn spawn_outbox_replay(&self, every: Duration) {
+        if self.outbox.is_none() {
+            return;
+        }
+        let publisher = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                let replayed = publisher.replay_outbox().await;
+                if replayed > 0 {
+                    info!(replayed = replayed, "outbox_replayed");
+                }
+            }
+        });
+    }
 
-        channel
+    /// Publish `body` to `exchange` with `routing_key` (a queue name on the
+    /// default exchange `""`) and, unless confirms are off, wait for the
+    /// broker to confirm it.
+    async fn send(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        message_id: &str,
+        request_id: Option<&str>,
+        tier: Option<&TenantTier>,
+        body: &[u8],
+    ) -> Result<()> {
+        let target = if exchange.is_empty() {
+            routing_key.to_string()
+        } else {
+            format!("{} ({})", exchange, routing_key)
+        };
+        self.chaos_drop(&target)?;
+        let channel = self.ensure_connected().await?;
+
+        let confirm = channel
             .basic_publish(
-                "",
-                SIMULATOR_QUEUE,
+                exchange,
+                routing_key,
                 BasicPublishOptions::default(),
-                &body,
-                BasicProperties::default()
-                    .with_delivery_mode(2) // Persistent
-                    .with_content_type("application/json".into())
-                    .with_message_id(job.message_id.clone().into()),
+                body,
+                self.properties(body, request_id, tier)
+                    .with_message_id(message_id.into()),
             )
             .await
-            .context("Failed to publish to simulator queue")?
+            .map_err(|e| BobNetError::publish(&target, e))?;
+        if !self.confirms {
+            return Ok(());
+        }
+
+        let confirmation = confirm
             .await
-            .context("Failed to confirm publish")?;
+            .map_err(|e| BobNetError::publish(&target, e))?;
+        if confirmation.is_nack() {
+            return Err(BobNetError::publish(&target, PublishError::Nacked));
+        }
+        Ok(())
+    }
 
-        info!(
-            queue = SIMULATOR_QUEUE,
-            message_id = %job.message_id,
-            body_length = body.len(),
-            "rabbitmq_simulator_published"
-        );
+    /// Messages ready on each of the queues the publisher declares: the
+    /// inbound queue and the simulator queues.
+    pub async fn queue_depths(&self) -> Result<Vec<(&'static str, u32)>> {
+        let channel = self.ensure_connected().await?;
+        // Passive, so it only reads the counts; the queues exist since
+        // connecting declared them
+        let options = QueueDeclareOptions {
+            passive: true,
+            ..Default::default()
+        };
 
-        Ok(())
+        let mut depths = Vec::with_capacity(SIMULATOR_QUEUES.len() + 1);
+        for &queue in std::iter::once(&INBOUND_QUEUE).chain(SIMULATOR_QUEUES) {
+            let declared = channel
+                .queue_declare(queue, options, FieldTable::default())
+                .await
+                .map_err(|e| BobNetError::connect(format!("failed to inspect queue {}", queue), e))?;
+            depths.push((queue, declared.message_count()));
+        }
+        Ok(depths)
     }
 
     /// Close the connection gracefully.
@@ -207,6 +738,75 @@ impl Publisher {
     }
 }
 
+/// Declare the topic exchanges, bind the inbound queue to every inbound key
+/// and each bound simulator queue (declaring it, e.g. a tier queue) to its
+/// pattern.
+async fn declare_exchanges(
+    ch: &Channel,
+    bindings: &[QueueBinding],
+    max_priority: Option<u8>,
+) -> Result<()> {
+    let durable = ExchangeDeclareOptions {
+        durable: true,
+        ..Default::default()
+    };
+    for exchange in [INBOUND_EXCHANGE, JOBS_EXCHANGE] {
+        ch.exchange_declare(exchange, ExchangeKind::Topic, durable, FieldTable::default())
+            .await
+            .map_err(|e| BobNetError::connect(format!("failed to declare exchange {}", exchange), e))?;
+    }
+
+    ch.queue_bind(
+        INBOUND_QUEUE,
+        INBOUND_EXCHANGE,
+        "#",
+        QueueBindOptions::default(),
+        FieldTable::default(),
+    )
+    .await
+    .map_err(|e| BobNetError::connect("failed to bind inbound queue", e))?;
+
+    for binding in bindings {
+        ch.queue_declare(
+            &binding.queue,
+            QueueDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            simulator_queue_args(max_priority),
+        )
+        .await
+        .map_err(|e| {
+            BobNetError::connect(format!("failed to declare simulator queue {}", binding.queue), e)
+        })?;
+        ch.queue_bind(
+            &binding.queue,
+            JOBS_EXCHANGE,
+            &binding.pattern,
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .map_err(|e| BobNetError::connect(format!("failed to bind queue {}", binding.queue), e))?;
+    }
+
+    info!(
+        inbound_exchange = INBOUND_EXCHANGE,
+        jobs_exchange = JOBS_EXCHANGE,
+        bindings = ?bindings,
+        "rabbitmq_exchanges_declared"
+    );
+    Ok(())
+}
+
+/// Current Unix time in seconds, used for the AMQP `timestamp` property.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -217,4 +817,44 @@ mod tests {
         // Just verify it can be created
         assert!(Arc::strong_count(&publisher.inner) == 1);
     }
+
+    #[tokio::test]
+    async fn test_connect_failure_fails_fast() {
+        // Nothing listens on port 1
+        let publisher = Publisher::new("amqp://127.0.0.1:1".to_string())
+            .with_connect_timeout(Duration::from_secs(5));
+        let job = SimulatorJob::new("msg-1".to_string(), "user@example.com".to_string(), None);
+
+        let first = publisher.publish_simulator(&job).await.unwrap_err();
+        assert!(first.is_unavailable());
+
+        // Until the timeout has passed, publishes fail without a new attempt
+        let started = Instant::now();
+        let second = publisher.publish_simulator(&job).await.unwrap_err();
+        assert!(matches!(
+            second,
+            BobNetError::Unavailable("last connect attempt failed")
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_outbox_keeps_unpublished_messages() {
+        let dir = std::env::temp_dir().join(format!("bobnet-publisher-outbox-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let publisher = Publisher::new("amqp://127.0.0.1:1".to_string())
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_outbox(Outbox::open(&dir).unwrap());
+        let job = SimulatorJob::new("msg-1".to_string(), "user@example.com".to_string(), None);
+
+        // Accepted although the broker is down, and still journaled
+        publisher.publish_simulator(&job).await.unwrap();
+        assert_eq!(publisher.replay_outbox().await, 0);
+        let pending = Outbox::open(&dir).unwrap().pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.message_id, "msg-1");
+        assert_eq!(pending[0].1.routing_key, SIMULATOR_QUEUE);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,115 @@
+//! Request IDs that follow one email from webhook to simulation report.
+//!
+//! The web server accepts the `X-Request-Id` a caller sends, or generates
+//! one, and stamps it into the `x-request-id` header of the inbound message.
+//! The processor copies it onto the job (and its header), and the worker onto
+//! the report, so every service's logs for one email can be joined on it.
+//! Messages published without one (e.g. by the Python web server) are given
+//! one by the first Rust service that sees them.
+
+#[cfg(feature = "amqp")]
+use lapin::types::{AMQPValue, FieldTable, LongString, ShortString};
+#[cfg(feature = "amqp")]
+use lapin::BasicProperties;
+
+/// HTTP and AMQP header carrying the request ID.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied request ID that is accepted.
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// A new random request ID: 32 hex characters.
+pub fn generate() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// `provided` if it is a usable request ID, otherwise a new one.
+///
+/// Usable IDs are non-empty, at most [`MAX_REQUEST_ID_LEN`] characters and
+/// made of ASCII letters, digits and `-_.:`, so they are safe to log and to
+/// echo back in a header.
+pub fn accept(provided: Option<&str>) -> String {
+    provided
+        .map(str::trim)
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(generate)
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Add the request ID header to `headers`.
+#[cfg(feature = "amqp")]
+pub fn insert(headers: &mut FieldTable, request_id: &str) {
+    headers.insert(
+        ShortString::from(REQUEST_ID_HEADER),
+        AMQPValue::LongString(LongString::from(request_id)),
+    );
+}
+
+/// The request ID header of a delivery, if present.
+#[cfg(feature = "amqp")]
+pub fn of(properties: &BasicProperties) -> Option<String> {
+    properties
+        .headers()
+        .as_ref()?
+        .inner()
+        .iter()
+        .find(|(name, _)| name.as_str() == REQUEST_ID_HEADER)
+        .and_then(|(_, value)| match value {
+            AMQPValue::LongString(s) => std::str::from_utf8(s.as_bytes()).ok(),
+            _ => None,
+        })
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_format() {
+        let id = generate();
+        assert_eq!(id.len(), 32);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(id, generate());
+    }
+
+    #[test]
+    fn test_accept_keeps_valid_ids() {
+        assert_eq!(accept(Some("req-42")), "req-42");
+        assert_eq!(accept(Some(" abc:1.2_3 ")), "abc:1.2_3");
+    }
+
+    #[test]
+    fn test_accept_replaces_unusable_ids() {
+        let long = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+        for provided in [
+            None,
+            Some(""),
+            Some("has space"),
+            Some("a\nb"),
+            Some(long.as_str()),
+        ] {
+            let id = accept(provided);
+            assert_eq!(id.len(), 32, "{:?}", provided);
+        }
+    }
+
+    #[cfg(feature = "amqp")]
+    #[test]
+    fn test_header_round_trip() {
+        let mut headers = FieldTable::default();
+        insert(&mut headers, "req-42");
+        let properties = BasicProperties::default().with_headers(headers);
+
+        assert_eq!(of(&properties).as_deref(), Some("req-42"));
+        assert_eq!(of(&BasicProperties::default()), None);
+    }
+}
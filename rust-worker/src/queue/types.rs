@@ -12,6 +12,27 @@ pub const INBOUND_QUEUE: &str = "inbound_webhooks";
 /// Queue name for parsed email simulation jobs.
 pub const SIMULATOR_QUEUE: &str = "email_simulator";
 
+/// Simulator queue for jobs that arrived via Mailgun (provider routing).
+pub const MAILGUN_SIMULATOR_QUEUE: &str = "email_simulator.mailgun";
+
+/// Simulator queue for jobs that arrived via Cloudflare (provider routing).
+pub const CLOUDFLARE_SIMULATOR_QUEUE: &str = "email_simulator.cloudflare";
+
+/// Simulator queue for jobs that arrived via a generic webhook (provider routing).
+pub const GENERIC_SIMULATOR_QUEUE: &str = "email_simulator.generic";
+
+/// Every simulator queue the publisher declares.
+pub const SIMULATOR_QUEUES: &[&str] = &[
+    SIMULATOR_QUEUE,
+    MAILGUN_SIMULATOR_QUEUE,
+    CLOUDFLARE_SIMULATOR_QUEUE,
+    GENERIC_SIMULATOR_QUEUE,
+];
+
+/// Default shadow simulator queue the processor mirrors jobs to
+/// (`MIRROR_QUEUE`).
+pub const MIRROR_QUEUE: &str = "email_simulator_shadow";
+
 // =============================================================================
 // Inbound Webhook Types (inbound_webhooks queue)
 // =============================================================================
@@ -29,6 +50,38 @@ pub enum InboundWebhook {
     /// Raw Cloudflare JSON payload
     #[serde(rename = "cloudflare")]
     Cloudflare(CloudflareRawPayload),
+    /// Fields mapped out of a configured generic JSON webhook
+    #[serde(rename = "generic")]
+    Generic(GenericRawPayload),
+}
+
+impl InboundWebhook {
+    /// Provider name, matching the serialized `provider` tag.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            InboundWebhook::Mailgun(_) => "mailgun",
+            InboundWebhook::Cloudflare(_) => "cloudflare",
+            InboundWebhook::Generic(_) => "generic",
+        }
+    }
+
+    /// Recipient address of the webhook.
+    pub fn recipient(&self) -> &str {
+        match self {
+            InboundWebhook::Mailgun(p) => &p.recipient,
+            InboundWebhook::Cloudflare(p) => &p.to,
+            InboundWebhook::Generic(p) => &p.to,
+        }
+    }
+
+    /// Provider-specific simulator queue for jobs parsed from this webhook.
+    pub fn simulator_queue(&self) -> &'static str {
+        match self {
+            InboundWebhook::Mailgun(_) => MAILGUN_SIMULATOR_QUEUE,
+            InboundWebhook::Cloudflare(_) => CLOUDFLARE_SIMULATOR_QUEUE,
+            InboundWebhook::Generic(_) => GENERIC_SIMULATOR_QUEUE,
+        }
+    }
 }
 
 /// Raw Mailgun webhook payload (form-encoded data).
@@ -65,6 +118,15 @@ pub struct MailgunRawPayload {
     /// Webhook token (for signature verification - already verified by web server)
     #[serde(default)]
     pub token: String,
+    /// When the web server received the webhook, in Unix milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at_ms: Option<u64>,
+    /// Where a store-and-notify route's message is kept on Mailgun
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_url: Option<String>,
+    /// Full MIME of the stored message, once retrieved by the processor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_mime: Option<String>,
 }
 
 /// Raw Cloudflare webhook payload (JSON).
@@ -79,10 +141,32 @@ pub struct CloudflareRawPayload {
     pub subject: String,
     /// Webhook timestamp
     pub timestamp: String,
+    /// When the web server received the webhook, in Unix milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at_ms: Option<u64>,
     /// Raw RFC 5322 email content (headers + body)
     pub raw_content: String,
 }
 
+/// Fields the web server mapped out of a generic JSON webhook (see
+/// [`GenericSource`](super::generic::GenericSource)).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenericRawPayload {
+    /// Name of the configured source that received it
+    pub source: String,
+    /// Recipient email address
+    pub to: String,
+    /// HTML body content
+    #[serde(default)]
+    pub html: Option<String>,
+    /// Message-Id, when the source maps one
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// When the web server received the webhook, in Unix milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at_ms: Option<u64>,
+}
+
 // =============================================================================
 // Simulator Job Types (email_simulator queue)
 // =============================================================================
@@ -90,8 +174,9 @@ pub struct CloudflareRawPayload {
 /// Parsed job ready for email simulation.
 ///
 /// This is the format expected by the worker's email simulator.
-/// It matches the existing Python job format for compatibility.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// It matches the existing Python job format for compatibility; the
+/// `overrides` object is omitted when empty, and optional when consumed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SimulatorJob {
     /// Unique message identifier
     pub message_id: String,
@@ -99,12 +184,199 @@ pub struct SimulatorJob {
     pub to: String,
     /// HTML content to simulate opens/clicks on
     pub html: Option<String>,
+    /// SHA-256 of the HTML, kept in the content store instead of `html`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html_sha256: Option<String>,
+    /// When the provider received the email, in Unix seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<u64>,
+    /// When the web server received the webhook, in Unix milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at_ms: Option<u64>,
+    /// When the processor published the job, in Unix milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enqueued_at_ms: Option<u64>,
+    /// ID of the webhook request the job came from (`X-Request-Id`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Settings this job simulates with instead of the worker's
+    #[serde(default, skip_serializing_if = "JobOverrides::is_empty")]
+    pub overrides: JobOverrides,
 }
 
 impl SimulatorJob {
     /// Create a new simulator job.
     pub fn new(message_id: String, to: String, html: Option<String>) -> Self {
-        Self { message_id, to, html }
+        Self {
+            message_id,
+            to,
+            html,
+            html_sha256: None,
+            received_at: None,
+            received_at_ms: None,
+            enqueued_at_ms: None,
+            request_id: None,
+            overrides: JobOverrides::default(),
+        }
+    }
+
+    /// Build a job field by field, with optional per-job overrides.
+    pub fn builder() -> SimulatorJobBuilder {
+        SimulatorJobBuilder::default()
+    }
+}
+
+/// Per-job replacements for the worker's simulation settings.
+///
+/// Unset fields fall back to the worker configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobOverrides {
+    /// Probability of simulating an email open (0.0 - 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_probability: Option<f64>,
+    /// Probability of simulating a click (0.0 - 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub click_probability: Option<f64>,
+    /// Maximum number of links to click
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<usize>,
+    /// Delay range in ms before opening
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_delay_ms: Option<(u64, u64)>,
+    /// Delay range in ms between clicks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub click_delay_ms: Option<(u64, u64)>,
+    /// User agent to fetch with, instead of one picked from the pool
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Seed of the job's random choices, to replay a report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// URL the worker POSTs the job's report to as JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    /// Go through every decision but answer each fetch locally, so nothing
+    /// is counted as engagement (mirrored jobs)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+impl JobOverrides {
+    /// Whether no setting is overridden.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Builder for [`SimulatorJob`] with typed setters.
+///
+/// ```
+/// use bobnet::SimulatorJob;
+///
+/// let job = SimulatorJob::builder()
+///     .message_id("msg-1")
+///     .to("user+tag@example.com")
+///     .html("<html>...</html>")
+///     .click_probability(1.0)
+///     .max_clicks(1)
+///     .seed(42)
+///     .build();
+/// assert_eq!(job.overrides.max_clicks, Some(1));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SimulatorJobBuilder {
+    job: SimulatorJob,
+}
+
+impl SimulatorJobBuilder {
+    /// Unique message identifier
+    pub fn message_id(mut self, value: impl Into<String>) -> Self {
+        self.job.message_id = value.into();
+        self
+    }
+
+    /// Recipient email address
+    pub fn to(mut self, value: impl Into<String>) -> Self {
+        self.job.to = value.into();
+        self
+    }
+
+    /// HTML content to simulate opens/clicks on
+    pub fn html(mut self, value: impl Into<String>) -> Self {
+        self.job.html = Some(value.into());
+        self
+    }
+
+    /// When the provider received the email, in Unix seconds
+    pub fn received_at(mut self, value: u64) -> Self {
+        self.job.received_at = Some(value);
+        self
+    }
+
+    /// ID of the webhook request the job came from (`X-Request-Id`)
+    pub fn request_id(mut self, value: impl Into<String>) -> Self {
+        self.job.request_id = Some(value.into());
+        self
+    }
+
+    /// Probability of simulating an email open (0.0 - 1.0)
+    pub fn open_probability(mut self, value: f64) -> Self {
+        self.job.overrides.open_probability = Some(value);
+        self
+    }
+
+    /// Probability of simulating a click (0.0 - 1.0)
+    pub fn click_probability(mut self, value: f64) -> Self {
+        self.job.overrides.click_probability = Some(value);
+        self
+    }
+
+    /// Maximum number of links to click
+    pub fn max_clicks(mut self, value: usize) -> Self {
+        self.job.overrides.max_clicks = Some(value);
+        self
+    }
+
+    /// Delay range in ms before opening
+    pub fn open_delay_ms(mut self, range: (u64, u64)) -> Self {
+        self.job.overrides.open_delay_ms = Some(range);
+        self
+    }
+
+    /// Delay range in ms between clicks
+    pub fn click_delay_ms(mut self, range: (u64, u64)) -> Self {
+        self.job.overrides.click_delay_ms = Some(range);
+        self
+    }
+
+    /// User agent to fetch with, instead of one picked from the pool
+    pub fn user_agent(mut self, value: impl Into<String>) -> Self {
+        self.job.overrides.user_agent = Some(value.into());
+        self
+    }
+
+    /// Seed of the job's random choices, to replay a report
+    pub fn seed(mut self, value: u64) -> Self {
+        self.job.overrides.seed = Some(value);
+        self
+    }
+
+    /// URL the worker POSTs the job's report to as JSON
+    pub fn callback_url(mut self, value: impl Into<String>) -> Self {
+        self.job.overrides.callback_url = Some(value.into());
+        self
+    }
+
+    /// Answer every fetch locally instead of over the network
+    pub fn dry_run(mut self) -> Self {
+        self.job.overrides.dry_run = true;
+        self
+    }
+
+    /// Finish the job.
+    pub fn build(self) -> SimulatorJob {
+        self.job
     }
 }
 
@@ -125,6 +397,9 @@ mod tests {
             from_field: "sender@example.com".to_string(),
             timestamp: "1234567890".to_string(),
             token: "token123".to_string(),
+            received_at_ms: None,
+            message_url: None,
+            body_mime: None,
         });
 
         let json = serde_json::to_string(&payload).unwrap();
@@ -146,11 +421,14 @@ mod tests {
             to: "recipient@example.com".to_string(),
             subject: "Test Subject".to_string(),
             timestamp: "2024-01-01T00:00:00Z".to_string(),
+            received_at_ms: None,
             raw_content: "From: sender@example.com\r\n\r\nBody".to_string(),
         });
 
         let json = serde_json::to_string(&payload).unwrap();
         assert!(json.contains("\"provider\":\"cloudflare\""));
+        assert_eq!(payload.provider(), "cloudflare");
+        assert_eq!(payload.simulator_queue(), "email_simulator.cloudflare");
     }
 
     #[test]
@@ -168,4 +446,32 @@ mod tests {
         assert_eq!(parsed.to, "test@example.com");
         assert_eq!(parsed.html, Some("<html>Test</html>".to_string()));
     }
+
+    #[test]
+    fn test_simulator_job_overrides_serialization() {
+        // Jobs from the Python publisher carry no overrides
+        let parsed: SimulatorJob =
+            serde_json::from_str(r#"{"message_id":"m","to":"a@example.com","html":null}"#).unwrap();
+        assert!(parsed.overrides.is_empty());
+        assert_eq!(parsed.received_at, None);
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert!(!json.contains("overrides"));
+        assert!(!json.contains("received_at"));
+        assert!(!json.contains("enqueued_at_ms"));
+        assert!(!json.contains("request_id"));
+
+        let job = SimulatorJob::builder()
+            .message_id("msg123")
+            .to("test@example.com")
+            .open_probability(1.0)
+            .click_delay_ms((0, 10))
+            .callback_url("https://hooks.example.com/done")
+            .build();
+        let json = serde_json::to_string(&job).unwrap();
+        assert!(json.contains(r#""overrides":{"open_probability":1.0,"click_delay_ms":[0,10],"#));
+
+        let parsed: SimulatorJob = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.overrides, job.overrides);
+        assert_eq!(parsed.overrides.max_clicks, None);
+    }
 }
@@ -0,0 +1,321 @@
+//! Hot reload of simulation parameters.
+//!
+//! Configuration is layered, highest precedence first: command-line flags,
+//! the optional remote source (`REMOTE_CONFIG_URL`), the optional
+//! `CONFIG_FILE` of `KEY=VALUE` lines, then the environment. On SIGHUP, when
+//! the file's modification time changes, or when the remote source returns
+//! new values, the configuration is rebuilt and its simulation parameters
+//! (probabilities, delays, domain lists, UA pool) are swapped in atomically.
+//! Jobs load the config once when they start, so in-flight jobs keep the
+//! values they began with.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use reqwest::Client;
+use tracing::{info, warn};
+
+use bobnet::cli::Overrides;
+use bobnet::config::env_var;
+use bobnet::WorkerConfig;
+
+/// The layers a configuration is built from.
+pub struct Sources {
+    overrides: Overrides,
+    file: Option<String>,
+    remote_url: Option<String>,
+    /// Values from the last successful remote fetch
+    remote: HashMap<String, String>,
+    client: Client,
+}
+
+/// Load the startup configuration and the sources it was built from.
+///
+/// Fails if `CONFIG_FILE` cannot be read or, with `CONFIG_STRICT`, if any
+/// value is invalid. An unreachable remote source is logged and skipped so
+/// the fleet can still start without its control plane.
+pub async fn initial(overrides: Overrides) -> Result<(WorkerConfig, Sources)> {
+    let base = overrides.load(WorkerConfig::from_vars_checked)?;
+
+    let mut sources = Sources {
+        overrides,
+        file: base.config_file.clone(),
+        remote_url: base.remote_config_url.clone(),
+        remote: HashMap::new(),
+        client: Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create remote config client")?,
+    };
+
+    if let Err(e) = sources.refresh_remote().await {
+        warn!(error = %e, "remote_config_fetch_failed");
+    }
+
+    let config = sources.build()?;
+    Ok((config, sources))
+}
+
+impl Sources {
+    /// Build a config from every layer.
+    fn build(&self) -> Result<WorkerConfig> {
+        let file = match &self.file {
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file {}", path))?;
+                parse_env_file(&contents)
+            }
+            None => HashMap::new(),
+        };
+
+        let config = WorkerConfig::from_vars_checked(&|name| {
+            self.overrides
+                .get(name)
+                .or_else(|| self.remote.get(name).cloned())
+                .or_else(|| file.get(name).cloned())
+                .or_else(|| env_var(name))
+        })?;
+
+        Ok(config)
+    }
+
+    /// Fetch the remote source, returning whether its values changed.
+    async fn refresh_remote(&mut self) -> Result<bool> {
+        let Some(url) = &self.remote_url else {
+            return Ok(false);
+        };
+
+        let body = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .with_context(|| format!("Failed to fetch remote config from {}", url))?
+            .text()
+            .await
+            .context("Failed to read remote config body")?;
+
+        let values = parse_remote_body(&body);
+        if values == self.remote {
+            return Ok(false);
+        }
+
+        info!(keys = values.len(), "remote_config_fetched");
+        self.remote = values;
+        Ok(true)
+    }
+}
+
+/// Parse a remote config document.
+///
+/// Accepts a flat JSON object keyed by variable name (numbers, booleans and
+/// arrays are converted to their env-var form) or `KEY=VALUE` lines, such as
+/// a Consul KV entry fetched with `?raw`.
+pub fn parse_remote_body(body: &str) -> HashMap<String, String> {
+    match serde_json::from_str::<HashMap<String, serde_json::Value>>(body) {
+        Ok(object) => object
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Number(n) => n.to_string(),
+                    serde_json::Value::Bool(b) => b.to_string(),
+                    serde_json::Value::Array(items) => items
+                        .iter()
+                        .map(|item| match item {
+                            serde_json::Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    _ => return None,
+                };
+                Some((key, value))
+            })
+            .collect(),
+        Err(_) => parse_env_file(body),
+    }
+}
+
+/// Parse `KEY=VALUE` lines, skipping blanks and `#` comments.
+///
+/// Values may be wrapped in single or double quotes.
+pub fn parse_env_file(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Reload on SIGHUP, config file changes and remote updates until the
+/// process exits.
+pub async fn watch(live: Arc<ArcSwap<WorkerConfig>>, mut sources: Sources) {
+    let (interval_secs, remote_interval_secs) = {
+        let config = live.load();
+        (config.config_watch_interval_secs, config.remote_config_interval_secs)
+    };
+
+    #[cfg(unix)]
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(e) => {
+            warn!(error = %e, "config_sighup_handler_failed");
+            None
+        }
+    };
+
+    let watching = sources.file.is_some() && interval_secs > 0;
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+    let mut last_modified = sources.file.as_deref().and_then(modified_at);
+
+    let polling = sources.remote_url.is_some() && remote_interval_secs > 0;
+    let mut remote_ticker =
+        tokio::time::interval(Duration::from_secs(remote_interval_secs.max(1)));
+    remote_ticker.tick().await;
+
+    info!(
+        config_file = ?sources.file,
+        watch_interval_secs = interval_secs,
+        remote_config = sources.remote_url.is_some(),
+        remote_interval_secs = remote_interval_secs,
+        "config_reload_enabled"
+    );
+
+    loop {
+        #[cfg(unix)]
+        let sighup = async {
+            match hangup.as_mut() {
+                Some(signal) => signal.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let sighup = std::future::pending::<Option<()>>();
+
+        let reason = tokio::select! {
+            _ = sighup => "sighup",
+            _ = ticker.tick(), if watching => {
+                let modified = sources.file.as_deref().and_then(modified_at);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                "file_changed"
+            }
+            _ = remote_ticker.tick(), if polling => {
+                match sources.refresh_remote().await {
+                    Ok(true) => "remote_changed",
+                    Ok(false) => continue,
+                    Err(e) => {
+                        warn!(error = %e, "remote_config_fetch_failed");
+                        continue;
+                    }
+                }
+            }
+        };
+
+        reload(&live, &sources, reason);
+    }
+}
+
+/// Rebuild the configuration and swap in its simulation parameters.
+fn reload(live: &ArcSwap<WorkerConfig>, sources: &Sources, reason: &str) {
+    let fresh = match sources.build() {
+        Ok(config) => config,
+        Err(e) => {
+            warn!(reason = reason, error = %e, "config_reload_failed");
+            return;
+        }
+    };
+
+    let mut next = WorkerConfig::clone(&live.load());
+    next.apply_simulation_params(&fresh);
+
+    info!(
+        reason = reason,
+        open_probability = next.simulate_open_probability,
+        click_probability = next.simulate_click_probability,
+        max_clicks = next.max_clicks,
+        open_delay_ms = ?next.open_delay_ms,
+        click_delay_ms = ?next.click_delay_ms,
+        canary_percent = next.canary_percent,
+        "config_reloaded"
+    );
+
+    live.store(Arc::new(next));
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_file() {
+        let vars = parse_env_file(
+            "# comment\n\nSIMULATE_OPEN_PROBABILITY=0.5\nexport MAX_CLICKS = 3\nUSER_AGENT_POOL=\"A/1.0,B/1.0\"\ninvalid line\n",
+        );
+
+        assert_eq!(vars.get("SIMULATE_OPEN_PROBABILITY").map(String::as_str), Some("0.5"));
+        assert_eq!(vars.get("MAX_CLICKS").map(String::as_str), Some("3"));
+        assert_eq!(vars.get("USER_AGENT_POOL").map(String::as_str), Some("A/1.0,B/1.0"));
+        assert_eq!(vars.len(), 3);
+    }
+
+    #[test]
+    fn test_reload_keeps_startup_settings() {
+        let path = std::env::temp_dir().join(format!("bobnet-reload-{}.env", std::process::id()));
+        fs::write(&path, "SIMULATE_CLICK_PROBABILITY=0.9\nWORKER_CONCURRENCY=3\n").unwrap();
+
+        let sources = Sources {
+            overrides: Overrides::new(),
+            file: path.to_str().map(String::from),
+            remote_url: None,
+            remote: HashMap::from([("SIMULATE_OPEN_PROBABILITY".to_string(), "0.2".to_string())]),
+            client: Client::new(),
+        };
+
+        let live = ArcSwap::from_pointee(WorkerConfig::from_vars(&|_| None));
+        reload(&live, &sources, "test");
+        fs::remove_file(&path).unwrap();
+
+        let config = live.load();
+        assert_eq!(config.simulate_click_probability, 0.9);
+        assert_eq!(config.simulate_open_probability, 0.2);
+        assert_eq!(config.worker_concurrency, 100);
+    }
+
+    #[test]
+    fn test_parse_remote_body() {
+        let json = parse_remote_body(
+            r#"{"SIMULATE_OPEN_PROBABILITY": 0.4, "ADAPTIVE_CONCURRENCY": true, "LINK_DOMAIN_DENYLIST": ["a.com", "b.com"], "IGNORED": null}"#,
+        );
+        assert_eq!(json.get("SIMULATE_OPEN_PROBABILITY").map(String::as_str), Some("0.4"));
+        assert_eq!(json.get("ADAPTIVE_CONCURRENCY").map(String::as_str), Some("true"));
+        assert_eq!(json.get("LINK_DOMAIN_DENYLIST").map(String::as_str), Some("a.com,b.com"));
+        assert!(!json.contains_key("IGNORED"));
+
+        let raw = parse_remote_body("MAX_CLICKS=4\n");
+        assert_eq!(raw.get("MAX_CLICKS").map(String::as_str), Some("4"));
+    }
+}
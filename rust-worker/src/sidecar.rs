@@ -0,0 +1,443 @@
+//! Worker health and metrics sidecar.
+//!
+//! The worker has no inbound HTTP traffic of its own, so this optional
+//! server exposes liveness, Prometheus metrics, and a compact JSON document
+//! of autoscaling signals (backlog, message age, in-flight exposure) meant
+//! for KEDA's metrics-api scaler or an HPA external metric.
+//!
+//! It also serves `/stats?since=1h`, this worker's simulation outcomes over a
+//! recent window, which `bobnet-cli stats` collects from every worker,
+//! `/campaigns/{id}`, the funnel of each campaign this worker simulated, and
+//! `/usage?since=30d`, what each tenant used, `/drift`, each tenant's
+//! realized open and click rates against their targets, and
+//! `/timings?jobs=200`, where recent jobs spent their time, stage by stage.
+//!
+//! When `API_KEYS` is set, `/metrics`, `/scaling`, `/stats`, `/campaigns`,
+//! `/usage`, `/drift` and `/timings` require a key with the `metrics` scope; `/health`
+//! stays open for probes.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use bobnet::campaign::{CampaignStats, CampaignSummary};
+use bobnet::metrics::{Counter, Gauge, Histogram, Registry};
+use bobnet::simulate::latency::{Latency, LATENCY_BUCKETS};
+use bobnet::simulate::report::SimulationReport;
+use bobnet::simulate::timing::{StageTimings, TimingBreakdown};
+use bobnet::stats::{parse_window, SimulationStats, StatsSummary};
+use bobnet::tenant::drift::{DriftMonitor, DriftPolicy, TenantDrift};
+use bobnet::tenant::usage::{DailyUsage, UsageLedger, UsageReport};
+use bobnet::util::instance::instance_id;
+use bobnet::auth::{require_scope, ApiKeys, SCOPE_METRICS};
+
+/// Metrics tracked by the worker.
+pub struct WorkerMetrics {
+    pub registry: Registry,
+    pub jobs_received: Counter,
+    pub jobs_completed: Counter,
+    pub jobs_expired: Counter,
+    pub poison_messages: Counter,
+    pub in_flight: Gauge,
+    pub queue_ready: Gauge,
+    pub queue_consumers: Gauge,
+    /// Age of the last message delivered, or 0 once the queues are drained
+    pub message_age_seconds: Gauge,
+    /// When `message_age_seconds` was last observed, in Unix seconds
+    pub message_age_observed_at: Gauge,
+    pub concurrency_limit: Gauge,
+    pub prefetch: Gauge,
+    /// Webhook receipt to the simulator job's publish
+    pub inbound_latency: Histogram,
+    /// Simulator job publish to the worker starting it
+    pub queue_latency: Histogram,
+    /// Worker start to the end of the simulation
+    pub simulate_latency: Histogram,
+    /// Webhook receipt to the end of the simulation
+    pub end_to_end_latency: Histogram,
+    /// Tenants whose open or click rate is drifting from its target
+    pub drifting_tenants: Gauge,
+    /// Times a tenant's rate started drifting
+    pub drift_alerts: Counter,
+    /// Recent job outcomes for `/stats`
+    pub stats: SimulationStats,
+    /// Recent campaign funnels for `/campaigns`
+    pub campaigns: CampaignStats,
+    /// Daily usage per tenant for `/usage`
+    pub usage: UsageLedger,
+    /// Realized rates per tenant for `/drift`
+    pub drift: DriftMonitor,
+    /// Stage times of recent jobs for `/timings`
+    pub timings: StageTimings,
+}
+
+impl WorkerMetrics {
+    /// Create the worker metrics, labelled with this instance's ID.
+    pub fn new() -> Self {
+        let registry = Registry::with_labels(&[("instance", instance_id())]);
+        Self {
+            jobs_received: registry.counter(
+                "bobnet_worker_jobs_received_total",
+                "Jobs delivered to this worker",
+            ),
+            jobs_completed: registry.counter(
+                "bobnet_worker_jobs_completed_total",
+                "Jobs simulated and acknowledged",
+            ),
+            jobs_expired: registry.counter(
+                "bobnet_worker_jobs_expired_total",
+                "Jobs skipped for being older than MAX_MESSAGE_AGE_SECS",
+            ),
+            poison_messages: registry.counter(
+                "bobnet_worker_poison_messages_total",
+                "Jobs moved to the DLQ after MAX_DELIVERY_ATTEMPTS deliveries",
+            ),
+            in_flight: registry.gauge(
+                "bobnet_worker_in_flight_jobs",
+                "Jobs delivered but not yet acknowledged",
+            ),
+            queue_ready: registry.gauge(
+                "bobnet_worker_queue_ready_messages",
+                "Messages ready across the consumed simulator queues",
+            ),
+            queue_consumers: registry.gauge(
+                "bobnet_worker_queue_consumers",
+                "Consumers attached to the consumed simulator queues",
+            ),
+            message_age_seconds: registry.gauge(
+                "bobnet_worker_message_age_seconds",
+                "Age of the most recently delivered message, 0 while the queues are empty",
+            ),
+            message_age_observed_at: registry.gauge(
+                "bobnet_worker_message_age_observed_at_seconds",
+                "Unix time the message age was last observed",
+            ),
+            concurrency_limit: registry.gauge(
+                "bobnet_worker_concurrency_limit",
+                "Current job concurrency limit",
+            ),
+            prefetch: registry.gauge(
+                "bobnet_worker_prefetch",
+                "Current prefetch of each consumer channel",
+            ),
+            inbound_latency: registry.histogram(
+                "bobnet_worker_inbound_latency_seconds",
+                "Webhook receipt to the simulator job's publish",
+                LATENCY_BUCKETS,
+            ),
+            queue_latency: registry.histogram(
+                "bobnet_worker_queue_latency_seconds",
+                "Simulator job publish to the worker starting it",
+                LATENCY_BUCKETS,
+            ),
+            simulate_latency: registry.histogram(
+                "bobnet_worker_simulate_latency_seconds",
+                "Worker start to the end of the simulation",
+                LATENCY_BUCKETS,
+            ),
+            end_to_end_latency: registry.histogram(
+                "bobnet_worker_end_to_end_latency_seconds",
+                "Webhook receipt to the end of the simulation",
+                LATENCY_BUCKETS,
+            ),
+            drifting_tenants: registry.gauge(
+                "bobnet_worker_drifting_tenants",
+                "Tenants whose realized open or click rate is off its target",
+            ),
+            drift_alerts: registry.counter(
+                "bobnet_worker_rate_drift_alerts_total",
+                "Times a tenant's realized open or click rate drifted off its target",
+            ),
+            stats: SimulationStats::new(),
+            campaigns: CampaignStats::new(),
+            usage: UsageLedger::new(),
+            drift: DriftMonitor::new(),
+            timings: StageTimings::new(),
+            registry,
+        }
+    }
+
+    /// Record the age of a message published at `published_at`, observed at
+    /// `now`, both in Unix seconds.
+    pub fn record_message_age(&self, published_at: u64, now: u64) {
+        self.message_age_seconds.set(now.saturating_sub(published_at) as i64);
+        self.message_age_observed_at.set(now as i64);
+    }
+
+    /// Record the consumed queues' depth, sampled at `now` in Unix seconds.
+    ///
+    /// With nothing waiting there is no backlog to age, so the message age
+    /// drops to 0 rather than keep reporting the last message's.
+    pub fn record_queue_depth(&self, ready: i64, consumers: i64, now: u64) {
+        self.queue_ready.set(ready);
+        self.queue_consumers.set(consumers);
+        if ready == 0 {
+            self.message_age_seconds.set(0);
+            self.message_age_observed_at.set(now as i64);
+        }
+    }
+
+    /// Add a finished job's stages to the latency histograms; stages the
+    /// job carried no timestamps for are left out.
+    pub fn record_latency(&self, latency: &Latency) {
+        let seconds = |ms: u64| ms as f64 / 1000.0;
+        if let Some(ms) = latency.inbound_ms {
+            self.inbound_latency.observe(seconds(ms));
+        }
+        if let Some(ms) = latency.queue_ms {
+            self.queue_latency.observe(seconds(ms));
+        }
+        self.simulate_latency.observe(seconds(latency.simulate_ms));
+        if let Some(ms) = latency.total_ms {
+            self.end_to_end_latency.observe(seconds(ms));
+        }
+    }
+}
+
+impl WorkerMetrics {
+    /// Count a finished job towards its tenant's realized rates, logging
+    /// each rate that starts or stops drifting under `policy`.
+    pub fn record_drift(&self, report: &SimulationReport, policy: &DriftPolicy) {
+        for alert in self.drift.record(report, policy) {
+            if alert.drifting {
+                self.drift_alerts.inc();
+                warn!(
+                    tenant = ?alert.tenant,
+                    rate = alert.rate.as_str(),
+                    target = alert.target,
+                    actual = alert.actual,
+                    jobs = alert.jobs,
+                    "rate_drift_detected"
+                );
+            } else {
+                info!(
+                    tenant = ?alert.tenant,
+                    rate = alert.rate.as_str(),
+                    target = alert.target,
+                    actual = alert.actual,
+                    jobs = alert.jobs,
+                    "rate_drift_recovered"
+                );
+            }
+        }
+        self.drifting_tenants.set(self.drift.drifting() as i64);
+    }
+}
+
+impl Default for WorkerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Autoscaling signals returned by `GET /scaling`.
+#[derive(Serialize)]
+pub struct ScalingSignals {
+    pub instance: &'static str,
+    pub queues: Vec<String>,
+    pub ready_messages: i64,
+    pub in_flight: i64,
+    pub backlog_estimate: i64,
+    pub consumers: i64,
+    pub message_age_seconds: i64,
+    pub message_age_observed_at: i64,
+    pub concurrency_limit: i64,
+}
+
+/// State shared by the sidecar handlers.
+struct SidecarState {
+    metrics: Arc<WorkerMetrics>,
+    queues: Vec<String>,
+}
+
+/// Serve the sidecar on the given port until the process exits.
+///
+/// `queues` are the simulator queues this worker consumes, reported in
+/// `/scaling` alongside their combined depth.
+pub async fn serve(
+    port: u16,
+    metrics: Arc<WorkerMetrics>,
+    queues: Vec<String>,
+    api_keys: Arc<ApiKeys>,
+) -> Result<()> {
+    let protected = Router::new()
+        .route("/metrics", get(prometheus))
+        .route("/scaling", get(scaling))
+        .route("/stats", get(stats))
+        .route("/campaigns", get(campaigns))
+        .route("/campaigns/:id", get(campaign))
+        .route("/usage", get(usage))
+        .route("/usage/:tag", get(tenant_usage))
+        .route("/drift", get(drift))
+        .route("/timings", get(timings));
+
+    let app = Router::new()
+        .route("/health", get(health))
+        .merge(require_scope(protected, &api_keys, SCOPE_METRICS))
+        .with_state(Arc::new(SidecarState { metrics, queues }));
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = TcpListener::bind(addr)
+        .await
+        .context("Failed to bind sidecar address")?;
+
+    info!(address = %addr, "worker_sidecar_listening");
+
+    axum::serve(listener, app)
+        .await
+        .context("Sidecar server error")?;
+
+    Ok(())
+}
+
+async fn health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+async fn prometheus(State(state): State<Arc<SidecarState>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.registry.render(),
+    )
+}
+
+async fn scaling(State(state): State<Arc<SidecarState>>) -> Json<ScalingSignals> {
+    let metrics = &state.metrics;
+    let ready = metrics.queue_ready.get();
+    let in_flight = metrics.in_flight.get();
+
+    Json(ScalingSignals {
+        instance: instance_id(),
+        queues: state.queues.clone(),
+        ready_messages: ready,
+        in_flight,
+        backlog_estimate: ready + in_flight,
+        consumers: metrics.queue_consumers.get(),
+        message_age_seconds: metrics.message_age_seconds.get(),
+        message_age_observed_at: metrics.message_age_observed_at.get(),
+        concurrency_limit: metrics.concurrency_limit.get(),
+    })
+}
+
+/// Query parameters of `GET /stats`.
+#[derive(Deserialize)]
+struct StatsQuery {
+    /// Window such as `15m` or `1h` (default `1h`)
+    since: Option<String>,
+}
+
+async fn stats(
+    State(state): State<Arc<SidecarState>>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<StatsSummary>, (StatusCode, String)> {
+    let since = query.since.as_deref().unwrap_or("1h");
+    let window = parse_window(since).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("invalid window `{}` (expected e.g. 90s, 15m, 1h or 2d)", since),
+        )
+    })?;
+    Ok(Json(state.metrics.stats.summary(window)))
+}
+
+async fn campaigns(State(state): State<Arc<SidecarState>>) -> Json<Vec<CampaignSummary>> {
+    Json(state.metrics.campaigns.list())
+}
+
+async fn campaign(
+    State(state): State<Arc<SidecarState>>,
+    Path(id): Path<String>,
+) -> Result<Json<CampaignSummary>, (StatusCode, String)> {
+    state.metrics.campaigns.get(&id).map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("campaign `{}` not seen in the last 24h", id),
+        )
+    })
+}
+
+/// Query parameters of `GET /usage`.
+#[derive(Deserialize)]
+struct UsageQuery {
+    /// Window such as `7d` or `30d`, counted in whole UTC days (default `30d`)
+    since: Option<String>,
+}
+
+/// Whole UTC days covered by the `since` window.
+fn usage_days(since: Option<&str>) -> Result<u64, (StatusCode, String)> {
+    let since = since.unwrap_or("30d");
+    let window = parse_window(since).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("invalid window `{}` (expected e.g. 1d, 7d or 30d)", since),
+        )
+    })?;
+    Ok(window.as_secs().div_ceil(24 * 60 * 60))
+}
+
+async fn usage(
+    State(state): State<Arc<SidecarState>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageReport>, (StatusCode, String)> {
+    let days = usage_days(query.since.as_deref())?;
+    Ok(Json(state.metrics.usage.report(days)))
+}
+
+async fn tenant_usage(
+    State(state): State<Arc<SidecarState>>,
+    Path(tag): Path<String>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<Vec<DailyUsage>>, (StatusCode, String)> {
+    let days = usage_days(query.since.as_deref())?;
+    Ok(Json(state.metrics.usage.daily(&tag, days)))
+}
+
+async fn drift(State(state): State<Arc<SidecarState>>) -> Json<Vec<TenantDrift>> {
+    Json(state.metrics.drift.snapshot())
+}
+
+/// Query parameters of `GET /timings`.
+#[derive(Deserialize)]
+struct TimingsQuery {
+    /// Most recent jobs to break down (default every job kept)
+    jobs: Option<usize>,
+}
+
+async fn timings(
+    State(state): State<Arc<SidecarState>>,
+    Query(query): Query<TimingsQuery>,
+) -> Json<TimingBreakdown> {
+    Json(state.metrics.timings.breakdown(query.jobs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_age_resets_when_drained() {
+        let metrics = WorkerMetrics::new();
+        metrics.record_message_age(1_000, 1_300);
+        assert_eq!(metrics.message_age_seconds.get(), 300);
+        assert_eq!(metrics.message_age_observed_at.get(), 1_300);
+
+        // Still a backlog: the last age stands
+        metrics.record_queue_depth(12, 2, 1_310);
+        assert_eq!(metrics.message_age_seconds.get(), 300);
+
+        metrics.record_queue_depth(0, 2, 1_320);
+        assert_eq!(metrics.message_age_seconds.get(), 0);
+        assert_eq!(metrics.message_age_observed_at.get(), 1_320);
+    }
+}
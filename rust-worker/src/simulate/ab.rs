@@ -0,0 +1,161 @@
+//! Named A/B cohorts with their own behavior profiles.
+//!
+//! `AB_COHORTS` splits recipients between named cohorts by weight, e.g.
+//! `control:50,engaged:50:0.9:0.6:3`, and simulates each cohort with its own
+//! open and click probabilities and click limit. Downstream A/B analytics can
+//! then be tested against a known engagement difference.
+//!
+//! Assignment hashes the recipient, so a subscriber stays in the same cohort
+//! across emails and redeliveries, independently of the canary split.
+
+use crate::config::WorkerConfig;
+use crate::util::hash::stable_hash;
+
+/// One cohort of the split.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbCohort {
+    /// Name reported as `ab_cohort` in results
+    pub name: String,
+    /// Share of recipients, relative to the other cohorts' weights
+    pub weight: u32,
+    /// Probability of simulating an email open (0.0 - 1.0)
+    pub open_probability: Option<f64>,
+    /// Probability of simulating a click (0.0 - 1.0)
+    pub click_probability: Option<f64>,
+    /// Maximum number of links to click per email
+    pub max_clicks: Option<usize>,
+}
+
+impl AbCohort {
+    /// Parse an entry of the form
+    /// `name:weight[:open_probability[:click_probability[:max_clicks]]]`;
+    /// empty settings keep the worker's.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let mut parts = entry.trim().split(':');
+        let name = parts.next()?.trim().to_string();
+        let weight = parts.next()?.trim().parse().ok()?;
+        let probability = |part: Option<&str>| match part.map(str::trim) {
+            None | Some("") => Some(None),
+            Some(p) => p
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=1.0).contains(p))
+                .map(Some),
+        };
+        let open_probability = probability(parts.next())?;
+        let click_probability = probability(parts.next())?;
+        let max_clicks = match parts.next().map(str::trim) {
+            None | Some("") => None,
+            Some(n) => Some(n.parse().ok()?),
+        };
+
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name || parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            name,
+            weight,
+            open_probability,
+            click_probability,
+            max_clicks,
+        })
+    }
+
+    /// `config` with this cohort's settings applied.
+    pub fn apply(&self, config: &WorkerConfig) -> WorkerConfig {
+        let mut config = config.clone();
+        if let Some(p) = self.open_probability {
+            config.simulate_open_probability = p;
+        }
+        if let Some(p) = self.click_probability {
+            config.simulate_click_probability = p;
+        }
+        if let Some(max) = self.max_clicks {
+            config.max_clicks = max;
+        }
+        config
+    }
+}
+
+/// Assign a recipient to one of `cohorts` in proportion to their weights.
+///
+/// Returns `None` when no cohort has a weight.
+pub fn assign<'a>(cohorts: &'a [AbCohort], recipient: &str) -> Option<&'a AbCohort> {
+    let total: u64 = cohorts.iter().map(|c| u64::from(c.weight)).sum();
+    if total == 0 {
+        return None;
+    }
+
+    // Salted, so the A/B split does not line up with the canary split
+    let mut bucket = stable_hash(&["ab", &recipient.trim().to_lowercase()]) % total;
+
+    cohorts.iter().find(|cohort| {
+        let weight = u64::from(cohort.weight);
+        if bucket < weight {
+            return true;
+        }
+        bucket -= weight;
+        false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cohort_parse() {
+        assert_eq!(
+            AbCohort::parse(" engaged:50:0.9::3 "),
+            Some(AbCohort {
+                name: "engaged".to_string(),
+                weight: 50,
+                open_probability: Some(0.9),
+                click_probability: None,
+                max_clicks: Some(3),
+            })
+        );
+        assert_eq!(
+            AbCohort::parse("control:50").map(|c| c.open_probability),
+            Some(None)
+        );
+        assert_eq!(AbCohort::parse("control"), None);
+        assert_eq!(AbCohort::parse(":50"), None);
+        assert_eq!(AbCohort::parse("a b:50"), None);
+        assert_eq!(AbCohort::parse("control:half"), None);
+        assert_eq!(AbCohort::parse("control:50:1.5"), None);
+        assert_eq!(AbCohort::parse("control:50:::1:extra"), None);
+    }
+
+    #[test]
+    fn test_assign_follows_weights() {
+        let cohorts = vec![
+            AbCohort::parse("control:75").unwrap(),
+            AbCohort::parse("engaged:25:1.0").unwrap(),
+            AbCohort::parse("unused:0").unwrap(),
+        ];
+        let mut counts = [0; 3];
+        for i in 0..2000 {
+            let cohort = assign(&cohorts, &format!("user{}@example.com", i)).unwrap();
+            counts[cohorts.iter().position(|c| c == cohort).unwrap()] += 1;
+        }
+        assert!((400..600).contains(&counts[1]), "got {:?}", counts);
+        assert_eq!(counts[2], 0);
+
+        // Sticky per recipient, whatever the case
+        assert_eq!(
+            assign(&cohorts, "User@Example.com"),
+            assign(&cohorts, "user@example.com")
+        );
+        assert_eq!(assign(&cohorts[2..], "user@example.com"), None);
+
+        // Pinned, so a Rust upgrade cannot move recipients between cohorts
+        assert_eq!(assign(&cohorts, "alice@example.com").unwrap().name, "control");
+        assert_eq!(assign(&cohorts, "bob@example.com").unwrap().name, "engaged");
+    }
+}
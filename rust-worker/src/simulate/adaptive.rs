@@ -0,0 +1,234 @@
+//! Adaptive concurrency control based on downstream fetch health.
+//!
+//! Implements an AIMD (additive-increase / multiplicative-decrease) limiter:
+//! when tracking endpoints start failing or slowing down, the number of jobs
+//! allowed to run at once is halved; once fetches are healthy again the limit
+//! grows back by one slot per window.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+/// Number of fetch samples collected before the limit is re-evaluated.
+const WINDOW_SIZE: usize = 20;
+
+/// Multiplicative decrease factor applied after an unhealthy window.
+const DECREASE_FACTOR: f64 = 0.5;
+
+/// AIMD concurrency limiter shared by all in-flight jobs.
+pub struct AdaptiveConcurrency {
+    limit: AtomicUsize,
+    in_flight: AtomicUsize,
+    min: usize,
+    max: usize,
+    error_threshold: f64,
+    latency_threshold: Duration,
+    window: Mutex<Window>,
+    notify: Notify,
+}
+
+/// Fetch outcomes observed since the last evaluation.
+#[derive(Default)]
+struct Window {
+    samples: usize,
+    failures: usize,
+    total_latency: Duration,
+}
+
+/// A slot in the concurrency limit, released when dropped.
+pub struct ConcurrencyPermit {
+    limiter: Arc<AdaptiveConcurrency>,
+}
+
+impl AdaptiveConcurrency {
+    /// Create a limiter that starts at `max` and never drops below `min`.
+    pub fn new(min: usize, max: usize, error_threshold: f64, latency_threshold: Duration) -> Self {
+        let max = max.max(1);
+        let min = min.clamp(1, max);
+
+        Self {
+            limit: AtomicUsize::new(max),
+            in_flight: AtomicUsize::new(0),
+            min,
+            max,
+            error_threshold,
+            latency_threshold,
+            window: Mutex::new(Window::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Current concurrency limit.
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Acquire)
+    }
+
+    /// Number of permits currently held.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Wait until a slot is available under the current limit.
+    pub async fn acquire(self: &Arc<Self>) -> ConcurrencyPermit {
+        loop {
+            let notified = self.notify.notified();
+            if let Some(permit) = self.try_acquire() {
+                return permit;
+            }
+            notified.await;
+        }
+    }
+
+    /// Take a slot if one is available without waiting.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<ConcurrencyPermit> {
+        let limit = self.limit();
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                if n < limit {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(|_| ConcurrencyPermit {
+                limiter: Arc::clone(self),
+            })
+    }
+
+    /// Record the outcome of a single outbound fetch.
+    ///
+    /// Every `WINDOW_SIZE` samples the limit is halved if the error rate or
+    /// average latency exceeded the configured thresholds, and otherwise
+    /// increased by one (up to the configured maximum).
+    pub fn record(&self, success: bool, latency: Duration) {
+        let (error_rate, avg_latency) = {
+            let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+            window.samples += 1;
+            if !success {
+                window.failures += 1;
+            }
+            window.total_latency += latency;
+
+            if window.samples < WINDOW_SIZE {
+                return;
+            }
+
+            let error_rate = window.failures as f64 / window.samples as f64;
+            let avg_latency = window.total_latency / window.samples as u32;
+            *window = Window::default();
+            (error_rate, avg_latency)
+        };
+
+        let current = self.limit();
+
+        if error_rate > self.error_threshold || avg_latency > self.latency_threshold {
+            let next = ((current as f64 * DECREASE_FACTOR) as usize).max(self.min);
+            if next < current {
+                self.limit.store(next, Ordering::Release);
+                tracing::warn!(
+                    previous_limit = current,
+                    new_limit = next,
+                    error_rate = error_rate,
+                    avg_latency_ms = avg_latency.as_millis() as u64,
+                    "adaptive_concurrency_decreased"
+                );
+            }
+        } else if current < self.max {
+            self.limit.store(current + 1, Ordering::Release);
+            self.notify.notify_one();
+            tracing::debug!(
+                previous_limit = current,
+                new_limit = current + 1,
+                error_rate = error_rate,
+                avg_latency_ms = avg_latency.as_millis() as u64,
+                "adaptive_concurrency_increased"
+            );
+        }
+    }
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::AcqRel);
+        self.limiter.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(min: usize, max: usize) -> Arc<AdaptiveConcurrency> {
+        Arc::new(AdaptiveConcurrency::new(
+            min,
+            max,
+            0.2,
+            Duration::from_millis(1000),
+        ))
+    }
+
+    #[test]
+    fn test_starts_at_max() {
+        let limiter = limiter(2, 10);
+        assert_eq!(limiter.limit(), 10);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_decreases_on_errors() {
+        let limiter = limiter(2, 10);
+        for _ in 0..WINDOW_SIZE {
+            limiter.record(false, Duration::from_millis(10));
+        }
+        assert_eq!(limiter.limit(), 5);
+    }
+
+    #[test]
+    fn test_decreases_on_latency() {
+        let limiter = limiter(2, 10);
+        for _ in 0..WINDOW_SIZE {
+            limiter.record(true, Duration::from_millis(5000));
+        }
+        assert_eq!(limiter.limit(), 5);
+    }
+
+    #[test]
+    fn test_never_below_min() {
+        let limiter = limiter(4, 10);
+        for _ in 0..(WINDOW_SIZE * 5) {
+            limiter.record(false, Duration::from_millis(10));
+        }
+        assert_eq!(limiter.limit(), 4);
+    }
+
+    #[test]
+    fn test_recovers_additively() {
+        let limiter = limiter(2, 10);
+        for _ in 0..WINDOW_SIZE {
+            limiter.record(false, Duration::from_millis(10));
+        }
+        assert_eq!(limiter.limit(), 5);
+
+        for _ in 0..(WINDOW_SIZE * 2) {
+            limiter.record(true, Duration::from_millis(10));
+        }
+        assert_eq!(limiter.limit(), 7);
+    }
+
+    #[test]
+    fn test_try_acquire_respects_limit() {
+        let limiter = limiter(1, 2);
+        let first = limiter.try_acquire();
+        let second = limiter.try_acquire();
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(limiter.try_acquire().is_none());
+
+        drop(first);
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(limiter.try_acquire().is_some());
+    }
+}
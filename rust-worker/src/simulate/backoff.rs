@@ -0,0 +1,172 @@
+//! Backing off tracking hosts that answer `429 Too Many Requests`.
+//!
+//! A 429 holds its host for the response's `Retry-After` (seconds or an
+//! HTTP date; [`DEFAULT_BACKOFF`] without one), capped at
+//! `RATE_LIMIT_BACKOFF_MAX_SECS`. The hold is shared by every job on the
+//! worker: fetches to a held host wait for it to end instead of adding to
+//! the load, and with `RATE_LIMIT_RETRIES` the rate-limited fetch itself is
+//! sent again once it does.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+
+use crate::tenant::usage::day_of;
+
+/// Hold on a host that answered 429 without a usable `Retry-After`.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Hosts held at once; expired holds are dropped first.
+const MAX_HOSTS: usize = 10_000;
+
+/// How long to wait before asking again, for a 429 response.
+pub fn rate_limited(response: &Response) -> Option<Duration> {
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, now));
+    Some(retry_after.unwrap_or(DEFAULT_BACKOFF))
+}
+
+/// Parse a `Retry-After` value, delay seconds or an IMF-fixdate such as
+/// `Wed, 21 Oct 2015 07:28:00 GMT`, at `now` (Unix seconds).
+///
+/// A date in the past is no delay.
+pub fn parse_retry_after(value: &str, now: u64) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    // `Wed, 21 Oct 2015 07:28:00 GMT`
+    let mut parts = value.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = match parts.next()? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.splitn(3, ':');
+    let (h, m, s): (u64, u64, u64) = (
+        time.next()?.parse().ok()?,
+        time.next()?.parse().ok()?,
+        time.next()?.parse().ok()?,
+    );
+    if parts.next()? != "GMT" || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+    let days = day_of(&format!("{:04}-{:02}-{:02}", year, month, day))?;
+    let at = days * 86_400 + h * 3600 + m * 60 + s;
+    Some(Duration::from_secs(at.saturating_sub(now)))
+}
+
+/// Hosts backed off after a 429, shared by every job of a simulator.
+#[derive(Debug, Default)]
+pub struct HostBackoff {
+    held: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `host` for `duration`, unless it is already held for longer.
+    pub fn hold(&self, host: &str, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        if held.len() >= MAX_HOSTS && !held.contains_key(host) {
+            let now = Instant::now();
+            held.retain(|_, until| *until > now);
+            if held.len() >= MAX_HOSTS {
+                return;
+            }
+        }
+        let entry = held.entry(host.to_string()).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+
+    /// How long `host` is still held for, if it is.
+    pub fn remaining(&self, host: &str) -> Option<Duration> {
+        let held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        held.get(host)
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, retry_after: Option<&str>) -> Response {
+        let mut builder = http::Response::builder().status(status);
+        if let Some(value) = retry_after {
+            builder = builder.header(RETRY_AFTER, value);
+        }
+        Response::from(builder.body(Vec::new()).unwrap())
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        // 2015-10-21 07:28:00 UTC
+        let at = 1_445_412_480;
+        assert_eq!(parse_retry_after("120", at), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", at - 30),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", at + 30),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("-5", at), None);
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 25:28:00 GMT", at), None);
+        assert_eq!(parse_retry_after("Wed, 21 Foo 2015 07:28:00 GMT", at), None);
+        assert_eq!(parse_retry_after("soon", at), None);
+
+        assert_eq!(rate_limited(&response(200, Some("10"))), None);
+        assert_eq!(
+            rate_limited(&response(429, Some("10"))),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(rate_limited(&response(429, None)), Some(DEFAULT_BACKOFF));
+    }
+
+    #[test]
+    fn test_hold_keeps_the_longest() {
+        let backoff = HostBackoff::new();
+        assert_eq!(backoff.remaining("pixel.example.com"), None);
+
+        backoff.hold("pixel.example.com", Duration::from_secs(60));
+        backoff.hold("pixel.example.com", Duration::from_secs(1));
+        let remaining = backoff.remaining("pixel.example.com").unwrap();
+        assert!(remaining > Duration::from_secs(50), "{:?}", remaining);
+        assert_eq!(backoff.remaining("click.example.com"), None);
+
+        backoff.hold("click.example.com", Duration::ZERO);
+        assert_eq!(backoff.remaining("click.example.com"), None);
+    }
+}
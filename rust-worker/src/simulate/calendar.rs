@@ -0,0 +1,167 @@
+//! Weekend and holiday engagement calendar.
+//!
+//! People read less mail on weekends and holidays. `CALENDAR`, or a tenant
+//! profile's `calendar`, lists days of the week and dates with the factor
+//! open and click probabilities are scaled by on them, e.g.
+//! `sat:0.6,sun:0.5,2024-12-25:0.2`, so journey tests running for weeks see
+//! a realistic weekly rhythm.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::tenant::usage::day_of;
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Largest offset from UTC in minutes a calendar can be kept in.
+pub const MAX_UTC_OFFSET_MINS: i32 = 14 * 60;
+
+/// Names of the days of the week, Monday first.
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Days a calendar entry covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarDays {
+    /// Every week on this day, 0 for Monday to 6 for Sunday
+    Weekday(u64),
+    /// From the first to the last day (days since the Unix epoch), inclusive
+    Dates { first: u64, last: u64 },
+}
+
+impl CalendarDays {
+    /// Whether `day` (days since the Unix epoch) is covered.
+    pub fn contains(&self, day: u64) -> bool {
+        match *self {
+            // 1970-01-01 was a Thursday
+            CalendarDays::Weekday(weekday) => (day + 3) % 7 == weekday,
+            CalendarDays::Dates { first, last } => (first..=last).contains(&day),
+        }
+    }
+}
+
+/// Days with the factor open and click probabilities are scaled by on them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEntry {
+    pub days: CalendarDays,
+    pub factor: f64,
+}
+
+impl CalendarEntry {
+    /// Parse an entry of the form `days:factor`, where `days` is a day of
+    /// the week (`sat` or `saturday`), a date (`2024-12-25`) or a date range
+    /// (`2024-12-24..2024-12-26`).
+    ///
+    /// Returns `None` for unknown days, ranges that end before they start
+    /// and factors outside 0.0 - 1.0.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (days, factor) = entry.trim().rsplit_once(':')?;
+        let factor: f64 = factor.trim().parse().ok()?;
+        if !(0.0..=1.0).contains(&factor) {
+            return None;
+        }
+
+        let days = days.trim().to_lowercase();
+        let days = match days.split_once("..") {
+            Some((first, last)) => {
+                let (first, last) = (day_of(first.trim())?, day_of(last.trim())?);
+                if last < first {
+                    return None;
+                }
+                CalendarDays::Dates { first, last }
+            }
+            None => match day_of(&days) {
+                Some(day) => CalendarDays::Dates {
+                    first: day,
+                    last: day,
+                },
+                None if days.len() >= 3 => {
+                    let weekday = WEEKDAYS.iter().position(|name| name.starts_with(&days))?;
+                    CalendarDays::Weekday(weekday as u64)
+                }
+                None => return None,
+            },
+        };
+        Some(Self { days, factor })
+    }
+}
+
+/// Factor for `day` (days since the Unix epoch): the lowest of the entries
+/// covering it, or 1.0 when none does.
+pub fn factor_on(calendar: &[CalendarEntry], day: u64) -> f64 {
+    calendar
+        .iter()
+        .filter(|entry| entry.days.contains(day))
+        .map(|entry| entry.factor)
+        .fold(1.0, f64::min)
+}
+
+/// Today, in days since the Unix epoch, at `utc_offset_mins` from UTC.
+pub fn today(utc_offset_mins: i32) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    day_at(now, utc_offset_mins)
+}
+
+/// The day of `now` (Unix seconds), in days since the Unix epoch, at
+/// `utc_offset_mins` from UTC.
+pub fn day_at(now: u64, utc_offset_mins: i32) -> u64 {
+    let local = now as i64 + i64::from(utc_offset_mins) * 60;
+    u64::try_from(local.div_euclid(SECS_PER_DAY)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_entry_parse() {
+        assert_eq!(
+            CalendarEntry::parse(" Sat : 0.6 "),
+            Some(CalendarEntry {
+                days: CalendarDays::Weekday(5),
+                factor: 0.6,
+            })
+        );
+        assert_eq!(
+            CalendarEntry::parse("sunday:0.5").map(|e| e.days),
+            Some(CalendarDays::Weekday(6))
+        );
+        assert_eq!(
+            CalendarEntry::parse("2024-12-24..2024-12-26:0.2").map(|e| e.days),
+            Some(CalendarDays::Dates {
+                first: 20_081,
+                last: 20_083,
+            })
+        );
+        assert_eq!(CalendarEntry::parse("sa:0.5"), None);
+        assert_eq!(CalendarEntry::parse("sat:1.5"), None);
+        assert_eq!(CalendarEntry::parse("sat"), None);
+        assert_eq!(CalendarEntry::parse("2024-12-26..2024-12-24:0.2"), None);
+    }
+
+    #[test]
+    fn test_factor_on() {
+        let calendar: Vec<CalendarEntry> = ["sat:0.6", "sun:0.5", "2024-12-25:0.2", "wed:0.9"]
+            .iter()
+            .filter_map(|e| CalendarEntry::parse(e))
+            .collect();
+
+        // 2024-12-21 was a Saturday and 2024-12-25 a Wednesday
+        let saturday = day_of("2024-12-21").unwrap();
+        assert_eq!(factor_on(&calendar, saturday), 0.6);
+        assert_eq!(factor_on(&calendar, saturday + 1), 0.5);
+        assert_eq!(factor_on(&calendar, saturday + 2), 1.0);
+        assert_eq!(factor_on(&calendar, saturday + 4), 0.2);
+        assert_eq!(factor_on(&calendar, saturday + 11), 0.9);
+        assert_eq!(factor_on(&[], saturday), 1.0);
+    }
+}
@@ -0,0 +1,252 @@
+//! Calibration self-test of the open, click and link selection math.
+//!
+//! `bobnet-worker --calibrate N` runs N synthetic jobs through the
+//! [`Simulator`], answering every fetch locally with an empty `200` and
+//! skipping every delay, then compares how often jobs opened and clicked,
+//! and how the stable cohort's clicks spread over the links, with the rates
+//! the configuration asks for. Run it after changing the selection code or
+//! the probability settings.
+//!
+//! Settings that change a recipient's rates or hold its job back
+//! (`AB_COHORTS`, `RECIPIENT_PRESETS`, `RECIPIENT_ACTIVE_HOURS`) and those
+//! that change what is fetched (domain lists, `SCROLL_DEPTH`, chaos,
+//! `HTTP_CASSETTE`) are left out, so the targets are the configured ones.
+//! Links are drawn with replacement whatever `UNIQUE_CLICKS` says: only
+//! then is a link's share of clicks its share of the total weight.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use super::calendar::{factor_on, today};
+use super::cohort::Cohort;
+use super::fetch::NullFetcher;
+use super::simulator::{Job, Simulator};
+use crate::config::WorkerConfig;
+use crate::error::Result;
+
+/// Domain of the synthetic jobs' recipients and URLs.
+pub const CALIBRATION_DOMAIN: &str = "calibration.invalid";
+
+/// `data-click-rate` of each synthetic link; links without one weigh the
+/// effective click probability.
+const LINK_RATES: &[Option<f64>] = &[None, Some(0.05), Some(0.2), Some(0.6), None];
+
+/// Standard errors a realized rate may stray from its target.
+const TOLERANCE: f64 = 4.0;
+
+/// One realized rate against its target.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    /// What was measured
+    pub name: String,
+    /// Rate the configuration asks for
+    pub target: f64,
+    /// Rate the simulator produced
+    pub realized: f64,
+    /// Trials the realized rate was measured over
+    pub samples: usize,
+}
+
+impl Check {
+    /// Whether the realized rate is within [`TOLERANCE`] standard errors
+    /// of the target.
+    pub fn passed(&self) -> bool {
+        if self.samples == 0 {
+            return self.target == 0.0;
+        }
+        let error = (self.target * (1.0 - self.target) / self.samples as f64).sqrt();
+        (self.realized - self.target).abs() <= TOLERANCE * error + f64::EPSILON
+    }
+}
+
+/// Result of a calibration run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Calibration {
+    /// Synthetic jobs simulated
+    pub jobs: usize,
+    /// Open rate, click rate, then each link's share of stable-cohort clicks
+    pub checks: Vec<Check>,
+}
+
+impl Calibration {
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(Check::passed)
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Calibration over {} synthetic jobs", self.jobs)?;
+        writeln!(
+            f,
+            "{:<28} {:>8} {:>9} {:>8}",
+            "check", "target", "realized", "samples"
+        )?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "{:<28} {:>8.4} {:>9.4} {:>8}  {}",
+                check.name,
+                check.target,
+                check.realized,
+                check.samples,
+                if check.passed() { "ok" } else { "OFF" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Simulate `jobs` synthetic jobs under `config` and compare what they did
+/// with the configured rates.
+pub async fn calibrate(config: &WorkerConfig, jobs: usize) -> Result<Calibration> {
+    let config = WorkerConfig {
+        allow_domains: None,
+        deny_domains: None,
+        scroll_depth: None,
+        http_cassette: None,
+        ab_cohorts: None,
+        recipient_presets: None,
+        recipient_active_hours: None,
+        unique_clicks: false,
+        ..config.clone()
+    };
+    let mut core = config.core.clone();
+    core.chaos = None;
+    let config = WorkerConfig { core, ..config };
+
+    let calendar = config
+        .calendar
+        .as_deref()
+        .map_or(1.0, |calendar| factor_on(calendar, today(config.calendar_utc_offset_mins)));
+    let open_target = (config.simulate_open_probability * calendar).min(1.0)
+        * (1.0 - config.click_without_open_probability);
+    let click_target = (config.simulate_click_probability * calendar).min(1.0);
+    let weights: Vec<f64> = LINK_RATES
+        .iter()
+        .map(|rate| rate.unwrap_or(click_target))
+        .collect();
+    let total_weight: f64 = weights.iter().sum();
+
+    let simulator = Simulator::new(config)?
+        .with_fetcher(NullFetcher)
+        .without_delays();
+    let html = synthetic_html();
+    let urls: Vec<String> = (1..=LINK_RATES.len()).map(link_url).collect();
+
+    let mut opened = 0;
+    let mut clicked = 0;
+    let mut selections = vec![0usize; LINK_RATES.len()];
+    for i in 0..jobs {
+        let job = Job {
+            message_id: Some(format!("calibration-{}@{}", i, CALIBRATION_DOMAIN)),
+            to: format!("calibration-{}@{}", i, CALIBRATION_DOMAIN),
+            html: Some(html.clone()),
+            ..Job::default()
+        };
+        let report = simulator.simulate(&job).await;
+        opened += usize::from(report.opened);
+        clicked += usize::from(!report.clicks.is_empty());
+        if report.persona.cohort == Cohort::Stable {
+            for click in &report.clicks {
+                if let Some(link) = urls.iter().position(|url| *url == click.url) {
+                    selections[link] += 1;
+                }
+            }
+        }
+    }
+
+    let rate = |count: usize, of: usize| if of == 0 { 0.0 } else { count as f64 / of as f64 };
+    let mut checks = vec![
+        Check {
+            name: "open rate".to_string(),
+            target: open_target,
+            realized: rate(opened, jobs),
+            samples: jobs,
+        },
+        Check {
+            name: "click rate".to_string(),
+            target: click_target,
+            realized: rate(clicked, jobs),
+            samples: jobs,
+        },
+    ];
+    let selected: usize = selections.iter().sum();
+    for (i, (weight, count)) in weights.iter().zip(&selections).enumerate() {
+        checks.push(Check {
+            name: format!("link {} share (weight {:.2})", i + 1, weight),
+            target: if total_weight > 0.0 { weight / total_weight } else { 0.0 },
+            realized: rate(*count, selected),
+            samples: selected,
+        });
+    }
+    Ok(Calibration { jobs, checks })
+}
+
+fn link_url(n: usize) -> String {
+    format!("https://{}/link/{}", CALIBRATION_DOMAIN, n)
+}
+
+/// HTML of the synthetic email: a 1x1 pixel and one link per
+/// [`LINK_RATES`] entry.
+fn synthetic_html() -> String {
+    let mut html = format!(
+        r#"<html><body><img src="https://{}/open.gif" width="1" height="1">"#,
+        CALIBRATION_DOMAIN
+    );
+    for (i, rate) in LINK_RATES.iter().enumerate() {
+        let rate = rate.map_or_else(String::new, |r| format!(r#" data-click-rate="{}""#, r));
+        html += &format!(r#"<p><a href="{}"{}>Link {}</a></p>"#, link_url(i + 1), rate, i + 1);
+    }
+    html + "</body></html>"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_calibration_matches_configured_rates() {
+        let config = WorkerConfig {
+            simulate_open_probability: 0.7,
+            simulate_click_probability: 0.3,
+            click_without_open_probability: 0.1,
+            max_clicks: 2,
+            simulation_seed: Some(7),
+            ..WorkerConfig::default()
+        };
+        let calibration = calibrate(&config, 1000).await.unwrap();
+        assert!(calibration.passed(), "{}", calibration);
+        assert_eq!(calibration.checks.len(), 2 + LINK_RATES.len());
+        assert!((calibration.checks[0].target - 0.63).abs() < 1e-9);
+        // Every stable-cohort clicker draws max_clicks links
+        let selected = calibration.checks[2].samples;
+        assert!(selected > 0 && selected % 2 == 0);
+
+        // A rate the simulator doesn't produce is reported
+        let off = Check {
+            name: "open rate".to_string(),
+            target: 0.5,
+            realized: 0.6,
+            samples: 1000,
+        };
+        assert!(!off.passed());
+    }
+
+    #[tokio::test]
+    async fn test_calibration_ignores_unique_clicks() {
+        // Drawn without replacement, 4 of 5 links would take near-equal shares
+        let config = WorkerConfig {
+            simulate_open_probability: 0.7,
+            simulate_click_probability: 0.3,
+            max_clicks: 4,
+            unique_clicks: true,
+            simulation_seed: Some(7),
+            ..WorkerConfig::default()
+        };
+        let calibration = calibrate(&config, 1000).await.unwrap();
+        assert!(calibration.passed(), "{}", calibration);
+    }
+}
@@ -0,0 +1,389 @@
+//! Record and replay of simulation fetches.
+//!
+//! With `HTTP_CASSETTE` set, every fetch made through
+//! [`super::fetch::FetchContext`] goes through a cassette file:
+//!
+//! - `HTTP_CASSETTE_MODE=record`: fetches go out as usual and each response
+//!   (status, headers, start of the body) is appended to the file, which
+//!   stays a valid cassette however soon the recording stops
+//! - `HTTP_CASSETTE_MODE=replay` (default): nothing is sent; responses come
+//!   from the file, and a URL that was never recorded fails the fetch
+//!
+//! A URL requested several times replays its recordings in order, repeating
+//! the last one once they run out. This lets a production run be reproduced
+//! locally or in tests without network access.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::VarSource;
+
+/// Largest response body stored per interaction; callers only look at the
+/// status, so the rest is dropped to keep cassettes small.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Headers not recorded, since they describe the original transfer.
+const SKIPPED_HEADERS: &[&str] = &["content-length", "transfer-encoding", "connection"];
+
+/// Whether a cassette is being written or read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Send fetches and save their responses
+    Record,
+    /// Answer fetches from saved responses without sending them
+    #[default]
+    Replay,
+}
+
+impl CassetteMode {
+    /// Parse `record` or `replay`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "record" => Some(CassetteMode::Record),
+            "replay" => Some(CassetteMode::Replay),
+            _ => None,
+        }
+    }
+
+    /// Stable name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CassetteMode::Record => "record",
+            CassetteMode::Replay => "replay",
+        }
+    }
+}
+
+/// Cassette file and mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CassetteConfig {
+    /// Path of the cassette file
+    pub path: String,
+    /// Whether the file is written or read
+    pub mode: CassetteMode,
+}
+
+impl CassetteConfig {
+    /// Load cassette settings, or `None` unless `HTTP_CASSETTE` is set.
+    pub fn from_vars(var: &VarSource<'_>) -> Option<Self> {
+        let path = var("HTTP_CASSETTE")?;
+        let mode = var("HTTP_CASSETTE_MODE")
+            .and_then(|v| CassetteMode::parse(&v))
+            .unwrap_or_default();
+        Some(Self { path, mode })
+    }
+}
+
+/// Error reading, writing or replaying a cassette.
+#[derive(Debug, thiserror::Error)]
+pub enum CassetteError {
+    #[error("failed to read cassette {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid cassette {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("failed to write cassette {path}: {source}")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("no recorded response for {0}")]
+    Miss(String),
+    #[error("recorded response for {url} is invalid: {source}")]
+    Invalid { url: String, source: http::Error },
+}
+
+/// One recorded fetch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    /// Requested URL
+    pub url: String,
+    /// Response status code
+    pub status: u16,
+    /// Response headers
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// Hex-encoded start of the response body
+    #[serde(default)]
+    pub body_hex: String,
+}
+
+impl Interaction {
+    fn to_response(&self) -> Result<Response, CassetteError> {
+        let mut builder = http::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let body = hex::decode(&self.body_hex).unwrap_or_default();
+        let response = builder.body(body).map_err(|source| CassetteError::Invalid {
+            url: self.url.clone(),
+            source,
+        })?;
+        Ok(Response::from(response))
+    }
+}
+
+/// An open recording: a JSON array that each interaction is appended to
+/// by rewriting only its closing bracket.
+struct Recording {
+    file: File,
+    written: usize,
+}
+
+impl Recording {
+    /// Start an empty recording at `path`, replacing any file there.
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        file.write_all(b"[]\n")?;
+        Ok(Self { file, written: 0 })
+    }
+
+    /// Append one serialized interaction.
+    fn append(&mut self, json: &str) -> io::Result<()> {
+        // Over `]\n` for the first entry, `\n]\n` after it
+        let (back, separator) = if self.written == 0 { (2, "\n") } else { (3, ",\n") };
+        self.file.seek(SeekFrom::End(-back))?;
+        self.file.write_all(format!("{}{}\n]\n", separator, json).as_bytes())?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+/// A cassette file being recorded or replayed.
+pub struct Cassette {
+    mode: CassetteMode,
+    path: PathBuf,
+    state: Mutex<State>,
+    /// File being recorded to, shared with the blocking tasks appending
+    recording: Option<Arc<Mutex<Recording>>>,
+}
+
+#[derive(Default)]
+struct State {
+    interactions: Vec<Interaction>,
+    /// How many times each URL has been replayed
+    replayed: HashMap<String, usize>,
+}
+
+impl Cassette {
+    /// Open a cassette: load it for replay, or start an empty recording.
+    pub fn open(config: &CassetteConfig) -> Result<Self, CassetteError> {
+        let path = PathBuf::from(&config.path);
+        let (interactions, recording) = match config.mode {
+            CassetteMode::Replay => (load(&path)?, None),
+            CassetteMode::Record => {
+                let recording = Recording::create(&path).map_err(|source| CassetteError::Write {
+                    path: path.clone(),
+                    source,
+                })?;
+                (Vec::new(), Some(Arc::new(Mutex::new(recording))))
+            }
+        };
+
+        info!(
+            path = %config.path,
+            mode = config.mode.as_str(),
+            interactions = interactions.len(),
+            "http_cassette_opened"
+        );
+
+        Ok(Self {
+            recording,
+            ..Self::with_interactions(config.mode, path, interactions)
+        })
+    }
+
+    /// A cassette replaying `interactions`, e.g. in tests.
+    pub fn replaying(interactions: Vec<Interaction>) -> Self {
+        Self::with_interactions(CassetteMode::Replay, PathBuf::new(), interactions)
+    }
+
+    fn with_interactions(mode: CassetteMode, path: PathBuf, interactions: Vec<Interaction>) -> Self {
+        Self {
+            mode,
+            path,
+            state: Mutex::new(State {
+                interactions,
+                replayed: HashMap::new(),
+            }),
+            recording: None,
+        }
+    }
+
+    /// Whether fetches are answered from the cassette instead of sent.
+    pub fn is_replay(&self) -> bool {
+        self.mode == CassetteMode::Replay
+    }
+
+    /// The recorded response for the next fetch of `url`.
+    pub fn replay(&self, url: &str) -> Result<Response, CassetteError> {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *guard;
+
+        let matches: Vec<&Interaction> = state.interactions.iter().filter(|i| i.url == url).collect();
+        let Some(last) = matches.len().checked_sub(1) else {
+            return Err(CassetteError::Miss(url.to_string()));
+        };
+
+        let count = state.replayed.entry(url.to_string()).or_default();
+        let interaction = matches[(*count).min(last)];
+        *count += 1;
+        interaction.to_response()
+    }
+
+    /// Save `response` to the cassette and return an equivalent one.
+    ///
+    /// The body is read here, so the returned response is already complete.
+    /// It is appended to the file on the blocking pool, without rewriting
+    /// what was recorded before.
+    pub async fn record(&self, url: &str, response: Response) -> Result<Response, RecordError> {
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.bytes().await?;
+
+        let interaction = Interaction {
+            url: url.to_string(),
+            status,
+            headers,
+            body_hex: hex::encode(&body[..body.len().min(MAX_BODY_BYTES)]),
+        };
+        let replayed = interaction.to_response()?;
+
+        if let Some(recording) = &self.recording {
+            let json = serde_json::to_string(&interaction).expect("interactions serialize");
+            let recording = Arc::clone(recording);
+            let appended = tokio::task::spawn_blocking(move || {
+                recording.lock().unwrap_or_else(|e| e.into_inner()).append(&json)
+            })
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)));
+            appended.map_err(|source| CassetteError::Write {
+                path: self.path.clone(),
+                source,
+            })?;
+        }
+        Ok(replayed)
+    }
+}
+
+/// Error recording a fetch: reading its body or writing the cassette.
+#[derive(Debug, thiserror::Error)]
+pub enum RecordError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Cassette(#[from] CassetteError),
+}
+
+fn load(path: &Path) -> Result<Vec<Interaction>, CassetteError> {
+    let contents = fs::read_to_string(path).map_err(|source| CassetteError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_str(&contents).map_err(|source| CassetteError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interaction(url: &str, status: u16, body: &[u8]) -> Interaction {
+        Interaction {
+            url: url.to_string(),
+            status,
+            headers: vec![("content-type".to_string(), "image/gif".to_string())],
+            body_hex: hex::encode(body),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_in_order_then_repeat_last() {
+        let cassette = Cassette::replaying(vec![
+            interaction("https://t.example.com/open", 503, b""),
+            interaction("https://t.example.com/click", 302, b""),
+            interaction("https://t.example.com/open", 200, b"GIF89a"),
+        ]);
+
+        let statuses: Vec<u16> = (0..3)
+            .map(|_| cassette.replay("https://t.example.com/open").unwrap().status().as_u16())
+            .collect();
+        assert_eq!(statuses, vec![503, 200, 200]);
+
+        let response = cassette.replay("https://t.example.com/open").unwrap();
+        assert_eq!(response.headers()["content-type"], "image/gif");
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"GIF89a");
+
+        assert!(matches!(
+            cassette.replay("https://t.example.com/other"),
+            Err(CassetteError::Miss(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cassette_file_roundtrip() {
+        let path = std::env::temp_dir().join(format!("bobnet-cassette-{}.json", std::process::id()));
+        let config = |mode| CassetteConfig {
+            path: path.to_string_lossy().into_owned(),
+            mode,
+        };
+
+        let recorder = Cassette::open(&config(CassetteMode::Record)).unwrap();
+        assert!(load(&path).unwrap().is_empty());
+        for (status, body) in [(503, &b""[..]), (200, &b"GIF89a"[..])] {
+            let response = http::Response::builder().status(status).body(body.to_vec()).unwrap();
+            recorder
+                .record("https://t.example.com/open", Response::from(response))
+                .await
+                .unwrap();
+            // Valid after every fetch
+            assert!(load(&path).is_ok());
+        }
+
+        let cassette = Cassette::open(&config(CassetteMode::Replay)).unwrap();
+        assert!(cassette.is_replay());
+        let statuses: Vec<u16> = (0..2)
+            .map(|_| cassette.replay("https://t.example.com/open").unwrap().status().as_u16())
+            .collect();
+        assert_eq!(statuses, vec![503, 200]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_config_from_vars() {
+        let var = |name: &str| match name {
+            "HTTP_CASSETTE" => Some("fixtures/prod.json".to_string()),
+            "HTTP_CASSETTE_MODE" => Some("Record".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            CassetteConfig::from_vars(&var),
+            Some(CassetteConfig {
+                path: "fixtures/prod.json".to_string(),
+                mode: CassetteMode::Record,
+            })
+        );
+        assert_eq!(CassetteConfig::from_vars(&|_: &str| None), None);
+        assert_eq!(CassetteMode::parse("rewind"), None);
+    }
+}
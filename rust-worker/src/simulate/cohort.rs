@@ -0,0 +1,76 @@
+//! Canary cohort assignment for A/B testing simulation behavior.
+//!
+//! A configurable percentage of recipients is routed to the experimental
+//! (canary) behavior profile while everyone else stays on the stable path.
+//! Assignment hashes the recipient, so a subscriber stays in the same
+//! cohort across emails and redeliveries.
+
+use serde::Serialize;
+
+use crate::util::hash::stable_hash;
+
+/// Behavior profile a job is simulated with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cohort {
+    /// Current production behavior
+    #[default]
+    Stable,
+    /// Experimental behavior (per-recipient persona, positional click model)
+    Canary,
+}
+
+impl Cohort {
+    /// Stable name used in logs and results.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cohort::Stable => "stable",
+            Cohort::Canary => "canary",
+        }
+    }
+
+    /// Assign a recipient to a cohort given the canary percentage (0 - 100).
+    pub fn assign(recipient: &str, canary_percent: f64) -> Self {
+        if canary_percent <= 0.0 {
+            return Cohort::Stable;
+        }
+
+        let bucket = (stable_hash(&[&recipient.trim().to_lowercase()]) % 10_000) as f64 / 100.0;
+
+        if bucket < canary_percent {
+            Cohort::Canary
+        } else {
+            Cohort::Stable
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_bounds() {
+        assert_eq!(Cohort::assign("user@example.com", 0.0), Cohort::Stable);
+        assert_eq!(Cohort::assign("user@example.com", 100.0), Cohort::Canary);
+    }
+
+    #[test]
+    fn test_assign_is_stable_per_recipient() {
+        let a = Cohort::assign("User@Example.com", 50.0);
+        let b = Cohort::assign("user@example.com", 50.0);
+        assert_eq!(a, b);
+
+        // Pinned, so a Rust upgrade cannot move recipients between cohorts
+        assert_eq!(Cohort::assign("bob@example.com", 30.0), Cohort::Canary);
+        assert_eq!(Cohort::assign("alice@example.com", 30.0), Cohort::Stable);
+    }
+
+    #[test]
+    fn test_assign_roughly_matches_percentage() {
+        let canary = (0..2000)
+            .filter(|i| Cohort::assign(&format!("user{}@example.com", i), 10.0) == Cohort::Canary)
+            .count();
+        assert!((100..300).contains(&canary), "got {} canary of 2000", canary);
+    }
+}
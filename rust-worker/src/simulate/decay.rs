@@ -0,0 +1,91 @@
+//! Open probability decay by message age.
+//!
+//! People open fresh mail more often than mail that sat unread for hours.
+//! When the worker drains a backlog, a job's age (from the webhook
+//! timestamp to the open roll) scales its open probability down along a
+//! [`DecayCurve`], so a late drain reads as late opens rather than an
+//! instant spike. Set `OPEN_DECAY_HALF_LIFE_SECS` to enable it.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Shape of the open probability's fall with message age.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecayCurve {
+    /// Halves every half-life
+    #[default]
+    Exponential,
+    /// Falls in a straight line, to half at one half-life and zero at two
+    Linear,
+}
+
+impl DecayCurve {
+    /// Parse `exponential` or `linear`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "exponential" => Some(DecayCurve::Exponential),
+            "linear" => Some(DecayCurve::Linear),
+            _ => None,
+        }
+    }
+
+    /// Stable name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DecayCurve::Exponential => "exponential",
+            DecayCurve::Linear => "linear",
+        }
+    }
+
+    /// Factor (0.0 - 1.0) the open probability of a message `age_secs` old
+    /// is scaled by.
+    ///
+    /// Messages younger than `grace_secs` keep their full probability; the
+    /// curve starts from there. A zero `half_life_secs` disables decay.
+    pub fn factor(&self, age_secs: u64, half_life_secs: u64, grace_secs: u64) -> f64 {
+        if half_life_secs == 0 {
+            return 1.0;
+        }
+        let half_lives = age_secs.saturating_sub(grace_secs) as f64 / half_life_secs as f64;
+        match self {
+            DecayCurve::Exponential => 0.5f64.powf(half_lives),
+            DecayCurve::Linear => (1.0 - half_lives / 2.0).max(0.0),
+        }
+    }
+}
+
+/// Seconds since `received_at` (Unix seconds); zero for future timestamps.
+pub fn message_age_secs(received_at: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    now.saturating_sub(received_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay_curves() {
+        let exp = DecayCurve::Exponential;
+        assert_eq!(exp.factor(0, 3600, 0), 1.0);
+        assert_eq!(exp.factor(3600, 3600, 0), 0.5);
+        assert_eq!(exp.factor(7200, 3600, 0), 0.25);
+        assert_eq!(exp.factor(4000, 3600, 400), 0.5);
+        assert_eq!(exp.factor(100, 3600, 600), 1.0);
+        assert_eq!(exp.factor(86_400, 0, 0), 1.0);
+
+        let linear = DecayCurve::Linear;
+        assert_eq!(linear.factor(1800, 3600, 0), 0.75);
+        assert_eq!(linear.factor(3600, 3600, 0), 0.5);
+        assert_eq!(linear.factor(86_400, 3600, 0), 0.0);
+
+        assert_eq!(DecayCurve::parse(" Linear "), Some(DecayCurve::Linear));
+        assert_eq!(DecayCurve::parse("cliff"), None);
+        assert_eq!(message_age_secs(u64::MAX), 0);
+    }
+}
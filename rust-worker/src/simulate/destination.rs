@@ -0,0 +1,116 @@
+//! Checks that wrapped click links land where they say.
+//!
+//! Tracking wrappers (SFMC click links, most ESP redirectors) often carry
+//! the intended destination in a query parameter such as `url` or `u`. A
+//! broken wrapper can still answer `200`, from an error page or the ESP's
+//! home page, which would otherwise count as a successful click. Each click
+//! whose link carries a destination is compared with the URL it finally
+//! landed on, and the result is recorded on its [`FetchReport`].
+//!
+//! [`FetchReport`]: super::report::FetchReport
+
+use serde::Serialize;
+use url::Url;
+
+/// Query parameters that carry a wrapped link's destination.
+const DESTINATION_PARAMS: &[&str] = &[
+    "url",
+    "u",
+    "dest",
+    "destination",
+    "redirect",
+    "redirect_url",
+    "target",
+    "link",
+    "r",
+];
+
+/// Where a wrapped click was meant to land, and whether it did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DestinationCheck {
+    /// Destination decoded from the link
+    pub expected: String,
+    /// Whether the final URL has the destination's host and path
+    pub matched: bool,
+}
+
+impl DestinationCheck {
+    /// Compare the destination carried by `link`, if any, with `landed`.
+    pub fn of(link: &str, landed: &str) -> Option<Self> {
+        let expected = expected_destination(link)?;
+        let matched = lands_on(&expected, landed);
+        Some(Self { expected, matched })
+    }
+}
+
+/// The destination a wrapped `link` carries: the first [`DESTINATION_PARAMS`]
+/// query parameter holding an absolute http(s) URL on another host.
+pub fn expected_destination(link: &str) -> Option<String> {
+    let link = Url::parse(link).ok()?;
+    link.query_pairs()
+        .filter(|(name, _)| DESTINATION_PARAMS.contains(&name.to_lowercase().as_str()))
+        .filter_map(|(_, value)| Url::parse(value.trim()).ok())
+        .find(|url| matches!(url.scheme(), "http" | "https") && url.host_str() != link.host_str())
+        .map(String::from)
+}
+
+/// Whether `landed` is `expected`, ignoring the scheme, a `www.` prefix, a
+/// trailing slash and the query string, to which trackers add parameters.
+pub fn lands_on(expected: &str, landed: &str) -> bool {
+    let key = |url: &str| {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_lowercase();
+        let host = host.strip_prefix("www.").unwrap_or(&host).to_string();
+        Some((host, url.path().trim_end_matches('/').to_string()))
+    };
+    matches!((key(expected), key(landed)), (Some(a), Some(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_destination() {
+        assert_eq!(
+            expected_destination(
+                "https://click.s4.exct.net/?qs=abc&url=https%3A%2F%2Fshop.example.com%2Fsale%3Fid%3D1"
+            )
+            .as_deref(),
+            Some("https://shop.example.com/sale?id=1")
+        );
+        assert_eq!(
+            expected_destination("https://t.example.net/c?U=https://shop.example.com/").as_deref(),
+            Some("https://shop.example.com/")
+        );
+        // Opaque wrappers, relative targets and links to themselves carry none
+        assert_eq!(expected_destination("https://click.s4.exct.net/?qs=abc123"), None);
+        assert_eq!(expected_destination("https://t.example.net/c?url=/home"), None);
+        assert_eq!(
+            expected_destination("https://shop.example.com/login?redirect=https://shop.example.com/cart"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_landing_comparison() {
+        assert!(lands_on(
+            "https://shop.example.com/sale",
+            "http://www.shop.example.com/sale/?utm_source=email"
+        ));
+        assert!(!lands_on("https://shop.example.com/sale", "https://shop.example.com/"));
+        assert!(!lands_on("https://shop.example.com/sale", "https://esp.example.net/sale"));
+
+        let check = DestinationCheck::of(
+            "https://t.example.net/c?url=https://shop.example.com/sale",
+            "https://esp.example.net/error",
+        );
+        assert_eq!(
+            check,
+            Some(DestinationCheck {
+                expected: "https://shop.example.com/sale".to_string(),
+                matched: false,
+            })
+        );
+    }
+}
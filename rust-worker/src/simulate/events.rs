@@ -0,0 +1,150 @@
+//! Async callbacks fired as a simulation progresses.
+//!
+//! Register callbacks on [`SimulationEvents`] and attach it with
+//! [`Simulator::with_events`](super::simulator::Simulator::with_events) to run
+//! side effects, such as updating an internal test-run tracker, without
+//! touching the simulation code:
+//!
+//! ```no_run
+//! use bobnet::simulate::events::SimulationEvents;
+//!
+//! let events = SimulationEvents::new()
+//!     .on_click(|event| async move {
+//!         println!("{} clicked {}", event.to, event.click.url);
+//!     })
+//!     .on_complete(|report| async move {
+//!         println!("{} finished: {}", report.message_id, report.status.as_str());
+//!     });
+//! ```
+//!
+//! Callbacks are awaited in registration order on the job's own task, so a
+//! slow callback delays the rest of the job; spawn inside the callback to
+//! detach long work.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use serde::Serialize;
+
+use super::report::{FetchReport, SimulationReport};
+
+type Callback<E> = Arc<dyn Fn(E) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// The email was opened: at least one pixel or image fetch succeeded.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenEvent {
+    /// Message ID from the job
+    pub message_id: String,
+    /// Recipient email address
+    pub to: String,
+    /// Pixel and image fetches, the SFMC open pixel first
+    pub pixels: Vec<FetchReport>,
+}
+
+/// A link was clicked, successfully or not.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClickEvent {
+    /// Message ID from the job
+    pub message_id: String,
+    /// Recipient email address
+    pub to: String,
+    /// The click, with its delay, status and redirect chain
+    pub click: FetchReport,
+}
+
+/// Registry of async simulation callbacks.
+///
+/// Cheap to clone: clones share the callbacks.
+#[derive(Clone, Default)]
+pub struct SimulationEvents {
+    on_open: Vec<Callback<OpenEvent>>,
+    on_click: Vec<Callback<ClickEvent>>,
+    on_complete: Vec<Callback<SimulationReport>>,
+}
+
+impl SimulationEvents {
+    /// A registry without callbacks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` once per opened email, after its open fetches.
+    pub fn on_open<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(OpenEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_open.push(Arc::new(move |e| f(e).boxed()));
+        self
+    }
+
+    /// Call `f` after each click fetch.
+    pub fn on_click<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(ClickEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_click.push(Arc::new(move |e| f(e).boxed()));
+        self
+    }
+
+    /// Call `f` with the report of every finished simulation, including
+    /// ones that ended early (see [`SimulationReport::status`]).
+    pub fn on_complete<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(SimulationReport) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_complete.push(Arc::new(move |e| f(e).boxed()));
+        self
+    }
+
+    /// Emitter for the job sent to `to` as `message_id`.
+    pub fn for_job<'a>(&'a self, message_id: &'a str, to: &'a str) -> JobEvents<'a> {
+        JobEvents {
+            events: self,
+            message_id,
+            to,
+        }
+    }
+
+    pub(crate) async fn completed(&self, report: &SimulationReport) {
+        for callback in &self.on_complete {
+            callback(report.clone()).await;
+        }
+    }
+}
+
+/// Emits one job's events to a [`SimulationEvents`] registry.
+pub struct JobEvents<'a> {
+    events: &'a SimulationEvents,
+    message_id: &'a str,
+    to: &'a str,
+}
+
+impl JobEvents<'_> {
+    /// Fire `on_open` with the job's open fetches.
+    pub async fn opened(&self, pixels: &[FetchReport]) {
+        for callback in &self.events.on_open {
+            callback(OpenEvent {
+                message_id: self.message_id.to_string(),
+                to: self.to.to_string(),
+                pixels: pixels.to_vec(),
+            })
+            .await;
+        }
+    }
+
+    /// Fire `on_click` with one click.
+    pub async fn clicked(&self, click: &FetchReport) {
+        for callback in &self.events.on_click {
+            callback(ClickEvent {
+                message_id: self.message_id.to_string(),
+                to: self.to.to_string(),
+                click: click.clone(),
+            })
+            .await;
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! Shared outbound fetch plumbing for opens and clicks.
+//!
+//! Every simulated request goes through [`FetchContext::get`], which sends it
+//! over the job's [`HttpFetcher`] (a `reqwest::Client` unless an embedder or
+//! test supplies its own) with the job's headers and timeout, injects chaos faults when enabled, checks
+//! certificate pins, records or replays it through a cassette when one is
+//! set, and reports the outcome to the adaptive concurrency limiter.
+//!
+//! With rate limits set, a host that answers `429` is backed off for its
+//! `Retry-After` (see [`super::backoff`]): later fetches to it wait until the
+//! hold ends, and the rate-limited request is retried up to
+//! [`RateLimits::retries`] times.
+//!
+//! Redirects are followed here, hop by hop, so every hop is pinned, recorded
+//! and reported. Clients that follow redirects themselves still work, but
+//! their intermediate hops go unreported.
+
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use reqwest::header::LOCATION;
+use reqwest::{Client, Response, Url};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use super::adaptive::AdaptiveConcurrency;
+use super::backoff::{rate_limited, HostBackoff};
+use super::cassette::{Cassette, CassetteError, RecordError};
+use super::tls::{verify_pin, CertPin, PinError};
+use crate::chaos::{ChaosConfig, FetchFault};
+use crate::util::cancel::sleep_unless_cancelled;
+use crate::util::url::sanitize;
+
+/// Most redirects followed for one fetch, as reqwest's default policy.
+pub const MAX_REDIRECTS: usize = 10;
+
+/// Error from an outbound fetch.
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    /// The HTTP request itself failed (connect, timeout, ...)
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    /// Chaos mode failed the fetch without sending it
+    #[error("fetch failed by chaos fault injection")]
+    Injected,
+    /// The response came from a certificate that doesn't match its pin
+    #[error(transparent)]
+    Pin(#[from] PinError),
+    /// The cassette has no response for the URL, or could not be written
+    #[error(transparent)]
+    Cassette(#[from] CassetteError),
+    /// A custom [`HttpFetcher`] failed
+    #[error("transport error: {0}")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// Shutdown began while the fetch waited out a rate-limited host
+    #[error("fetch abandoned at shutdown")]
+    Cancelled,
+}
+
+impl From<RecordError> for FetchError {
+    fn from(e: RecordError) -> Self {
+        match e {
+            RecordError::Http(e) => FetchError::Http(e),
+            RecordError::Cassette(e) => FetchError::Cassette(e),
+        }
+    }
+}
+
+/// Transport that simulated fetches are sent over.
+///
+/// Implemented for [`reqwest::Client`]. Tests and embedders can supply a mock
+/// or instrumented transport instead; it is asked for one hop at a time, and
+/// should return redirects rather than follow them so each hop is reported.
+pub trait HttpFetcher: Send + Sync {
+    /// Send a single GET request for `url` with `headers`, within `timeout`.
+    fn fetch<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(String, String)],
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<Response, FetchError>>;
+}
+
+impl HttpFetcher for Client {
+    fn fetch<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(String, String)],
+        timeout: Duration,
+    ) -> BoxFuture<'a, Result<Response, FetchError>> {
+        let mut request = self.get(url).timeout(timeout);
+        for (key, value) in headers {
+            request = request.header(key.as_str(), value.as_str());
+        }
+        Box::pin(async move { Ok(request.send().await?) })
+    }
+}
+
+/// Answers every request with an empty `200` without touching the network,
+/// for dry-run jobs and calibration.
+pub struct NullFetcher;
+
+impl HttpFetcher for NullFetcher {
+    fn fetch<'a>(
+        &'a self,
+        _url: &'a str,
+        _headers: &'a [(String, String)],
+        _timeout: Duration,
+    ) -> BoxFuture<'a, Result<Response, FetchError>> {
+        Box::pin(async {
+            let response = http::Response::builder().status(200).body(Vec::new());
+            Ok(Response::from(response.expect("static response is valid")))
+        })
+    }
+}
+
+/// How fetches treat hosts that answer `429 Too Many Requests`.
+#[derive(Clone, Copy)]
+pub struct RateLimits<'a> {
+    /// Hosts currently backed off, shared across jobs
+    pub hosts: &'a HostBackoff,
+    /// Longest a host is backed off for, whatever its `Retry-After`
+    pub max_backoff: Duration,
+    /// Times a rate-limited request is sent again after backing off
+    pub retries: u32,
+    /// Shutdown token; a fetch waiting out a backoff is abandoned when it fires
+    pub cancel: Option<&'a CancellationToken>,
+}
+
+/// Per-job settings shared by every outbound fetch.
+#[derive(Clone, Copy)]
+pub struct FetchContext<'a> {
+    /// Transport the requests are sent over
+    pub fetcher: &'a dyn HttpFetcher,
+    /// Headers sent with every request (user agent etc.)
+    pub headers: &'a [(String, String)],
+    /// Per-request timeout
+    pub timeout: Duration,
+    /// Optional adaptive concurrency limiter fed with fetch outcomes
+    pub limiter: Option<&'a AdaptiveConcurrency>,
+    /// Optional chaos settings for fault injection
+    pub chaos: Option<&'a ChaosConfig>,
+    /// Per-domain certificate pins checked on every response
+    pub pins: &'a [CertPin],
+    /// Optional cassette that fetches are recorded to or replayed from
+    pub cassette: Option<&'a Cassette>,
+    /// Optional backing off of hosts that answer `429`
+    pub rate_limits: Option<RateLimits<'a>>,
+}
+
+impl<'a> FetchContext<'a> {
+    /// Create a context with no limiter or chaos faults.
+    pub fn new(
+        fetcher: &'a dyn HttpFetcher,
+        headers: &'a [(String, String)],
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            fetcher,
+            headers,
+            timeout,
+            limiter: None,
+            chaos: None,
+            pins: &[],
+            cassette: None,
+            rate_limits: None,
+        }
+    }
+
+    /// Send a GET request for `url`, following redirects.
+    pub async fn get(&self, url: &str) -> Result<Response, FetchError> {
+        self.get_with_redirects(url)
+            .await
+            .map(|(response, _)| response)
+    }
+
+    /// Send a GET request for `url`, following redirects, and return the
+    /// final response with the URLs redirected to.
+    ///
+    /// A redirect whose target is invalid, or beyond [`MAX_REDIRECTS`], is
+    /// returned as the final response.
+    pub async fn get_with_redirects(
+        &self,
+        url: &str,
+    ) -> Result<(Response, Vec<String>), FetchError> {
+        let started = Instant::now();
+        let result = self.follow(url).await;
+
+        // An abandoned fetch says nothing about the host
+        let abandoned = matches!(result, Err(FetchError::Cancelled));
+        if let Some(limiter) = self.limiter.filter(|_| !abandoned) {
+            let ok = matches!(
+                &result,
+                Ok((resp, _)) if (200..400).contains(&resp.status().as_u16())
+            );
+            limiter.record(ok, started.elapsed());
+        }
+
+        result
+    }
+
+    async fn follow(&self, url: &str) -> Result<(Response, Vec<String>), FetchError> {
+        self.inject_fault(url).await?;
+
+        let mut redirects: Vec<String> = Vec::new();
+        loop {
+            let current = redirects.last().map_or(url, String::as_str);
+            let response = self.send(current).await?;
+            match redirect_target(current, &response) {
+                Some(next) if redirects.len() < MAX_REDIRECTS => {
+                    tracing::debug!(from = current, to = %next, "fetch_redirect");
+                    redirects.push(next);
+                }
+                _ => return Ok((response, redirects)),
+            }
+        }
+    }
+
+    /// Apply the chaos fault rolled for this fetch, if any.
+    async fn inject_fault(&self, url: &str) -> Result<(), FetchError> {
+        match self.chaos.and_then(|c| c.fetch_fault()) {
+            Some(FetchFault::Error) => {
+                tracing::warn!(url = url, "chaos_fetch_error_injected");
+                return Err(FetchError::Injected);
+            }
+            Some(FetchFault::Slow(delay)) => {
+                tracing::warn!(
+                    url = url,
+                    delay_ms = delay.as_millis() as u64,
+                    "chaos_fetch_slowed"
+                );
+                sleep(delay).await;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    async fn send(&self, url: &str) -> Result<Response, FetchError> {
+        if let Some(cassette) = self.cassette.filter(|c| c.is_replay()) {
+            return Ok(cassette.replay(url)?);
+        }
+
+        let response = self.fetch_within_rate_limits(url).await?;
+        if let Err(e) = verify_pin(self.pins, &response) {
+            tracing::error!(error = %e, "tls_pin_mismatch");
+            return Err(e.into());
+        }
+
+        match self.cassette {
+            Some(cassette) => Ok(cassette.record(url, response).await?),
+            None => Ok(response),
+        }
+    }
+
+    /// Send `url` over the fetcher, waiting out any backoff of its host and
+    /// retrying a `429` as many times as the rate limits allow.
+    async fn fetch_within_rate_limits(&self, url: &str) -> Result<Response, FetchError> {
+        let Some(limits) = self.rate_limits else {
+            return self.fetcher.fetch(url, self.headers, self.timeout).await;
+        };
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        let mut attempt = 0;
+        loop {
+            if let Some(remaining) = limits.hosts.remaining(&host) {
+                if !sleep_unless_cancelled(remaining.min(limits.max_backoff), limits.cancel).await {
+                    return Err(FetchError::Cancelled);
+                }
+            }
+            let response = self.fetcher.fetch(url, self.headers, self.timeout).await?;
+            let Some(retry_after) = rate_limited(&response) else {
+                return Ok(response);
+            };
+            let backoff = retry_after.min(limits.max_backoff);
+            limits.hosts.hold(&host, backoff);
+            tracing::warn!(
+                host = %host,
+                backoff_ms = backoff.as_millis() as u64,
+                attempt = attempt,
+                "fetch_rate_limited"
+            );
+            if attempt >= limits.retries {
+                return Ok(response);
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Where a redirect response points, resolved against `url` and sanitized.
+fn redirect_target(url: &str, response: &Response) -> Option<String> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    let target = Url::parse(url).ok()?.join(location).ok()?;
+    sanitize(target.as_str()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Answers from a fixed table of `(url, status, location)` and records
+    /// every request.
+    struct Canned {
+        routes: &'static [(&'static str, u16, Option<&'static str>)],
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl Canned {
+        fn new(routes: &'static [(&'static str, u16, Option<&'static str>)]) -> Self {
+            Self {
+                routes,
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl HttpFetcher for Canned {
+        fn fetch<'a>(
+            &'a self,
+            url: &'a str,
+            _headers: &'a [(String, String)],
+            _timeout: Duration,
+        ) -> BoxFuture<'a, Result<Response, FetchError>> {
+            self.requests.lock().unwrap().push(url.to_string());
+            let route = self.routes.iter().find(|(u, _, _)| *u == url);
+            Box::pin(async move {
+                let (_, status, location) = route
+                    .ok_or_else(|| FetchError::Transport(format!("no route to {}", url).into()))?;
+                let mut builder = http::Response::builder().status(*status);
+                if let Some(location) = location {
+                    builder = builder.header(LOCATION, *location);
+                }
+                Ok(Response::from(builder.body(Vec::new()).unwrap()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follows_redirects_over_custom_fetcher() {
+        let fetcher = Canned::new(&[
+            ("https://click.example.com/c", 302, Some("/hop")),
+            (
+                "https://click.example.com/hop",
+                301,
+                Some("https://shop.example.com/"),
+            ),
+            ("https://shop.example.com/", 200, None),
+        ]);
+        let ctx = FetchContext::new(&fetcher, &[], Duration::from_secs(1));
+
+        let (response, redirects) = ctx
+            .get_with_redirects("https://click.example.com/c")
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            redirects,
+            vec!["https://click.example.com/hop", "https://shop.example.com/"]
+        );
+        assert_eq!(fetcher.requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_loops_stop() {
+        let fetcher = Canned::new(&[("https://loop.example.com/", 302, Some("/"))]);
+        let ctx = FetchContext::new(&fetcher, &[], Duration::from_secs(1));
+
+        let (response, redirects) = ctx
+            .get_with_redirects("https://loop.example.com/")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 302);
+        assert_eq!(redirects.len(), MAX_REDIRECTS);
+
+        let missing = ctx.get("https://missing.example.com/").await.unwrap_err();
+        assert!(matches!(missing, FetchError::Transport(_)));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_hosts_back_off_and_retry() {
+        let fetcher = Canned::new(&[("https://pixel.example.com/o.gif", 429, None)]);
+        let hosts = HostBackoff::new();
+        let ctx = FetchContext {
+            rate_limits: Some(RateLimits {
+                hosts: &hosts,
+                max_backoff: Duration::from_millis(20),
+                retries: 2,
+                cancel: None,
+            }),
+            ..FetchContext::new(&fetcher, &[], Duration::from_secs(1))
+        };
+
+        let started = Instant::now();
+        let response = ctx.get("https://pixel.example.com/o.gif").await.unwrap();
+        assert_eq!(response.status(), 429);
+        // The first request, then two retries once each backoff ended
+        assert_eq!(fetcher.requests.lock().unwrap().len(), 3);
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert!(hosts.remaining("pixel.example.com").is_some());
+        assert_eq!(hosts.remaining("click.example.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_is_abandoned_at_shutdown() {
+        let fetcher = Canned::new(&[("https://pixel.example.com/o.gif", 200, None)]);
+        let hosts = HostBackoff::new();
+        hosts.hold("pixel.example.com", Duration::from_secs(60));
+        let cancel = CancellationToken::new();
+        let ctx = FetchContext {
+            rate_limits: Some(RateLimits {
+                hosts: &hosts,
+                max_backoff: Duration::from_secs(60),
+                retries: 0,
+                cancel: Some(&cancel),
+            }),
+            ..FetchContext::new(&fetcher, &[], Duration::from_secs(1))
+        };
+
+        let started = Instant::now();
+        let (result, ()) = tokio::join!(ctx.get("https://pixel.example.com/o.gif"), async {
+            sleep(Duration::from_millis(20)).await;
+            cancel.cancel();
+        });
+        assert!(matches!(result, Err(FetchError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(fetcher.requests.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+//! End-to-end latency from webhook receipt to simulation.
+//!
+//! The web server stamps each webhook with `received_at_ms` as it arrives,
+//! the processor stamps the simulator job with `enqueued_at_ms` as it
+//! publishes it, and the worker times the simulation itself. The worker puts
+//! the stages in each job's report and in histograms on its sidecar's
+//! `/metrics`, so an "engagement within N minutes" SLA can be checked
+//! against real traffic.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Upper bounds in seconds of the latency histogram buckets, from queue
+/// hops under a second to jobs held for hours by their open delay.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.05, 0.25, 1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 21_600.0, 86_400.0,
+];
+
+/// Current Unix time in milliseconds.
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Time a job spent in each stage of the pipeline, in milliseconds.
+///
+/// Stages before the worker are missing when the job did not carry their
+/// timestamps, e.g. jobs published with `bobnet-cli` or by an older
+/// processor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Latency {
+    /// Webhook receipt to the simulator job's publish: the inbound queue
+    /// and the processor
+    pub inbound_ms: Option<u64>,
+    /// Simulator job publish to the worker starting it
+    pub queue_ms: Option<u64>,
+    /// Worker start to the end of the simulation, delays included
+    pub simulate_ms: u64,
+    /// Webhook receipt to the end of the simulation
+    pub total_ms: Option<u64>,
+}
+
+impl Latency {
+    /// Stages between the job's `received_at_ms` and `enqueued_at_ms`, when
+    /// known, and the worker's `started_at_ms` and `finished_at_ms` (all
+    /// Unix milliseconds).
+    ///
+    /// Clock skew between hosts can stamp a stage's end before its start;
+    /// such a stage counts as zero.
+    pub fn measure(
+        received_at_ms: Option<u64>,
+        enqueued_at_ms: Option<u64>,
+        started_at_ms: u64,
+        finished_at_ms: u64,
+    ) -> Self {
+        Self {
+            inbound_ms: received_at_ms
+                .zip(enqueued_at_ms)
+                .map(|(received, enqueued)| enqueued.saturating_sub(received)),
+            queue_ms: enqueued_at_ms.map(|enqueued| started_at_ms.saturating_sub(enqueued)),
+            simulate_ms: finished_at_ms.saturating_sub(started_at_ms),
+            total_ms: received_at_ms.map(|received| finished_at_ms.saturating_sub(received)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let latency = Latency::measure(Some(1_000), Some(1_250), 4_250, 64_250);
+        assert_eq!(
+            latency,
+            Latency {
+                inbound_ms: Some(250),
+                queue_ms: Some(3_000),
+                simulate_ms: 60_000,
+                total_ms: Some(63_250),
+            }
+        );
+
+        // A job published without timestamps only times the simulation
+        let latency = Latency::measure(None, None, 4_250, 5_000);
+        assert_eq!(latency.inbound_ms, None);
+        assert_eq!(latency.queue_ms, None);
+        assert_eq!(latency.simulate_ms, 750);
+
+        // Skewed clocks never report negative stages
+        let latency = Latency::measure(Some(2_000), Some(1_500), 0, 0);
+        assert_eq!(latency.inbound_ms, Some(0));
+    }
+}
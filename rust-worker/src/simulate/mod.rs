@@ -1,4 +1,24 @@
 //! Email simulation module for open and click behavior.
 
+pub mod ab;
+pub mod adaptive;
+pub mod backoff;
+pub mod calendar;
+pub mod calibrate;
+pub mod cassette;
 pub mod clicker;
+pub mod cohort;
+pub mod decay;
+pub mod destination;
+pub mod events;
+pub mod fetch;
+pub mod latency;
 pub mod opener;
+pub mod preset;
+pub mod report;
+pub mod scroll;
+pub mod simulator;
+pub mod timezone;
+pub mod timing;
+pub mod tls;
+pub mod wrapped;
@@ -1,48 +1,43 @@
 //! Open simulation - fetching tracking pixels and images.
 
-use reqwest::Client;
-use std::time::Duration;
-use tracing;
-
-/// Fetch a single URL and return whether it succeeded.
-pub async fn fetch_single_url(
-    client: &Client,
-    url: &str,
-    headers: &[(String, String)],
-    timeout: Duration,
-) -> bool {
+use std::time::Instant;
+
+use super::fetch::{FetchContext, FetchError};
+use super::report::FetchReport;
+use crate::util::url::sanitize_or_log;
+
+/// Fetch a single URL and report the outcome.
+///
+/// URLs rejected by [`crate::util::url::sanitize`] are not fetched, and
+/// return `None`.
+pub async fn fetch_single_url(ctx: &FetchContext<'_>, url: &str) -> Option<FetchReport> {
+    let url = sanitize_or_log(url)?;
+    let url = url.as_str();
+
     tracing::info!(
         url = url,
         url_length = url.len(),
-        timeout_seconds = timeout.as_secs_f64(),
+        timeout_seconds = ctx.timeout.as_secs_f64(),
         "open_pixel_fetch_starting"
     );
 
-    let mut request = client.get(url).timeout(timeout);
-    
-    for (key, value) in headers {
-        request = request.header(key.as_str(), value.as_str());
-    }
-
-    match request.send().await {
-        Ok(resp) => {
+    let started = Instant::now();
+    let result = ctx.get_with_redirects(url).await;
+    match &result {
+        Ok((resp, _)) => {
             let status = resp.status().as_u16();
-            let is_success = (200..400).contains(&status);
-
             tracing::info!(
                 url = url,
                 status_code = status,
-                is_success = is_success,
+                is_success = (200..400).contains(&status),
                 "open_pixel_fetch_complete"
             );
-
-            is_success
         }
-        Err(e) => {
+        Err(FetchError::Http(e)) => {
             if e.is_timeout() {
                 tracing::error!(
                     url = url,
-                    timeout_seconds = timeout.as_secs_f64(),
+                    timeout_seconds = ctx.timeout.as_secs_f64(),
                     error = %e,
                     "open_pixel_fetch_timeout"
                 );
@@ -59,36 +54,63 @@ pub async fn fetch_single_url(
                     "open_pixel_fetch_error"
                 );
             }
-            false
+        }
+        Err(e) => {
+            tracing::error!(
+                url = url,
+                error = %e,
+                "open_pixel_fetch_error"
+            );
         }
     }
+
+    Some(FetchReport::new(url, started.elapsed(), &result))
 }
 
+/// Most images an open fetches, unless a scroll depth says otherwise.
+pub const MAX_OPEN_IMAGES: usize = 5;
+
+/// Most known open pixels an open fetches, one per tracking layer.
+pub const MAX_OPEN_PIXELS: usize = 10;
+
 /// Simulate opening an email by fetching tracking images.
 ///
-/// Fetches up to 5 images concurrently and returns true if any succeeded.
-pub async fn simulate_open(
-    client: &Client,
+/// Fetches up to [`MAX_OPEN_IMAGES`] valid images concurrently and reports
+/// each fetch; the open succeeded if any of them did.
+pub async fn simulate_open(ctx: &FetchContext<'_>, image_urls: &[String]) -> Vec<FetchReport> {
+    simulate_open_to(ctx, image_urls, MAX_OPEN_IMAGES).await
+}
+
+/// Like [`simulate_open`], fetching up to `limit` images.
+pub async fn simulate_open_to(
+    ctx: &FetchContext<'_>,
     image_urls: &[String],
-    headers: &[(String, String)],
-    timeout: Duration,
-) -> bool {
-    if image_urls.is_empty() {
-        return false;
-    }
+    limit: usize,
+) -> Vec<FetchReport> {
+    // Cap the images to avoid flooding, skipping rejected URLs
+    let urls_to_fetch: Vec<_> = image_urls
+        .iter()
+        .filter_map(|url| sanitize_or_log(url))
+        .take(limit)
+        .collect();
 
-    // Cap to 5 images to avoid flooding
-    let urls_to_fetch: Vec<_> = image_urls.iter().take(5).collect();
+    if urls_to_fetch.is_empty() {
+        return Vec::new();
+    }
 
     // Fetch all images concurrently
     let futures: Vec<_> = urls_to_fetch
         .iter()
-        .map(|url| fetch_single_url(client, url, headers, timeout))
+        .map(|url| fetch_single_url(ctx, url))
         .collect();
 
-    let results = futures::future::join_all(futures).await;
+    let results: Vec<FetchReport> = futures::future::join_all(futures)
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
 
-    let successful = results.iter().filter(|&&r| r).count();
+    let successful = results.iter().filter(|r| r.succeeded()).count();
     let any_success = successful > 0;
 
     tracing::info!(
@@ -98,5 +120,5 @@ pub async fn simulate_open(
         "simulate_open_complete"
     );
 
-    any_success
+    results
 }
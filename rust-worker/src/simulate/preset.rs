@@ -0,0 +1,220 @@
+//! Behavior presets by recipient domain.
+//!
+//! Mailbox providers change what a sender sees: Gmail fetches images
+//! through its image proxy, Outlook's link protection fetches a link
+//! before the reader's click lands, and corporate gateways scan every link
+//! on delivery for readers who rarely engage. `RECIPIENT_PRESETS` maps
+//! recipient domains to a [`DomainPreset`], so a test list mixing
+//! providers produces each provider's pattern.
+//!
+//! `builtin` stands for [`BUILTIN_PRESETS`]; later entries override it,
+//! `*` matches every other domain and `none` turns presets off for a
+//! domain, e.g. `builtin,example.com:none,*:corporate`.
+
+use std::time::Instant;
+
+use futures::future::join_all;
+use serde::Serialize;
+
+use super::fetch::FetchContext;
+use super::report::FetchReport;
+use crate::config::WorkerConfig;
+use crate::util::url::sanitize_or_log;
+
+/// Built-in domain to preset mapping.
+pub const BUILTIN_PRESETS: &[(&str, DomainPreset)] = &[
+    ("gmail.com", DomainPreset::Gmail),
+    ("googlemail.com", DomainPreset::Gmail),
+    ("outlook.com", DomainPreset::Outlook),
+    ("hotmail.com", DomainPreset::Outlook),
+    ("live.com", DomainPreset::Outlook),
+    ("msn.com", DomainPreset::Outlook),
+];
+
+/// User agent of Gmail's image proxy, which fetches images for the reader.
+pub const GOOGLE_IMAGE_PROXY_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 5.1; rv:11.0) Gecko Firefox/11.0 (via ggpht.com GoogleImageProxy)";
+
+/// User agent link scanners fetch with.
+pub const SCANNER_USER_AGENT: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; Trident/7.0; rv:11.0) like Gecko";
+
+/// Factor corporate recipients' open and click probabilities are scaled by.
+pub const CORPORATE_ENGAGEMENT: f64 = 0.5;
+
+/// Most links a delivery scan fetches.
+pub const MAX_SCANNED_LINKS: usize = 20;
+
+/// How a class of mailbox provider handles a recipient's mail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainPreset {
+    /// Images are fetched by Gmail's image proxy, all at once; clicks come
+    /// from the reader
+    Gmail,
+    /// The links the reader is about to click are first fetched by a link
+    /// scanner
+    Outlook,
+    /// A gateway scans every link on delivery, and the reader opens and
+    /// clicks half as often
+    Corporate,
+}
+
+impl DomainPreset {
+    /// Parse `gmail`, `outlook` or `corporate`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "gmail" => Some(DomainPreset::Gmail),
+            "outlook" => Some(DomainPreset::Outlook),
+            "corporate" => Some(DomainPreset::Corporate),
+            _ => None,
+        }
+    }
+
+    /// Stable name used in logs and results.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainPreset::Gmail => "gmail",
+            DomainPreset::Outlook => "outlook",
+            DomainPreset::Corporate => "corporate",
+        }
+    }
+
+    /// `config` with the preset's settings applied.
+    pub fn apply(&self, config: &WorkerConfig) -> WorkerConfig {
+        let mut config = config.clone();
+        match self {
+            // The proxy loads every image, however far the reader scrolls
+            DomainPreset::Gmail => config.scroll_depth = None,
+            DomainPreset::Outlook => {}
+            DomainPreset::Corporate => {
+                config.simulate_open_probability *= CORPORATE_ENGAGEMENT;
+                config.simulate_click_probability *= CORPORATE_ENGAGEMENT;
+            }
+        }
+        config
+    }
+}
+
+/// A `RECIPIENT_PRESETS` entry: the preset of a domain and its subdomains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresetRule {
+    /// Lowercase domain, or `*` for any domain
+    pub domain: String,
+    /// Preset, or `None` for the worker's plain behavior
+    pub preset: Option<DomainPreset>,
+}
+
+impl PresetRule {
+    /// Parse a `RECIPIENT_PRESETS` entry: `builtin`, which expands to
+    /// [`BUILTIN_PRESETS`], or `domain:preset`, with `preset` one of
+    /// `gmail`, `outlook`, `corporate` or `none`.
+    pub fn parse(entry: &str) -> Option<Vec<Self>> {
+        if entry.trim().eq_ignore_ascii_case("builtin") {
+            return Some(
+                BUILTIN_PRESETS
+                    .iter()
+                    .map(|(domain, preset)| Self {
+                        domain: domain.to_string(),
+                        preset: Some(*preset),
+                    })
+                    .collect(),
+            );
+        }
+        let (domain, preset) = entry.trim().rsplit_once(':')?;
+        let domain = domain.trim().to_lowercase();
+        let preset = match preset.trim().to_lowercase().as_str() {
+            "none" => None,
+            other => Some(DomainPreset::parse(other)?),
+        };
+        (!domain.is_empty()).then(|| vec![Self { domain, preset }])
+    }
+
+    /// Whether `domain` is this rule's domain or one of its subdomains.
+    fn matches_domain(&self, domain: &str) -> bool {
+        domain == self.domain
+            || domain
+                .strip_suffix(self.domain.as_str())
+                .is_some_and(|rest| rest.ends_with('.'))
+    }
+}
+
+/// Preset of recipient `to` under `rules`.
+///
+/// The most specific matching domain wins, the later rule among equals, and
+/// `*` applies when no domain matches.
+pub fn preset_for(rules: &[PresetRule], to: &str) -> Option<DomainPreset> {
+    let domain = to.rsplit_once('@')?.1.trim().to_lowercase();
+    let by_domain = rules
+        .iter()
+        .filter(|rule| rule.matches_domain(&domain))
+        .max_by_key(|rule| rule.domain.len());
+    by_domain
+        .or_else(|| rules.iter().rev().find(|rule| rule.domain == "*"))
+        .and_then(|rule| rule.preset)
+}
+
+/// Fetch `links` as a link scanner would, concurrently, with `ctx`'s
+/// headers (see [`SCANNER_USER_AGENT`]).
+pub async fn scan_links(ctx: &FetchContext<'_>, links: &[String]) -> Vec<FetchReport> {
+    let scans = links.iter().filter_map(|link| sanitize_or_log(link)).map(|url| async move {
+        let started = Instant::now();
+        let result = ctx.get_with_redirects(&url).await;
+        let scan = FetchReport::new(&url, started.elapsed(), &result);
+        tracing::info!(
+            url = %url,
+            status_code = ?scan.status,
+            error = ?scan.error,
+            "link_scan_fetch"
+        );
+        scan
+    });
+    join_all(scans).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(entries: &str) -> Vec<PresetRule> {
+        entries
+            .split(',')
+            .flat_map(|entry| PresetRule::parse(entry).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_preset_for_recipient() {
+        let rules = rules("builtin,mail.example.com:none,example.com:corporate,*:outlook");
+        assert_eq!(preset_for(&rules, "a@Gmail.com"), Some(DomainPreset::Gmail));
+        assert_eq!(preset_for(&rules, "a@eu.outlook.com"), Some(DomainPreset::Outlook));
+        assert_eq!(preset_for(&rules, "a@example.com"), Some(DomainPreset::Corporate));
+        assert_eq!(preset_for(&rules, "a@mail.example.com"), None);
+        assert_eq!(preset_for(&rules, "a@other.org"), Some(DomainPreset::Outlook));
+        assert_eq!(preset_for(&rules, "not-an-address"), None);
+
+        // Later entries override the built-in ones
+        let rules = self::rules("builtin,gmail.com:corporate");
+        assert_eq!(preset_for(&rules, "a@gmail.com"), Some(DomainPreset::Corporate));
+        assert_eq!(preset_for(&rules, "a@other.org"), None);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        for entry in ["gmail.com", "gmail.com:yahoo", ":gmail"] {
+            assert_eq!(PresetRule::parse(entry), None, "{}", entry);
+        }
+    }
+
+    #[test]
+    fn test_corporate_engagement() {
+        let config = WorkerConfig::builder()
+            .simulate_open_probability(0.6)
+            .simulate_click_probability(0.2)
+            .build();
+        let corporate = DomainPreset::Corporate.apply(&config);
+        assert_eq!(corporate.simulate_open_probability, 0.3);
+        assert_eq!(corporate.simulate_click_probability, 0.1);
+        assert_eq!(DomainPreset::Outlook.apply(&config).simulate_open_probability, 0.6);
+    }
+}
@@ -0,0 +1,399 @@
+//! What a simulated job did.
+//!
+//! A [`SimulationReport`] is returned by
+//! [`Simulator::simulate`](super::simulator::Simulator::simulate) and, when
+//! `RESULTS_QUEUE` is set, published by the worker as JSON. It lists every
+//! pixel and click fetch with its status, latency and redirect chain, the
+//! delays actually waited, the persona the job was simulated as, why any
+//! step was skipped, and the seed that replays its random choices.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use reqwest::Response;
+use serde::Serialize;
+
+use super::clicker::extract_domain;
+use super::cohort::Cohort;
+use super::destination::DestinationCheck;
+use super::fetch::FetchError;
+use super::latency::Latency;
+use super::preset::DomainPreset;
+use crate::html::{ContactKind, PixelHeuristic};
+use crate::tenant::quota::QuotaWindow;
+use crate::util::client::ClientPersona;
+use crate::util::user_agent::UserAgentCategory;
+
+/// Final status of a simulated job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// All simulation steps ran
+    Completed,
+    /// Shutdown interrupted the job after it had started fetching
+    Partial,
+    /// Shutdown interrupted the job before any fetch; it should be requeued
+    NotStarted,
+    /// The HTML was too large or too slow to parse; nothing was fetched
+    HtmlRejected,
+    /// The tenant had reached its `TENANT_QUOTAS` limit; nothing was fetched
+    OverQuota,
+    /// The recipient was outside their active hours; nothing was fetched and
+    /// the job should be retried when they begin
+    Deferred,
+    /// The message was older than `MAX_MESSAGE_AGE_SECS`; nothing was fetched
+    Expired,
+}
+
+impl JobStatus {
+    /// Stable name used in logs and results.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Completed => "completed",
+            JobStatus::Partial => "partial",
+            JobStatus::NotStarted => "not_started",
+            JobStatus::HtmlRejected => "html_rejected",
+            JobStatus::OverQuota => "over_quota",
+            JobStatus::Deferred => "deferred",
+            JobStatus::Expired => "expired",
+        }
+    }
+}
+
+/// Who the job was simulated as.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Persona {
+    /// Behavior profile
+    pub cohort: Cohort,
+    /// Named `AB_COHORTS` cohort the recipient belongs to, if any
+    pub ab_cohort: Option<String>,
+    /// `RECIPIENT_PRESETS` preset of the recipient's domain, if any
+    pub preset: Option<DomainPreset>,
+    /// Email client picked from the client mix, if one is configured
+    pub client: Option<ClientPersona>,
+    /// User agent sent with every fetch
+    pub user_agent: String,
+    /// Device class of the user agent
+    pub user_agent_category: UserAgentCategory,
+    /// Offset from UTC in minutes of the recipient's time zone, if known
+    pub utc_offset_mins: Option<i32>,
+}
+
+/// One pixel or click fetch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FetchReport {
+    /// URL fetched
+    pub url: String,
+    /// Status of the final response, if one arrived
+    pub status: Option<u16>,
+    /// Why the fetch failed without a response
+    pub error: Option<String>,
+    /// Time from the first request to the final response or error
+    pub latency_ms: u64,
+    /// URLs redirected to after `url`, in order
+    pub redirects: Vec<String>,
+    /// Redirects followed, the length of `redirects`
+    pub redirect_count: usize,
+    /// Time waited before the fetch
+    pub delay_ms: u64,
+    /// How the image was recognised as a tracking pixel, for pixel fetches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heuristic: Option<PixelHeuristic>,
+    /// Where a wrapped click link was meant to land, and whether it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<DestinationCheck>,
+}
+
+impl FetchReport {
+    /// Describe the outcome of fetching `url`.
+    pub fn new(
+        url: &str,
+        latency: Duration,
+        result: &Result<(Response, Vec<String>), FetchError>,
+    ) -> Self {
+        let (status, error, redirects) = match result {
+            Ok((response, redirects)) => {
+                (Some(response.status().as_u16()), None, redirects.clone())
+            }
+            Err(e) => (None, Some(e.to_string()), Vec::new()),
+        };
+        Self {
+            url: url.to_string(),
+            status,
+            error,
+            latency_ms: latency.as_millis() as u64,
+            redirect_count: redirects.len(),
+            redirects,
+            delay_ms: 0,
+            heuristic: None,
+            destination: None,
+        }
+    }
+
+    /// Whether a success or redirect status came back.
+    pub fn succeeded(&self) -> bool {
+        self.status.is_some_and(|s| (200..400).contains(&s))
+    }
+}
+
+/// Why a simulation step did not happen.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Skip {
+    /// The HTML was too large or too slow to parse
+    HtmlRejected { detail: String },
+    /// The message was `age_secs` old, over `MAX_MESSAGE_AGE_SECS`
+    Expired { age_secs: u64, max_age_secs: u64 },
+    /// The tenant had reached its hourly or daily job limit, which starts
+    /// over after `retry_after_secs`
+    Quota {
+        window: QuotaWindow,
+        limit: u64,
+        retry_after_secs: u64,
+    },
+    /// It was `local_hour` for the recipient, outside `RECIPIENT_ACTIVE_HOURS`;
+    /// the job is due again after `resume_after_secs`
+    ActiveHours {
+        local_hour: u64,
+        resume_after_secs: u64,
+    },
+    /// The open roll was not under the open probability
+    OpenRoll { roll: f64, probability: f64 },
+    /// The recipient blocks images, so no pixel or image was fetched
+    /// (`CLICK_WITHOUT_OPEN_PROBABILITY`); clicks were still rolled for
+    ImagesBlocked,
+    /// An open was due but the email has no fetchable images
+    NoImages,
+    /// With `PIXEL_ONLY_OPENS`, the email's `images` other images were not
+    /// fetched because it has an open pixel
+    PixelOnly { images: usize },
+    /// With `SCROLL_DEPTH`, `images` images were below where the reader
+    /// stopped scrolling
+    BelowFold { images: usize },
+    /// With `WRAPPED_IMAGES=click`, `images` images inside links were left
+    /// to be clicked instead of fetched on open
+    WrappedImages { images: usize },
+    /// The click roll was not under the click probability
+    ClickRoll { roll: f64, probability: f64 },
+    /// A click was due but no link was chosen: none passed the URL,
+    /// unsubscribe and domain filters, or all had a zero click rate
+    NoLinks,
+    /// With `WRAPPED_IMAGES=open`, `links` links around images were left out
+    /// of the click, their images having been fetched on open
+    WrappedLinks { links: usize },
+    /// The link's domain had reached its `CLICK_DOMAIN_OVERRIDES` limit
+    DomainLimit { url: String },
+    /// Shutdown began before the step (`open`, `images` or `clicks`)
+    Shutdown { step: &'static str },
+}
+
+/// A tap on a `mailto:` or `tel:` link: it opens an app on the reader's
+/// device, so nothing is fetched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContactInteraction {
+    /// Which app the link opens
+    pub kind: ContactKind,
+    /// The link's `href`
+    pub url: String,
+}
+
+/// Everything a simulated job did.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    /// Message ID from the job
+    pub message_id: String,
+    /// Recipient email address
+    pub to: String,
+    /// Customer tag extracted from plus addressing (e.g., "tag" from "user+tag@example.com")
+    pub customer_tag: Option<String>,
+    /// Campaign (SFMC job) ID found on the email's tracking URLs
+    pub campaign_id: Option<String>,
+    /// Whether the simulation ran to completion
+    pub status: JobStatus,
+    /// Persona the job was simulated as
+    pub persona: Persona,
+    /// Time spent analysing the email's HTML, unless the job ended first
+    pub analysis_ms: Option<u64>,
+    /// Time waited before the open, unless the job ended first
+    pub open_delay_ms: Option<u64>,
+    /// Seconds from the webhook timestamp to the open roll, when the job
+    /// carried one
+    pub message_age_secs: Option<u64>,
+    /// Factor the open probability was scaled by for the message's age,
+    /// when `OPEN_DECAY_HALF_LIFE_SECS` applied
+    pub open_decay: Option<f64>,
+    /// Factor open and click probabilities were scaled by for the day, when
+    /// a `CALENDAR` applied
+    pub calendar_factor: Option<f64>,
+    /// Probability the open was rolled against, after every scaling; unset
+    /// for readers who block images and jobs that ended before the roll
+    pub open_probability: Option<f64>,
+    /// Probability the clicks were rolled against, after every scaling;
+    /// unset for emails without links and jobs that ended before the roll
+    pub click_probability: Option<f64>,
+    /// Images the reader scrolled down to, when `SCROLL_DEPTH` applied
+    pub scroll_depth: Option<usize>,
+    /// Whether any pixel or image fetch succeeded
+    pub opened: bool,
+    /// Pixel and image fetches, the SFMC open pixel first
+    pub pixels: Vec<FetchReport>,
+    /// Link clicks in order, each with the delay before it
+    pub clicks: Vec<FetchReport>,
+    /// Link scanner fetches: a corporate gateway's delivery scan, or
+    /// Outlook's check of the links about to be clicked
+    pub scans: Vec<FetchReport>,
+    /// `mailto:` and `tel:` links the reader tapped, which fetch nothing
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub interactions: Vec<ContactInteraction>,
+    /// Steps that were skipped, and why
+    pub skipped: Vec<Skip>,
+    /// Time spent in each stage from webhook receipt to the end of the
+    /// simulation; set by the worker
+    pub latency: Option<Latency>,
+    /// Seed the job's random choices were drawn from; pass it to
+    /// [`Simulator::simulate_seeded`](super::simulator::Simulator::simulate_seeded)
+    /// to replay them
+    pub seed: u64,
+    /// When the simulation started, in Unix seconds; message ages, the
+    /// calendar day and active hours were taken from it
+    pub started_at: u64,
+    /// Simulation variables the worker was configured with (see
+    /// [`SIMULATION_VARS`](crate::config::SIMULATION_VARS)), for replays
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, String>,
+    /// Whether the job was a dry run, its fetches answered locally (see
+    /// [`JobOverrides::dry_run`](crate::queue::JobOverrides::dry_run))
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// ID of the worker instance that simulated the job
+    pub instance_id: &'static str,
+    /// ID of the webhook request the job came from (`X-Request-Id`)
+    pub request_id: Option<String>,
+}
+
+impl SimulationReport {
+    /// Number of successful clicks.
+    pub fn successful_clicks(&self) -> usize {
+        self.clicks.iter().filter(|c| c.succeeded()).count()
+    }
+
+    /// Clicks that failed to fetch or returned an error status.
+    pub fn click_errors(&self) -> usize {
+        self.clicks.len() - self.successful_clicks()
+    }
+
+    /// Clicks that landed somewhere other than their link's destination.
+    pub fn destination_mismatches(&self) -> usize {
+        self.clicks
+            .iter()
+            .filter(|c| c.destination.as_ref().is_some_and(|d| !d.matched))
+            .count()
+    }
+
+    /// Domain of each successful click, in click order.
+    pub fn clicked_domains(&self) -> Vec<String> {
+        self.clicks
+            .iter()
+            .filter(|c| c.succeeded())
+            .map(|c| extract_domain(&c.url))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_json() {
+        let report = SimulationReport {
+            message_id: "msg-1".to_string(),
+            to: "user@example.com".to_string(),
+            customer_tag: None,
+            campaign_id: None,
+            status: JobStatus::Completed,
+            persona: Persona {
+                cohort: Cohort::Stable,
+                ab_cohort: Some("control".to_string()),
+                preset: None,
+                client: None,
+                user_agent: "Mozilla/5.0 (iPhone)".to_string(),
+                user_agent_category: UserAgentCategory::Mobile,
+                utc_offset_mins: None,
+            },
+            analysis_ms: None,
+            open_delay_ms: Some(1200),
+            message_age_secs: None,
+            open_decay: None,
+            calendar_factor: None,
+            open_probability: None,
+            click_probability: None,
+            scroll_depth: None,
+            opened: false,
+            pixels: Vec::new(),
+            clicks: vec![
+                FetchReport {
+                    url: "https://shop.example.com/a".to_string(),
+                    status: Some(200),
+                    redirects: vec!["https://shop.example.com/b".to_string()],
+                    redirect_count: 1,
+                    destination: Some(DestinationCheck {
+                        expected: "https://shop.example.com/a".to_string(),
+                        matched: false,
+                    }),
+                    ..FetchReport::default()
+                },
+                FetchReport {
+                    url: "https://broken.example.com/".to_string(),
+                    error: Some("timed out".to_string()),
+                    ..FetchReport::default()
+                },
+            ],
+            scans: Vec::new(),
+            interactions: Vec::new(),
+            skipped: vec![Skip::OpenRoll {
+                roll: 0.9,
+                probability: 0.5,
+            }],
+            latency: Some(Latency {
+                queue_ms: Some(1500),
+                simulate_ms: 61_000,
+                ..Latency::default()
+            }),
+            seed: 42,
+            started_at: 0,
+            config: Default::default(),
+            dry_run: false,
+            instance_id: "worker-1",
+            request_id: Some("req-1".to_string()),
+        };
+
+        assert_eq!(report.successful_clicks(), 1);
+        assert_eq!(report.click_errors(), 1);
+        assert_eq!(report.clicked_domains(), vec!["shop.example.com"]);
+        assert_eq!(report.destination_mismatches(), 1);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["status"], "completed");
+        assert_eq!(json["persona"]["cohort"], "stable");
+        assert_eq!(json["persona"]["ab_cohort"], "control");
+        assert_eq!(json["persona"]["user_agent_category"], "mobile");
+        assert_eq!(
+            json["clicks"][0]["redirects"][0],
+            "https://shop.example.com/b"
+        );
+        assert_eq!(json["clicks"][0]["redirect_count"], 1);
+        assert_eq!(json["clicks"][0]["destination"]["matched"], false);
+        assert!(json["clicks"][1].get("destination").is_none());
+        assert_eq!(json["clicks"][1]["url"], "https://broken.example.com/");
+        assert!(json["clicks"][1]["status"].is_null());
+        assert_eq!(json["clicks"][1]["error"], "timed out");
+        assert_eq!(json["clicks"][1]["redirect_count"], 0);
+        assert_eq!(json["skipped"][0]["reason"], "open_roll");
+        assert_eq!(json["skipped"][0]["probability"], 0.5);
+        assert_eq!(json["latency"]["queue_ms"], 1500);
+        assert!(json["latency"]["total_ms"].is_null());
+        assert_eq!(json["seed"], 42);
+        assert_eq!(json["request_id"], "req-1");
+    }
+}
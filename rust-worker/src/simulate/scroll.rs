@@ -0,0 +1,100 @@
+//! Scroll-depth image loading.
+//!
+//! Readers don't all reach the bottom of an email, and images below where
+//! they stop are never rendered. With `SCROLL_DEPTH` set, an open fetches
+//! the email's images in document order down to a depth drawn for each job
+//! from a [`ScrollDepth`] distribution, instead of the first five, so
+//! image-level analytics show the drop-off of a real audience.
+
+use rand::Rng;
+
+/// Most images one open fetches, however deep the reader scrolls.
+pub const MAX_SCROLL_IMAGES: usize = 50;
+
+/// Distribution of how many images, in document order, a reader sees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollDepth {
+    /// Every depth from `min` to `max` images is equally likely
+    Uniform { min: usize, max: usize },
+    /// After each image the reader stops with probability `stop`, so each
+    /// image is seen by `1 - stop` as many readers as the one before
+    Geometric { stop: f64 },
+}
+
+impl ScrollDepth {
+    /// Parse `uniform:min-max` (e.g. `uniform:1-8`) or `geometric:stop`
+    /// (e.g. `geometric:0.3`, with `stop` above 0.0 and at most 1.0).
+    pub fn parse(value: &str) -> Option<Self> {
+        let (kind, params) = value.trim().split_once(':')?;
+        match kind.trim().to_lowercase().as_str() {
+            "uniform" => {
+                let (min, max) = params.split_once('-')?;
+                let (min, max) = (min.trim().parse().ok()?, max.trim().parse().ok()?);
+                (1..=max).contains(&min).then_some(ScrollDepth::Uniform { min, max })
+            }
+            "geometric" => {
+                let stop: f64 = params.trim().parse().ok()?;
+                (stop > 0.0 && stop <= 1.0).then_some(ScrollDepth::Geometric { stop })
+            }
+            _ => None,
+        }
+    }
+
+    /// Draw how many images a reader sees, from 1 to [`MAX_SCROLL_IMAGES`].
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        let depth = match *self {
+            ScrollDepth::Uniform { min, max } => rng.gen_range(min..=max),
+            ScrollDepth::Geometric { stop } => {
+                let mut depth = 1;
+                while depth < MAX_SCROLL_IMAGES && !rng.gen_bool(stop) {
+                    depth += 1;
+                }
+                depth
+            }
+        };
+        depth.clamp(1, MAX_SCROLL_IMAGES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_parse_scroll_depth() {
+        assert_eq!(
+            ScrollDepth::parse("uniform:2-8"),
+            Some(ScrollDepth::Uniform { min: 2, max: 8 })
+        );
+        assert_eq!(
+            ScrollDepth::parse(" Geometric: 0.25 "),
+            Some(ScrollDepth::Geometric { stop: 0.25 })
+        );
+        assert_eq!(ScrollDepth::parse("uniform:0-8"), None);
+        assert_eq!(ScrollDepth::parse("uniform:9-8"), None);
+        assert_eq!(ScrollDepth::parse("geometric:0"), None);
+        assert_eq!(ScrollDepth::parse("geometric:1.5"), None);
+        assert_eq!(ScrollDepth::parse("normal:3"), None);
+        assert_eq!(ScrollDepth::parse("uniform"), None);
+    }
+
+    #[test]
+    fn test_depths_drop_off() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let uniform = ScrollDepth::Uniform { min: 2, max: 4 };
+        assert!((0..200).all(|_| (2..=4).contains(&uniform.sample(&mut rng))));
+
+        // Half the readers stop after each image
+        let geometric = ScrollDepth::Geometric { stop: 0.5 };
+        let depths: Vec<usize> = (0..4000).map(|_| geometric.sample(&mut rng)).collect();
+        let reached = |n: usize| depths.iter().filter(|&&d| d >= n).count();
+        assert_eq!(reached(1), 4000);
+        assert!((1800..2200).contains(&reached(2)));
+        assert!((800..1200).contains(&reached(3)));
+        assert!(depths.iter().all(|&d| d <= MAX_SCROLL_IMAGES));
+
+        assert!((0..100).all(|_| ScrollDepth::Geometric { stop: 1.0 }.sample(&mut rng) == 1));
+    }
+}
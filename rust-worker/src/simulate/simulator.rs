@@ -0,0 +1,2361 @@
+//! Embeddable open/click simulation engine.
+//!
+//! [`Simulator`] wraps the HTTP client, the worker configuration and the
+//! analysis pipeline, so other services can simulate a job directly without
+//! RabbitMQ. The worker consumes its queues through the same type.
+//!
+//! ```no_run
+//! # async fn example() -> bobnet::error::Result<()> {
+//! use bobnet::simulate::simulator::{Job, Simulator};
+//!
+//! let simulator = Simulator::new(bobnet::WorkerConfig::builder().build())?;
+//! let report = simulator
+//!     .simulate(&Job {
+//!         message_id: Some("msg-1".to_string()),
+//!         to: "user+tag@example.com".to_string(),
+//!         html: Some("<html>...</html>".to_string()),
+//!         ..Default::default()
+//!     })
+//!     .await;
+//! println!("opened: {}, clicks: {}", report.opened, report.successful_clicks());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reqwest::redirect::Policy;
+use reqwest::Client;
+use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::campaign::campaign_of;
+use crate::campaign::overrides::CampaignOverrides;
+use crate::config::WorkerConfig;
+use crate::error::{BobNetError, Result};
+use crate::html::{ContactKind, HtmlLimits, HtmlSummary, PixelHeuristic};
+use crate::queue::{JobOverrides, SimulatorJob};
+use crate::simulate::ab;
+use crate::simulate::adaptive::AdaptiveConcurrency;
+use crate::simulate::calendar::{day_at, factor_on};
+use crate::simulate::backoff::HostBackoff;
+use crate::simulate::cassette::Cassette;
+use crate::simulate::clicker::{
+    choose_links_positional, choose_links_weighted, draw_click_delays, filter_links_with_rates,
+    is_unsubscribe_link, perform_clicks,
+};
+use crate::simulate::cohort::Cohort;
+use crate::simulate::events::SimulationEvents;
+use crate::simulate::fetch::{FetchContext, HttpFetcher, NullFetcher, RateLimits};
+use crate::simulate::opener::{
+    fetch_single_url, simulate_open_to, MAX_OPEN_IMAGES, MAX_OPEN_PIXELS,
+};
+use crate::simulate::preset::{
+    preset_for, scan_links, DomainPreset, GOOGLE_IMAGE_PROXY_USER_AGENT, MAX_SCANNED_LINKS,
+    SCANNER_USER_AGENT,
+};
+use crate::simulate::report::{
+    ContactInteraction, FetchReport, JobStatus, Persona, SimulationReport, Skip,
+};
+use crate::simulate::timezone::{local_hour, offset_for, until_active, WAKE_SPREAD_SECS};
+use crate::simulate::wrapped::WrappedImagePolicy;
+use crate::tenant::quota::QuotaUsage;
+use crate::tenant::{TenantProfile, TenantProfiles};
+use crate::util::cancel::{is_cancelled, sleep_unless_cancelled};
+use crate::util::client::{pick_client, pick_client_for, ClientPersona};
+use crate::util::hash::stable_hash;
+use crate::util::instance::instance_id;
+use crate::util::url::{sanitize, sanitize_or_log};
+use crate::util::user_agent::{
+    build_headers, pick_user_agent, pick_user_agent_for, FetchKind, UserAgentCategory,
+    WeightedAgent,
+};
+
+/// Email to simulate, as received from the simulator queue.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Job {
+    /// Unique message identifier
+    pub message_id: Option<String>,
+    /// Recipient email address (may contain plus addressing)
+    pub to: String,
+    /// HTML content of the email
+    pub html: Option<String>,
+    /// SHA-256 of the HTML when it is in the content store instead; see
+    /// [`crate::content`]
+    #[serde(default)]
+    pub html_sha256: Option<String>,
+    /// When the provider received the email, in Unix seconds; its age
+    /// decays the open probability
+    #[serde(default)]
+    pub received_at: Option<u64>,
+    /// When the web server received the webhook, in Unix milliseconds
+    #[serde(default)]
+    pub received_at_ms: Option<u64>,
+    /// When the processor published the job, in Unix milliseconds
+    #[serde(default)]
+    pub enqueued_at_ms: Option<u64>,
+    /// ID of the webhook request the job came from (`X-Request-Id`)
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// Settings this job simulates with instead of the simulator's
+    #[serde(default)]
+    pub overrides: JobOverrides,
+}
+
+impl Job {
+    /// When the email was received, in Unix seconds: the provider's
+    /// timestamp, else the web server's.
+    pub fn received_at_secs(&self) -> Option<u64> {
+        self.received_at.or(self.received_at_ms.map(|ms| ms / 1000))
+    }
+}
+
+impl From<SimulatorJob> for Job {
+    fn from(job: SimulatorJob) -> Self {
+        Self {
+            message_id: Some(job.message_id),
+            to: job.to,
+            html: job.html,
+            html_sha256: job.html_sha256,
+            received_at: job.received_at,
+            received_at_ms: job.received_at_ms,
+            enqueued_at_ms: job.enqueued_at_ms,
+            request_id: job.request_id,
+            overrides: job.overrides,
+        }
+    }
+}
+
+/// Open/click simulation engine.
+///
+/// Cheap to clone: clones share the transport, limiter, cassette, event
+/// callbacks, tenant profiles, tenant quota usage, rate-limited hosts and
+/// campaign overrides.
+#[derive(Clone)]
+pub struct Simulator {
+    fetcher: Arc<dyn HttpFetcher>,
+    config: Arc<WorkerConfig>,
+    limiter: Option<Arc<AdaptiveConcurrency>>,
+    cassette: Option<Arc<Cassette>>,
+    events: Arc<SimulationEvents>,
+    profiles: Option<Arc<TenantProfiles>>,
+    quotas: Arc<QuotaUsage>,
+    backoff: Arc<HostBackoff>,
+    campaign_overrides: Option<Arc<CampaignOverrides>>,
+    /// Time jobs are simulated at, in Unix seconds, instead of the clock
+    clock: Option<u64>,
+    /// Whether open and click delays are waited out
+    wait: bool,
+}
+
+impl Simulator {
+    /// Create a simulator for `config`.
+    ///
+    /// Builds an HTTP client with the configured TLS policy and opens
+    /// `HTTP_CASSETTE` when set.
+    pub fn new(config: impl Into<Arc<WorkerConfig>>) -> Result<Self> {
+        let config = config.into();
+        let client = config
+            .tls
+            .apply(
+                Client::builder()
+                    .pool_max_idle_per_host(100)
+                    // Followed by `FetchContext`, so each hop is reported
+                    .redirect(Policy::none()),
+            )
+            .map_err(BobNetError::setup)?
+            .build()
+            .map_err(BobNetError::setup)?;
+        let cassette = config
+            .http_cassette
+            .as_ref()
+            .map(Cassette::open)
+            .transpose()
+            .map_err(BobNetError::setup)?;
+
+        Ok(Self {
+            fetcher: Arc::new(client),
+            config,
+            limiter: None,
+            cassette: cassette.map(Arc::new),
+            events: Arc::default(),
+            profiles: None,
+            quotas: Arc::default(),
+            backoff: Arc::default(),
+            campaign_overrides: None,
+            clock: None,
+            wait: true,
+        })
+    }
+
+    /// Use `client` for fetches instead of the one built from the config.
+    ///
+    /// Redirect chains are only reported if `client` does not follow
+    /// redirects itself.
+    pub fn with_client(self, client: Client) -> Self {
+        self.with_fetcher(client)
+    }
+
+    /// Send fetches over a custom transport, e.g. a mock or an instrumented
+    /// client.
+    pub fn with_fetcher(mut self, fetcher: impl HttpFetcher + 'static) -> Self {
+        self.fetcher = Arc::new(fetcher);
+        self
+    }
+
+    /// Feed fetch outcomes to an adaptive concurrency limiter.
+    pub fn with_limiter(mut self, limiter: Option<Arc<AdaptiveConcurrency>>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Fire `events` callbacks as each job is opened, clicked and completed.
+    pub fn with_events(mut self, events: SimulationEvents) -> Self {
+        self.events = Arc::new(events);
+        self
+    }
+
+    /// Simulate each job with its tenant's profile, looked up by the
+    /// recipient's plus tag, in place of the matching config settings.
+    pub fn with_profiles(mut self, profiles: TenantProfiles) -> Self {
+        self.profiles = Some(Arc::new(profiles));
+        self
+    }
+
+    /// Simulate each job with its campaign's override, looked up by the
+    /// campaign ID on its tracking URLs, over every other setting.
+    pub fn with_campaign_overrides(mut self, overrides: CampaignOverrides) -> Self {
+        self.campaign_overrides = Some(Arc::new(overrides));
+        self
+    }
+
+    /// Simulate jobs as if it were `now` (Unix seconds), e.g. a report's
+    /// [`started_at`](SimulationReport::started_at) when replaying it:
+    /// message ages, the calendar day and active hours are taken from it.
+    pub fn with_clock(mut self, now: u64) -> Self {
+        self.clock = Some(now);
+        self
+    }
+
+    /// Draw open and click delays as usual, but don't wait them out.
+    pub fn without_delays(mut self) -> Self {
+        self.wait = false;
+        self
+    }
+
+    /// The time jobs are simulated at, in Unix seconds.
+    fn now(&self) -> u64 {
+        self.clock.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        })
+    }
+
+    /// A simulator sharing this one's transport, limiter, cassette, event
+    /// callbacks, tenant profiles, tenant quota usage and campaign overrides,
+    /// with different parameters (e.g. after a config reload).
+    pub fn with_config(&self, config: Arc<WorkerConfig>) -> Self {
+        Self {
+            config,
+            ..self.clone()
+        }
+    }
+
+    /// The configuration jobs are simulated with.
+    pub fn config(&self) -> &WorkerConfig {
+        &self.config
+    }
+
+    /// The profile of the tenant `to` belongs to, if profiles are configured
+    /// and it has one.
+    pub async fn tenant_profile(&self, to: &str) -> Option<Arc<TenantProfile>> {
+        match (&self.profiles, extract_plus_tag(to)) {
+            (Some(profiles), Some(tag)) => profiles.get(&tag).await,
+            _ => None,
+        }
+    }
+
+    /// Simulate opening and clicking `job`.
+    pub async fn simulate(&self, job: &Job) -> SimulationReport {
+        self.run(job, None, None).await
+    }
+
+    /// Simulate `job`, stopping early once `cancel` fires.
+    ///
+    /// Remaining delays are skipped and the job stops after its current
+    /// fetch; a job cancelled before any fetch reports
+    /// [`JobStatus::NotStarted`].
+    pub async fn simulate_until(&self, job: &Job, cancel: &CancellationToken) -> SimulationReport {
+        self.run(job, None, Some(cancel)).await
+    }
+
+    /// Simulate `job` with its random choices drawn from `seed`, which takes
+    /// precedence over the job's own seed override.
+    ///
+    /// Given the same config and HTML, passing a report's
+    /// [`seed`](SimulationReport::seed) repeats its persona, delays, rolls
+    /// and link choices.
+    pub async fn simulate_seeded(&self, job: &Job, seed: u64) -> SimulationReport {
+        self.run(job, Some(seed), None).await
+    }
+
+    pub(crate) async fn run(
+        &self,
+        job: &Job,
+        seed: Option<u64>,
+        cancel: Option<&CancellationToken>,
+    ) -> SimulationReport {
+        // The recipient's domain preset underlies every other setting
+        let preset = self
+            .config
+            .recipient_presets
+            .as_deref()
+            .and_then(|rules| preset_for(rules, &job.to));
+        let base = match preset {
+            Some(preset) => Cow::Owned(preset.apply(&self.config)),
+            None => Cow::Borrowed(self.config.as_ref()),
+        };
+        let tag = extract_plus_tag(&job.to);
+        let profile = self.tenant_profile(&job.to).await;
+        let base = match (&profile, &tag) {
+            (Some(profile), Some(tag)) => Cow::Owned(profile.apply_for(tag, &base)),
+            _ => base,
+        };
+        // An A/B cohort's settings win over the profile, but not the job's
+        let ab_cohort = base
+            .ab_cohorts
+            .as_deref()
+            .and_then(|cohorts| ab::assign(cohorts, &job.to))
+            .cloned();
+        let base = match &ab_cohort {
+            Some(cohort) => Cow::Owned(cohort.apply(&base)),
+            None => base,
+        };
+        let config = apply_overrides(&base, &job.overrides);
+        let seed = seed
+            .or(job.overrides.seed)
+            .unwrap_or_else(|| job_seed(config.simulation_seed, job));
+        let ab_cohort = ab_cohort.map(|cohort| cohort.name);
+        let report = simulate_job(self, &config, job, ab_cohort, preset, seed, cancel).await;
+        self.events.completed(&report).await;
+        report
+    }
+}
+
+/// The config a job simulates with: `config` with `overrides` applied.
+///
+/// Probabilities are clamped to 0.0 - 1.0 and delay ranges put in order, as
+/// overrides arrive unvalidated with the job.
+fn apply_overrides<'a>(
+    config: &'a WorkerConfig,
+    overrides: &JobOverrides,
+) -> Cow<'a, WorkerConfig> {
+    if overrides.is_empty() {
+        return Cow::Borrowed(config);
+    }
+
+    let ordered = |(lo, hi): (u64, u64)| (lo.min(hi), lo.max(hi));
+    let mut config = config.clone();
+    if let Some(p) = overrides.open_probability {
+        config.simulate_open_probability = p.clamp(0.0, 1.0);
+    }
+    if let Some(p) = overrides.click_probability {
+        config.simulate_click_probability = p.clamp(0.0, 1.0);
+    }
+    if let Some(max) = overrides.max_clicks {
+        config.max_clicks = max;
+    }
+    if let Some(range) = overrides.open_delay_ms {
+        config.open_delay_ms = ordered(range);
+    }
+    if let Some(range) = overrides.click_delay_ms {
+        config.click_delay_ms = ordered(range);
+    }
+    if let Some(agent) = &overrides.user_agent {
+        // A pool of one fixes the agent for stable and canary recipients alike
+        config.user_agent_pool = Some(vec![WeightedAgent::from(agent.as_str())]);
+        config.client_mix = None;
+    }
+    Cow::Owned(config)
+}
+
+/// `probability` scaled by `factor`, unless the job fixes it with its
+/// `overridden` value.
+fn scaled(probability: f64, factor: f64, overridden: Option<f64>) -> f64 {
+    match overridden {
+        Some(_) => probability,
+        None => probability * factor,
+    }
+}
+
+/// Seed for one job's random choices.
+///
+/// With `SIMULATION_SEED` set it is derived from the seed and the message ID,
+/// so a rerun repeats every job whatever order the jobs arrive in, even on
+/// a build with a newer Rust.
+fn job_seed(base: Option<u64>, job: &Job) -> u64 {
+    match base {
+        Some(base) => {
+            stable_hash(&[&base.to_string(), job.message_id.as_deref().unwrap_or_default()])
+        }
+        None => rand::random(),
+    }
+}
+
+/// Extract plus tag from an email address.
+///
+/// For "user+tag@example.com", returns Some("tag").
+/// For "user@example.com", returns None.
+pub(crate) fn extract_plus_tag(email: &str) -> Option<String> {
+    let local = email.split('@').next()?;
+    if local.contains('+') {
+        local.split_once('+').map(|(_, tag)| tag.to_string())
+    } else {
+        None
+    }
+}
+
+/// Simulate a single email job.
+///
+/// This function:
+/// 1. Extracts the plus tag from the recipient address and assigns the
+///    recipient to the stable or canary behavior cohort, alongside its
+///    A/B cohort, and looks up the recipient's time zone
+/// 2. Defers the job if the recipient is outside their active hours, and
+///    counts it against its tenant's quota, ending it if over quota
+/// 3. Parses the HTML once, within the configured size and time limits, and
+///    applies the override of the campaign it belongs to
+/// 4. Applies a random delay before opening
+/// 5. With configured probability, simulates email open by fetching tracking pixels
+/// 6. With configured probability, simulates link clicks using weighted selection
+///
+/// # Arguments
+///
+/// * `simulator` - Transport, limiter, cassette, event callbacks, quota
+///   usage and campaign overrides to simulate with
+/// * `config` - Application configuration, with the job's overrides applied
+/// * `job` - The job to process
+/// * `ab_cohort` - Name of the recipient's A/B cohort, whose settings are
+///   already in `config`
+/// * `preset` - Preset of the recipient's domain, whose settings are already
+///   in `config`; it also picks who fetches images and scans links
+/// * `seed` - Seed of every random choice: user agent, delays, rolls and links
+/// * `cancel` - Optional shutdown token; when fired, remaining delays are
+///   skipped and the job stops after its current fetch
+///
+/// # Returns
+///
+/// A [`SimulationReport`] describing the outcome of the simulation.
+async fn simulate_job(
+    simulator: &Simulator,
+    config: &WorkerConfig,
+    job: &Job,
+    ab_cohort: Option<String>,
+    preset: Option<DomainPreset>,
+    seed: u64,
+    cancel: Option<&CancellationToken>,
+) -> SimulationReport {
+    let message_id = job.message_id.clone().unwrap_or_else(|| "unknown".to_string());
+    let html = job.html.as_deref().unwrap_or("");
+    let html_length = html.len();
+
+    info!(
+        message_id = %message_id,
+        to = %job.to,
+        html_length = html_length,
+        html_is_empty = html.is_empty(),
+        request_id = ?job.request_id,
+        "worker_job_received"
+    );
+
+    let now = simulator.now();
+    let cohort = Cohort::assign(&job.to, config.canary_percent);
+    let rng = &mut StdRng::seed_from_u64(seed);
+
+    // Pick an email client or else a user agent (sticky per recipient for
+    // canary) and build headers
+    let client = config.client_mix.as_deref().and_then(|mix| match cohort {
+        Cohort::Stable => pick_client(mix, rng),
+        Cohort::Canary => pick_client_for(mix, &job.to),
+    });
+    let user_agent = match (client, cohort) {
+        (Some(client), _) => client.user_agent().to_string(),
+        (None, Cohort::Stable) => pick_user_agent(config.user_agent_pool.as_deref(), rng),
+        (None, Cohort::Canary) => pick_user_agent_for(config.user_agent_pool.as_deref(), &job.to),
+    };
+    let headers_for = |kind| match client {
+        Some(client) => client.headers(kind),
+        None => build_headers(&user_agent, kind),
+    };
+    let (image_headers, page_headers) = (headers_for(FetchKind::Image), headers_for(FetchKind::Page));
+    let timeout = Duration::from_millis(config.request_timeout_ms);
+    let rate_limits = (config.rate_limit_backoff_max_secs > 0).then(|| RateLimits {
+        hosts: &simulator.backoff,
+        max_backoff: Duration::from_secs(config.rate_limit_backoff_max_secs),
+        retries: config.rate_limit_retries,
+        cancel,
+    });
+    // Dry runs go through every decision without touching the network
+    let fetch = if job.overrides.dry_run {
+        FetchContext::new(&NullFetcher, &page_headers, timeout)
+    } else {
+        FetchContext {
+            limiter: simulator.limiter.as_deref(),
+            chaos: config.core.chaos.as_ref(),
+            pins: &config.tls.pins,
+            cassette: simulator.cassette.as_deref(),
+            rate_limits,
+            ..FetchContext::new(simulator.fetcher.as_ref(), &page_headers, timeout)
+        }
+    };
+    let events = simulator.events.for_job(&message_id, &job.to);
+
+    // Opens ask for images, which Gmail's image proxy fetches; link scanners
+    // have their own agent
+    let proxy_headers = build_headers(GOOGLE_IMAGE_PROXY_USER_AGENT, FetchKind::Image);
+    let open_fetch = FetchContext {
+        headers: match preset {
+            Some(DomainPreset::Gmail) => &proxy_headers,
+            _ => &image_headers,
+        },
+        ..fetch
+    };
+    let scanner_headers = build_headers(SCANNER_USER_AGENT, FetchKind::Page);
+    let scan_fetch = FetchContext {
+        headers: &scanner_headers,
+        ..fetch
+    };
+
+    let mut report = SimulationReport {
+        message_id: message_id.clone(),
+        to: job.to.clone(),
+        // Extract customer tag from plus addressing
+        customer_tag: extract_plus_tag(&job.to),
+        campaign_id: None,
+        status: JobStatus::Completed,
+        persona: Persona {
+            cohort,
+            ab_cohort,
+            preset,
+            client,
+            user_agent_category: client.map_or_else(
+                || UserAgentCategory::of(&user_agent),
+                ClientPersona::category,
+            ),
+            user_agent,
+            utc_offset_mins: offset_for(config, &job.to),
+        },
+        analysis_ms: None,
+        open_delay_ms: None,
+        message_age_secs: None,
+        open_decay: None,
+        calendar_factor: None,
+        open_probability: None,
+        click_probability: None,
+        scroll_depth: None,
+        opened: false,
+        pixels: Vec::new(),
+        clicks: Vec::new(),
+        scans: Vec::new(),
+        interactions: Vec::new(),
+        skipped: Vec::new(),
+        latency: None,
+        seed,
+        started_at: now,
+        config: config.simulation_vars.clone(),
+        dry_run: job.overrides.dry_run,
+        instance_id: instance_id(),
+        request_id: job.request_id.clone(),
+    };
+
+    // Expire messages too old to engage with, so a backlog drained after an
+    // outage does not open and click days late
+    let age_secs = job.received_at_secs().map(|at| now.saturating_sub(at));
+    if let Some(age_secs) = age_secs.filter(|_| config.max_message_age_secs > 0) {
+        if age_secs > config.max_message_age_secs {
+            warn!(
+                message_id = %message_id,
+                age_secs = age_secs,
+                max_age_secs = config.max_message_age_secs,
+                "worker_message_expired"
+            );
+            report.status = JobStatus::Expired;
+            report.message_age_secs = Some(age_secs);
+            report.skipped.push(Skip::Expired {
+                age_secs,
+                max_age_secs: config.max_message_age_secs,
+            });
+            return report;
+        }
+    }
+
+    // Leave recipients outside their active hours until their next window,
+    // spreading the deferred jobs over its first hour
+    if let (Some(hours), Some(offset)) = (
+        config.recipient_active_hours,
+        report.persona.utc_offset_mins,
+    ) {
+        if let Some(wait_secs) = until_active(now, offset, hours) {
+            let spread_secs = WAKE_SPREAD_SECS.min((hours.1 - hours.0) * 3600);
+            let resume_after_secs = wait_secs + rng.gen_range(0..spread_secs);
+            let local_hour = local_hour(now, offset);
+            info!(
+                message_id = %message_id,
+                utc_offset_mins = offset,
+                local_hour = local_hour,
+                resume_after_secs = resume_after_secs,
+                "recipient_outside_active_hours"
+            );
+            report.status = JobStatus::Deferred;
+            report.skipped.push(Skip::ActiveHours {
+                local_hour,
+                resume_after_secs,
+            });
+            return report;
+        }
+    }
+
+    // Refuse tenants over quota before doing any work for them
+    if let (Some(quotas), Some(tag)) = (&config.tenant_quotas, &report.customer_tag) {
+        if let Err(exceeded) = simulator.quotas.admit(quotas, tag) {
+            info!(
+                message_id = %message_id,
+                customer_tag = %tag,
+                window = exceeded.window.as_str(),
+                limit = exceeded.limit,
+                "tenant_quota_exceeded"
+            );
+            report.status = JobStatus::OverQuota;
+            report.skipped.push(Skip::Quota {
+                window: exceeded.window,
+                limit: exceeded.limit,
+                retry_after_secs: exceeded.retry_after.as_secs(),
+            });
+            return report;
+        }
+    }
+
+    // Parse once, off the runtime; hostile or broken HTML ends the job here
+    let limits = HtmlLimits {
+        max_bytes: config.max_html_bytes,
+        parse_budget: Duration::from_millis(config.html_parse_budget_ms),
+    };
+    let analysis = Instant::now();
+    let parsed = HtmlSummary::parse_within(html, limits).await;
+    report.analysis_ms = Some(analysis.elapsed().as_millis() as u64);
+    let summary = match parsed {
+        Ok(summary) => summary,
+        Err(e) => {
+            warn!(
+                message_id = %message_id,
+                reason = e.as_str(),
+                error = %e,
+                html_length = html_length,
+                "worker_html_rejected"
+            );
+            report.status = JobStatus::HtmlRejected;
+            report.skipped.push(Skip::HtmlRejected {
+                detail: e.to_string(),
+            });
+            return report;
+        }
+    };
+    report.campaign_id = campaign_of(&summary);
+
+    // A campaign's override wins over every other setting
+    let campaign = match (&simulator.campaign_overrides, &report.campaign_id) {
+        (Some(overrides), Some(id)) => overrides.get(id).await,
+        _ => None,
+    };
+    let config = match &campaign {
+        Some(campaign) => {
+            info!(
+                message_id = %message_id,
+                campaign_id = ?report.campaign_id,
+                "campaign_override_applied"
+            );
+            Cow::Owned(campaign.apply(config))
+        }
+        None => Cow::Borrowed(config),
+    };
+    let config = config.as_ref();
+
+    // A corporate gateway scans the links on delivery, before anyone reads
+    // the email; unsubscribe links are never scanned
+    if preset == Some(DomainPreset::Corporate) {
+        let links: Vec<String> = filter_links_with_rates(
+            &summary.links,
+            config.allow_domains.as_deref(),
+            config.deny_domains.as_deref(),
+        )
+        .into_iter()
+        .map(|link| link.url)
+        .filter(|url| !is_unsubscribe_link(url))
+        .take(MAX_SCANNED_LINKS)
+        .collect();
+        info!(
+            message_id = %message_id,
+            links = links.len(),
+            "worker_delivery_scan"
+        );
+        report.scans = scan_links(&scan_fetch, &links).await;
+    }
+
+    // The template's delay range applies unless the job sets its own
+    let open_delay_ms = match job.overrides.open_delay_ms {
+        Some(_) => config.open_delay_ms,
+        None => summary.open_delay_ms.unwrap_or(config.open_delay_ms),
+    };
+
+    // Draw the delay and rolls upfront, so they do not depend on fetch results
+    let delay_ms = rng.gen_range(open_delay_ms.0..=open_delay_ms.1);
+    let open_roll: f64 = rng.gen();
+    let click_roll: f64 = rng.gen();
+    // Drawn only when configured, so seeds from before it existed replay alike
+    let images_blocked = config.click_without_open_probability > 0.0
+        && rng.gen::<f64>() < config.click_without_open_probability;
+
+    // Random delay before potential open
+    info!(
+        message_id = %message_id,
+        delay_ms = delay_ms,
+        global_override_found = summary.open_delay_ms.is_some(),
+        "worker_delay_start"
+    );
+    let pause_ms = if simulator.wait { delay_ms } else { 0 };
+    if !sleep_unless_cancelled(Duration::from_millis(pause_ms), cancel).await {
+        info!(message_id = %message_id, "worker_job_cancelled_before_start");
+        report.status = JobStatus::NotStarted;
+        report.skipped.push(Skip::Shutdown { step: "open" });
+        return report;
+    }
+    report.open_delay_ms = Some(delay_ms);
+
+    // Check for global open rate override in HTML, unless the campaign sets one
+    let global_open_rate = summary.open_rate;
+    let campaign_open_rate = campaign.as_ref().and_then(|c| c.open_probability);
+    let open_probability = match campaign_open_rate {
+        Some(_) => config.simulate_open_probability,
+        None => global_open_rate.unwrap_or(config.simulate_open_probability),
+    };
+
+    // Weekends and holidays see less engagement
+    report.calendar_factor = config
+        .calendar
+        .as_deref()
+        .map(|calendar| factor_on(calendar, day_at(now, config.calendar_utc_offset_mins)));
+    let calendar_factor = report.calendar_factor.unwrap_or(1.0);
+
+    // Older messages open less often, unless the job fixes its probability
+    report.message_age_secs = age_secs;
+    report.open_decay = match report.message_age_secs {
+        Some(age)
+            if config.open_decay_half_life_secs > 0 && job.overrides.open_probability.is_none() =>
+        {
+            Some(config.open_decay_curve.factor(
+                age,
+                config.open_decay_half_life_secs,
+                config.open_decay_grace_secs,
+            ))
+        }
+        _ => None,
+    };
+    let effective_open_probability = scaled(
+        open_probability,
+        report.open_decay.unwrap_or(1.0) * calendar_factor,
+        job.overrides.open_probability,
+    );
+
+    info!(
+        message_id = %message_id,
+        campaign_override_found = campaign_open_rate.is_some(),
+        global_override_found = global_open_rate.is_some(),
+        global_override_value = ?global_open_rate,
+        message_age_secs = ?report.message_age_secs,
+        open_decay = ?report.open_decay,
+        calendar_factor = ?report.calendar_factor,
+        effective_probability = effective_open_probability,
+        "worker_open_rate_determined"
+    );
+
+    if !images_blocked {
+        report.open_probability = Some(effective_open_probability);
+    }
+
+    // Simulate open with probability check
+    let mut opened = false;
+    let will_attempt_open = open_roll < effective_open_probability;
+
+    info!(
+        message_id = %message_id,
+        roll = open_roll,
+        threshold = effective_open_probability,
+        will_attempt_open = will_attempt_open,
+        images_blocked = images_blocked,
+        "worker_open_roll"
+    );
+
+    if images_blocked {
+        // An image-blocking reader never loads the pixel but may still click
+        info!(
+            message_id = %message_id,
+            reason = "images_blocked",
+            "worker_open_skipped"
+        );
+        report.skipped.push(Skip::ImagesBlocked);
+    } else if will_attempt_open {
+        // Known open pixels go first: SFMC (Classic and Advanced) and any
+        // other tracking layer the email passed through
+        let open_pixels: Vec<String> = summary
+            .open_pixels
+            .iter()
+            .filter_map(|url| sanitize_or_log(url))
+            .collect();
+        let heuristic_of = |url: &str| {
+            summary
+                .tracking_pixels
+                .iter()
+                .find(|pixel| sanitize(&pixel.url).ok().as_deref() == Some(url))
+                .map(|pixel| pixel.heuristic)
+        };
+
+        // With SCROLL_DEPTH, images load in document order down to where the
+        // reader stops; otherwise probable tracking pixels go first, so the
+        // image cap never leaves them out
+        let scroll_depth = config.scroll_depth.map(|depth| depth.sample(&mut *rng));
+        report.scroll_depth = scroll_depth;
+        let mut images = summary.images.clone();
+        if scroll_depth.is_none() {
+            images.sort_by_key(|url| sanitize(url).map_or(true, |url| heuristic_of(&url).is_none()));
+        }
+
+        info!(
+            message_id = %message_id,
+            open_pixels_found = open_pixels.len(),
+            tracking_pixels_found = summary.tracking_pixels.len(),
+            total_images_found = images.len(),
+            scroll_depth = ?scroll_depth,
+            "worker_open_analysis"
+        );
+
+        // Fetch each open pixel, so every tracking layer records the open
+        for pixel_url in open_pixels.iter().take(MAX_OPEN_PIXELS) {
+            info!(
+                message_id = %message_id,
+                url = %pixel_url,
+                "worker_pixel_fetch_starting"
+            );
+
+            let pixel = fetch_single_url(&open_fetch, pixel_url).await;
+            let pixel_result = pixel.as_ref().is_some_and(|p| p.succeeded());
+
+            info!(
+                message_id = %message_id,
+                url = %pixel_url,
+                success = pixel_result,
+                "worker_pixel_fetch"
+            );
+
+            if pixel_result {
+                opened = true;
+            }
+            report.pixels.extend(pixel.map(|pixel| FetchReport {
+                heuristic: Some(PixelHeuristic::EspPattern),
+                ..pixel
+            }));
+        }
+
+        // Remove open pixels from regular images list
+        images.retain(|u| sanitize(u).map_or(true, |u| !open_pixels.contains(&u)));
+
+        // With WRAPPED_IMAGES=click, images inside links are left to the
+        // click, unless they look like tracking pixels
+        if config.wrapped_images == WrappedImagePolicy::Click {
+            let found = images.len();
+            images.retain(|url| {
+                !summary.wrapped_images.iter().any(|w| &w.image == url)
+                    || sanitize(url).is_ok_and(|url| heuristic_of(&url).is_some())
+            });
+            if images.len() < found {
+                report.skipped.push(Skip::WrappedImages {
+                    images: found - images.len(),
+                });
+            }
+        }
+
+        // With PIXEL_ONLY_OPENS, only pixels are fetched if the email has any
+        if config.pixel_only_opens {
+            let found = images.len();
+            let is_pixel = |url: &String| sanitize(url).is_ok_and(|url| heuristic_of(&url).is_some());
+            if !open_pixels.is_empty() {
+                images.clear();
+            } else if images.iter().any(is_pixel) {
+                images.retain(is_pixel);
+            }
+            if images.len() < found {
+                report.skipped.push(Skip::PixelOnly {
+                    images: found - images.len(),
+                });
+            }
+        }
+
+        // Simulate open via regular images (skipped once shutdown has begun,
+        // or when the pixel alone registers the open)
+        let open_result = if images.is_empty() && !open_pixels.is_empty() {
+            false
+        } else if is_cancelled(cancel) {
+            report.skipped.push(Skip::Shutdown { step: "images" });
+            false
+        } else {
+            if let Some(depth) = scroll_depth.filter(|&depth| images.len() > depth) {
+                report.skipped.push(Skip::BelowFold {
+                    images: images.len() - depth,
+                });
+            }
+            let limit = scroll_depth.unwrap_or(MAX_OPEN_IMAGES);
+            let mut fetched = simulate_open_to(&open_fetch, &images, limit).await;
+            if fetched.is_empty() && open_pixels.is_empty() {
+                report.skipped.push(Skip::NoImages);
+            }
+            for image in &mut fetched {
+                image.heuristic = heuristic_of(&image.url);
+                if let Some(heuristic) = image.heuristic {
+                    info!(
+                        message_id = %message_id,
+                        heuristic = heuristic.as_str(),
+                        success = image.succeeded(),
+                        "worker_tracking_pixel_fetch"
+                    );
+                }
+            }
+            let any_success = fetched.iter().any(|f| f.succeeded());
+            report.pixels.extend(fetched);
+            any_success
+        };
+        opened = open_result || opened;
+
+        let opened_source = if !open_pixels.is_empty() && opened {
+            "special_pixel"
+        } else if open_result {
+            "regular_images"
+        } else {
+            "none"
+        };
+
+        info!(
+            message_id = %message_id,
+            opened = opened,
+            opened_source = opened_source,
+            "worker_open_final_status"
+        );
+    } else {
+        info!(
+            message_id = %message_id,
+            reason = "probability_check_failed",
+            "worker_open_skipped"
+        );
+        report.skipped.push(Skip::OpenRoll {
+            roll: open_roll,
+            probability: effective_open_probability,
+        });
+    }
+    report.opened = opened;
+    if opened {
+        events.opened(&report.pixels).await;
+    }
+
+    // Check for global click rate override in HTML, unless the campaign sets one
+    let global_click_rate = summary.click_rate;
+    let campaign_click_rate = campaign.as_ref().and_then(|c| c.click_probability);
+    let click_probability = match campaign_click_rate {
+        Some(_) => config.simulate_click_probability,
+        None => global_click_rate.unwrap_or(config.simulate_click_probability),
+    };
+    let effective_click_probability = scaled(
+        click_probability,
+        calendar_factor,
+        job.overrides.click_probability,
+    );
+
+    info!(
+        message_id = %message_id,
+        campaign_override_found = campaign_click_rate.is_some(),
+        global_override_found = global_click_rate.is_some(),
+        global_override_value = ?global_click_rate,
+        calendar_factor = ?report.calendar_factor,
+        effective_probability = effective_click_probability,
+        "worker_click_rate_determined"
+    );
+
+    if !summary.links.is_empty() {
+        report.click_probability = Some(effective_click_probability);
+    }
+
+    // Simulate clicks with probability check
+    let will_attempt_click = click_roll < effective_click_probability;
+
+    info!(
+        message_id = %message_id,
+        roll = click_roll,
+        threshold = effective_click_probability,
+        will_attempt_click = will_attempt_click,
+        "worker_click_roll"
+    );
+
+    if will_attempt_click && is_cancelled(cancel) {
+        info!(
+            message_id = %message_id,
+            reason = "shutdown",
+            "worker_click_skipped"
+        );
+        report.skipped.push(Skip::Shutdown { step: "clicks" });
+    } else if will_attempt_click {
+        // Extract links with their individual click rates
+        let links_with_rates = &summary.links;
+
+        // Filter by domain allow/deny lists, then by the campaign's links
+        let mut filtered_links = filter_links_with_rates(
+            links_with_rates,
+            config.allow_domains.as_deref(),
+            config.deny_domains.as_deref(),
+        );
+        if let Some(campaign) = &campaign {
+            filtered_links.retain(|link| campaign.allows_link(&link.url));
+        }
+
+        // With WRAPPED_IMAGES=open, links around images were opened, not clicked
+        if config.wrapped_images == WrappedImagePolicy::Open {
+            let found = filtered_links.len();
+            filtered_links.retain(|link| !summary.wrapped_images.iter().any(|w| w.link == link.url));
+            if filtered_links.len() < found {
+                report.skipped.push(Skip::WrappedLinks {
+                    links: found - filtered_links.len(),
+                });
+            }
+        }
+
+        // Choose links using the cohort's click model
+        let chosen = match cohort {
+            Cohort::Stable => choose_links_weighted(
+                &filtered_links,
+                config.max_clicks,
+                effective_click_probability,
+                config.unique_clicks,
+                rng,
+            ),
+            Cohort::Canary => choose_links_positional(
+                &filtered_links,
+                config.max_clicks,
+                effective_click_probability,
+                rng,
+            ),
+        };
+
+        info!(
+            message_id = %message_id,
+            cohort = cohort.as_str(),
+            total_links_found = links_with_rates.len(),
+            links_after_filter = filtered_links.len(),
+            links_chosen = chosen.len(),
+            "worker_click_analysis"
+        );
+
+        if chosen.is_empty() {
+            report.skipped.push(Skip::NoLinks);
+        } else {
+            if preset == Some(DomainPreset::Outlook) {
+                info!(
+                    message_id = %message_id,
+                    links = chosen.len(),
+                    "worker_link_prefetch"
+                );
+                report.scans.extend(scan_links(&scan_fetch, &chosen).await);
+            }
+            let pacing = config.click_domain_overrides.as_deref().unwrap_or_default();
+            let delays_ms = draw_click_delays(&chosen, config.click_delay_ms, pacing, rng);
+            let outcome = perform_clicks(
+                &fetch,
+                &chosen,
+                &delays_ms,
+                pacing,
+                simulator.wait,
+                &events,
+                cancel,
+            )
+            .await;
+            report.clicks = outcome.clicks;
+            report.skipped.extend(outcome.skipped);
+        }
+    } else {
+        report.skipped.push(Skip::ClickRoll {
+            roll: click_roll,
+            probability: effective_click_probability,
+        });
+    }
+
+    // Tapping a mailto: or tel: link opens an app, so it is recorded but never
+    // fetched. Rolled only when enabled, so existing seeds replay unchanged
+    if config.contact_link_probability > 0.0 && !is_cancelled(cancel) {
+        report.interactions = summary
+            .contact_links
+            .iter()
+            .filter(|_| rng.gen::<f64>() < config.contact_link_probability)
+            .filter_map(|url| {
+                Some(ContactInteraction {
+                    kind: ContactKind::of(url)?,
+                    url: url.clone(),
+                })
+            })
+            .collect();
+    }
+
+    if is_cancelled(cancel) {
+        report.status = JobStatus::Partial;
+    }
+
+    info!(
+        message_id = %report.message_id,
+        to = %report.to,
+        customer_tag = ?report.customer_tag,
+        opened = report.opened,
+        clicks = report.successful_clicks(),
+        click_errors = report.click_errors(),
+        destination_mismatches = report.destination_mismatches(),
+        contact_interactions = report.interactions.len(),
+        skipped = report.skipped.len(),
+        status = report.status.as_str(),
+        cohort = report.persona.cohort.as_str(),
+        ab_cohort = ?report.persona.ab_cohort,
+        client = report.persona.client.map_or("", ClientPersona::as_str),
+        ua_category = report.persona.user_agent_category.as_str(),
+        instance = report.instance_id,
+        request_id = ?report.request_id,
+        "email_simulation_complete"
+    );
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::decay::message_age_secs;
+    use crate::simulate::destination::DestinationCheck;
+    use crate::simulate::fetch::FetchError;
+    use crate::simulate::preset::PresetRule;
+    use crate::simulate::scroll::ScrollDepth;
+    use crate::testing::MockServer;
+    use futures::future::BoxFuture;
+
+    /// Config that always opens and clicks once, without delays.
+    fn eager_config() -> WorkerConfig {
+        WorkerConfig::builder()
+            .simulate_open_probability(1.0)
+            .simulate_click_probability(1.0)
+            .max_clicks(1)
+            .open_delay_ms((0, 0))
+            .click_delay_ms((0, 0))
+            .request_timeout_ms(500)
+            .build()
+    }
+
+    async fn simulate(config: WorkerConfig, html: String) -> SimulationReport {
+        let job = Job {
+            message_id: Some("msg-mock".to_string()),
+            to: "user+tag@example.com".to_string(),
+            html: Some(html),
+            ..Default::default()
+        };
+        Simulator::new(config).unwrap().simulate(&job).await
+    }
+
+    #[test]
+    fn test_extract_plus_tag() {
+        assert_eq!(
+            extract_plus_tag("user+tag@example.com"),
+            Some("tag".to_string())
+        );
+        assert_eq!(
+            extract_plus_tag("user+customer123@example.com"),
+            Some("customer123".to_string())
+        );
+        assert_eq!(extract_plus_tag("user@example.com"), None);
+        assert_eq!(extract_plus_tag("user"), None);
+    }
+
+    #[test]
+    fn test_job_deserialization() {
+        let json = r#"{
+            "message_id": "msg-123",
+            "to": "test+tag@example.com",
+            "html": "<html><body>Test</body></html>"
+        }"#;
+
+        let job: Job = serde_json::from_str(json).unwrap();
+        assert_eq!(job.message_id, Some("msg-123".to_string()));
+        assert_eq!(job.to, "test+tag@example.com");
+        assert!(job.html.is_some());
+    }
+
+    #[test]
+    fn test_job_deserialization_minimal() {
+        let json = r#"{"to": "test@example.com"}"#;
+
+        let job: Job = serde_json::from_str(json).unwrap();
+        assert_eq!(job.message_id, None);
+        assert_eq!(job.to, "test@example.com");
+        assert_eq!(job.html, None);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_opens_and_follows_redirects() {
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Shop</a></body></html>"#,
+            server.url("/open/track?id=1"),
+            server.url("/redirect/2"),
+        );
+
+        let result = simulate(eager_config(), html).await;
+
+        assert_eq!(result.status, JobStatus::Completed);
+        assert!(result.opened);
+        assert_eq!(result.successful_clicks(), 1);
+        assert_eq!(result.click_errors(), 0);
+        assert_eq!(result.customer_tag.as_deref(), Some("tag"));
+        assert_eq!(result.open_delay_ms, Some(0));
+        assert_eq!(result.pixels.len(), 1);
+        assert_eq!(result.pixels[0].status, Some(200));
+        assert_eq!(
+            result.clicks[0].redirects,
+            vec![server.url("/redirect/1"), server.url("/landed")]
+        );
+        assert_eq!(result.clicks[0].redirect_count, 2);
+        assert_eq!(result.clicks[0].status, Some(200));
+        assert!(result.skipped.is_empty());
+        server.assert_hit("/open/track?id=1");
+        server.assert_hit("/redirect/2");
+        server.assert_hit("/landed");
+    }
+
+    #[tokio::test]
+    async fn test_simulate_counts_error_pages_and_timeouts() {
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Broken</a></body></html>"#,
+            server.url("/slow/5000"),
+            server.url("/status/500"),
+        );
+        let config = WorkerConfig {
+            request_timeout_ms: 100,
+            ..eager_config()
+        };
+
+        let result = simulate(config, html).await;
+
+        assert!(!result.opened);
+        assert!(result.pixels[0].error.is_some());
+        assert_eq!(result.successful_clicks(), 0);
+        assert_eq!(result.click_errors(), 1);
+        assert_eq!(result.clicks[0].status, Some(500));
+        server.assert_hit("/slow/5000");
+        server.assert_hit("/status/500");
+    }
+
+    #[tokio::test]
+    async fn test_simulate_skips_fetches_when_rolls_fail() {
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Shop</a></body></html>"#,
+            server.url("/pixel.gif"),
+            server.url("/landing"),
+        );
+        let config = WorkerConfig {
+            simulate_open_probability: 0.0,
+            simulate_click_probability: 0.0,
+            ..eager_config()
+        };
+
+        let result = simulate(config, html).await;
+
+        assert!(!result.opened);
+        assert!(result.clicks.is_empty());
+        assert!(matches!(
+            result.skipped.as_slice(),
+            [Skip::OpenRoll { .. }, Skip::ClickRoll { .. }]
+        ));
+        assert!(server.hits().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_fetches_nothing() {
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Shop</a></body></html>"#,
+            server.url("/pixel.gif"),
+            server.url("/landing"),
+        );
+        let job: Job = SimulatorJob::builder()
+            .message_id("msg-dry-run")
+            .to("user@example.com")
+            .html(html)
+            .dry_run()
+            .build()
+            .into();
+
+        let report = Simulator::new(eager_config()).unwrap().simulate(&job).await;
+
+        assert!(report.dry_run);
+        assert!(report.opened);
+        assert_eq!(report.successful_clicks(), 1);
+        assert!(server.hits().is_empty());
+    }
+
+    /// Answers every request with `200` and records its URL, user agent and
+    /// `Accept` header.
+    #[derive(Clone, Default)]
+    struct Recording {
+        urls: Arc<std::sync::Mutex<Vec<String>>>,
+        agents: Arc<std::sync::Mutex<Vec<String>>>,
+        accepts: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl HttpFetcher for Recording {
+        fn fetch<'a>(
+            &'a self,
+            url: &'a str,
+            headers: &'a [(String, String)],
+            _timeout: Duration,
+        ) -> BoxFuture<'a, Result<reqwest::Response, FetchError>> {
+            self.urls.lock().unwrap().push(url.to_string());
+            let agent = headers.iter().find(|(name, _)| name == "User-Agent");
+            self.agents
+                .lock()
+                .unwrap()
+                .push(agent.map_or_else(String::new, |(_, agent)| agent.clone()));
+            let accept = headers.iter().find(|(name, _)| name == "Accept");
+            self.accepts
+                .lock()
+                .unwrap()
+                .push(accept.map_or_else(String::new, |(_, accept)| accept.clone()));
+            Box::pin(async {
+                let response = http::Response::builder().status(200).body(Vec::new());
+                Ok(reqwest::Response::from(response.unwrap()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_contact_links_are_recorded_not_fetched() {
+        let html = r#"<a href="mailto:help@example.com">Email us</a>
+            <a href="tel:+15550100">Call us</a>
+            <a href="https://shop.example.com/sale">Sale</a>"#;
+        let job = Job {
+            message_id: Some("msg-contact".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html.to_string()),
+            ..Default::default()
+        };
+        let fetcher = Recording::default();
+        let simulate = |probability| {
+            let config = WorkerConfig {
+                contact_link_probability: probability,
+                ..eager_config()
+            };
+            let simulator = Simulator::new(config).unwrap().with_fetcher(fetcher.clone());
+            let job = job.clone();
+            async move { simulator.simulate(&job).await }
+        };
+
+        let report = simulate(1.0).await;
+        assert_eq!(
+            report.interactions,
+            vec![
+                ContactInteraction {
+                    kind: ContactKind::Mailto,
+                    url: "mailto:help@example.com".to_string(),
+                },
+                ContactInteraction {
+                    kind: ContactKind::Tel,
+                    url: "tel:+15550100".to_string(),
+                },
+            ]
+        );
+        assert_eq!(report.clicks.len(), 1);
+        assert_eq!(
+            *fetcher.urls.lock().unwrap(),
+            vec!["https://shop.example.com/sale"]
+        );
+
+        assert!(simulate(0.0).await.interactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recipient_domain_presets() {
+        let html = r#"<img src="https://cdn.example.com/hero.png">
+            <a href="https://shop.example.com/sale">Sale</a>"#;
+        let config = WorkerConfig {
+            recipient_presets: Some(
+                ["builtin", "corp.example.com:corporate"]
+                    .iter()
+                    .flat_map(|entry| PresetRule::parse(entry).unwrap())
+                    .collect(),
+            ),
+            user_agent_pool: Some(vec![WeightedAgent::from("Reader/1.0")]),
+            ..eager_config()
+        };
+        let simulate = |to: &str| {
+            let job = Job {
+                message_id: Some("msg-preset".to_string()),
+                to: to.to_string(),
+                html: Some(html.to_string()),
+                ..Default::default()
+            };
+            let fetcher = Recording::default();
+            let simulator = Simulator::new(config.clone())
+                .unwrap()
+                .with_fetcher(fetcher.clone());
+            async move {
+                let report = simulator.simulate(&job).await;
+                let accepts = fetcher.accepts.lock().unwrap().clone();
+                let fetches: Vec<(String, String)> = fetcher
+                    .urls
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .cloned()
+                    .zip(fetcher.agents.lock().unwrap().iter().cloned())
+                    .collect();
+                (report, fetches, accepts)
+            }
+        };
+        let fetch = |url: &str, agent: &str| (url.to_string(), agent.to_string());
+
+        // Gmail's proxy fetches the image; the reader clicks
+        let (report, fetches, _) = simulate("user@gmail.com").await;
+        assert_eq!(report.persona.preset, Some(DomainPreset::Gmail));
+        assert_eq!(
+            fetches,
+            vec![
+                fetch("https://cdn.example.com/hero.png", GOOGLE_IMAGE_PROXY_USER_AGENT),
+                fetch("https://shop.example.com/sale", "Reader/1.0"),
+            ]
+        );
+
+        // Outlook's scanner checks the link before the reader's click
+        let (report, fetches, _) = simulate("user@hotmail.com").await;
+        assert_eq!(report.scans.len(), 1);
+        assert_eq!(
+            fetches,
+            vec![
+                fetch("https://cdn.example.com/hero.png", "Reader/1.0"),
+                fetch("https://shop.example.com/sale", SCANNER_USER_AGENT),
+                fetch("https://shop.example.com/sale", "Reader/1.0"),
+            ]
+        );
+
+        // A corporate gateway scans on delivery, and the reader engages less
+        let (report, fetches, _) = simulate("user@corp.example.com").await;
+        assert_eq!(report.persona.preset, Some(DomainPreset::Corporate));
+        assert_eq!(fetches[0], fetch("https://shop.example.com/sale", SCANNER_USER_AGENT));
+        assert_eq!(report.scans[0].url, "https://shop.example.com/sale");
+
+        // Images and clicks ask for what a browser would
+        let (report, fetches, accepts) = simulate("user@example.org").await;
+        assert_eq!(report.persona.preset, None);
+        assert!(report.scans.is_empty());
+        assert_eq!(fetches.len(), 2);
+        assert_eq!(accepts, vec![FetchKind::Image.accept(), FetchKind::Page.accept()]);
+    }
+
+    #[tokio::test]
+    async fn test_wrapped_image_policies() {
+        // The wrapping href only matches its link once both are normalized
+        let html = r#"<html><body>
+            <a href="https://Shop.Example.COM:443/sale#hero"><img src="https://cdn.example.com/hero.png"></a>
+            <a href="https://shop.example.com/about">About</a>
+        </body></html>"#;
+        let job = Job {
+            message_id: Some("msg-wrapped-image".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html.to_string()),
+            ..Default::default()
+        };
+        let run = |policy| {
+            let config = WorkerConfig {
+                wrapped_images: policy,
+                max_clicks: 2,
+                ..eager_config()
+            };
+            let fetcher = Recording::default();
+            let simulator = Simulator::new(config).unwrap().with_fetcher(fetcher.clone());
+            let job = job.clone();
+            async move {
+                let report = simulator.simulate(&job).await;
+                let mut fetched = fetcher.urls.lock().unwrap().clone();
+                fetched.sort();
+                fetched.dedup();
+                (report.skipped, fetched)
+            }
+        };
+
+        let (skipped, fetched) = run(WrappedImagePolicy::Both).await;
+        assert!(skipped.is_empty());
+        assert_eq!(fetched[0], "https://cdn.example.com/hero.png");
+
+        // The creative counts as an open only
+        let (skipped, fetched) = run(WrappedImagePolicy::Open).await;
+        assert_eq!(skipped, vec![Skip::WrappedLinks { links: 1 }]);
+        assert_eq!(
+            fetched,
+            vec!["https://cdn.example.com/hero.png", "https://shop.example.com/about"]
+        );
+
+        // The creative counts as a click only; with no other image, no open
+        let (skipped, fetched) = run(WrappedImagePolicy::Click).await;
+        assert_eq!(skipped, vec![Skip::WrappedImages { images: 1 }, Skip::NoImages]);
+        assert!(!fetched.is_empty());
+        assert!(fetched.iter().all(|url| url.starts_with("https://shop.example.com/")));
+    }
+
+    #[tokio::test]
+    async fn test_every_open_pixel_is_fetched() {
+        let html = r#"<html><body>
+            <img src="https://cdn.example.com/hero.png">
+            <img src="https://cl.s4.exct.net/open.aspx?ffcb10-fe">
+            <img src="https://a1b2c3.emltrk.com/a1b2c3?d=1">
+        </body></html>"#;
+        let job = Job {
+            message_id: Some("msg-layers".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html.to_string()),
+            ..Default::default()
+        };
+        let config = WorkerConfig {
+            simulate_click_probability: 0.0,
+            ..eager_config()
+        };
+
+        let fetcher = Recording::default();
+        let report = Simulator::new(config)
+            .unwrap()
+            .with_fetcher(fetcher.clone())
+            .simulate(&job)
+            .await;
+        assert!(report.opened);
+        let pixels: Vec<_> = report
+            .pixels
+            .iter()
+            .map(|p| (p.url.as_str(), p.heuristic))
+            .collect();
+        assert_eq!(
+            pixels,
+            vec![
+                (
+                    "https://cl.s4.exct.net/open.aspx?ffcb10-fe",
+                    Some(PixelHeuristic::EspPattern)
+                ),
+                ("https://a1b2c3.emltrk.com/a1b2c3?d=1", Some(PixelHeuristic::EspPattern)),
+                ("https://cdn.example.com/hero.png", None),
+            ]
+        );
+        assert_eq!(fetcher.urls.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_pixel_only_opens_skip_other_images() {
+        let html = r#"<html><body>
+            <img src="https://cdn.example.com/hero.png">
+            <img src="https://cl.s4.exct.net/open.aspx?ffcb10-fe">
+            <img src="https://cdn.example.com/logo.png">
+        </body></html>"#;
+        let job = Job {
+            message_id: Some("msg-pixel".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html.to_string()),
+            ..Default::default()
+        };
+        let config = WorkerConfig {
+            simulate_click_probability: 0.0,
+            pixel_only_opens: true,
+            ..eager_config()
+        };
+
+        let fetcher = Recording::default();
+        let report = Simulator::new(config.clone())
+            .unwrap()
+            .with_fetcher(fetcher.clone())
+            .simulate(&job)
+            .await;
+        assert!(report.opened);
+        assert_eq!(
+            *fetcher.urls.lock().unwrap(),
+            vec!["https://cl.s4.exct.net/open.aspx?ffcb10-fe"]
+        );
+        assert!(report.skipped.contains(&Skip::PixelOnly { images: 2 }));
+        assert_eq!(report.pixels[0].heuristic, Some(PixelHeuristic::EspPattern));
+
+        // Without a pixel the images still register the open
+        let fetcher = Recording::default();
+        let job = Job {
+            html: Some(r#"<img src="https://cdn.example.com/hero.png">"#.to_string()),
+            ..job
+        };
+        let report = Simulator::new(config)
+            .unwrap()
+            .with_fetcher(fetcher.clone())
+            .simulate(&job)
+            .await;
+        assert!(report.opened);
+        assert_eq!(fetcher.urls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tracking_pixels_fetched_first() {
+        let mut html = String::new();
+        for i in 0..6 {
+            html.push_str(&format!(r#"<img src="https://cdn.example.com/{}.png">"#, i));
+        }
+        html.push_str(r#"<img src="https://esp.example.com/p.gif" width="1" height="1">"#);
+        let job = Job {
+            message_id: Some("msg-heuristic".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html),
+            ..Default::default()
+        };
+        let config = WorkerConfig {
+            simulate_click_probability: 0.0,
+            ..eager_config()
+        };
+
+        // The pixel is fetched although five images come before it
+        let fetcher = Recording::default();
+        let report = Simulator::new(config.clone())
+            .unwrap()
+            .with_fetcher(fetcher.clone())
+            .simulate(&job)
+            .await;
+        assert_eq!(report.pixels.len(), 5);
+        assert_eq!(report.pixels[0].url, "https://esp.example.com/p.gif");
+        assert_eq!(report.pixels[0].heuristic, Some(PixelHeuristic::OneByOne));
+        assert_eq!(report.pixels[1].heuristic, None);
+
+        // And alone with PIXEL_ONLY_OPENS
+        let fetcher = Recording::default();
+        let report = Simulator::new(WorkerConfig {
+            pixel_only_opens: true,
+            ..config
+        })
+        .unwrap()
+        .with_fetcher(fetcher.clone())
+        .simulate(&job)
+        .await;
+        assert!(report.opened);
+        assert_eq!(*fetcher.urls.lock().unwrap(), vec!["https://esp.example.com/p.gif"]);
+        assert!(report.skipped.contains(&Skip::PixelOnly { images: 6 }));
+    }
+
+    #[tokio::test]
+    async fn test_scroll_depth_fetches_images_in_document_order() {
+        let mut html = String::new();
+        for i in 0..6 {
+            html.push_str(&format!(r#"<img src="https://cdn.example.com/{}.png">"#, i));
+        }
+        html.push_str(r#"<img src="https://esp.example.com/p.gif" width="1" height="1">"#);
+        let job = Job {
+            message_id: Some("msg-scroll".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html),
+            ..Default::default()
+        };
+        let config = WorkerConfig {
+            simulate_click_probability: 0.0,
+            scroll_depth: Some(ScrollDepth::Uniform { min: 2, max: 2 }),
+            ..eager_config()
+        };
+
+        let fetcher = Recording::default();
+        let report = Simulator::new(config)
+            .unwrap()
+            .with_fetcher(fetcher.clone())
+            .simulate(&job)
+            .await;
+        assert!(report.opened);
+        assert_eq!(report.scroll_depth, Some(2));
+        let mut fetched = fetcher.urls.lock().unwrap().clone();
+        fetched.sort();
+        assert_eq!(
+            fetched,
+            vec!["https://cdn.example.com/0.png", "https://cdn.example.com/1.png"]
+        );
+        assert!(report.skipped.contains(&Skip::BelowFold { images: 5 }));
+    }
+
+    #[tokio::test]
+    async fn test_template_open_delay_override() {
+        let job = Job {
+            message_id: Some("msg-delay".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(r#"<div data-scope="global" data-open-delay-ms="20,20"></div>"#.to_string()),
+            ..Default::default()
+        };
+        let simulator = Simulator::new(eager_config())
+            .unwrap()
+            .with_fetcher(Recording::default());
+        assert_eq!(simulator.simulate(&job).await.open_delay_ms, Some(20));
+
+        // A job's own range wins over the template's
+        let job = Job {
+            overrides: JobOverrides {
+                open_delay_ms: Some((0, 0)),
+                ..Default::default()
+            },
+            ..job
+        };
+        assert_eq!(simulator.simulate(&job).await.open_delay_ms, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_image_blocking_recipient_clicks_without_opening() {
+        let job = Job {
+            message_id: Some("msg-blocked".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(
+                r#"<img src="https://cl.s4.exct.net/open.aspx?ffcb10-fe">
+                <a href="https://shop.example.com/sale">Sale</a>"#
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        let config = WorkerConfig {
+            click_without_open_probability: 1.0,
+            ..eager_config()
+        };
+
+        let fetcher = Recording::default();
+        let report = Simulator::new(config)
+            .unwrap()
+            .with_fetcher(fetcher.clone())
+            .simulate(&job)
+            .await;
+        assert!(!report.opened);
+        assert!(report.pixels.is_empty());
+        assert_eq!(report.skipped, vec![Skip::ImagesBlocked]);
+        assert_eq!(report.successful_clicks(), 1);
+        assert_eq!(
+            *fetcher.urls.lock().unwrap(),
+            vec!["https://shop.example.com/sale"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrapped_click_landing_off_destination_is_flagged() {
+        let job = Job {
+            message_id: Some("msg-wrapped".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(
+                r#"<a href="https://click.s4.exct.net/?qs=1&url=https%3A%2F%2Fshop.example.com%2Fsale">Sale</a>"#
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+
+        // The wrapper answers 200 itself instead of redirecting
+        let report = Simulator::new(eager_config())
+            .unwrap()
+            .with_fetcher(Recording::default())
+            .simulate(&job)
+            .await;
+        assert_eq!(report.successful_clicks(), 1);
+        assert_eq!(
+            report.clicks[0].destination,
+            Some(DestinationCheck {
+                expected: "https://shop.example.com/sale".to_string(),
+                matched: false,
+            })
+        );
+        assert_eq!(report.destination_mismatches(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_seeded_simulation_is_reproducible() {
+        let server = MockServer::start().await.unwrap();
+        let links: String = (0..8)
+            .map(|i| format!(r#"<a href="{}">Link</a>"#, server.url(&format!("/{}", i))))
+            .collect();
+        let html = format!(
+            r#"<html><body><img src="{}">{}</body></html>"#,
+            server.url("/pixel.gif"),
+            links
+        );
+        let job = Job {
+            message_id: Some("msg-seeded".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html),
+            ..Default::default()
+        };
+        let config = WorkerConfig::builder()
+            .simulate_open_probability(0.5)
+            .simulate_click_probability(0.5)
+            .max_clicks(3)
+            .open_delay_ms((0, 5))
+            .click_delay_ms((0, 5))
+            .simulation_seed(42)
+            .build();
+        let simulator = Simulator::new(config).unwrap();
+
+        let first = simulator.simulate(&job).await;
+        let second = simulator.simulate(&job).await;
+        let replayed = simulator.simulate_seeded(&job, first.seed).await;
+
+        let choices = |r: &SimulationReport| {
+            let clicks: Vec<_> = r.clicks.iter().map(|c| (&c.url, c.delay_ms)).collect();
+            format!("{:?}", (&r.persona, r.open_delay_ms, &r.skipped, clicks))
+        };
+        assert_eq!(second.seed, first.seed);
+        assert_eq!(choices(&second), choices(&first));
+        assert_eq!(choices(&replayed), choices(&first));
+    }
+
+    #[tokio::test]
+    async fn test_report_replays_from_its_recorded_config_and_clock() {
+        let server = MockServer::start().await.unwrap();
+        let links: String = (0..8)
+            .map(|i| format!(r#"<a href="{}">Link</a>"#, server.url(&format!("/{}", i))))
+            .collect();
+        let job = Job {
+            message_id: Some("msg-replay".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(format!("<html><body>{}</body></html>", links)),
+            received_at: Some(message_age_secs(0) - 7200),
+            ..Default::default()
+        };
+        let vars = |name: &str| match name {
+            "SIMULATE_CLICK_PROBABILITY" => Some("1".to_string()),
+            "MAX_CLICKS" => Some("3".to_string()),
+            "OPEN_DELAY_RANGE_MS" => Some("0,5".to_string()),
+            "CLICK_DELAY_RANGE_MS" => Some("0,5".to_string()),
+            "OPEN_DECAY_HALF_LIFE_SECS" => Some("3600".to_string()),
+            "CALENDAR" => Some("sat:0.5,sun:0.5".to_string()),
+            _ => None,
+        };
+        let recorded = Simulator::new(WorkerConfig::from_vars(&vars))
+            .unwrap()
+            .simulate(&job)
+            .await;
+        assert_eq!(recorded.config.len(), 6);
+        assert_eq!(recorded.config["MAX_CLICKS"], "3");
+
+        // Only what the report carries, a day later
+        let config = WorkerConfig::from_vars(&|name| recorded.config.get(name).cloned());
+        let replayed = Simulator::new(config)
+            .unwrap()
+            .with_clock(recorded.started_at)
+            .without_delays()
+            .simulate_seeded(&job, recorded.seed)
+            .await;
+
+        let choices = |r: &SimulationReport| {
+            let clicks: Vec<_> = r.clicks.iter().map(|c| (&c.url, c.delay_ms)).collect();
+            format!(
+                "{:?}",
+                (
+                    &r.persona,
+                    r.open_delay_ms,
+                    r.message_age_secs,
+                    r.open_decay,
+                    r.calendar_factor,
+                    &r.skipped,
+                    clicks
+                )
+            )
+        };
+        assert!(!recorded.clicks.is_empty());
+        assert_eq!(choices(&replayed), choices(&recorded));
+        assert_eq!(replayed.started_at, recorded.started_at);
+
+        // A pinned clock sets the message's age
+        let later = Simulator::new(WorkerConfig::from_vars(&vars))
+            .unwrap()
+            .with_clock(recorded.started_at + 86_400)
+            .without_delays()
+            .simulate_seeded(&job, recorded.seed)
+            .await;
+        assert_eq!(later.message_age_secs, recorded.message_age_secs.map(|age| age + 86_400));
+    }
+
+    #[tokio::test]
+    async fn test_events_fire_as_job_progresses() {
+        use crate::simulate::events::SimulationEvents;
+        use std::sync::Mutex;
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Shop</a></body></html>"#,
+            server.url("/open/track?id=1"),
+            server.url("/landing"),
+        );
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (on_open, on_click, on_complete) = (seen.clone(), seen.clone(), seen.clone());
+        let events = SimulationEvents::new()
+            .on_open(move |event| {
+                let seen = on_open.clone();
+                async move {
+                    let line = format!("open {} {}", event.to, event.pixels.len());
+                    seen.lock().unwrap().push(line);
+                }
+            })
+            .on_click(move |event| {
+                let seen = on_click.clone();
+                async move {
+                    let line = format!("click {} {}", event.message_id, event.click.succeeded());
+                    seen.lock().unwrap().push(line);
+                }
+            })
+            .on_complete(move |report| {
+                let seen = on_complete.clone();
+                async move {
+                    let line = format!("complete {}", report.status.as_str());
+                    seen.lock().unwrap().push(line);
+                }
+            });
+        let job = Job {
+            message_id: Some("msg-events".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html),
+            ..Default::default()
+        };
+
+        let simulator = Simulator::new(eager_config()).unwrap().with_events(events);
+        simulator.simulate(&job).await;
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                "open user@example.com 1",
+                "click msg-events true",
+                "complete completed",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_job_overrides_replace_config() {
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Shop</a></body></html>"#,
+            server.url("/open/track?id=1"),
+            server.url("/landing"),
+        );
+        let job: Job = SimulatorJob::builder()
+            .message_id("msg-overrides")
+            .to("user@example.com")
+            .html(html)
+            .click_probability(0.0)
+            .user_agent("TestAgent/1.0")
+            .seed(7)
+            .build()
+            .into();
+
+        let result = Simulator::new(eager_config()).unwrap().simulate(&job).await;
+
+        assert!(result.opened);
+        assert!(result.clicks.is_empty());
+        assert!(matches!(
+            result.skipped.as_slice(),
+            [Skip::ClickRoll { probability, .. }] if *probability == 0.0
+        ));
+        assert_eq!(result.persona.user_agent, "TestAgent/1.0");
+        assert_eq!(result.seed, 7);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_profile_applies_by_plus_tag() {
+        use crate::tenant::{TenantProfile, TenantProfiles};
+        use std::collections::HashMap;
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Shop</a></body></html>"#,
+            server.url("/open/track?id=1"),
+            server.url("/landing"),
+        );
+        let quiet = TenantProfile {
+            click_probability: Some(0.0),
+            quota_per_day: Some(2),
+            ..Default::default()
+        };
+        let profiles = HashMap::from([("quiet".to_string(), quiet)]);
+        let simulator = Simulator::new(eager_config())
+            .unwrap()
+            .with_profiles(TenantProfiles::new(profiles, Duration::from_secs(60)));
+        let job = |to: &str| Job {
+            message_id: Some("msg-tenant".to_string()),
+            to: to.to_string(),
+            html: Some(html.clone()),
+            ..Default::default()
+        };
+
+        let quiet = simulator.simulate(&job("user+Quiet@example.com")).await;
+        assert!(quiet.opened);
+        assert!(quiet.clicks.is_empty());
+
+        let other = simulator.simulate(&job("user+loud@example.com")).await;
+        assert_eq!(other.successful_clicks(), 1);
+
+        // Job overrides still win over the profile
+        let overridden = Job {
+            overrides: JobOverrides {
+                click_probability: Some(1.0),
+                ..Default::default()
+            },
+            ..job("user+quiet@example.com")
+        };
+        assert_eq!(simulator.simulate(&overridden).await.successful_clicks(), 1);
+
+        // The profile's quota applies without a TENANT_QUOTAS entry
+        let over = simulator.simulate(&job("user+quiet@example.com")).await;
+        assert_eq!(over.status, JobStatus::OverQuota);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_client_mix_sets_persona() {
+        use crate::tenant::{TenantProfile, TenantProfiles};
+        use crate::util::client::WeightedClient;
+        use std::collections::HashMap;
+
+        let mobile = TenantProfile {
+            client_mix: Some(vec!["gmail_android|80".to_string()]),
+            ..Default::default()
+        };
+        let profiles = HashMap::from([("mobile".to_string(), mobile)]);
+        let config = WorkerConfig {
+            client_mix: Some(vec![WeightedClient::parse("outlook_windows").unwrap()]),
+            ..eager_config()
+        };
+        let simulator = Simulator::new(config)
+            .unwrap()
+            .with_profiles(TenantProfiles::new(profiles, Duration::from_secs(60)));
+        let job = |to: &str| Job {
+            message_id: Some("msg-client".to_string()),
+            to: to.to_string(),
+            ..Default::default()
+        };
+
+        let persona = simulator
+            .simulate(&job("user+mobile@example.com"))
+            .await
+            .persona;
+        assert_eq!(persona.client, Some(ClientPersona::GmailAndroid));
+        assert_eq!(persona.user_agent_category, UserAgentCategory::Mobile);
+        assert_eq!(persona.user_agent, ClientPersona::GmailAndroid.user_agent());
+
+        let persona = simulator
+            .simulate(&job("user+other@example.com"))
+            .await
+            .persona;
+        assert_eq!(persona.client, Some(ClientPersona::OutlookWindows));
+
+        // A job's user agent override replaces the client
+        let overridden = Job {
+            overrides: JobOverrides {
+                user_agent: Some("TestAgent/1.0".to_string()),
+                ..Default::default()
+            },
+            ..job("user+mobile@example.com")
+        };
+        let persona = simulator.simulate(&overridden).await.persona;
+        assert_eq!(persona.client, None);
+        assert_eq!(persona.user_agent, "TestAgent/1.0");
+    }
+
+    #[tokio::test]
+    async fn test_ab_cohort_applies_its_settings() {
+        use crate::simulate::ab::AbCohort;
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"></body></html>"#,
+            server.url("/open/track?id=1")
+        );
+        let config = WorkerConfig {
+            ab_cohorts: Some(vec![AbCohort::parse("silent:1:0.0").unwrap()]),
+            ..eager_config()
+        };
+
+        let report = simulate(config, html).await;
+
+        assert_eq!(report.persona.ab_cohort.as_deref(), Some("silent"));
+        assert!(!report.opened);
+        assert!(matches!(
+            report.skipped.first(),
+            Some(Skip::OpenRoll { probability, .. }) if *probability == 0.0
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_open_probability_decays_with_age() {
+        use crate::simulate::decay::DecayCurve;
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"></body></html>"#,
+            server.url("/open/track?id=1")
+        );
+        let config = WorkerConfig {
+            open_decay_half_life_secs: 60,
+            open_decay_grace_secs: 60,
+            open_decay_curve: DecayCurve::Linear,
+            ..eager_config()
+        };
+        let simulator = Simulator::new(config).unwrap();
+        let now = message_age_secs(0);
+        let job = |received_at: u64| -> Job {
+            SimulatorJob::builder()
+                .message_id("msg-decay")
+                .to("user@example.com")
+                .html(html.clone())
+                .received_at(received_at)
+                .build()
+                .into()
+        };
+
+        let fresh = simulator.simulate(&job(now)).await;
+        assert!(fresh.opened);
+        assert_eq!(fresh.open_decay, Some(1.0));
+
+        let day_old = simulator.simulate(&job(now - 86_400)).await;
+        assert!(!day_old.opened);
+        assert!(day_old.message_age_secs >= Some(86_400));
+        assert_eq!(day_old.open_decay, Some(0.0));
+        assert!(matches!(
+            day_old.skipped.first(),
+            Some(Skip::OpenRoll { probability, .. }) if *probability == 0.0
+        ));
+
+        // A job that fixes its open probability is not decayed
+        let mut pinned = job(now - 86_400);
+        pinned.overrides.open_probability = Some(1.0);
+        let pinned = simulator.simulate(&pinned).await;
+        assert!(pinned.opened);
+        assert_eq!(pinned.open_decay, None);
+    }
+
+    #[tokio::test]
+    async fn test_old_message_is_expired() {
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"></body></html>"#,
+            server.url("/open/track?id=1")
+        );
+        let config = WorkerConfig {
+            max_message_age_secs: 3600,
+            ..eager_config()
+        };
+        let simulator = Simulator::new(config).unwrap();
+        let now = message_age_secs(0);
+        let job = Job {
+            message_id: Some("msg-expired".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html),
+            ..Default::default()
+        };
+
+        let fresh = simulator
+            .simulate(&Job {
+                received_at: Some(now - 60),
+                ..job.clone()
+            })
+            .await;
+        assert_eq!(fresh.status, JobStatus::Completed);
+        assert!(fresh.opened);
+
+        // Without the provider's timestamp, the web server's is used
+        let stale = simulator
+            .simulate(&Job {
+                received_at_ms: Some((now - 86_400) * 1000),
+                ..job.clone()
+            })
+            .await;
+        assert_eq!(stale.status, JobStatus::Expired);
+        assert!(!stale.opened);
+        assert!(stale.pixels.is_empty());
+        assert!(matches!(
+            stale.skipped.as_slice(),
+            [Skip::Expired { age_secs, max_age_secs: 3600 }] if *age_secs >= 86_400
+        ));
+
+        // Jobs without a timestamp never expire
+        assert_eq!(simulator.simulate(&job).await.status, JobStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_calendar_scales_probabilities() {
+        use crate::simulate::calendar::CalendarEntry;
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"><a href="{}">Shop</a></body></html>"#,
+            server.url("/open/track?id=1"),
+            server.url("/landing"),
+        );
+        // Every day is a holiday
+        let calendar = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"]
+            .iter()
+            .filter_map(|day| CalendarEntry::parse(&format!("{}:0.0", day)))
+            .collect();
+        let config = WorkerConfig {
+            calendar: Some(calendar),
+            ..eager_config()
+        };
+
+        let report = simulate(config.clone(), html.clone()).await;
+        assert_eq!(report.calendar_factor, Some(0.0));
+        assert!(!report.opened);
+        assert!(matches!(
+            report.skipped.as_slice(),
+            [
+                Skip::OpenRoll { probability: open, .. },
+                Skip::ClickRoll { probability: click, .. },
+            ] if *open == 0.0 && *click == 0.0
+        ));
+
+        // A job that fixes its open probability is not scaled
+        let job: Job = SimulatorJob::builder()
+            .message_id("msg-calendar")
+            .to("user@example.com")
+            .html(html)
+            .open_probability(1.0)
+            .build()
+            .into();
+        let report = Simulator::new(config).unwrap().simulate(&job).await;
+        assert!(report.opened);
+        assert_eq!(report.successful_clicks(), 0);
+        assert_eq!(report.open_probability, Some(1.0));
+        assert_eq!(report.click_probability, Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn test_recipient_outside_active_hours_is_deferred() {
+        use crate::simulate::timezone::ZoneOffset;
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"></body></html>"#,
+            server.url("/open/track?id=1")
+        );
+        // Recipients at UTC+0 are active in whichever half of the day it is not
+        let hour = local_hour(message_age_secs(0), 0);
+        let config = WorkerConfig {
+            timezone_domains: Some(vec![ZoneOffset::parse("example.com:0").unwrap()]),
+            recipient_active_hours: Some(if hour < 12 { (12, 24) } else { (0, 12) }),
+            ..eager_config()
+        };
+
+        let report = simulate(config.clone(), html.clone()).await;
+        assert_eq!(report.status, JobStatus::Deferred);
+        assert_eq!(report.persona.utc_offset_mins, Some(0));
+        assert!(report.pixels.is_empty());
+        assert!(matches!(
+            report.skipped.as_slice(),
+            [Skip::ActiveHours { resume_after_secs, .. }] if *resume_after_secs > 0
+        ));
+
+        // Recipients without a known time zone are not deferred
+        let config = WorkerConfig {
+            timezone_domains: None,
+            ..config
+        };
+        let report = simulate(config, html).await;
+        assert_eq!(report.status, JobStatus::Completed);
+        assert_eq!(report.persona.utc_offset_mins, None);
+        assert!(report.opened);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_over_quota_is_not_simulated() {
+        use crate::tenant::quota::{QuotaWindow, TenantQuota};
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><img src="{}"></body></html>"#,
+            server.url("/open/track?id=1")
+        );
+        let config = WorkerConfig {
+            tenant_quotas: Some(vec![TenantQuota::parse("acme:1:").unwrap()]),
+            ..eager_config()
+        };
+        let simulator = Simulator::new(config).unwrap();
+        let job = |to: &str| Job {
+            message_id: Some("msg-quota".to_string()),
+            to: to.to_string(),
+            html: Some(html.clone()),
+            ..Default::default()
+        };
+
+        let first = simulator.simulate(&job("user+acme@example.com")).await;
+        assert_eq!(first.status, JobStatus::Completed);
+        assert!(first.opened);
+
+        let second = simulator.simulate(&job("other+ACME@example.com")).await;
+        assert_eq!(second.status, JobStatus::OverQuota);
+        assert!(second.pixels.is_empty());
+        assert!(matches!(
+            second.skipped.as_slice(),
+            [Skip::Quota {
+                window: QuotaWindow::Hour,
+                limit: 1,
+                ..
+            }]
+        ));
+
+        // Tenants without a quota are not limited
+        let other = simulator.simulate(&job("user+beta@example.com")).await;
+        assert_eq!(other.status, JobStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_campaign_override_wins() {
+        use crate::campaign::overrides::{CampaignOverride, MemoryOverrides, OverrideStore};
+
+        let server = MockServer::start().await.unwrap();
+        let html = format!(
+            r#"<html><body><div data-scope="global" data-click-rate="0"></div>
+            <img src="{}"><a href="{}">Other</a><a href="{}">Buy</a></body></html>"#,
+            server.url("/open/track?j=777"),
+            server.url("/other"),
+            server.url("/cta"),
+        );
+        let store = Arc::new(MemoryOverrides::default());
+        let cta_only = CampaignOverride {
+            click_probability: Some(1.0),
+            max_clicks: Some(5),
+            click_only: Some(vec!["/cta".to_string()]),
+            ..Default::default()
+        };
+        store.save("777", &cta_only).await.unwrap();
+        let simulator = Simulator::new(eager_config())
+            .unwrap()
+            .with_campaign_overrides(CampaignOverrides::new(store, Duration::from_secs(60)));
+
+        let job = Job {
+            message_id: Some("msg-campaign".to_string()),
+            to: "user@example.com".to_string(),
+            html: Some(html),
+            ..Default::default()
+        };
+        let report = simulator.simulate(&job).await;
+
+        assert_eq!(report.campaign_id.as_deref(), Some("777"));
+        // Links are picked with replacement, up to the override's max_clicks
+        assert_eq!(report.clicks.len(), 5);
+        assert!(report.clicks.iter().all(|c| c.url == server.url("/cta")));
+    }
+}
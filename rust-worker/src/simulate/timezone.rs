@@ -0,0 +1,157 @@
+//! Recipient time zones and active hours.
+//!
+//! A recipient's offset from UTC comes from `TIMEZONE_TAGS` by the plus tag
+//! of their address, else from `TIMEZONE_DOMAINS` by its domain, else from
+//! the tenant profile's `utc_offset_mins` or `RECIPIENT_UTC_OFFSET_MINS`.
+//! With `RECIPIENT_ACTIVE_HOURS` set, a job arriving outside the
+//! recipient's local active hours is deferred to the start of their next
+//! active window, spread over its first hour, so engagement follows each
+//! recipient's day instead of clustering in the server's.
+
+use crate::config::WorkerConfig;
+use crate::simulate::calendar::MAX_UTC_OFFSET_MINS;
+use crate::simulate::simulator::extract_plus_tag;
+
+const SECS_PER_HOUR: u64 = 3600;
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Longest spread of deferred jobs after the start of an active window, so
+/// recipients do not all open at the top of the hour.
+pub const WAKE_SPREAD_SECS: u64 = SECS_PER_HOUR;
+
+/// A plus tag or domain with the offset from UTC of its recipients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneOffset {
+    pub name: String,
+    /// Offset from UTC in minutes, e.g. `-300` for US Eastern standard time
+    pub offset_mins: i32,
+}
+
+impl ZoneOffset {
+    /// Parse an entry of the form `name:offset_mins`, e.g. `example.de:60`.
+    ///
+    /// Returns `None` for empty names and offsets over 14 hours.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (name, offset) = entry.trim().rsplit_once(':')?;
+        let name = name.trim().to_lowercase();
+        let offset_mins: i32 = offset.trim().parse().ok()?;
+        if name.is_empty() || offset_mins.abs() > MAX_UTC_OFFSET_MINS {
+            return None;
+        }
+        Some(Self { name, offset_mins })
+    }
+
+    /// Whether `domain` is this entry's domain or one of its subdomains.
+    fn matches_domain(&self, domain: &str) -> bool {
+        domain == self.name
+            || domain
+                .strip_suffix(self.name.as_str())
+                .is_some_and(|rest| rest.ends_with('.'))
+    }
+}
+
+/// Offset from UTC in minutes of recipient `to`, if known.
+///
+/// A plus tag mapping wins over a domain mapping, which wins over the
+/// default; among domains the most specific one matches.
+pub fn offset_for(config: &WorkerConfig, to: &str) -> Option<i32> {
+    let by_tag = extract_plus_tag(to).and_then(|tag| {
+        let tag = tag.to_lowercase();
+        config
+            .timezone_tags
+            .as_deref()?
+            .iter()
+            .find(|zone| zone.name == tag)
+    });
+    let by_domain = || {
+        let domain = to.rsplit_once('@')?.1.trim().to_lowercase();
+        config
+            .timezone_domains
+            .as_deref()?
+            .iter()
+            .filter(|zone| zone.matches_domain(&domain))
+            .max_by_key(|zone| zone.name.len())
+    };
+    by_tag
+        .or_else(by_domain)
+        .map(|zone| zone.offset_mins)
+        .or(config.recipient_utc_offset_mins)
+}
+
+/// Hour of the day (0 - 23) at `now_secs` (Unix seconds) and
+/// `offset_mins` from UTC.
+pub fn local_hour(now_secs: u64, offset_mins: i32) -> u64 {
+    local_secs_of_day(now_secs, offset_mins) / SECS_PER_HOUR
+}
+
+/// Seconds from `now_secs` (Unix seconds) until the active hours
+/// `(start, end)` next begin at `offset_mins` from UTC, or `None` when the
+/// recipient is already in them.
+///
+/// Active hours run from the start of hour `start` to the start of hour
+/// `end`, e.g. `(7, 23)` is 07:00 to 23:00 local time. An empty window
+/// counts as always active.
+pub fn until_active(now_secs: u64, offset_mins: i32, (start, end): (u64, u64)) -> Option<u64> {
+    let now = local_secs_of_day(now_secs, offset_mins);
+    let (start, end) = (start * SECS_PER_HOUR, end * SECS_PER_HOUR);
+    if start >= end || (start..end).contains(&now) {
+        return None;
+    }
+    Some(if now < start {
+        start - now
+    } else {
+        SECS_PER_DAY - now + start
+    })
+}
+
+fn local_secs_of_day(now_secs: u64, offset_mins: i32) -> u64 {
+    let local = now_secs as i64 + i64::from(offset_mins) * 60;
+    local.rem_euclid(SECS_PER_DAY as i64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_for() {
+        let config = WorkerConfig {
+            timezone_tags: Some(vec![ZoneOffset::parse("apac:480").unwrap()]),
+            timezone_domains: Some(vec![
+                ZoneOffset::parse("de:60").unwrap(),
+                ZoneOffset::parse("Example.DE:+120").unwrap(),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(offset_for(&config, "user+APAC@example.de"), Some(480));
+        assert_eq!(offset_for(&config, "user@mail.example.de"), Some(120));
+        assert_eq!(offset_for(&config, "user@shop.de"), Some(60));
+        assert_eq!(offset_for(&config, "user@example.com"), None);
+
+        let config = WorkerConfig {
+            recipient_utc_offset_mins: Some(-300),
+            ..config
+        };
+        assert_eq!(offset_for(&config, "user@example.com"), Some(-300));
+
+        assert_eq!(ZoneOffset::parse(":60"), None);
+        assert_eq!(ZoneOffset::parse("de:900"), None);
+        assert_eq!(ZoneOffset::parse("de"), None);
+    }
+
+    #[test]
+    fn test_until_active() {
+        // 2024-01-01T06:30:00Z
+        let now = 1_704_090_600;
+        assert_eq!(local_hour(now, 0), 6);
+        assert_eq!(until_active(now, 0, (7, 23)), Some(1800));
+        // 07:30 in UTC+1 is already active
+        assert_eq!(until_active(now, 60, (7, 23)), None);
+        // 23:30 the day before in UTC-7 waits until 07:00
+        assert_eq!(local_hour(now, -420), 23);
+        assert_eq!(until_active(now, -420, (7, 23)), Some(7 * 3600 + 1800));
+        assert_eq!(until_active(now, 0, (0, 24)), None);
+        assert_eq!(until_active(now, 0, (9, 9)), None);
+    }
+}
@@ -0,0 +1,258 @@
+//! Where worker time goes, stage by stage.
+//!
+//! The latency histograms say how long jobs take end to end; they can't say
+//! whether a slowdown came from the queue, HTML parsing, the fetches or the
+//! delays. [`StageTimings`] keeps each stage's time for the last
+//! [`CAPACITY`] jobs from their reports, and the worker's sidecar serves the
+//! [`TimingBreakdown`] of any number of them at `GET /timings?jobs=200`, so a
+//! regression can be narrowed down to a stage without a profiler.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use super::report::SimulationReport;
+
+/// Jobs whose stage times are kept.
+pub const CAPACITY: usize = 1000;
+
+/// A stage of a job's time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Simulator job publish to the worker starting it
+    QueueWait,
+    /// Parsing the email's HTML
+    Analysis,
+    /// Pixel and image fetches
+    OpenFetches,
+    /// Open and click delays waited out
+    Delays,
+    /// Link click fetches
+    ClickFetches,
+    /// The rest of the simulation: fetching stored HTML, tenant and
+    /// campaign lookups, link scans
+    Other,
+}
+
+impl Stage {
+    /// Every stage, in the order a job passes through them.
+    pub const ALL: [Stage; 6] = [
+        Stage::QueueWait,
+        Stage::Analysis,
+        Stage::OpenFetches,
+        Stage::Delays,
+        Stage::ClickFetches,
+        Stage::Other,
+    ];
+
+}
+
+/// Milliseconds a job spent in each of [`Stage::ALL`], where known.
+pub type StageTimes = [Option<u64>; 6];
+
+/// Each stage's time in `report`.
+///
+/// Queue wait and the remainder need the worker's
+/// [`latency`](SimulationReport::latency); analysis needs the HTML to have
+/// been parsed. Fetch and delay stages are zero for jobs that made none.
+pub fn stage_times(report: &SimulationReport) -> StageTimes {
+    let open_fetches: u64 = report.pixels.iter().map(|p| p.latency_ms).sum();
+    let click_fetches: u64 = report.clicks.iter().map(|c| c.latency_ms).sum();
+    let delays = report.open_delay_ms.unwrap_or(0)
+        + report
+            .pixels
+            .iter()
+            .chain(&report.clicks)
+            .map(|f| f.delay_ms)
+            .sum::<u64>();
+    let accounted = report.analysis_ms.unwrap_or(0) + open_fetches + delays + click_fetches;
+    let latency = report.latency.as_ref();
+    [
+        latency.and_then(|l| l.queue_ms),
+        report.analysis_ms,
+        Some(open_fetches),
+        Some(delays),
+        Some(click_fetches),
+        latency.map(|l| l.simulate_ms.saturating_sub(accounted)),
+    ]
+}
+
+/// One stage over the jobs of a breakdown.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageSummary {
+    /// Which stage
+    pub stage: Stage,
+    /// Jobs the stage's time is known for
+    pub jobs: usize,
+    /// Time spent in the stage across those jobs
+    pub total_ms: u64,
+    /// Mean time per job
+    pub mean_ms: u64,
+    /// Median time per job
+    pub p50_ms: u64,
+    /// 95th percentile time per job
+    pub p95_ms: u64,
+    /// Longest time a job spent in the stage
+    pub max_ms: u64,
+    /// Share of the time of every stage spent in this one
+    pub share: f64,
+}
+
+/// Time per stage over recent jobs, served at `GET /timings`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimingBreakdown {
+    /// Jobs the breakdown covers, most recent
+    pub jobs: usize,
+    /// Every stage, in [`Stage::ALL`] order
+    pub stages: Vec<StageSummary>,
+}
+
+/// Stage times of the last [`CAPACITY`] jobs.
+#[derive(Debug, Default)]
+pub struct StageTimings {
+    recent: Mutex<VecDeque<StageTimes>>,
+}
+
+impl StageTimings {
+    /// Timings without any jobs yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a finished job's stage times, forgetting the oldest job's once
+    /// [`CAPACITY`] are kept.
+    pub fn record(&self, report: &SimulationReport) {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if recent.len() == CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(stage_times(report));
+    }
+
+    /// Breakdown of the last `jobs` jobs, or of every job kept.
+    pub fn breakdown(&self, jobs: Option<usize>) -> TimingBreakdown {
+        let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let jobs = jobs.map_or(recent.len(), |jobs| jobs.min(recent.len()));
+        let window: Vec<&StageTimes> = recent.iter().skip(recent.len() - jobs).collect();
+
+        let mut stages: Vec<StageSummary> = Stage::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, &stage)| {
+                let mut times: Vec<u64> = window.iter().filter_map(|t| t[i]).collect();
+                times.sort_unstable();
+                summarize(stage, &times)
+            })
+            .collect();
+        let total: u64 = stages.iter().map(|s| s.total_ms).sum();
+        if total > 0 {
+            for stage in &mut stages {
+                stage.share = stage.total_ms as f64 / total as f64;
+            }
+        }
+        TimingBreakdown { jobs, stages }
+    }
+}
+
+/// Summary of `stage` from its times, sorted.
+fn summarize(stage: Stage, sorted: &[u64]) -> StageSummary {
+    let total_ms: u64 = sorted.iter().sum();
+    let percentile = |p: f64| match sorted.len() {
+        0 => 0,
+        n => sorted[((n as f64 * p).ceil() as usize).clamp(1, n) - 1],
+    };
+    StageSummary {
+        stage,
+        jobs: sorted.len(),
+        total_ms,
+        mean_ms: total_ms.checked_div(sorted.len() as u64).unwrap_or(0),
+        p50_ms: percentile(0.5),
+        p95_ms: percentile(0.95),
+        max_ms: sorted.last().copied().unwrap_or(0),
+        share: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::latency::Latency;
+    use crate::simulate::report::FetchReport;
+    use crate::simulate::simulator::{Job, Simulator};
+    use crate::config::WorkerConfig;
+
+    async fn report() -> SimulationReport {
+        let job = Job {
+            to: "user@example.com".to_string(),
+            html: Some("<html><body><p>No links</p></body></html>".to_string()),
+            ..Job::default()
+        };
+        Simulator::new(WorkerConfig::default())
+            .unwrap()
+            .without_delays()
+            .simulate(&job)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_stage_times() {
+        let mut report = report().await;
+        report.analysis_ms = Some(5);
+        report.open_delay_ms = Some(1_000);
+        let fetch = |latency_ms, delay_ms| FetchReport {
+            latency_ms,
+            delay_ms,
+            ..FetchReport::default()
+        };
+        report.pixels = vec![fetch(40, 0), fetch(10, 0)];
+        report.clicks = vec![fetch(100, 2_000), fetch(200, 500)];
+
+        // Without the worker's latency, only the simulator's own stages
+        assert_eq!(
+            stage_times(&report),
+            [None, Some(5), Some(50), Some(3_500), Some(300), None]
+        );
+
+        report.latency = Some(Latency::measure(None, Some(0), 250, 4_250));
+        assert_eq!(
+            stage_times(&report),
+            [Some(250), Some(5), Some(50), Some(3_500), Some(300), Some(145)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_breakdown_of_recent_jobs() {
+        let timings = StageTimings::new();
+        assert_eq!(timings.breakdown(None).jobs, 0);
+        assert!(timings.breakdown(None).stages.iter().all(|s| s.share == 0.0));
+
+        let mut report = report().await;
+        report.open_delay_ms = Some(0);
+        for queue_ms in 1..=CAPACITY as u64 + 10 {
+            report.latency = Some(Latency {
+                queue_ms: Some(queue_ms),
+                simulate_ms: 0,
+                ..Latency::default()
+            });
+            report.analysis_ms = Some(queue_ms);
+            timings.record(&report);
+        }
+
+        let all = timings.breakdown(None);
+        assert_eq!(all.jobs, CAPACITY);
+        let queue = &all.stages[0];
+        assert_eq!(queue.stage, Stage::QueueWait);
+        assert_eq!((queue.jobs, queue.max_ms), (CAPACITY, CAPACITY as u64 + 10));
+        assert_eq!(queue.p50_ms, 510);
+        assert_eq!(queue.p95_ms, 960);
+        assert!((queue.share - 0.5).abs() < 1e-9);
+
+        let last = timings.breakdown(Some(4));
+        assert_eq!(last.jobs, 4);
+        let queue = &last.stages[0];
+        assert_eq!((queue.total_ms, queue.mean_ms, queue.p50_ms), (4_034, 1_008, 1_008));
+        assert_eq!(timings.breakdown(Some(5_000)).jobs, CAPACITY);
+    }
+}
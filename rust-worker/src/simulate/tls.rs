@@ -0,0 +1,238 @@
+//! TLS policy for the simulation HTTP client.
+//!
+//! - `TLS_CA_BUNDLE`: PEM file of extra trusted roots, for staging targets
+//!   behind a private CA
+//! - `TLS_CERT_PINS`: `domain:sha256` entries; responses from a pinned domain
+//!   (or its subdomains) must come from a leaf certificate whose DER SHA-256
+//!   matches, or the fetch fails
+//! - `TLS_INSECURE`: accept any certificate, for lab environments only
+//!
+//! Pins are checked once the response headers arrive, because the client
+//! has no hook into the handshake itself: a mismatched request has been sent
+//! but its response is discarded and the fetch counts as failed.
+
+use std::fs;
+
+use reqwest::tls::TlsInfo;
+use reqwest::{Certificate, ClientBuilder, Response};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::{env_var, parse_bool, parse_entries, VarSource};
+
+/// Expected leaf certificate fingerprint for a domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertPin {
+    /// Domain the pin applies to, including its subdomains
+    pub domain: String,
+    /// Lowercase hex SHA-256 of the DER-encoded leaf certificate
+    pub sha256: String,
+}
+
+impl CertPin {
+    /// Parse an entry of the form `domain:sha256hex`.
+    ///
+    /// The fingerprint may use `:` separators, as printed by
+    /// `openssl x509 -noout -fingerprint -sha256`.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (domain, fingerprint) = entry.trim().split_once(':')?;
+        let domain = domain.trim().trim_start_matches('.').to_lowercase();
+        let sha256: String = fingerprint
+            .trim()
+            .chars()
+            .filter(|c| *c != ':')
+            .collect::<String>()
+            .to_lowercase();
+
+        if domain.is_empty() || sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        Some(Self { domain, sha256 })
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        host == self.domain
+            || host
+                .strip_suffix(self.domain.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    }
+}
+
+/// Why a pinned response was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PinError {
+    #[error("certificate for {host} does not match the pin for {domain}")]
+    Mismatch { host: String, domain: String },
+    #[error("no certificate presented by pinned host {host}")]
+    NoCertificate { host: String },
+}
+
+/// Error applying the TLS policy to the HTTP client.
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("failed to read CA bundle {path}: {source}")]
+    ReadBundle {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid CA bundle {path}: {source}")]
+    InvalidBundle {
+        path: String,
+        source: reqwest::Error,
+    },
+}
+
+/// TLS settings for outbound simulation fetches.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsPolicy {
+    /// Path to a PEM bundle of extra trusted root certificates
+    pub ca_bundle: Option<String>,
+    /// Per-domain leaf certificate pins
+    pub pins: Vec<CertPin>,
+    /// Accept invalid certificates (lab environments only)
+    pub insecure: bool,
+}
+
+impl TlsPolicy {
+    /// Load TLS settings from the environment.
+    pub fn from_env() -> Self {
+        Self::from_vars(&env_var)
+    }
+
+    /// Load TLS settings from an arbitrary variable lookup.
+    pub fn from_vars(var: &VarSource<'_>) -> Self {
+        Self {
+            ca_bundle: var("TLS_CA_BUNDLE"),
+            pins: parse_entries(var, "TLS_CERT_PINS", CertPin::parse).unwrap_or_default(),
+            insecure: parse_bool(var, "TLS_INSECURE").unwrap_or(false),
+        }
+    }
+
+    /// Apply the policy to a client builder.
+    pub fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder, TlsError> {
+        if let Some(path) = &self.ca_bundle {
+            let pem = fs::read(path).map_err(|source| TlsError::ReadBundle {
+                path: path.clone(),
+                source,
+            })?;
+            let roots = Certificate::from_pem_bundle(&pem).map_err(|source| {
+                TlsError::InvalidBundle {
+                    path: path.clone(),
+                    source,
+                }
+            })?;
+            info!(path = %path, certificates = roots.len(), "tls_ca_bundle_loaded");
+            for root in roots {
+                builder = builder.add_root_certificate(root);
+            }
+        }
+
+        if !self.pins.is_empty() {
+            let domains: Vec<&str> = self.pins.iter().map(|p| p.domain.as_str()).collect();
+            info!(domains = ?domains, "tls_cert_pins_enabled");
+            builder = builder.tls_info(true);
+        }
+
+        if self.insecure {
+            warn!(
+                warning = "certificate validation is disabled for simulation fetches",
+                "tls_insecure_mode_enabled"
+            );
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder)
+    }
+}
+
+/// Check a response against the pin for its host, if any.
+pub fn verify_pin(pins: &[CertPin], response: &Response) -> Result<(), PinError> {
+    let Some(host) = response.url().host_str() else {
+        return Ok(());
+    };
+    let Some(pin) = pins.iter().find(|p| p.matches(host)) else {
+        return Ok(());
+    };
+
+    let certificate = response
+        .extensions()
+        .get::<TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .ok_or_else(|| PinError::NoCertificate {
+            host: host.to_string(),
+        })?;
+
+    if hex::encode(Sha256::digest(certificate)) == pin.sha256 {
+        Ok(())
+    } else {
+        Err(PinError::Mismatch {
+            host: host.to_string(),
+            domain: pin.domain.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn test_cert_pin_parse() {
+        let pin = CertPin::parse(&format!(" Example.com : {} ", FINGERPRINT.to_uppercase())).unwrap();
+        assert_eq!(pin.domain, "example.com");
+        assert_eq!(pin.sha256, FINGERPRINT);
+
+        let colons: Vec<String> = FINGERPRINT
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| String::from_utf8_lossy(pair).into_owned())
+            .collect();
+        let pin = CertPin::parse(&format!("example.com:{}", colons.join(":"))).unwrap();
+        assert_eq!(pin.sha256, FINGERPRINT);
+
+        assert_eq!(CertPin::parse("example.com:abc123"), None);
+        assert_eq!(CertPin::parse(&format!(":{}", FINGERPRINT)), None);
+        assert_eq!(CertPin::parse("example.com"), None);
+    }
+
+    #[test]
+    fn test_cert_pin_matches_subdomains() {
+        let pin = CertPin::parse(&format!("example.com:{}", FINGERPRINT)).unwrap();
+        assert!(pin.matches("example.com"));
+        assert!(pin.matches("click.example.com"));
+        assert!(!pin.matches("badexample.com"));
+        assert!(!pin.matches("example.org"));
+    }
+
+    #[test]
+    fn test_policy_from_vars() {
+        let pins = format!("example.com:{}", FINGERPRINT);
+        let var = |name: &str| match name {
+            "TLS_CA_BUNDLE" => Some("/etc/ssl/staging.pem".to_string()),
+            "TLS_CERT_PINS" => Some(pins.clone()),
+            "TLS_INSECURE" => Some("true".to_string()),
+            _ => None,
+        };
+        let policy = TlsPolicy::from_vars(&var);
+        assert_eq!(policy.ca_bundle.as_deref(), Some("/etc/ssl/staging.pem"));
+        assert_eq!(policy.pins.len(), 1);
+        assert!(policy.insecure);
+
+        assert_eq!(TlsPolicy::from_vars(&|_: &str| None), TlsPolicy::default());
+    }
+
+    #[test]
+    fn test_apply_reports_missing_bundle() {
+        let policy = TlsPolicy {
+            ca_bundle: Some("/nonexistent/bundle.pem".to_string()),
+            ..TlsPolicy::default()
+        };
+        assert!(matches!(
+            policy.apply(reqwest::Client::builder()),
+            Err(TlsError::ReadBundle { .. })
+        ));
+    }
+}
@@ -0,0 +1,43 @@
+//! Images wrapped in links.
+//!
+//! A creative such as `<a href="..."><img src="..."></a>` is both an image
+//! the open fetches and a link a click may follow, so one reader's
+//! interaction with it can be counted twice. `WRAPPED_IMAGES` picks how
+//! such creatives are treated; the default keeps both, as before.
+
+use serde::Serialize;
+
+/// How an image inside a link is simulated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrappedImagePolicy {
+    /// The image is fetched on open and its link may be clicked
+    #[default]
+    Both,
+    /// The image is fetched on open; its link is never clicked
+    Open,
+    /// The link may be clicked; the image is not fetched on open, unless it
+    /// is a tracking pixel
+    Click,
+}
+
+impl WrappedImagePolicy {
+    /// Parse `both`, `open` or `click`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "both" => Some(WrappedImagePolicy::Both),
+            "open" => Some(WrappedImagePolicy::Open),
+            "click" => Some(WrappedImagePolicy::Click),
+            _ => None,
+        }
+    }
+
+    /// Stable name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WrappedImagePolicy::Both => "both",
+            WrappedImagePolicy::Open => "open",
+            WrappedImagePolicy::Click => "click",
+        }
+    }
+}
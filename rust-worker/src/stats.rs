@@ -0,0 +1,317 @@
+//! Rolling simulation outcome statistics.
+//!
+//! The worker records each job's outcome into per-minute buckets kept for
+//! [`RETENTION`]. Its sidecar serves a [`StatsSummary`] over any window of
+//! that at `GET /stats?since=1h`, and `bobnet-cli stats` and the web
+//! server's `/dashboard` merge the summaries of every worker (see
+//! [`fetch_summary`]). Windows matter during test sends: counters since
+//! process start would bury a send among everything before it.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// How far back outcomes are kept.
+pub const RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+const BUCKET_SECS: u64 = 60;
+
+/// Outcome of one simulated job, as recorded.
+#[derive(Debug, Clone, Copy)]
+pub struct Outcome<'a> {
+    /// Final job status, e.g. `completed` or `html_rejected`
+    pub status: &'a str,
+    /// Whether the open was simulated successfully
+    pub opened: bool,
+    /// Domain of each successful click
+    pub clicked_domains: &'a [String],
+    /// Clicks that failed
+    pub click_errors: usize,
+}
+
+/// Outcome totals over a window.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsSummary {
+    /// Length of the window in seconds
+    pub window_secs: u64,
+    /// Jobs simulated
+    pub jobs: u64,
+    /// Jobs whose open was simulated
+    pub opened: u64,
+    /// Jobs with at least one successful click
+    pub clicked: u64,
+    /// Successful clicks
+    pub clicks: u64,
+    /// Clicks that failed to fetch or returned an error status
+    pub click_errors: u64,
+    /// Deliveries rejected because they were not valid jobs
+    pub rejected: u64,
+    /// Jobs per final status
+    #[serde(default)]
+    pub statuses: BTreeMap<String, u64>,
+    /// Successful clicks per domain
+    #[serde(default)]
+    pub domains: BTreeMap<String, u64>,
+}
+
+impl StatsSummary {
+    /// Share of jobs that were opened, or `None` without jobs.
+    pub fn open_rate(&self) -> Option<f64> {
+        rate(self.opened, self.jobs)
+    }
+
+    /// Share of jobs with at least one click, or `None` without jobs.
+    pub fn click_rate(&self) -> Option<f64> {
+        rate(self.clicked, self.jobs)
+    }
+
+    /// The `n` most clicked domains, most clicked first.
+    pub fn top_domains(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut domains: Vec<(&str, u64)> = self
+            .domains
+            .iter()
+            .map(|(domain, &count)| (domain.as_str(), count))
+            .collect();
+        domains.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        domains.truncate(n);
+        domains
+    }
+
+    /// Add another summary's totals, e.g. from another worker.
+    pub fn merge(&mut self, other: &StatsSummary) {
+        self.window_secs = self.window_secs.max(other.window_secs);
+        self.jobs += other.jobs;
+        self.opened += other.opened;
+        self.clicked += other.clicked;
+        self.clicks += other.clicks;
+        self.click_errors += other.click_errors;
+        self.rejected += other.rejected;
+        for (status, count) in &other.statuses {
+            *self.statuses.entry(status.clone()).or_default() += count;
+        }
+        for (domain, count) in &other.domains {
+            *self.domains.entry(domain.clone()).or_default() += count;
+        }
+    }
+
+    fn add(&mut self, outcome: &Outcome<'_>) {
+        self.jobs += 1;
+        self.opened += u64::from(outcome.opened);
+        self.clicked += u64::from(!outcome.clicked_domains.is_empty());
+        self.clicks += outcome.clicked_domains.len() as u64;
+        self.click_errors += outcome.click_errors as u64;
+        *self.statuses.entry(outcome.status.to_string()).or_default() += 1;
+        for domain in outcome.clicked_domains {
+            *self.domains.entry(domain.clone()).or_default() += 1;
+        }
+    }
+}
+
+fn rate(count: u64, total: u64) -> Option<f64> {
+    if total == 0 {
+        None
+    } else {
+        Some(count as f64 / total as f64)
+    }
+}
+
+struct Bucket {
+    /// Minutes since the Unix epoch
+    minute: u64,
+    totals: StatsSummary,
+}
+
+/// Per-minute outcome totals for the last [`RETENTION`].
+#[derive(Default)]
+pub struct SimulationStats {
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+impl SimulationStats {
+    /// Create empty statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a simulated job.
+    pub fn record(&self, outcome: &Outcome<'_>) {
+        self.update(current_minute(), |totals| totals.add(outcome));
+    }
+
+    /// Record a delivery rejected because it was not a valid job.
+    pub fn record_rejected(&self) {
+        self.update(current_minute(), |totals| totals.rejected += 1);
+    }
+
+    /// Totals over the last `since`, rounded up to whole minutes and capped
+    /// at [`RETENTION`].
+    pub fn summary(&self, since: Duration) -> StatsSummary {
+        self.summary_at(current_minute(), since)
+    }
+
+    fn update(&self, minute: u64, apply: impl FnOnce(&mut StatsSummary)) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.back().is_none_or(|b| b.minute < minute) {
+            buckets.push_back(Bucket {
+                minute,
+                totals: StatsSummary::default(),
+            });
+        }
+        // The clock may step back; count into the newest bucket then
+        if let Some(bucket) = buckets.back_mut() {
+            apply(&mut bucket.totals);
+        }
+
+        let oldest = minute.saturating_sub(RETENTION.as_secs() / BUCKET_SECS);
+        while buckets.front().is_some_and(|b| b.minute <= oldest) {
+            buckets.pop_front();
+        }
+    }
+
+    fn summary_at(&self, minute: u64, since: Duration) -> StatsSummary {
+        let window = since.min(RETENTION).as_secs().div_ceil(BUCKET_SECS);
+        let oldest = minute.saturating_sub(window);
+
+        let mut summary = StatsSummary::default();
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        for bucket in buckets.iter().filter(|b| b.minute > oldest) {
+            summary.merge(&bucket.totals);
+        }
+        summary.window_secs = window * BUCKET_SECS;
+        summary
+    }
+}
+
+fn current_minute() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / BUCKET_SECS
+}
+
+/// Fetch one worker's summary over the last `since` from its sidecar at
+/// `base`, presenting `api_key` when the workers set `API_KEYS`.
+pub async fn fetch_summary(
+    client: &Client,
+    base: &str,
+    since: Duration,
+    api_key: Option<&str>,
+) -> Result<StatsSummary> {
+    let url = format!("{}/stats?since={}s", base.trim_end_matches('/'), since.as_secs());
+    let mut request = client.get(&url);
+    if let Some(key) = api_key {
+        request = request.bearer_auth(key);
+    }
+
+    let response = request.send().await.context("unreachable")?;
+    let status = response.status();
+    let body = response.text().await.context("Failed to read response")?;
+    if !status.is_success() {
+        anyhow::bail!("{} returned {}: {}", url, status, body.trim());
+    }
+    serde_json::from_str(&body).with_context(|| format!("{} returned an invalid summary", url))
+}
+
+/// Parse a window such as `90s`, `15m`, `1h` or `2d`; a bare number is
+/// seconds.
+pub fn parse_window(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(number.checked_mul(secs)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome<'a>(opened: bool, clicked_domains: &'a [String]) -> Outcome<'a> {
+        Outcome {
+            status: "completed",
+            opened,
+            clicked_domains,
+            click_errors: 0,
+        }
+    }
+
+    #[test]
+    fn test_summary_window() {
+        let stats = SimulationStats::new();
+        let domains = vec!["click.example.com".to_string(), "shop.example.com".to_string()];
+
+        // Two hours ago, then within the last hour
+        stats.update(1_000, |t| t.add(&outcome(true, &domains)));
+        stats.update(1_100, |t| t.add(&outcome(true, &domains[..1])));
+        stats.update(1_110, |t| t.add(&outcome(false, &[])));
+        stats.update(1_110, |t| t.rejected += 1);
+
+        let hour = stats.summary_at(1_120, Duration::from_secs(3600));
+        assert_eq!(hour.window_secs, 3600);
+        assert_eq!(hour.jobs, 2);
+        assert_eq!(hour.opened, 1);
+        assert_eq!(hour.clicked, 1);
+        assert_eq!(hour.rejected, 1);
+        assert_eq!(hour.open_rate(), Some(0.5));
+        assert_eq!(hour.top_domains(5), vec![("click.example.com", 1)]);
+
+        let day = stats.summary_at(1_120, Duration::from_secs(48 * 3600));
+        assert_eq!(day.window_secs, RETENTION.as_secs());
+        assert_eq!(day.jobs, 3);
+        assert_eq!(day.clicks, 3);
+        assert_eq!(day.statuses["completed"], 3);
+        assert_eq!(day.top_domains(1), vec![("click.example.com", 2)]);
+
+        assert_eq!(StatsSummary::default().click_rate(), None);
+    }
+
+    #[test]
+    fn test_old_buckets_expire() {
+        let stats = SimulationStats::new();
+        stats.update(10, |t| t.rejected += 1);
+        stats.update(10 + 24 * 60, |t| t.rejected += 1);
+        assert_eq!(stats.buckets.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut total = StatsSummary::default();
+        let mut worker = StatsSummary {
+            window_secs: 3600,
+            jobs: 4,
+            opened: 3,
+            ..StatsSummary::default()
+        };
+        worker.domains.insert("click.example.com".to_string(), 2);
+        total.merge(&worker);
+        total.merge(&worker);
+        assert_eq!(total.window_secs, 3600);
+        assert_eq!(total.jobs, 8);
+        assert_eq!(total.open_rate(), Some(0.75));
+        assert_eq!(total.domains["click.example.com"], 4);
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_window("15m"), Some(Duration::from_secs(900)));
+        assert_eq!(parse_window("2d"), Some(Duration::from_secs(2 * 86400)));
+        assert_eq!(parse_window("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_window("1w"), None);
+        assert_eq!(parse_window("h"), None);
+    }
+}
@@ -0,0 +1,396 @@
+//! Realized-rate drift per tenant.
+//!
+//! Every simulated job records the probabilities its open and clicks were
+//! rolled against. Summed over a tenant's recent jobs they give the opens
+//! and clicks the configuration asked for; the worker compares them with
+//! what its fetches actually achieved. A broken pixel detector, a tracking
+//! host that stopped answering or a change to the selection code shows up
+//! as a realized rate that no longer follows its target.
+//!
+//! With `RATE_DRIFT_THRESHOLD` set, a tenant whose realized open or click
+//! rate over the last `RATE_DRIFT_WINDOW_SECS` strays further than the
+//! threshold from its target, over at least `RATE_DRIFT_MIN_JOBS` jobs, is
+//! reported once as a [`DriftAlert`] when it starts drifting and once when
+//! it recovers. Jobs without a plus tag are tracked together as one tenant.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::config::WorkerConfig;
+use crate::simulate::report::{JobStatus, SimulationReport};
+
+/// Most tenants tracked at once; jobs of further tenants are not tracked.
+const MAX_TENANTS: usize = 10_000;
+
+const BUCKET_SECS: u64 = 60;
+
+/// When a tenant's rates count as drifting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftPolicy {
+    /// Largest gap between the realized and target rate, e.g. `0.1`
+    pub threshold: f64,
+    /// How far back jobs are counted
+    pub window: Duration,
+    /// Fewest jobs a rate is judged over
+    pub min_jobs: u64,
+}
+
+impl DriftPolicy {
+    /// The policy `config` sets, or `None` when `RATE_DRIFT_THRESHOLD` is unset.
+    pub fn from_config(config: &WorkerConfig) -> Option<Self> {
+        config.rate_drift_threshold.map(|threshold| Self {
+            threshold,
+            window: Duration::from_secs(config.rate_drift_window_secs),
+            min_jobs: config.rate_drift_min_jobs,
+        })
+    }
+
+    fn window_minutes(&self) -> u64 {
+        self.window.as_secs().div_ceil(BUCKET_SECS).max(1)
+    }
+}
+
+/// Which rate drifted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rate {
+    Open,
+    Click,
+}
+
+impl Rate {
+    /// Stable name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rate::Open => "open",
+            Rate::Click => "click",
+        }
+    }
+}
+
+/// Rolls of one rate and how they came out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RateTotals {
+    /// Jobs that rolled for it
+    pub jobs: u64,
+    /// Sum of the probabilities rolled against
+    pub expected: f64,
+    /// Jobs that achieved it
+    pub realized: u64,
+}
+
+impl RateTotals {
+    /// Average probability rolled against, or `None` without jobs.
+    pub fn target(&self) -> Option<f64> {
+        (self.jobs > 0).then(|| self.expected / self.jobs as f64)
+    }
+
+    /// Share of jobs that achieved it, or `None` without jobs.
+    pub fn actual(&self) -> Option<f64> {
+        (self.jobs > 0).then(|| self.realized as f64 / self.jobs as f64)
+    }
+
+    fn add(&mut self, probability: Option<f64>, achieved: bool) {
+        if let Some(probability) = probability {
+            self.jobs += 1;
+            self.expected += probability;
+            self.realized += u64::from(achieved);
+        }
+    }
+
+    fn merge(&mut self, other: &RateTotals) {
+        self.jobs += other.jobs;
+        self.expected += other.expected;
+        self.realized += other.realized;
+    }
+
+    /// Whether the rate drifts under `policy`, or `None` with too few jobs
+    /// to judge.
+    fn drifts(&self, policy: &DriftPolicy) -> Option<bool> {
+        let (target, actual) = (self.target()?, self.actual()?);
+        (self.jobs >= policy.min_jobs).then(|| (actual - target).abs() > policy.threshold)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    open: RateTotals,
+    click: RateTotals,
+}
+
+impl Totals {
+    fn of(report: &SimulationReport) -> Self {
+        let mut totals = Self::default();
+        totals.open.add(report.open_probability, report.opened);
+        totals
+            .click
+            .add(report.click_probability, report.successful_clicks() > 0);
+        totals
+    }
+
+    fn merge(&mut self, other: &Totals) {
+        self.open.merge(&other.open);
+        self.click.merge(&other.click);
+    }
+
+    fn rate(&self, rate: Rate) -> &RateTotals {
+        match rate {
+            Rate::Open => &self.open,
+            Rate::Click => &self.click,
+        }
+    }
+}
+
+/// A tenant's rate started or stopped drifting.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DriftAlert {
+    /// Customer tag, or `None` for jobs without one
+    pub tenant: Option<String>,
+    pub rate: Rate,
+    /// Average probability rolled against over the window
+    pub target: f64,
+    /// Share of jobs that achieved it over the window
+    pub actual: f64,
+    /// Jobs in the window
+    pub jobs: u64,
+    /// Whether the rate started drifting, rather than recovered
+    pub drifting: bool,
+}
+
+/// A tenant's rates over the window, as served at `GET /drift`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TenantDrift {
+    /// Customer tag, or `None` for jobs without one
+    pub tenant: Option<String>,
+    pub open: RateTotals,
+    pub click: RateTotals,
+    /// Rates currently drifting
+    pub drifting: Vec<Rate>,
+}
+
+#[derive(Default)]
+struct TenantWindow {
+    /// Totals per minute since the Unix epoch, oldest first
+    buckets: VecDeque<(u64, Totals)>,
+    drifting: Vec<Rate>,
+}
+
+impl TenantWindow {
+    fn totals(&self) -> Totals {
+        let mut totals = Totals::default();
+        for (_, bucket) in &self.buckets {
+            totals.merge(bucket);
+        }
+        totals
+    }
+
+    fn expire(&mut self, minute: u64, window_minutes: u64) {
+        let oldest = minute.saturating_sub(window_minutes);
+        while self.buckets.front().is_some_and(|(m, _)| *m <= oldest) {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    tenants: HashMap<Option<String>, TenantWindow>,
+    /// Window of the last policy recorded with
+    window_minutes: u64,
+}
+
+/// Rolling open and click rates per tenant, checked against their targets.
+#[derive(Default)]
+pub struct DriftMonitor {
+    state: Mutex<State>,
+}
+
+impl DriftMonitor {
+    /// Create an empty monitor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a finished job against its tenant and return the tenant's rates
+    /// that started or stopped drifting under `policy`.
+    ///
+    /// Only completed jobs count: the others ended before their rolls
+    /// played out.
+    pub fn record(&self, report: &SimulationReport, policy: &DriftPolicy) -> Vec<DriftAlert> {
+        if report.status != JobStatus::Completed {
+            return Vec::new();
+        }
+        let tenant = report.customer_tag.as_ref().map(|tag| tag.to_lowercase());
+        self.record_at(current_minute(), tenant, &Totals::of(report), policy)
+    }
+
+    /// Tenants with a rate currently drifting.
+    pub fn drifting(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .tenants
+            .values()
+            .filter(|tenant| !tenant.drifting.is_empty())
+            .count()
+    }
+
+    /// Every tracked tenant's rates over the window, drifting tenants first.
+    pub fn snapshot(&self) -> Vec<TenantDrift> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (minute, window_minutes) = (current_minute(), state.window_minutes);
+        state.tenants.retain(|_, tenant| {
+            tenant.expire(minute, window_minutes);
+            !tenant.buckets.is_empty()
+        });
+        let mut tenants: Vec<TenantDrift> = state
+            .tenants
+            .iter()
+            .map(|(tag, tenant)| {
+                let totals = tenant.totals();
+                TenantDrift {
+                    tenant: tag.clone(),
+                    open: totals.open,
+                    click: totals.click,
+                    drifting: tenant.drifting.clone(),
+                }
+            })
+            .collect();
+        tenants.sort_by(|a, b| {
+            (a.drifting.is_empty(), &a.tenant).cmp(&(b.drifting.is_empty(), &b.tenant))
+        });
+        tenants
+    }
+
+    fn record_at(
+        &self,
+        minute: u64,
+        tenant: Option<String>,
+        totals: &Totals,
+        policy: &DriftPolicy,
+    ) -> Vec<DriftAlert> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.window_minutes = policy.window_minutes();
+        if state.tenants.len() >= MAX_TENANTS && !state.tenants.contains_key(&tenant) {
+            return Vec::new();
+        }
+
+        let window = state.tenants.entry(tenant.clone()).or_default();
+        match window.buckets.back_mut() {
+            // The clock may step back; count into the newest bucket then
+            Some((last, bucket)) if *last >= minute => bucket.merge(totals),
+            _ => window.buckets.push_back((minute, *totals)),
+        }
+        window.expire(minute, policy.window_minutes());
+
+        let sums = window.totals();
+        let mut alerts = Vec::new();
+        for rate in [Rate::Open, Rate::Click] {
+            let rolls = sums.rate(rate);
+            let Some(drifts) = rolls.drifts(policy) else {
+                continue;
+            };
+            if drifts == window.drifting.contains(&rate) {
+                continue;
+            }
+            if drifts {
+                window.drifting.push(rate);
+            } else {
+                window.drifting.retain(|r| *r != rate);
+            }
+            alerts.push(DriftAlert {
+                tenant: tenant.clone(),
+                rate,
+                target: rolls.target().unwrap_or_default(),
+                actual: rolls.actual().unwrap_or_default(),
+                jobs: rolls.jobs,
+                drifting: drifts,
+            });
+        }
+        alerts
+    }
+}
+
+fn current_minute() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / BUCKET_SECS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: DriftPolicy = DriftPolicy {
+        threshold: 0.2,
+        window: Duration::from_secs(600),
+        min_jobs: 10,
+    };
+
+    fn job(open_probability: f64, opened: bool) -> Totals {
+        let mut totals = Totals::default();
+        totals.open.add(Some(open_probability), opened);
+        totals
+    }
+
+    #[test]
+    fn test_drift_alerts_once_and_recovers() {
+        let monitor = DriftMonitor::new();
+        let tenant = Some("acme".to_string());
+
+        // Too few jobs to judge, then a pixel that never loads
+        for _ in 0..9 {
+            assert!(monitor.record_at(100, tenant.clone(), &job(0.5, false), &POLICY).is_empty());
+        }
+        let alerts = monitor.record_at(100, tenant.clone(), &job(0.5, false), &POLICY);
+        assert_eq!(
+            alerts,
+            vec![DriftAlert {
+                tenant: tenant.clone(),
+                rate: Rate::Open,
+                target: 0.5,
+                actual: 0.0,
+                jobs: 10,
+                drifting: true,
+            }]
+        );
+        assert!(monitor.record_at(101, tenant.clone(), &job(0.5, false), &POLICY).is_empty());
+        assert_eq!(monitor.drifting(), 1);
+
+        // Other tenants are judged on their own
+        assert!(monitor.record_at(101, None, &job(0.5, true), &POLICY).is_empty());
+
+        // Once the broken minutes leave the window, the tenant recovers
+        let mut recovered = Vec::new();
+        for i in 0..10 {
+            let opened = i % 2 == 0;
+            recovered.extend(monitor.record_at(111, tenant.clone(), &job(0.5, opened), &POLICY));
+        }
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].jobs, 10);
+        assert!(!recovered[0].drifting);
+        assert_eq!(recovered[0].actual, 0.5);
+        assert_eq!(monitor.drifting(), 0);
+    }
+
+    #[test]
+    fn test_rates_without_a_roll_are_not_counted() {
+        let mut totals = RateTotals::default();
+        totals.add(None, false);
+        assert_eq!(totals.target(), None);
+        totals.add(Some(0.3), true);
+        totals.add(Some(0.5), false);
+        assert_eq!(totals.target(), Some(0.4));
+        assert_eq!(totals.actual(), Some(0.5));
+        assert_eq!(totals.drifts(&POLICY), None);
+        let policy = DriftPolicy {
+            min_jobs: 2,
+            ..POLICY
+        };
+        assert_eq!(totals.drifts(&policy), Some(false));
+    }
+}
@@ -0,0 +1,606 @@
+//! Per-tenant simulation profiles.
+//!
+//! A tenant is the customer tag from the recipient's plus address
+//! (`user+acme@example.com` is tenant `acme`). Its [`TenantProfile`] replaces
+//! the worker's open and click probabilities, click limit, user agents or
+//! email client mix, link domain lists, calendar, recipient time zone and
+//! job quota for that tenant's jobs, and gives them a default callback URL,
+//! so one deployment can give each customer its own engagement curve and
+//! audience. Job overrides still take precedence.
+//!
+//! Profiles are JSON documents kept in a [`ProfileStore`]: Redis (feature
+//! `redis`), Postgres (feature `postgres`), or any embedder implementation.
+//! The web server's admin API writes them, so onboarding a customer needs
+//! no redeploy. [`TenantProfiles`] caches them in-process, including tenants
+//! without one, so a store lookup happens at most once per tenant per TTL.
+//!
+//! [`quota`] caps how many jobs each tenant is simulated per hour and day,
+//! [`tier`] routes each tenant's jobs to its tier's simulator queue,
+//! [`usage`] counts what each tenant used per day, and [`drift`] watches
+//! each tenant's realized open and click rates against their targets.
+
+pub mod drift;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod quota;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod tier;
+pub mod usage;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::WorkerConfig;
+use crate::simulate::calendar::{CalendarEntry, MAX_UTC_OFFSET_MINS};
+use crate::util::client::WeightedClient;
+use crate::util::user_agent::WeightedAgent;
+
+use self::quota::TenantQuota;
+
+/// URL schemes [`connect`] accepts.
+pub const STORE_SCHEMES: &[&str] = &["redis", "rediss", "postgres", "postgresql"];
+
+/// Most tenants cached at once; expired entries are evicted beyond it.
+const MAX_CACHED_TENANTS: usize = 10_000;
+
+/// Longest customer tag a profile can be registered for.
+const MAX_TAG_LEN: usize = 64;
+
+/// Error from a profile store.
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    /// The URL names a store this build does not support
+    #[error(
+        "unsupported profile store `{0}` (expected redis or postgres, with its feature enabled)"
+    )]
+    UnsupportedScheme(String),
+    /// The store could not be reached or queried
+    #[error("profile store error: {0}")]
+    Store(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The stored profile is not valid profile JSON
+    #[error("invalid profile for tenant `{tag}`: {source}")]
+    Invalid {
+        tag: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The store can only be read
+    #[error("profile store is read-only")]
+    ReadOnly,
+}
+
+impl ProfileError {
+    /// Wrap a store client error.
+    pub fn store(e: impl std::error::Error + Send + Sync + 'static) -> Self {
+        ProfileError::Store(Box::new(e))
+    }
+}
+
+/// Whether `value` can be a tenant's customer tag: 1 to 64 letters, digits,
+/// `-`, `_` or `.`.
+pub fn is_tenant_tag(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_TAG_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Simulation settings for one tenant; unset fields keep the worker's.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantProfile {
+    /// Probability of simulating an email open (0.0 - 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_probability: Option<f64>,
+    /// Probability of simulating a click (0.0 - 1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub click_probability: Option<f64>,
+    /// Maximum number of links to click per email
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<usize>,
+    /// User agent pool, as `agent` or `agent|weight` entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agents: Option<Vec<String>>,
+    /// Email client mix, as `client` or `client|weight` entries; wins over
+    /// `user_agents`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_mix: Option<Vec<String>>,
+    /// Domains links may be clicked on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_domains: Option<Vec<String>>,
+    /// Domains links are never clicked on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deny_domains: Option<Vec<String>>,
+    /// Weekend and holiday calendar, as `day:factor` entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calendar: Option<Vec<String>>,
+    /// Offset from UTC in minutes of the calendar's days
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calendar_utc_offset_mins: Option<i32>,
+    /// Offset from UTC in minutes of recipients without a `TIMEZONE_TAGS`
+    /// or `TIMEZONE_DOMAINS` mapping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utc_offset_mins: Option<i32>,
+    /// Most jobs simulated per hour, replacing the tenant's `TENANT_QUOTAS`
+    /// entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_per_hour: Option<u64>,
+    /// Most jobs simulated per day, replacing the tenant's `TENANT_QUOTAS`
+    /// entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_per_day: Option<u64>,
+    /// URL reports are POSTed to for jobs without their own `callback_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+}
+
+impl TenantProfile {
+    /// Parse the profile stored for `tag`.
+    pub fn parse(tag: &str, raw: &str) -> Result<Self, ProfileError> {
+        serde_json::from_str(raw).map_err(|source| ProfileError::Invalid {
+            tag: tag.to_string(),
+            source,
+        })
+    }
+
+    /// Problems that would make the profile misbehave.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (name, value) in [
+            ("open_probability", self.open_probability),
+            ("click_probability", self.click_probability),
+        ] {
+            if value.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+                errors.push(format!("{}: must be between 0.0 and 1.0", name));
+            }
+        }
+        if let Some(entries) = &self.user_agents {
+            if let Some(entry) = entries.iter().find(|e| WeightedAgent::parse(e).is_none()) {
+                errors.push(format!("user_agents: invalid entry `{}`", entry));
+            }
+        }
+        if let Some(entries) = &self.client_mix {
+            if let Some(entry) = entries.iter().find(|e| WeightedClient::parse(e).is_none()) {
+                errors.push(format!("client_mix: invalid entry `{}`", entry));
+            }
+        }
+        for (name, domains) in [
+            ("allow_domains", &self.allow_domains),
+            ("deny_domains", &self.deny_domains),
+        ] {
+            if domains
+                .as_ref()
+                .is_some_and(|d| d.iter().any(|d| d.trim().is_empty()))
+            {
+                errors.push(format!("{}: must not contain empty domains", name));
+            }
+        }
+        if let Some(entries) = &self.calendar {
+            if let Some(entry) = entries.iter().find(|e| CalendarEntry::parse(e).is_none()) {
+                errors.push(format!("calendar: invalid entry `{}`", entry));
+            }
+        }
+        for (name, value) in [
+            ("calendar_utc_offset_mins", self.calendar_utc_offset_mins),
+            ("utc_offset_mins", self.utc_offset_mins),
+        ] {
+            if value.is_some_and(|mins| mins.abs() > MAX_UTC_OFFSET_MINS) {
+                errors.push(format!(
+                    "{}: must be between -{1} and {1}",
+                    name, MAX_UTC_OFFSET_MINS
+                ));
+            }
+        }
+        if let Some(url) = &self.callback_url {
+            match url::Url::parse(url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                Ok(url) => errors.push(format!(
+                    "callback_url: unsupported scheme `{}` (expected http or https)",
+                    url.scheme()
+                )),
+                Err(e) => errors.push(format!("callback_url: {}", e)),
+            }
+        }
+        errors
+    }
+
+    /// The job quota of tenant `tag`, if the profile sets one.
+    pub fn quota(&self, tag: &str) -> Option<TenantQuota> {
+        if self.quota_per_hour.is_none() && self.quota_per_day.is_none() {
+            return None;
+        }
+        Some(TenantQuota {
+            tag: tag.to_lowercase(),
+            per_hour: self.quota_per_hour,
+            per_day: self.quota_per_day,
+        })
+    }
+
+    /// `config` with this profile applied for tenant `tag`, including its
+    /// quota.
+    pub fn apply_for(&self, tag: &str, config: &WorkerConfig) -> WorkerConfig {
+        let mut config = self.apply(config);
+        if let Some(quota) = self.quota(tag) {
+            // Quotas are matched in order, so the profile's comes first
+            let mut quotas = vec![quota];
+            quotas.extend(config.tenant_quotas.take().unwrap_or_default());
+            config.tenant_quotas = Some(quotas);
+        }
+        config
+    }
+
+    /// `config` with this profile's simulation settings applied.
+    pub fn apply(&self, config: &WorkerConfig) -> WorkerConfig {
+        let mut config = config.clone();
+        if let Some(p) = self.open_probability {
+            config.simulate_open_probability = p.clamp(0.0, 1.0);
+        }
+        if let Some(p) = self.click_probability {
+            config.simulate_click_probability = p.clamp(0.0, 1.0);
+        }
+        if let Some(max) = self.max_clicks {
+            config.max_clicks = max;
+        }
+        if let Some(entries) = &self.user_agents {
+            let pool: Vec<WeightedAgent> = entries
+                .iter()
+                .filter_map(|e| WeightedAgent::parse(e))
+                .collect();
+            if !pool.is_empty() {
+                // The tenant's own agents win over the worker's client mix
+                config.user_agent_pool = Some(pool);
+                config.client_mix = None;
+            }
+        }
+        if let Some(entries) = &self.client_mix {
+            let mix: Vec<WeightedClient> = entries
+                .iter()
+                .filter_map(|e| WeightedClient::parse(e))
+                .collect();
+            if !mix.is_empty() {
+                config.client_mix = Some(mix);
+            }
+        }
+        if let Some(domains) = &self.allow_domains {
+            config.allow_domains = Some(domains.clone());
+        }
+        if let Some(domains) = &self.deny_domains {
+            config.deny_domains = Some(domains.clone());
+        }
+        if let Some(entries) = &self.calendar {
+            config.calendar = Some(
+                entries
+                    .iter()
+                    .filter_map(|e| CalendarEntry::parse(e))
+                    .collect(),
+            );
+        }
+        if let Some(mins) = self.calendar_utc_offset_mins {
+            config.calendar_utc_offset_mins = mins;
+        }
+        if let Some(mins) = self.utc_offset_mins {
+            config.recipient_utc_offset_mins = Some(mins);
+        }
+        config
+    }
+}
+
+/// Source of tenant profiles, keyed by lowercase customer tag.
+///
+/// Stores that can be written implement [`save`](ProfileStore::save) and
+/// [`delete`](ProfileStore::delete), which otherwise fail with
+/// [`ProfileError::ReadOnly`].
+pub trait ProfileStore: Send + Sync {
+    /// Look up the profile of `tag`, or `None` if the tenant has none.
+    fn load<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantProfile>, ProfileError>>;
+
+    /// Register `profile` as the profile of `tag`, replacing any.
+    fn save<'a>(
+        &'a self,
+        _tag: &'a str,
+        _profile: &'a TenantProfile,
+    ) -> BoxFuture<'a, Result<(), ProfileError>> {
+        Box::pin(async { Err(ProfileError::ReadOnly) })
+    }
+
+    /// Remove the profile of `tag`; `false` if it had none.
+    fn delete<'a>(&'a self, _tag: &'a str) -> BoxFuture<'a, Result<bool, ProfileError>> {
+        Box::pin(async { Err(ProfileError::ReadOnly) })
+    }
+}
+
+/// Fixed profiles, for tests and embedders that load them elsewhere.
+impl ProfileStore for HashMap<String, TenantProfile> {
+    fn load<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantProfile>, ProfileError>> {
+        Box::pin(async move { Ok(self.get(tag).cloned()) })
+    }
+}
+
+/// Profiles kept in process memory, for tests and single-process embedders.
+#[derive(Default)]
+pub struct MemoryProfiles {
+    profiles: Mutex<HashMap<String, TenantProfile>>,
+}
+
+impl ProfileStore for MemoryProfiles {
+    fn load<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantProfile>, ProfileError>> {
+        let profile = self.profiles.lock().unwrap().get(tag).cloned();
+        Box::pin(async move { Ok(profile) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        tag: &'a str,
+        profile: &'a TenantProfile,
+    ) -> BoxFuture<'a, Result<(), ProfileError>> {
+        self.profiles
+            .lock()
+            .unwrap()
+            .insert(tag.to_string(), profile.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, tag: &'a str) -> BoxFuture<'a, Result<bool, ProfileError>> {
+        let removed = self.profiles.lock().unwrap().remove(tag).is_some();
+        Box::pin(async move { Ok(removed) })
+    }
+}
+
+struct Cached {
+    profile: Option<Arc<TenantProfile>>,
+    fetched: Instant,
+}
+
+/// In-process cache in front of a [`ProfileStore`].
+pub struct TenantProfiles {
+    store: Box<dyn ProfileStore>,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, Cached>>,
+}
+
+impl TenantProfiles {
+    /// Cache profiles from `store` for `ttl`.
+    pub fn new(store: impl ProfileStore + 'static, ttl: Duration) -> Self {
+        Self {
+            store: Box::new(store),
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The profile of tenant `tag`, if it has one.
+    ///
+    /// When the store fails, the last known profile is used until it
+    /// answers again, and tenants never seen get the worker's settings.
+    pub async fn get(&self, tag: &str) -> Option<Arc<TenantProfile>> {
+        let tag = tag.to_lowercase();
+        let stale = {
+            let cache = self.cache.lock().unwrap();
+            match cache.get(&tag) {
+                Some(cached) if cached.fetched.elapsed() < self.ttl => {
+                    return cached.profile.clone()
+                }
+                Some(cached) => cached.profile.clone(),
+                None => None,
+            }
+        };
+
+        let profile = match self.store.load(&tag).await {
+            Ok(profile) => profile.map(Arc::new),
+            Err(e) => {
+                warn!(customer_tag = %tag, error = %e, "tenant_profile_load_failed");
+                return stale;
+            }
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHED_TENANTS {
+            cache.retain(|_, cached| cached.fetched.elapsed() < self.ttl);
+        }
+        cache.insert(
+            tag,
+            Cached {
+                profile: profile.clone(),
+                fetched: Instant::now(),
+            },
+        );
+        profile
+    }
+}
+
+/// Connect to the profile store at `url`, caching profiles for `ttl`.
+///
+/// The scheme picks the store: `redis://` or `rediss://` needs the `redis`
+/// feature, `postgres://` or `postgresql://` the `postgres` feature.
+pub async fn connect(url: &str, ttl: Duration) -> Result<TenantProfiles, ProfileError> {
+    Ok(TenantProfiles {
+        store: open_store(url).await?,
+        ttl,
+        cache: Mutex::new(HashMap::new()),
+    })
+}
+
+/// Connect to the profile store at `url` without a cache, e.g. to manage
+/// profiles.
+pub async fn connect_store(url: &str) -> Result<Arc<dyn ProfileStore>, ProfileError> {
+    Ok(Arc::from(open_store(url).await?))
+}
+
+async fn open_store(url: &str) -> Result<Box<dyn ProfileStore>, ProfileError> {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    match scheme {
+        #[cfg(feature = "redis")]
+        "redis" | "rediss" => Ok(Box::new(self::redis::RedisProfiles::connect(url).await?)),
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => Ok(Box::new(
+            self::postgres::PostgresProfiles::connect(url).await?,
+        )),
+        other => Err(ProfileError::UnsupportedScheme(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Store counting its lookups, failing once `fail` is set.
+    struct Counting {
+        profiles: HashMap<String, TenantProfile>,
+        loads: Arc<AtomicUsize>,
+        fail: Arc<Mutex<bool>>,
+    }
+
+    impl ProfileStore for Counting {
+        fn load<'a>(
+            &'a self,
+            tag: &'a str,
+        ) -> BoxFuture<'a, Result<Option<TenantProfile>, ProfileError>> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            let fail = *self.fail.lock().unwrap();
+            Box::pin(async move {
+                if fail {
+                    return Err(ProfileError::store(std::io::Error::other("down")));
+                }
+                self.profiles.load(tag).await
+            })
+        }
+    }
+
+    fn acme() -> TenantProfile {
+        TenantProfile::parse(
+            "acme",
+            r#"{"click_probability": 0.9, "max_clicks": 5, "user_agents": ["Bot/1|2", "|x"]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_profile_apply() {
+        let config = acme().apply(&WorkerConfig::default());
+        assert_eq!(config.simulate_click_probability, 0.9);
+        assert_eq!(config.max_clicks, 5);
+        assert_eq!(
+            config.user_agent_pool,
+            Some(vec![WeightedAgent {
+                agent: "Bot/1".to_string(),
+                weight: 2.0,
+            }])
+        );
+        // Unset fields keep the worker's
+        assert_eq!(config.simulate_open_probability, 0.7);
+        assert_eq!(config.allow_domains, None);
+
+        // The tenant's agents win over the worker's client mix, and its own
+        // client mix over the worker's
+        let worker = WorkerConfig {
+            client_mix: Some(vec![WeightedClient::parse("outlook_windows").unwrap()]),
+            ..Default::default()
+        };
+        assert_eq!(acme().apply(&worker).client_mix, None);
+        let mobile = TenantProfile {
+            client_mix: Some(vec![
+                "gmail_android|80".to_string(),
+                "gmail_web|20".to_string(),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            mobile.apply(&worker).client_mix.map(|mix| mix.len()),
+            Some(2)
+        );
+
+        assert!(matches!(
+            TenantProfile::parse("acme", r#"{"max_clicks": "many"}"#),
+            Err(ProfileError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn test_profile_quota_and_validate() {
+        let profile = TenantProfile::parse(
+            "acme",
+            r#"{"quota_per_hour": 10, "callback_url": "https://hooks.example.com/acme"}"#,
+        )
+        .unwrap();
+        assert!(profile.validate().is_empty());
+
+        let config = WorkerConfig {
+            tenant_quotas: Some(vec![TenantQuota::parse("acme:100:1000").unwrap()]),
+            ..Default::default()
+        };
+        let quotas = profile.apply_for("ACME", &config).tenant_quotas.unwrap();
+        assert_eq!(
+            quota::quota_for(&quotas, "acme").map(|q| (q.per_hour, q.per_day)),
+            Some((Some(10), None))
+        );
+        assert_eq!(acme().quota("acme"), None);
+
+        let invalid = TenantProfile {
+            open_probability: Some(-0.1),
+            user_agents: Some(vec!["|x".to_string()]),
+            client_mix: Some(vec!["thunderbird".to_string()]),
+            deny_domains: Some(vec![" ".to_string()]),
+            calendar: Some(vec!["someday:0.5".to_string()]),
+            calendar_utc_offset_mins: Some(-1000),
+            utc_offset_mins: Some(1000),
+            callback_url: Some("ftp://hooks.example.com".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(invalid.validate().len(), 8);
+
+        assert!(is_tenant_tag("acme.eu-1"));
+        assert!(!is_tenant_tag("acme/eu"));
+        assert!(!is_tenant_tag(""));
+    }
+
+    #[tokio::test]
+    async fn test_profiles_are_cached() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let fail = Arc::new(Mutex::new(false));
+        let store = Counting {
+            profiles: HashMap::from([("acme".to_string(), acme())]),
+            loads: Arc::clone(&loads),
+            fail: Arc::clone(&fail),
+        };
+        let profiles = TenantProfiles::new(store, Duration::from_secs(60));
+
+        assert_eq!(profiles.get("ACME").await.as_deref(), Some(&acme()));
+        assert_eq!(profiles.get("acme").await.as_deref(), Some(&acme()));
+        assert!(profiles.get("other").await.is_none());
+        assert!(profiles.get("other").await.is_none());
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        // Expired entries are looked up again, and kept if the store fails
+        let profiles = TenantProfiles {
+            ttl: Duration::ZERO,
+            ..profiles
+        };
+        *fail.lock().unwrap() = true;
+        assert_eq!(profiles.get("acme").await.as_deref(), Some(&acme()));
+        assert!(profiles.get("new").await.is_none());
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_connect_unsupported_scheme() {
+        let result = connect("mysql://localhost/profiles", Duration::from_secs(60)).await;
+        assert!(matches!(result, Err(ProfileError::UnsupportedScheme(s)) if s == "mysql"));
+    }
+}
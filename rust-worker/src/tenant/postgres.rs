@@ -0,0 +1,82 @@
+//! Tenant profiles stored in Postgres.
+//!
+//! Profiles are kept in a `tenant_profiles` table keyed by tag:
+//!
+//! ```sql
+//! CREATE TABLE tenant_profiles (tag text PRIMARY KEY, profile jsonb NOT NULL);
+//! INSERT INTO tenant_profiles VALUES ('acme', '{"open_probability": 0.4}');
+//! ```
+
+use futures::future::BoxFuture;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+use super::{ProfileError, ProfileStore, TenantProfile};
+
+const SELECT: &str = "SELECT profile::text FROM tenant_profiles WHERE tag = $1";
+
+const UPSERT: &str = "INSERT INTO tenant_profiles (tag, profile) VALUES ($1, $2::jsonb) \
+     ON CONFLICT (tag) DO UPDATE SET profile = EXCLUDED.profile";
+
+const DELETE: &str = "DELETE FROM tenant_profiles WHERE tag = $1";
+
+/// Profiles kept in Postgres over a small connection pool.
+#[derive(Clone)]
+pub struct PostgresProfiles {
+    pool: PgPool,
+}
+
+impl PostgresProfiles {
+    /// Connect to the database at `url` (`postgres://` or `postgresql://`).
+    pub async fn connect(url: &str) -> Result<Self, ProfileError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .connect(url)
+            .await
+            .map_err(ProfileError::store)?;
+        Ok(Self { pool })
+    }
+}
+
+impl ProfileStore for PostgresProfiles {
+    fn load<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantProfile>, ProfileError>> {
+        Box::pin(async move {
+            let raw: Option<String> = sqlx::query_scalar(SELECT)
+                .bind(tag)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(ProfileError::store)?;
+            raw.map(|raw| TenantProfile::parse(tag, &raw)).transpose()
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        tag: &'a str,
+        profile: &'a TenantProfile,
+    ) -> BoxFuture<'a, Result<(), ProfileError>> {
+        Box::pin(async move {
+            let raw = serde_json::to_string(profile).map_err(ProfileError::store)?;
+            sqlx::query(UPSERT)
+                .bind(tag)
+                .bind(raw)
+                .execute(&self.pool)
+                .await
+                .map_err(ProfileError::store)?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, tag: &'a str) -> BoxFuture<'a, Result<bool, ProfileError>> {
+        Box::pin(async move {
+            let result = sqlx::query(DELETE)
+                .bind(tag)
+                .execute(&self.pool)
+                .await
+                .map_err(ProfileError::store)?;
+            Ok(result.rows_affected() > 0)
+        })
+    }
+}
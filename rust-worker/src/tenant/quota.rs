@@ -0,0 +1,278 @@
+//! Per-tenant hourly and daily job quotas.
+//!
+//! `TENANT_QUOTAS` caps how many jobs each customer tag is simulated per hour
+//! and per day, so one tenant's runaway campaign cannot take over the shared
+//! fetch capacity. Jobs over quota are not simulated: the worker drops or
+//! delays them according to `TENANT_QUOTA_ACTION`.
+//!
+//! Usage is counted in fixed windows starting at a tenant's first job, and
+//! per worker process: with several replicas each one enforces the full
+//! quota.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Length of the hourly window.
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// Length of the daily window.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Most tenants counted at once; tenants idle for a day are evicted beyond it.
+const MAX_COUNTED_TENANTS: usize = 10_000;
+
+/// Tag of the quota applying to tenants without their own.
+pub const DEFAULT_TENANT: &str = "*";
+
+/// Job limits of one tenant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantQuota {
+    /// Lowercase customer tag, or `*` for every tenant without its own entry
+    pub tag: String,
+    /// Most jobs simulated per hour, if limited
+    pub per_hour: Option<u64>,
+    /// Most jobs simulated per day, if limited
+    pub per_day: Option<u64>,
+}
+
+impl TenantQuota {
+    /// Parse an entry of the form `tag:per_hour:per_day`; either limit may be
+    /// left empty.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let mut parts = entry.trim().split(':');
+        let tag = parts.next()?.trim().to_lowercase();
+        let limit = |part: &str| match part.trim() {
+            "" => Some(None),
+            n => n.parse().ok().map(Some),
+        };
+        let per_hour = limit(parts.next()?)?;
+        let per_day = limit(parts.next()?)?;
+
+        if tag.is_empty() || (per_hour.is_none() && per_day.is_none()) || parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            tag,
+            per_hour,
+            per_day,
+        })
+    }
+}
+
+/// Find the quota of `tag`, falling back to the `*` entry.
+pub fn quota_for<'a>(quotas: &'a [TenantQuota], tag: &str) -> Option<&'a TenantQuota> {
+    quotas
+        .iter()
+        .find(|q| q.tag.eq_ignore_ascii_case(tag))
+        .or_else(|| quotas.iter().find(|q| q.tag == DEFAULT_TENANT))
+}
+
+/// Quota window a tenant ran out of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaWindow {
+    Hour,
+    Day,
+}
+
+impl QuotaWindow {
+    /// Stable name used in logs and results.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuotaWindow::Hour => "hour",
+            QuotaWindow::Day => "day",
+        }
+    }
+}
+
+/// A tenant's job was refused because a window's limit was reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// Window that is full
+    pub window: QuotaWindow,
+    /// Jobs allowed in the window
+    pub limit: u64,
+    /// Time until the window starts over
+    pub retry_after: Duration,
+}
+
+/// What the worker does with jobs over quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuotaAction {
+    /// Acknowledge the job without simulating it
+    #[default]
+    Drop,
+    /// Republish the job to be retried after `TENANT_QUOTA_DELAY_SECS`
+    Delay,
+}
+
+impl QuotaAction {
+    /// Parse `drop` or `delay`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "drop" => Some(QuotaAction::Drop),
+            "delay" => Some(QuotaAction::Delay),
+            _ => None,
+        }
+    }
+
+    /// Stable name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuotaAction::Drop => "drop",
+            QuotaAction::Delay => "delay",
+        }
+    }
+}
+
+/// Jobs admitted in one window.
+struct Window {
+    started: Instant,
+    count: u64,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            count: 0,
+        }
+    }
+
+    /// Start over once `length` has passed.
+    fn roll(&mut self, length: Duration) {
+        if self.started.elapsed() >= length {
+            *self = Window::new();
+        }
+    }
+
+    /// Refuse the job if `limit` jobs were already admitted.
+    fn check(
+        &self,
+        limit: Option<u64>,
+        length: Duration,
+        window: QuotaWindow,
+    ) -> Result<(), QuotaExceeded> {
+        match limit {
+            Some(limit) if self.count >= limit => Err(QuotaExceeded {
+                window,
+                limit,
+                retry_after: length.saturating_sub(self.started.elapsed()),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Usage {
+    hour: Window,
+    day: Window,
+}
+
+/// Jobs admitted per tenant in the current hour and day.
+#[derive(Default)]
+pub struct QuotaUsage {
+    tenants: Mutex<HashMap<String, Usage>>,
+}
+
+impl QuotaUsage {
+    /// Count a job for `tag` against its entry in `quotas`, or refuse it if
+    /// the hourly or daily limit is reached.
+    ///
+    /// Refused jobs are not counted, so a delayed job is admitted once the
+    /// window starts over. Tenants without a quota are always admitted.
+    pub fn admit(&self, quotas: &[TenantQuota], tag: &str) -> Result<(), QuotaExceeded> {
+        let Some(quota) = quota_for(quotas, tag) else {
+            return Ok(());
+        };
+
+        let mut tenants = self.tenants.lock().unwrap();
+        if tenants.len() >= MAX_COUNTED_TENANTS {
+            tenants.retain(|_, usage| usage.day.started.elapsed() < DAY);
+        }
+        let usage = tenants.entry(tag.to_lowercase()).or_insert_with(|| Usage {
+            hour: Window::new(),
+            day: Window::new(),
+        });
+        usage.hour.roll(HOUR);
+        usage.day.roll(DAY);
+        usage.day.check(quota.per_day, DAY, QuotaWindow::Day)?;
+        usage.hour.check(quota.per_hour, HOUR, QuotaWindow::Hour)?;
+
+        usage.hour.count += 1;
+        usage.day.count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_parse() {
+        assert_eq!(
+            TenantQuota::parse(" Acme:100:1000 "),
+            Some(TenantQuota {
+                tag: "acme".to_string(),
+                per_hour: Some(100),
+                per_day: Some(1000),
+            })
+        );
+        assert_eq!(
+            TenantQuota::parse("*::500").map(|q| (q.per_hour, q.per_day)),
+            Some((None, Some(500)))
+        );
+        assert_eq!(TenantQuota::parse("acme::"), None);
+        assert_eq!(TenantQuota::parse("acme:100"), None);
+        assert_eq!(TenantQuota::parse(":100:1000"), None);
+        assert_eq!(TenantQuota::parse("acme:many:1000"), None);
+        assert_eq!(TenantQuota::parse("acme:1:2:3"), None);
+    }
+
+    #[test]
+    fn test_admit_counts_per_tenant() {
+        let quotas = vec![
+            TenantQuota::parse("acme:2:").unwrap(),
+            TenantQuota::parse("*::3").unwrap(),
+        ];
+        let usage = QuotaUsage::default();
+
+        assert!(usage.admit(&quotas, "acme").is_ok());
+        assert!(usage.admit(&quotas, "ACME").is_ok());
+        let exceeded = usage.admit(&quotas, "acme").unwrap_err();
+        assert_eq!(exceeded.window, QuotaWindow::Hour);
+        assert_eq!(exceeded.limit, 2);
+        assert!(exceeded.retry_after <= HOUR);
+
+        // Other tenants share the default quota but not its usage
+        for tag in ["beta", "gamma"] {
+            for _ in 0..3 {
+                assert!(usage.admit(&quotas, tag).is_ok());
+            }
+            assert_eq!(
+                usage.admit(&quotas, tag).map_err(|e| e.window),
+                Err(QuotaWindow::Day)
+            );
+        }
+
+        // Without a default, unlisted tenants are unlimited
+        assert!(usage.admit(&quotas[..1], "delta").is_ok());
+    }
+
+    #[test]
+    fn test_window_starts_over() {
+        let mut window = Window::new();
+        window.count = 5;
+        assert!(window.check(Some(5), HOUR, QuotaWindow::Hour).is_err());
+        window.roll(HOUR);
+        assert_eq!(window.count, 5);
+        window.roll(Duration::ZERO);
+        assert_eq!(window.count, 0);
+        assert!(window.check(Some(5), HOUR, QuotaWindow::Hour).is_ok());
+    }
+}
@@ -0,0 +1,69 @@
+//! Tenant profiles stored in Redis.
+//!
+//! Each profile is a JSON string under `bobnet:profile:<tag>`, e.g.
+//! `SET bobnet:profile:acme '{"open_probability": 0.4}'`.
+
+use ::redis::aio::ConnectionManager;
+use ::redis::AsyncCommands;
+use futures::future::BoxFuture;
+
+use super::{ProfileError, ProfileStore, TenantProfile};
+
+/// Prefix of the key each tenant's profile is stored under.
+pub const KEY_PREFIX: &str = "bobnet:profile:";
+
+/// Profiles kept in Redis over a reconnecting connection.
+#[derive(Clone)]
+pub struct RedisProfiles {
+    conn: ConnectionManager,
+}
+
+impl RedisProfiles {
+    /// Connect to the Redis server at `url` (`redis://` or `rediss://`).
+    pub async fn connect(url: &str) -> Result<Self, ProfileError> {
+        let client = ::redis::Client::open(url).map_err(ProfileError::store)?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .map_err(ProfileError::store)?;
+        Ok(Self { conn })
+    }
+}
+
+fn key(tag: &str) -> String {
+    format!("{}{}", KEY_PREFIX, tag)
+}
+
+impl ProfileStore for RedisProfiles {
+    fn load<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> BoxFuture<'a, Result<Option<TenantProfile>, ProfileError>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let raw: Option<String> = conn.get(key(tag)).await.map_err(ProfileError::store)?;
+            raw.map(|raw| TenantProfile::parse(tag, &raw)).transpose()
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        tag: &'a str,
+        profile: &'a TenantProfile,
+    ) -> BoxFuture<'a, Result<(), ProfileError>> {
+        Box::pin(async move {
+            let raw = serde_json::to_string(profile).map_err(ProfileError::store)?;
+            let mut conn = self.conn.clone();
+            conn.set::<_, _, ()>(key(tag), raw)
+                .await
+                .map_err(ProfileError::store)
+        })
+    }
+
+    fn delete<'a>(&'a self, tag: &'a str) -> BoxFuture<'a, Result<bool, ProfileError>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let removed: u64 = conn.del(key(tag)).await.map_err(ProfileError::store)?;
+            Ok(removed > 0)
+        })
+    }
+}
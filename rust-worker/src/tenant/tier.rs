@@ -0,0 +1,172 @@
+//! Tier-based routing of jobs to simulator queues.
+//!
+//! `TENANT_TIERS` assigns customer tags to tiers, e.g.
+//! `acme:premium,*:bulk`, and the processor publishes each tenant's jobs to
+//! its tier's queue (`email_simulator.premium`, `email_simulator.bulk`).
+//! Each queue can then be consumed by its own worker pool with its own
+//! concurrency, so premium test sends are not stuck behind bulk backfills.
+//!
+//! A tier can instead be given an AMQP priority, e.g.
+//! `acme:premium:9,*:bulk:1`: its jobs then stay on the shared simulator
+//! queue, stamped with the priority, and with `SIMULATOR_MAX_PRIORITY` making
+//! that a priority queue the broker delivers paid campaigns before internal
+//! bulk traffic, without a worker pool per tier. Every job with a tier
+//! carries it in the `x-bobnet-tier` header.
+
+use serde::{Deserialize, Serialize};
+
+use crate::queue::SIMULATOR_QUEUE;
+use crate::simulate::simulator::extract_plus_tag;
+
+use super::quota::DEFAULT_TENANT;
+
+/// Tier of one tenant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantTier {
+    /// Lowercase customer tag, or `*` for every tenant without its own entry
+    pub tag: String,
+    /// Tier name, the suffix of the tier's simulator queue
+    pub tier: String,
+    /// AMQP priority of the tier's jobs, which then stay on the shared queue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+}
+
+impl TenantTier {
+    /// Parse an entry of the form `tag:tier` or `tag:tier:priority`, with a
+    /// priority from 0 to 255.
+    ///
+    /// Tier names are lowercase letters, digits, `-` or `_`, so they make
+    /// valid queue names.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (tag, rest) = entry.trim().split_once(':')?;
+        let (tier, priority) = match rest.split_once(':') {
+            Some((tier, priority)) => (tier, Some(priority.trim().parse().ok()?)),
+            None => (rest, None),
+        };
+        let tag = tag.trim().to_lowercase();
+        let tier = tier.trim().to_lowercase();
+        let valid_tier = !tier.is_empty()
+            && tier
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if tag.is_empty() || !valid_tier {
+            return None;
+        }
+        Some(Self {
+            tag,
+            tier,
+            priority,
+        })
+    }
+
+    /// Simulator queue of this tier.
+    pub fn queue(&self) -> String {
+        format!("{}.{}", SIMULATOR_QUEUE, self.tier)
+    }
+
+    /// The queue jobs of this tier are routed to, unless it has a priority
+    /// and they stay on the shared queue.
+    pub fn own_queue(&self) -> Option<String> {
+        self.priority.is_none().then(|| self.queue())
+    }
+}
+
+/// Find the tier of `tag`, falling back to the `*` entry.
+///
+/// Recipients without a plus tag only match the `*` entry.
+pub fn tier_for<'a>(tiers: &'a [TenantTier], tag: Option<&str>) -> Option<&'a TenantTier> {
+    tag.and_then(|tag| tiers.iter().find(|t| t.tag.eq_ignore_ascii_case(tag)))
+        .or_else(|| tiers.iter().find(|t| t.tag == DEFAULT_TENANT))
+}
+
+/// Tier of the recipient `to`, by its plus tag.
+pub fn recipient_tier<'a>(tiers: &'a [TenantTier], to: &str) -> Option<&'a TenantTier> {
+    tier_for(tiers, extract_plus_tag(to).as_deref())
+}
+
+/// Simulator queue of the recipient `to`'s tier, if it has its own.
+pub fn route(tiers: &[TenantTier], to: &str) -> Option<String> {
+    recipient_tier(tiers, to).and_then(TenantTier::own_queue)
+}
+
+/// Every tier's own simulator queue, once each.
+pub fn tier_queues(tiers: &[TenantTier]) -> Vec<String> {
+    let mut queues: Vec<String> = tiers.iter().filter_map(TenantTier::own_queue).collect();
+    queues.sort();
+    queues.dedup();
+    queues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tier_parse() {
+        assert_eq!(
+            TenantTier::parse(" Acme : Premium "),
+            Some(TenantTier {
+                tag: "acme".to_string(),
+                tier: "premium".to_string(),
+                priority: None,
+            })
+        );
+        assert_eq!(
+            TenantTier::parse("acme:premium: 9").and_then(|t| t.priority),
+            Some(9)
+        );
+        assert_eq!(TenantTier::parse("acme:premium:256"), None);
+        assert_eq!(TenantTier::parse("acme:premium:"), None);
+        assert_eq!(TenantTier::parse("acme"), None);
+        assert_eq!(TenantTier::parse("acme:"), None);
+        assert_eq!(TenantTier::parse(":premium"), None);
+        assert_eq!(TenantTier::parse("acme:pre.mium"), None);
+    }
+
+    #[test]
+    fn test_route_by_plus_tag() {
+        let tiers = vec![
+            TenantTier::parse("acme:premium").unwrap(),
+            TenantTier::parse("beta:premium").unwrap(),
+            TenantTier::parse("*:bulk").unwrap(),
+        ];
+
+        assert_eq!(
+            route(&tiers, "user+ACME@example.com").as_deref(),
+            Some("email_simulator.premium")
+        );
+        assert_eq!(
+            route(&tiers, "user+gamma@example.com").as_deref(),
+            Some("email_simulator.bulk")
+        );
+        assert_eq!(
+            route(&tiers, "user@example.com").as_deref(),
+            Some("email_simulator.bulk")
+        );
+        assert_eq!(route(&tiers[..1], "user@example.com"), None);
+        assert_eq!(
+            tier_queues(&tiers),
+            vec!["email_simulator.bulk", "email_simulator.premium"]
+        );
+    }
+
+    #[test]
+    fn test_prioritized_tiers_share_the_queue() {
+        let tiers = vec![
+            TenantTier::parse("acme:paid:9").unwrap(),
+            TenantTier::parse("*:bulk:1").unwrap(),
+        ];
+
+        assert_eq!(route(&tiers, "user+acme@example.com"), None);
+        assert_eq!(
+            recipient_tier(&tiers, "user+acme@example.com").map(|t| (t.tier.as_str(), t.priority)),
+            Some(("paid", Some(9)))
+        );
+        assert_eq!(
+            recipient_tier(&tiers, "user@example.com").and_then(|t| t.priority),
+            Some(1)
+        );
+        assert!(tier_queues(&tiers).is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+//! Per-tenant usage accounting.
+//!
+//! The worker counts, per customer tag and UTC day, the emails it received
+//! and the opens, clicks and outbound requests it simulated for them. Its
+//! sidecar serves the totals at `GET /usage?since=30d` and a tenant's daily
+//! breakdown at `GET /usage/{tag}`, for usage reports and checking
+//! contractual limits.
+//!
+//! With `USAGE_FILE` set, the counts are loaded from the file at startup and
+//! saved to it every `USAGE_SAVE_INTERVAL_SECS` and at shutdown, so they
+//! survive restarts. Counts are per worker: sum every worker's report for a
+//! deployment's usage.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::simulate::report::SimulationReport;
+
+/// How many days of usage are kept, today included.
+pub const RETENTION_DAYS: u64 = 400;
+
+/// Most tenants counted per day; jobs of further tenants are not counted.
+const MAX_TENANTS_PER_DAY: usize = 10_000;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Error loading or saving the usage file.
+#[derive(Debug, thiserror::Error)]
+pub enum UsageError {
+    #[error("failed to read usage file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid usage file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("failed to write usage file {path}: {source}")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// What one tenant used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantUsage {
+    /// Emails received, whether or not they were simulated
+    pub emails: u64,
+    /// Emails whose open was simulated
+    pub opens: u64,
+    /// Successful clicks
+    pub clicks: u64,
+    /// Outbound HTTP requests, counting every redirect hop
+    pub requests: u64,
+}
+
+impl TenantUsage {
+    /// Usage of one simulated job.
+    pub fn of(report: &SimulationReport) -> Self {
+        let requests = report
+            .pixels
+            .iter()
+            .chain(&report.clicks)
+            .map(|fetch| 1 + fetch.redirects.len() as u64)
+            .sum();
+        Self {
+            emails: 1,
+            opens: u64::from(report.opened),
+            clicks: report.successful_clicks() as u64,
+            requests,
+        }
+    }
+
+    /// Add another period's or worker's usage.
+    pub fn merge(&mut self, other: &TenantUsage) {
+        self.emails += other.emails;
+        self.opens += other.opens;
+        self.clicks += other.clicks;
+        self.requests += other.requests;
+    }
+}
+
+/// One day of a tenant's usage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    /// UTC date, `YYYY-MM-DD`
+    pub date: String,
+    #[serde(flatten)]
+    pub usage: TenantUsage,
+}
+
+/// Usage of every tenant over recent days.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageReport {
+    /// First UTC date counted, `YYYY-MM-DD`
+    pub since: String,
+    /// Usage per customer tag
+    pub tenants: BTreeMap<String, TenantUsage>,
+}
+
+type Days = BTreeMap<u64, BTreeMap<String, TenantUsage>>;
+
+/// Daily usage per tenant for the last [`RETENTION_DAYS`].
+#[derive(Default)]
+pub struct UsageLedger {
+    /// Usage per day since the Unix epoch, then per lowercase tag
+    days: Mutex<Days>,
+}
+
+impl UsageLedger {
+    /// Create an empty ledger.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a job against its tenant; jobs without a plus tag are not counted.
+    pub fn record(&self, report: &SimulationReport) {
+        if let Some(tag) = &report.customer_tag {
+            self.record_on(today(), tag, &TenantUsage::of(report));
+        }
+    }
+
+    fn record_on(&self, day: u64, tag: &str, usage: &TenantUsage) {
+        let mut days = self.days.lock().unwrap_or_else(|e| e.into_inner());
+        let tenants = days.entry(day).or_default();
+        let tag = tag.to_lowercase();
+        if tenants.len() < MAX_TENANTS_PER_DAY || tenants.contains_key(&tag) {
+            tenants.entry(tag).or_default().merge(usage);
+        }
+        expire(&mut days, day);
+    }
+
+    /// Usage of every tenant over the last `days` days, today included.
+    pub fn report(&self, days: u64) -> UsageReport {
+        self.report_at(today(), days)
+    }
+
+    fn report_at(&self, today: u64, days: u64) -> UsageReport {
+        let first = first_day(today, days);
+        let mut tenants: BTreeMap<String, TenantUsage> = BTreeMap::new();
+        let ledger = self.days.lock().unwrap_or_else(|e| e.into_inner());
+        for day_tenants in ledger.range(first..).map(|(_, tenants)| tenants) {
+            for (tag, usage) in day_tenants {
+                tenants.entry(tag.clone()).or_default().merge(usage);
+            }
+        }
+        UsageReport {
+            since: date_of(first),
+            tenants,
+        }
+    }
+
+    /// `tag`'s usage per day over the last `days` days, oldest first; days
+    /// without usage are left out.
+    pub fn daily(&self, tag: &str, days: u64) -> Vec<DailyUsage> {
+        self.daily_at(today(), tag, days)
+    }
+
+    fn daily_at(&self, today: u64, tag: &str, days: u64) -> Vec<DailyUsage> {
+        let tag = tag.to_lowercase();
+        let ledger = self.days.lock().unwrap_or_else(|e| e.into_inner());
+        ledger
+            .range(first_day(today, days)..)
+            .filter_map(|(&day, tenants)| {
+                tenants.get(&tag).map(|usage| DailyUsage {
+                    date: date_of(day),
+                    usage: *usage,
+                })
+            })
+            .collect()
+    }
+
+    /// Add the usage saved in `path`; a missing file is not an error.
+    pub fn load(&self, path: &Path) -> Result<(), UsageError> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(source) => {
+                return Err(UsageError::Read {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+        let saved: BTreeMap<String, BTreeMap<String, TenantUsage>> = serde_json::from_str(&json)
+            .map_err(|source| UsageError::Parse {
+                path: path.to_path_buf(),
+                source,
+            })?;
+
+        let mut days = self.days.lock().unwrap_or_else(|e| e.into_inner());
+        for (date, tenants) in saved {
+            let Some(day) = day_of(&date) else { continue };
+            let day_tenants = days.entry(day).or_default();
+            for (tag, usage) in tenants {
+                day_tenants
+                    .entry(tag.to_lowercase())
+                    .or_default()
+                    .merge(&usage);
+            }
+        }
+        expire(&mut days, today());
+        Ok(())
+    }
+
+    /// Write the ledger to `path`, replacing it only once fully written.
+    pub fn save(&self, path: &Path) -> Result<(), UsageError> {
+        let saved: BTreeMap<String, BTreeMap<String, TenantUsage>> = {
+            let days = self.days.lock().unwrap_or_else(|e| e.into_inner());
+            days.iter()
+                .map(|(&day, tenants)| (date_of(day), tenants.clone()))
+                .collect()
+        };
+        let json = serde_json::to_string_pretty(&saved).expect("usage serializes");
+
+        let write_error = |source| UsageError::Write {
+            path: path.to_path_buf(),
+            source,
+        };
+        let partial = path.with_extension("tmp");
+        fs::write(&partial, json).map_err(write_error)?;
+        fs::rename(&partial, path).map_err(write_error)
+    }
+}
+
+/// Drop days that fell out of [`RETENTION_DAYS`].
+fn expire(days: &mut Days, today: u64) {
+    let first = first_day(today, RETENTION_DAYS);
+    while days.first_key_value().is_some_and(|(&day, _)| day < first) {
+        days.pop_first();
+    }
+}
+
+/// First day of the `days` days ending `today`, capped at [`RETENTION_DAYS`].
+fn first_day(today: u64, days: u64) -> u64 {
+    today.saturating_sub(days.clamp(1, RETENTION_DAYS) - 1)
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / SECS_PER_DAY
+}
+
+/// `YYYY-MM-DD` of a day since the Unix epoch.
+pub(crate) fn date_of(day: u64) -> String {
+    // Howard Hinnant's civil_from_days, for days on or after 1970-01-01
+    let z = day + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + u64::from(m <= 2);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Day since the Unix epoch of a `YYYY-MM-DD` date.
+pub(crate) fn day_of(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-');
+    let y: u64 = parts.next()?.parse().ok()?;
+    let m: u64 = parts.next()?.parse().ok()?;
+    let d: u64 = parts.next()?.parse().ok()?;
+    if y < 1970 || !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+
+    // Howard Hinnant's days_from_civil
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = if m > 2 { m - 3 } else { m + 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(emails: u64, opens: u64) -> TenantUsage {
+        TenantUsage {
+            emails,
+            opens,
+            clicks: 0,
+            requests: opens,
+        }
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(date_of(0), "1970-01-01");
+        assert_eq!(date_of(20_742), "2026-10-16");
+        assert_eq!(date_of(11_016), "2000-02-29");
+        for day in [0, 59, 60, 11_016, 20_742, 30_000] {
+            assert_eq!(day_of(&date_of(day)), Some(day));
+        }
+        assert_eq!(day_of("2026-13-01"), None);
+        assert_eq!(day_of("yesterday"), None);
+    }
+
+    #[test]
+    fn test_report_and_daily() {
+        let ledger = UsageLedger::new();
+        ledger.record_on(100, "Acme", &usage(2, 1));
+        ledger.record_on(101, "acme", &usage(3, 3));
+        ledger.record_on(101, "beta", &usage(1, 0));
+
+        let report = ledger.report_at(101, 1);
+        assert_eq!(report.since, date_of(101));
+        assert_eq!(report.tenants["acme"], usage(3, 3));
+
+        let report = ledger.report_at(101, 7);
+        assert_eq!(report.tenants["acme"], usage(5, 4));
+        assert_eq!(report.tenants["beta"], usage(1, 0));
+
+        let daily = ledger.daily_at(101, "ACME", 30);
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[0].date, date_of(100));
+        assert_eq!(daily[0].usage, usage(2, 1));
+
+        // Days past the retention are dropped as new days are counted
+        ledger.record_on(100 + RETENTION_DAYS, "beta", &usage(1, 0));
+        assert_eq!(
+            ledger
+                .daily_at(100 + RETENTION_DAYS, "acme", RETENTION_DAYS)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("bobnet-usage-{}.json", std::process::id()));
+        let ledger = UsageLedger::new();
+        ledger.record_on(today(), "acme", &usage(2, 1));
+        ledger.save(&path).unwrap();
+
+        let restored = UsageLedger::new();
+        restored.load(&path).unwrap();
+        restored.load(&path).unwrap();
+        assert_eq!(restored.report(1).tenants["acme"], usage(4, 2));
+
+        fs::remove_file(&path).unwrap();
+        assert!(UsageLedger::new().load(&path).is_ok());
+    }
+}
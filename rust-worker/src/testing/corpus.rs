@@ -0,0 +1,192 @@
+//! Fixture corpus: real-world emails with golden analysis expectations.
+//!
+//! A corpus is a directory of `.eml` and `.html` fixtures, each next to a
+//! `.json` golden file of the same name. `.eml` fixtures go through
+//! [`parse_raw_email`] first, as the processor does; every fixture's HTML
+//! is then analysed with [`HtmlSummary::parse`], and the [`Analysis`]
+//! compared with the golden file. Only the fields the golden file lists
+//! are compared, so a hand-written expectation can pin just the pixels or
+//! links it cares about.
+//!
+//! Run the tests with `BOBNET_UPDATE_GOLDEN=1` to write every golden file
+//! from the current parser instead, then review the diff.
+//!
+//! ```no_run
+//! use bobnet::testing::corpus;
+//!
+//! let checked = corpus::check_all(std::path::Path::new("testdata/corpus")).unwrap();
+//! assert!(checked > 0);
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::html::HtmlSummary;
+use crate::process::parse_raw_email;
+
+/// Environment variable that makes [`Fixture::check`] write golden files.
+pub const UPDATE_GOLDEN_VAR: &str = "BOBNET_UPDATE_GOLDEN";
+
+/// What the parser found in a fixture.
+#[derive(Debug, Clone, Serialize)]
+pub struct Analysis {
+    /// Message-Id of an `.eml` fixture
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Everything read from the HTML
+    #[serde(flatten)]
+    pub html: HtmlSummary,
+}
+
+/// An email in a corpus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    /// File name without its extension
+    pub name: String,
+    /// Path to the `.eml` or `.html` file
+    pub path: PathBuf,
+}
+
+impl Fixture {
+    /// Golden file holding the fixture's expected [`Analysis`].
+    pub fn golden_path(&self) -> PathBuf {
+        self.path.with_extension("json")
+    }
+
+    /// Parse the fixture as the processor and worker would.
+    pub fn analyze(&self) -> Result<Analysis, String> {
+        let content = fs::read_to_string(&self.path)
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        if !is_eml(&self.path) {
+            return Ok(Analysis {
+                message_id: None,
+                html: HtmlSummary::parse(&content),
+            });
+        }
+        let parsed = parse_raw_email(&content).map_err(|e| format!("{}: {}", self.name, e))?;
+        let html = parsed
+            .html
+            .ok_or_else(|| format!("{}: no HTML body", self.name))?;
+        Ok(Analysis {
+            message_id: parsed.message_id,
+            html: HtmlSummary::parse(&html),
+        })
+    }
+
+    /// Compare the fixture's analysis with its golden file, or write the
+    /// golden file when [`UPDATE_GOLDEN_VAR`] is set.
+    pub fn check(&self) -> Result<(), String> {
+        let actual = serde_json::to_value(self.analyze()?).map_err(|e| e.to_string())?;
+        let golden = self.golden_path();
+        if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+            let json = serde_json::to_string_pretty(&actual).map_err(|e| e.to_string())?;
+            return fs::write(&golden, json + "\n")
+                .map_err(|e| format!("{}: {}", golden.display(), e));
+        }
+
+        let expected = fs::read_to_string(&golden).map_err(|e| {
+            format!(
+                "{}: {} (set {}=1 to write it)",
+                golden.display(),
+                e,
+                UPDATE_GOLDEN_VAR
+            )
+        })?;
+        let expected: Value = serde_json::from_str(&expected)
+            .map_err(|e| format!("{}: {}", golden.display(), e))?;
+        let differences = differences(&expected, &actual)?;
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{}:\n  {}", self.name, differences.join("\n  ")))
+        }
+    }
+}
+
+/// Every fixture in `dir`, by name.
+pub fn load(dir: &Path) -> io::Result<Vec<Fixture>> {
+    let mut fixtures = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !matches!(path.extension().and_then(|ext| ext.to_str()), Some("eml" | "html")) {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            fixtures.push(Fixture {
+                name: name.to_string(),
+                path: path.clone(),
+            });
+        }
+    }
+    fixtures.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(fixtures)
+}
+
+/// Check every fixture in `dir`, returning how many were checked or every
+/// failure at once.
+pub fn check_all(dir: &Path) -> Result<usize, String> {
+    let fixtures = load(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    if fixtures.is_empty() {
+        return Err(format!("{}: no .eml or .html fixtures", dir.display()));
+    }
+    let failures: Vec<String> = fixtures
+        .iter()
+        .filter_map(|fixture| fixture.check().err())
+        .collect();
+    if failures.is_empty() {
+        Ok(fixtures.len())
+    } else {
+        Err(failures.join("\n"))
+    }
+}
+
+fn is_eml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "eml")
+}
+
+/// The golden fields whose values differ from `actual`, one line each.
+fn differences(expected: &Value, actual: &Value) -> Result<Vec<String>, String> {
+    let expected = expected
+        .as_object()
+        .ok_or("golden file is not a JSON object")?;
+    Ok(expected
+        .iter()
+        .filter(|(field, value)| actual.get(field.as_str()) != Some(*value))
+        .map(|(field, value)| {
+            let found = actual.get(field.as_str()).unwrap_or(&Value::Null);
+            format!("{}: expected {}, found {}", field, value, found)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_corpus_matches_golden() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/corpus");
+        if let Err(failures) = check_all(&dir) {
+            panic!("corpus analysis changed:\n{}", failures);
+        }
+    }
+
+    #[test]
+    fn test_only_golden_fields_are_compared() {
+        let actual = json!({"open_pixels": ["https://a/open.gif"], "images": []});
+        assert!(differences(&json!({"images": []}), &actual).unwrap().is_empty());
+        assert_eq!(
+            differences(&json!({"open_pixels": [], "links": []}), &actual).unwrap(),
+            vec![
+                r#"links: expected [], found null"#.to_string(),
+                r#"open_pixels: expected [], found ["https://a/open.gif"]"#.to_string(),
+            ]
+        );
+        assert!(differences(&json!([]), &actual).is_err());
+    }
+}
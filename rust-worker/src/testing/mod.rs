@@ -0,0 +1,215 @@
+//! Test support: a local mock of the servers simulation fetches go to, and
+//! a corpus of real-world emails to check the parser against.
+//!
+//! Enabled with the `test-support` feature. [`MockServer`] listens on a
+//! random loopback port and records every request path, so tests can run the
+//! real open and click pipeline and then assert which URLs were hit:
+//!
+//! - `/pixel.gif`, `/open/...`: a 1x1 GIF, like a tracking pixel
+//! - `/redirect/<n>`: a chain of `n` 302 redirects ending at `/landed`
+//! - `/slow/<ms>`: answers after `ms` milliseconds
+//! - `/status/<code>`: an error page with that status
+//! - anything else: a small HTML page
+//!
+//! ```no_run
+//! # async fn example() {
+//! use bobnet::testing::MockServer;
+//!
+//! let server = MockServer::start().await.unwrap();
+//! let html = format!(r#"<img src="{}">"#, server.url("/pixel.gif"));
+//! // ... simulate an email containing `html` ...
+//! server.assert_hit("/pixel.gif");
+//! # }
+//! ```
+//!
+//! [`corpus`] runs the parser over a directory of fixture emails and
+//! compares what it finds with golden expectations.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "processor")]
+pub mod corpus;
+
+/// Smallest valid GIF: a single transparent pixel.
+pub const PIXEL_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00,
+    0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+type Hits = Arc<Mutex<Vec<String>>>;
+
+/// Local HTTP server emulating tracking pixels, redirects, slow endpoints
+/// and error pages. Stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    hits: Hits,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Start the server on a random loopback port.
+    pub async fn start() -> io::Result<Self> {
+        let hits = Hits::default();
+        let app = Router::new()
+            .route("/pixel.gif", get(pixel))
+            .route("/open/*rest", get(pixel))
+            .route("/redirect/:hops", get(redirect))
+            .route("/slow/:ms", get(slow))
+            .route("/status/:code", get(status))
+            .fallback(page)
+            .layer(middleware::from_fn_with_state(hits.clone(), record));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Self { addr, hits, task })
+    }
+
+    /// Absolute URL of `path` on this server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Every request path (with query) received so far, in order.
+    pub fn hits(&self) -> Vec<String> {
+        self.hits.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// How many times `path` was requested.
+    pub fn hit_count(&self, path: &str) -> usize {
+        self.hits().iter().filter(|hit| *hit == path).count()
+    }
+
+    /// Forget the requests received so far.
+    pub fn reset(&self) {
+        self.hits.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Panic unless `path` was requested.
+    #[track_caller]
+    pub fn assert_hit(&self, path: &str) {
+        assert!(
+            self.hit_count(path) > 0,
+            "expected a request for {}, got {:?}",
+            path,
+            self.hits()
+        );
+    }
+
+    /// Panic if `path` was requested.
+    #[track_caller]
+    pub fn assert_not_hit(&self, path: &str) {
+        assert_eq!(
+            self.hit_count(path),
+            0,
+            "expected no request for {}, got {:?}",
+            path,
+            self.hits()
+        );
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn record(State(hits): State<Hits>, request: Request, next: Next) -> Response {
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or_else(|| request.uri().path().to_string(), |p| p.to_string());
+    hits.lock().unwrap_or_else(|e| e.into_inner()).push(path);
+    next.run(request).await
+}
+
+async fn pixel() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "image/gif")], PIXEL_GIF)
+}
+
+async fn redirect(Path(hops): Path<u32>) -> Response {
+    let next = match hops {
+        0 => return page().await.into_response(),
+        1 => "/landed".to_string(),
+        n => format!("/redirect/{}", n - 1),
+    };
+    (StatusCode::FOUND, [(header::LOCATION, next)]).into_response()
+}
+
+async fn slow(Path(ms): Path<u64>) -> impl IntoResponse {
+    tokio::time::sleep(Duration::from_millis(ms)).await;
+    page().await
+}
+
+async fn status(Path(code): Path<u16>) -> impl IntoResponse {
+    let status = StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, format!("<html><body>Error {}</body></html>", status.as_u16()))
+}
+
+async fn page() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/html")],
+        "<html><body>Landing page</body></html>",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_server_routes() {
+        let server = MockServer::start().await.unwrap();
+        let client = reqwest::Client::new();
+
+        let pixel = client.get(server.url("/open/abc?j=1")).send().await.unwrap();
+        assert_eq!(pixel.status(), 200);
+        assert_eq!(pixel.bytes().await.unwrap().as_ref(), PIXEL_GIF);
+
+        let landed = client.get(server.url("/redirect/2")).send().await.unwrap();
+        assert_eq!(landed.url().path(), "/landed");
+
+        let error = client.get(server.url("/status/503")).send().await.unwrap();
+        assert_eq!(error.status(), 503);
+
+        assert_eq!(
+            server.hits(),
+            vec!["/open/abc?j=1", "/redirect/2", "/redirect/1", "/landed", "/status/503"]
+        );
+        server.assert_hit("/landed");
+        server.assert_not_hit("/pixel.gif");
+
+        server.reset();
+        assert!(server.hits().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_slow_endpoint_times_out() {
+        let server = MockServer::start().await.unwrap();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        assert!(client.get(server.url("/slow/2000")).send().await.is_err());
+        server.assert_hit("/slow/2000");
+    }
+}
@@ -0,0 +1,52 @@
+//! Cooperative cancellation helpers for shutdown.
+
+use std::time::Duration;
+
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+/// Sleep for `duration` unless `cancel` fires first.
+///
+/// Returns `true` if the full delay elapsed and `false` if it was cut short
+/// by cancellation (or the token was already cancelled).
+pub async fn sleep_unless_cancelled(duration: Duration, cancel: Option<&CancellationToken>) -> bool {
+    match cancel {
+        Some(token) => {
+            if token.is_cancelled() {
+                return false;
+            }
+            tokio::select! {
+                _ = sleep(duration) => true,
+                _ = token.cancelled() => false,
+            }
+        }
+        None => {
+            sleep(duration).await;
+            true
+        }
+    }
+}
+
+/// Whether the optional token has been cancelled.
+pub fn is_cancelled(cancel: Option<&CancellationToken>) -> bool {
+    cancel.map(|t| t.is_cancelled()).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sleep_completes_without_token() {
+        assert!(sleep_unless_cancelled(Duration::from_millis(1), None).await);
+    }
+
+    #[tokio::test]
+    async fn test_sleep_cut_short_when_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(!sleep_unless_cancelled(Duration::from_secs(60), Some(&token)).await);
+        assert!(is_cancelled(Some(&token)));
+        assert!(!is_cancelled(None));
+    }
+}
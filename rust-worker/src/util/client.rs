@@ -0,0 +1,220 @@
+//! Email client personas.
+//!
+//! A [`ClientPersona`] is the app and device a recipient reads mail with,
+//! e.g. the Gmail app on Android. Each one sends its own user agent and
+//! headers and reports its own device class. `CLIENT_MIX`, or a tenant
+//! profile's `client_mix`, weights them, e.g. `gmail_android|60,gmail_ios|20,
+//! outlook_windows|20` for an audience that is 80% mobile Gmail.
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::Serialize;
+
+use super::hash::stable_hash;
+use super::user_agent::{build_headers, FetchKind, UserAgentCategory};
+
+/// Email app and device a recipient reads mail with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientPersona {
+    GmailWeb,
+    GmailAndroid,
+    GmailIos,
+    AppleMailIphone,
+    AppleMailIpad,
+    AppleMailMac,
+    OutlookWindows,
+    OutlookIos,
+    YahooWeb,
+}
+
+impl ClientPersona {
+    /// Every persona, in the order they are documented.
+    pub const ALL: &'static [ClientPersona] = &[
+        ClientPersona::GmailWeb,
+        ClientPersona::GmailAndroid,
+        ClientPersona::GmailIos,
+        ClientPersona::AppleMailIphone,
+        ClientPersona::AppleMailIpad,
+        ClientPersona::AppleMailMac,
+        ClientPersona::OutlookWindows,
+        ClientPersona::OutlookIos,
+        ClientPersona::YahooWeb,
+    ];
+
+    /// Parse a persona name such as `gmail_android`.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|client| client.as_str() == name)
+    }
+
+    /// Stable name used in config, logs and results.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClientPersona::GmailWeb => "gmail_web",
+            ClientPersona::GmailAndroid => "gmail_android",
+            ClientPersona::GmailIos => "gmail_ios",
+            ClientPersona::AppleMailIphone => "apple_mail_iphone",
+            ClientPersona::AppleMailIpad => "apple_mail_ipad",
+            ClientPersona::AppleMailMac => "apple_mail_mac",
+            ClientPersona::OutlookWindows => "outlook_windows",
+            ClientPersona::OutlookIos => "outlook_ios",
+            ClientPersona::YahooWeb => "yahoo_web",
+        }
+    }
+
+    /// User agent the client fetches images and opens links with.
+    pub fn user_agent(self) -> &'static str {
+        match self {
+            ClientPersona::GmailWeb => "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+            ClientPersona::GmailAndroid => "Mozilla/5.0 (Linux; Android 14; Pixel 8 Build/UQ1A.240105.004; wv) AppleWebKit/537.36 (KHTML, like Gecko) Version/4.0 Chrome/120.0.6099.230 Mobile Safari/537.36",
+            ClientPersona::GmailIos => "Mozilla/5.0 (iPhone; CPU iPhone OS 17_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148 Gmail/6.0.240107",
+            ClientPersona::AppleMailIphone => "Mozilla/5.0 (iPhone; CPU iPhone OS 17_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148",
+            ClientPersona::AppleMailIpad => "Mozilla/5.0 (iPad; CPU OS 17_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148",
+            ClientPersona::AppleMailMac => "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko)",
+            ClientPersona::OutlookWindows => "Mozilla/4.0 (compatible; ms-office; MSOffice 16)",
+            ClientPersona::OutlookIos => "Mozilla/5.0 (iPhone; CPU iPhone OS 17_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148 Outlook-iOS/4.2403.0",
+            ClientPersona::YahooWeb => "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0",
+        }
+    }
+
+    /// Device class the client runs on.
+    pub fn category(self) -> UserAgentCategory {
+        match self {
+            ClientPersona::GmailAndroid
+            | ClientPersona::GmailIos
+            | ClientPersona::AppleMailIphone
+            | ClientPersona::OutlookIos => UserAgentCategory::Mobile,
+            ClientPersona::AppleMailIpad => UserAgentCategory::Tablet,
+            ClientPersona::GmailWeb
+            | ClientPersona::AppleMailMac
+            | ClientPersona::OutlookWindows
+            | ClientPersona::YahooWeb => UserAgentCategory::Desktop,
+        }
+    }
+
+    /// Headers the client sends with fetches of `kind`.
+    pub fn headers(self, kind: FetchKind) -> Vec<(String, String)> {
+        let mut headers = build_headers(self.user_agent(), kind);
+        // The Gmail app's web view names the app that embeds it
+        if self == ClientPersona::GmailAndroid {
+            set_header(&mut headers, "X-Requested-With", "com.google.android.gm");
+        }
+        headers
+    }
+}
+
+fn set_header(headers: &mut Vec<(String, String)>, name: &str, value: &str) {
+    match headers.iter_mut().find(|(key, _)| key == name) {
+        Some((_, existing)) => *existing = value.to_string(),
+        None => headers.push((name.to_string(), value.to_string())),
+    }
+}
+
+/// A client persona with its relative share of recipients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedClient {
+    pub client: ClientPersona,
+    pub weight: f64,
+}
+
+impl WeightedClient {
+    /// Parse a mix entry of the form `client` or `client|weight`.
+    ///
+    /// Entries without a weight count as `1.0`. Returns `None` for unknown
+    /// clients and weights that are not finite, non-negative numbers.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim();
+        let (name, weight) = match entry.rsplit_once('|') {
+            Some((name, weight)) => (name, weight.trim().parse::<f64>().ok()?),
+            None => (entry, 1.0),
+        };
+        if !weight.is_finite() || weight < 0.0 {
+            return None;
+        }
+        Some(Self {
+            client: ClientPersona::parse(name)?,
+            weight,
+        })
+    }
+}
+
+/// Pick a random client from `mix` in proportion to the weights; a mix
+/// whose weights are all zero is sampled uniformly.
+///
+/// Returns `None` for an empty mix.
+pub fn pick_client(mix: &[WeightedClient], rng: &mut impl Rng) -> Option<ClientPersona> {
+    choose(mix, rng)
+}
+
+/// Pick a client that stays fixed for a given recipient.
+pub fn pick_client_for(mix: &[WeightedClient], recipient: &str) -> Option<ClientPersona> {
+    let seed = stable_hash(&["client", &recipient.trim().to_lowercase()]);
+    choose(mix, &mut StdRng::seed_from_u64(seed))
+}
+
+fn choose<R: Rng>(mix: &[WeightedClient], rng: &mut R) -> Option<ClientPersona> {
+    mix.choose_weighted(rng, |c| c.weight)
+        .ok()
+        .or_else(|| mix.choose(rng))
+        .map(|c| c.client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_client_parse() {
+        assert_eq!(
+            WeightedClient::parse(" Gmail_Android | 80 "),
+            Some(WeightedClient {
+                client: ClientPersona::GmailAndroid,
+                weight: 80.0,
+            })
+        );
+        assert_eq!(
+            WeightedClient::parse("outlook_windows").map(|c| c.weight),
+            Some(1.0)
+        );
+        assert_eq!(WeightedClient::parse("thunderbird|1"), None);
+        assert_eq!(WeightedClient::parse("gmail_web|-1"), None);
+        assert_eq!(WeightedClient::parse("gmail_web|most"), None);
+
+        for client in ClientPersona::ALL {
+            assert_eq!(ClientPersona::parse(client.as_str()), Some(*client));
+            assert_eq!(
+                UserAgentCategory::of(client.user_agent()),
+                client.category()
+            );
+        }
+    }
+
+    #[test]
+    fn test_pick_client_follows_mix() {
+        let mix = vec![
+            WeightedClient::parse("gmail_android|1").unwrap(),
+            WeightedClient::parse("outlook_windows|0").unwrap(),
+        ];
+        for _ in 0..50 {
+            assert_eq!(
+                pick_client(&mix, &mut thread_rng()),
+                Some(ClientPersona::GmailAndroid)
+            );
+        }
+        assert_eq!(pick_client(&[], &mut thread_rng()), None);
+        assert_eq!(
+            pick_client_for(&mix, "User@example.com"),
+            pick_client_for(&mix, "user@example.com")
+        );
+
+        let headers = ClientPersona::GmailAndroid.headers(FetchKind::Page);
+        assert!(headers
+            .iter()
+            .any(|(k, v)| k == "X-Requested-With" && v == "com.google.android.gm"));
+        assert_eq!(headers.iter().filter(|(k, _)| k == "Accept").count(), 1);
+    }
+}
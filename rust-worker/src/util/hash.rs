@@ -0,0 +1,35 @@
+//! Stable hashing for sticky assignments.
+//!
+//! `DefaultHasher` makes no promise about its algorithm, so a Rust upgrade
+//! could silently move every recipient to another cohort or change which jobs
+//! are mirrored. Anything that must agree across processes, deploys or
+//! reruns hashes with [`stable_hash`] instead: 64-bit FNV-1a, fixed forever.
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a hash of `parts`, each followed by a zero byte so that
+/// `["ab", "c"]` and `["a", "bc"]` hash differently.
+pub fn stable_hash(parts: &[&str]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for part in parts {
+        for byte in part.bytes().chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable_hash_is_pinned() {
+        // Changing these moves every sticky assignment; don't.
+        assert_eq!(stable_hash(&[]), FNV_OFFSET_BASIS);
+        assert_eq!(stable_hash(&["a"]), 0x089b_e207_b544_f1e4);
+        assert_ne!(stable_hash(&["ab", "c"]), stable_hash(&["a", "bc"]));
+    }
+}
@@ -0,0 +1,61 @@
+//! Stable identity for this process instance.
+//!
+//! Generated once at startup from the host name plus a random suffix, so
+//! replicas sharing a host (or a recycled dyno name) stay distinguishable.
+
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+
+use rand::Rng;
+
+static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+/// Identifier for this process, e.g. `rust-worker.1-3fa92c`.
+///
+/// `WORKER_INSTANCE_ID` overrides the generated value.
+pub fn instance_id() -> &'static str {
+    INSTANCE_ID.get_or_init(|| {
+        env::var("WORKER_INSTANCE_ID")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| format!("{}-{}", hostname(), random_suffix()))
+    })
+}
+
+/// Best-effort host name: Heroku dyno name, `HOSTNAME`, then `/etc/hostname`.
+fn hostname() -> String {
+    ["DYNO", "HOSTNAME"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .chain(fs::read_to_string("/etc/hostname").ok())
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Six random hex characters.
+fn random_suffix() -> String {
+    let n: u32 = rand::thread_rng().gen_range(0..0x100_0000);
+    format!("{:06x}", n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_id_is_stable() {
+        let id = instance_id();
+        assert!(!id.is_empty());
+        assert_eq!(id, instance_id());
+    }
+
+    #[test]
+    fn test_random_suffix_format() {
+        let suffix = random_suffix();
+        assert_eq!(suffix.len(), 6);
+        assert!(suffix.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
@@ -0,0 +1,57 @@
+//! Tracing subscriber setup shared by the binaries.
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use super::redact::RedactingWriter;
+
+/// Log output format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum LogFormat {
+    /// One flattened JSON object per line (production default)
+    #[default]
+    Json,
+    /// Human-readable, colored output for local runs
+    Pretty,
+}
+
+/// Install the global subscriber; the level comes from `RUST_LOG` (default `info`).
+///
+/// Output passes through [`RedactingWriter`], so registered secrets never
+/// reach the logs.
+pub fn init(format: LogFormat) {
+    init_with_default(format, "info");
+}
+
+/// Like [`init`], with `default` as the filter when `RUST_LOG` is unset.
+pub fn init_with_default(format: LogFormat, default: &str) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default));
+    let registry = tracing_subscriber::registry().with(filter);
+    let writer = || RedactingWriter(std::io::stdout());
+
+    match format {
+        LogFormat::Json => registry.with(json_layer(writer)).init(),
+        LogFormat::Pretty => registry.with(fmt::layer().pretty().with_writer(writer)).init(),
+    }
+}
+
+/// The JSON output layer, writing one flattened object per line to `writer`.
+///
+/// Lines carry only the fields of the innermost span, not its parents', so
+/// a span nested in the worker's root span must repeat its `instance`.
+pub fn json_layer<S, W>(writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    fmt::layer()
+        .json()
+        .flatten_event(true)
+        .with_span_list(false)
+        .with_writer(writer)
+}
@@ -1,3 +1,12 @@
 //! Utility modules.
 
+pub mod cancel;
+pub mod client;
+pub mod hash;
+pub mod instance;
+pub mod logging;
+pub mod panic;
+pub mod redact;
+pub mod systemd;
+pub mod url;
 pub mod user_agent;
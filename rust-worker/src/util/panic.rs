@@ -0,0 +1,91 @@
+//! Panic containment around third-party parsers.
+//!
+//! mailparse and scraper are fed whatever arrives in a webhook. A panic in
+//! either would otherwise end the task handling the message, leaving it
+//! unacknowledged with nothing logged but the panic itself. [`contain`]
+//! turns the panic into an error the caller can quarantine like any other
+//! malformed message. [`contain_async`] does the same for a future, so a
+//! job that panics fails alone rather than the task running it.
+//!
+//! Containment needs unwinding panics; the release profile must not set
+//! `panic = "abort"`.
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use futures::FutureExt;
+use tracing::error;
+
+/// A panic caught by [`contain`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("panicked: {0}")]
+pub struct Panicked(pub String);
+
+/// Run `f`, returning its panic as an error instead of unwinding further.
+///
+/// `what` names the work in the `panic_contained` log line, e.g. `email`.
+/// Nothing `f` borrows is used again after a panic, so unwind safety is
+/// asserted rather than required.
+pub fn contain<T>(what: &'static str, f: impl FnOnce() -> T) -> Result<T, Panicked> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let panicked = Panicked(message(payload.as_ref()));
+        error!(what = what, error = %panicked.0, "panic_contained");
+        panicked
+    })
+}
+
+/// Await `future`, returning a panic while polling it as an error, like
+/// [`contain`].
+pub async fn contain_async<T>(what: &'static str, future: impl Future<Output = T>) -> Result<T, Panicked> {
+    AssertUnwindSafe(future).catch_unwind().await.map_err(|payload| {
+        let panicked = Panicked(message(payload.as_ref()));
+        error!(what = what, error = %panicked.0, "panic_contained");
+        panicked
+    })
+}
+
+/// The message a panic was raised with.
+pub fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contain_returns_panics_as_errors() {
+        assert_eq!(contain("test", || 42), Ok(42));
+        assert_eq!(
+            contain("test", || -> u8 { panic!("bad header") }),
+            Err(Panicked("bad header".to_string()))
+        );
+        let index = 7;
+        let err = contain("test", || [1, 2, 3][index]).unwrap_err();
+        assert!(err.0.contains("index out of bounds"), "{}", err);
+        assert_eq!(
+            contain("test", || std::panic::panic_any(3)).unwrap_err().0,
+            "non-string panic payload"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_contain_async_returns_panics_as_errors() {
+        assert_eq!(contain_async("test", async { 42 }).await, Ok(42));
+        let panicking = async {
+            tokio::task::yield_now().await;
+            panic!("bad job")
+        };
+        assert_eq!(
+            contain_async::<()>("test", panicking).await,
+            Err(Panicked("bad job".to_string()))
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! systemd readiness and watchdog notifications (`sd_notify`).
+//!
+//! Under a `Type=notify` unit, systemd passes a datagram socket in
+//! `NOTIFY_SOCKET`. Each binary reports `READY=1` once it is serving and
+//! `STOPPING=1` when shutdown begins. With `WatchdogSec=` set, systemd also
+//! passes `WATCHDOG_USEC`, and the processor's and worker's consume loops
+//! (and the web server's runtime) send `WATCHDOG=1` at half that interval,
+//! so a loop that hangs stops pinging and systemd restarts the service.
+//!
+//! Everything here does nothing outside systemd, e.g. on Kubernetes or
+//! Heroku.
+
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::config::VarSource;
+
+/// Tell systemd the service is up.
+pub fn ready() {
+    notify_logged("READY=1");
+}
+
+/// Tell systemd the service is shutting down.
+pub fn stopping() {
+    notify_logged("STOPPING=1");
+}
+
+/// Send `state` to the socket in `NOTIFY_SOCKET`, returning whether there
+/// was one.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => send(&socket, state).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+fn notify_logged(state: &str) {
+    if let Err(e) = notify(state) {
+        warn!(state = state, error = %e, "systemd_notify_failed");
+    }
+}
+
+/// Send `state` to the datagram socket at `socket`; a leading `@` names a
+/// Linux abstract socket.
+#[cfg(unix)]
+fn send(socket: &OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let sender = UnixDatagram::unbound()?;
+    match socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets need Linux",
+            ))
+        }
+        None => {
+            sender.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "NOTIFY_SOCKET needs Unix",
+    ))
+}
+
+/// The systemd watchdog of this process, when the unit sets `WatchdogSec=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchdog {
+    timeout: Duration,
+}
+
+impl Watchdog {
+    /// The watchdog systemd set up for this process, if any.
+    pub fn from_env() -> Option<Self> {
+        let watchdog = Self::from_vars(&|name| env::var(name).ok(), std::process::id())?;
+        info!(
+            timeout_ms = watchdog.timeout.as_millis() as u64,
+            "systemd_watchdog_enabled"
+        );
+        Some(watchdog)
+    }
+
+    /// The watchdog described by `NOTIFY_SOCKET`, `WATCHDOG_USEC` and
+    /// `WATCHDOG_PID` in `var`, for process `pid`.
+    ///
+    /// A `WATCHDOG_PID` naming another process means the watchdog is not
+    /// ours, e.g. it was inherited from a parent.
+    pub fn from_vars(var: &VarSource<'_>, pid: u32) -> Option<Self> {
+        var("NOTIFY_SOCKET")?;
+        let usec: u64 = var("WATCHDOG_USEC")?.trim().parse().ok()?;
+        if usec == 0 {
+            return None;
+        }
+        match var("WATCHDOG_PID") {
+            Some(watchdog_pid) if watchdog_pid.trim().parse() != Ok(pid) => None,
+            _ => Some(Self {
+                timeout: Duration::from_micros(usec),
+            }),
+        }
+    }
+
+    /// How often to ping: half the timeout, as systemd recommends.
+    pub fn period(&self) -> Duration {
+        self.timeout / 2
+    }
+
+    /// Tell systemd the service is still alive.
+    pub fn ping(&self) {
+        notify_logged("WATCHDOG=1");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_from_vars() {
+        let watchdog = |vars: &'static [(&'static str, &'static str)]| {
+            Watchdog::from_vars(
+                &move |name| {
+                    vars.iter()
+                        .find(|(var, _)| *var == name)
+                        .map(|(_, value)| value.to_string())
+                },
+                42,
+            )
+        };
+
+        const SOCKET: (&str, &str) = ("NOTIFY_SOCKET", "/run/systemd/notify");
+        let ours = watchdog(&[SOCKET, ("WATCHDOG_USEC", "30000000"), ("WATCHDOG_PID", "42")]);
+        assert_eq!(ours.map(|w| w.period()), Some(Duration::from_secs(15)));
+        assert!(watchdog(&[SOCKET, ("WATCHDOG_USEC", "30000000")]).is_some());
+        assert!(watchdog(&[SOCKET, ("WATCHDOG_USEC", "30000000"), ("WATCHDOG_PID", "7")]).is_none());
+        assert!(watchdog(&[SOCKET, ("WATCHDOG_USEC", "0")]).is_none());
+        assert!(watchdog(&[SOCKET]).is_none());
+        assert!(watchdog(&[("WATCHDOG_USEC", "30000000")]).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send_to_notify_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = env::temp_dir().join(format!("bobnet-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+
+        send(path.as_os_str(), "WATCHDOG=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"WATCHDOG=1");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(send(path.as_os_str(), "READY=1").is_err());
+    }
+}
@@ -1,6 +1,10 @@
 //! User agent rotation utilities.
 
 use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::Serialize;
+
+use super::hash::stable_hash;
 
 /// Default user agents if none are configured.
 const DEFAULT_USER_AGENTS: &[&str] = &[
@@ -12,25 +16,135 @@ const DEFAULT_USER_AGENTS: &[&str] = &[
     "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
 ];
 
+/// A user agent with its relative share of traffic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedAgent {
+    pub agent: String,
+    pub weight: f64,
+}
+
+impl WeightedAgent {
+    /// Parse a pool entry of the form `agent` or `agent|weight`.
+    ///
+    /// Entries without a weight count as `1.0`. Returns `None` if the weight
+    /// is not a finite, non-negative number.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim();
+        let (agent, weight) = match entry.rsplit_once('|') {
+            Some((agent, weight)) => (agent.trim(), weight.trim().parse::<f64>().ok()?),
+            None => (entry, 1.0),
+        };
+
+        if agent.is_empty() || !weight.is_finite() || weight < 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            agent: agent.to_string(),
+            weight,
+        })
+    }
+}
+
+impl From<&str> for WeightedAgent {
+    fn from(agent: &str) -> Self {
+        Self {
+            agent: agent.to_string(),
+            weight: 1.0,
+        }
+    }
+}
+
+/// Device class of a user agent, reported with simulation results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserAgentCategory {
+    Desktop,
+    Mobile,
+    Tablet,
+}
+
+impl UserAgentCategory {
+    /// Classify a user agent string by its platform tokens.
+    pub fn of(user_agent: &str) -> Self {
+        if user_agent.contains("iPad") || user_agent.contains("Tablet") {
+            UserAgentCategory::Tablet
+        } else if user_agent.contains("Mobile")
+            || user_agent.contains("iPhone")
+            || user_agent.contains("Android")
+        {
+            UserAgentCategory::Mobile
+        } else {
+            UserAgentCategory::Desktop
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UserAgentCategory::Desktop => "desktop",
+            UserAgentCategory::Mobile => "mobile",
+            UserAgentCategory::Tablet => "tablet",
+        }
+    }
+}
+
 /// Pick a random user agent from the configured pool or defaults.
-pub fn pick_user_agent(pool: Option<&[String]>) -> String {
-    let mut rng = thread_rng();
-    
+///
+/// Pool entries are chosen in proportion to their weights; a pool whose
+/// weights are all zero is sampled uniformly.
+pub fn pick_user_agent(pool: Option<&[WeightedAgent]>, rng: &mut impl Rng) -> String {
+    choose(pool, rng)
+}
+
+/// Pick a user agent that stays fixed for a given recipient.
+///
+/// Models a subscriber who reads mail on the same device every time. Across
+/// recipients, agents are still spread according to their weights.
+pub fn pick_user_agent_for(pool: Option<&[WeightedAgent]>, recipient: &str) -> String {
+    let seed = stable_hash(&[&recipient.trim().to_lowercase()]);
+    choose(pool, &mut StdRng::seed_from_u64(seed))
+}
+
+fn choose<R: Rng>(pool: Option<&[WeightedAgent]>, rng: &mut R) -> String {
     match pool {
-        Some(agents) if !agents.is_empty() => {
-            agents.choose(&mut rng).unwrap().clone()
-        }
-        _ => {
-            DEFAULT_USER_AGENTS.choose(&mut rng).unwrap().to_string()
+        Some(agents) if !agents.is_empty() => agents
+            .choose_weighted(rng, |a| a.weight)
+            .or_else(|_| agents.choose(rng).ok_or(()))
+            .map(|a| a.agent.clone())
+            .unwrap_or_default(),
+        _ => DEFAULT_USER_AGENTS.choose(rng).unwrap().to_string(),
+    }
+}
+
+/// What a simulated fetch asks for, which decides its `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchKind {
+    /// Tracking pixels and other images, loaded when the email is opened
+    Image,
+    /// Pages opened by clicking a link
+    Page,
+}
+
+impl FetchKind {
+    /// `Accept` header a browser sends for this kind of fetch.
+    ///
+    /// Some tracking systems fingerprint on it, so a blanket `*/*` gives
+    /// simulated traffic away.
+    pub fn accept(self) -> &'static str {
+        match self {
+            FetchKind::Image => "image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8",
+            FetchKind::Page => {
+                "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8"
+            }
         }
     }
 }
 
-/// Build standard headers for HTTP requests.
-pub fn build_headers(user_agent: &str) -> Vec<(String, String)> {
+/// Build standard headers for HTTP requests of `kind`.
+pub fn build_headers(user_agent: &str, kind: FetchKind) -> Vec<(String, String)> {
     vec![
         ("User-Agent".to_string(), user_agent.to_string()),
-        ("Accept".to_string(), "*/*".to_string()),
+        ("Accept".to_string(), kind.accept().to_string()),
         ("Accept-Language".to_string(), "en-US,en;q=0.9".to_string()),
         ("Connection".to_string(), "keep-alive".to_string()),
     ]
@@ -42,29 +156,90 @@ mod tests {
 
     #[test]
     fn test_pick_user_agent_default() {
-        let ua = pick_user_agent(None);
+        let ua = pick_user_agent(None, &mut thread_rng());
         assert!(!ua.is_empty());
         assert!(ua.contains("Mozilla"));
     }
 
     #[test]
     fn test_pick_user_agent_custom() {
-        let custom = vec!["CustomAgent/1.0".to_string()];
-        let ua = pick_user_agent(Some(&custom));
+        let custom = vec![WeightedAgent::from("CustomAgent/1.0")];
+        let ua = pick_user_agent(Some(&custom), &mut thread_rng());
         assert_eq!(ua, "CustomAgent/1.0");
     }
 
     #[test]
     fn test_pick_user_agent_empty_pool() {
-        let empty: Vec<String> = vec![];
-        let ua = pick_user_agent(Some(&empty));
+        let empty: Vec<WeightedAgent> = vec![];
+        let ua = pick_user_agent(Some(&empty), &mut thread_rng());
         assert!(ua.contains("Mozilla"));
     }
 
+    #[test]
+    fn test_pick_user_agent_for_is_sticky() {
+        let a = pick_user_agent_for(None, "user@example.com");
+        let b = pick_user_agent_for(None, "USER@example.com");
+        assert_eq!(a, b);
+
+        let custom = vec![WeightedAgent::from("A/1.0"), WeightedAgent::from("B/1.0")];
+        let ua = pick_user_agent_for(Some(&custom), "user@example.com");
+        assert!(custom.iter().any(|a| a.agent == ua));
+    }
+
+    #[test]
+    fn test_weighted_agent_parse() {
+        assert_eq!(
+            WeightedAgent::parse(" A/1.0 | 0.4 "),
+            Some(WeightedAgent { agent: "A/1.0".to_string(), weight: 0.4 })
+        );
+        assert_eq!(WeightedAgent::parse("B/1.0"), Some(WeightedAgent::from("B/1.0")));
+        assert_eq!(WeightedAgent::parse("C/1.0|heavy"), None);
+        assert_eq!(WeightedAgent::parse("C/1.0|-1"), None);
+        assert_eq!(WeightedAgent::parse("|0.5"), None);
+    }
+
+    #[test]
+    fn test_pick_user_agent_respects_weights() {
+        let pool = vec![
+            WeightedAgent { agent: "Never/1.0".to_string(), weight: 0.0 },
+            WeightedAgent { agent: "Always/1.0".to_string(), weight: 1.0 },
+        ];
+        for _ in 0..50 {
+            assert_eq!(
+                pick_user_agent(Some(&pool), &mut thread_rng()),
+                "Always/1.0"
+            );
+        }
+
+        let zero = vec![WeightedAgent { agent: "Zero/1.0".to_string(), weight: 0.0 }];
+        assert_eq!(pick_user_agent(Some(&zero), &mut thread_rng()), "Zero/1.0");
+    }
+
+    #[test]
+    fn test_user_agent_category() {
+        assert_eq!(UserAgentCategory::of(DEFAULT_USER_AGENTS[0]), UserAgentCategory::Desktop);
+        assert_eq!(UserAgentCategory::of(DEFAULT_USER_AGENTS[4]), UserAgentCategory::Mobile);
+        assert_eq!(UserAgentCategory::of(DEFAULT_USER_AGENTS[5]), UserAgentCategory::Mobile);
+        assert_eq!(
+            UserAgentCategory::of("Mozilla/5.0 (iPad; CPU OS 17_2 like Mac OS X)"),
+            UserAgentCategory::Tablet
+        );
+    }
+
     #[test]
     fn test_build_headers() {
-        let headers = build_headers("TestAgent/1.0");
+        let headers = build_headers("TestAgent/1.0", FetchKind::Image);
         assert_eq!(headers.len(), 4);
         assert!(headers.iter().any(|(k, v)| k == "User-Agent" && v == "TestAgent/1.0"));
+        let accept = |headers: &[(String, String)]| {
+            headers
+                .iter()
+                .find(|(k, _)| k == "Accept")
+                .map(|(_, v)| v.clone())
+                .unwrap()
+        };
+        assert!(accept(&headers).starts_with("image/avif,image/webp,"));
+        let page = build_headers("TestAgent/1.0", FetchKind::Page);
+        assert!(accept(&page).starts_with("text/html,"));
     }
 }
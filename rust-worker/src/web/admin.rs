@@ -0,0 +1,279 @@
+//! Admin API for per-campaign simulation overrides and tenant profiles.
+//!
+//! `PUT /admin/campaigns/{id}/overrides` registers how a campaign (SFMC job)
+//! is simulated, `GET` shows it and `DELETE` removes it. Overrides are kept
+//! in the `CAMPAIGN_OVERRIDES_URL` store, which workers read them from; the
+//! routes answer 503 when it is not configured.
+//!
+//! `/admin/tenants/{tag}` does the same for a tenant's profile (settings,
+//! quota, callback and domain lists) in the `TENANT_PROFILES_URL` store, so
+//! onboarding a customer is an API call instead of a redeploy.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use tracing::{error, info};
+
+use crate::campaign::is_campaign_id;
+use crate::campaign::overrides::{CampaignOverride, OverrideError, OverrideStore};
+use crate::tenant::{is_tenant_tag, ProfileError, ProfileStore, TenantProfile};
+use crate::web::handlers::AppState;
+
+type AdminError = (StatusCode, String);
+
+/// The configured store, after checking `id` is a campaign ID.
+fn store_for<'a>(state: &'a AppState, id: &str) -> Result<&'a Arc<dyn OverrideStore>, AdminError> {
+    if !is_campaign_id(id) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("invalid campaign ID `{}`", id),
+        ));
+    }
+    state.campaign_overrides.as_ref().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "campaign overrides are not configured (set CAMPAIGN_OVERRIDES_URL)".to_string(),
+        )
+    })
+}
+
+fn store_failed(id: &str, e: OverrideError) -> AdminError {
+    error!(campaign_id = %id, error = %e, "campaign_override_store_failed");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "campaign override store error".to_string(),
+    )
+}
+
+/// `GET /admin/campaigns/{id}/overrides`
+pub async fn get_campaign_override(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<CampaignOverride>, AdminError> {
+    let store = store_for(&state, &id)?;
+    match store.load(&id).await {
+        Ok(Some(value)) => Ok(Json(value)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("campaign `{}` has no override", id),
+        )),
+        Err(e) => Err(store_failed(&id, e)),
+    }
+}
+
+/// `PUT /admin/campaigns/{id}/overrides`
+pub async fn put_campaign_override(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(value): Json<CampaignOverride>,
+) -> Result<Json<CampaignOverride>, AdminError> {
+    let store = store_for(&state, &id)?;
+    let errors = value.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, errors.join("; ")));
+    }
+    store
+        .save(&id, &value)
+        .await
+        .map_err(|e| store_failed(&id, e))?;
+
+    info!(campaign_id = %id, "campaign_override_saved");
+    Ok(Json(value))
+}
+
+/// `DELETE /admin/campaigns/{id}/overrides`
+pub async fn delete_campaign_override(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    let store = store_for(&state, &id)?;
+    match store.delete(&id).await {
+        Ok(true) => {
+            info!(campaign_id = %id, "campaign_override_deleted");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            format!("campaign `{}` has no override", id),
+        )),
+        Err(e) => Err(store_failed(&id, e)),
+    }
+}
+
+/// The configured profile store, after checking `tag` is a customer tag.
+fn profile_store_for<'a>(
+    state: &'a AppState,
+    tag: &str,
+) -> Result<&'a Arc<dyn ProfileStore>, AdminError> {
+    if !is_tenant_tag(tag) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("invalid customer tag `{}`", tag),
+        ));
+    }
+    state.tenant_profiles.as_ref().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "tenant profiles are not configured (set TENANT_PROFILES_URL)".to_string(),
+        )
+    })
+}
+
+fn profile_store_failed(tag: &str, e: ProfileError) -> AdminError {
+    error!(customer_tag = %tag, error = %e, "tenant_profile_store_failed");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "tenant profile store error".to_string(),
+    )
+}
+
+/// `GET /admin/tenants/{tag}`
+pub async fn get_tenant(
+    State(state): State<AppState>,
+    Path(tag): Path<String>,
+) -> Result<Json<TenantProfile>, AdminError> {
+    let tag = tag.to_lowercase();
+    let store = profile_store_for(&state, &tag)?;
+    match store.load(&tag).await {
+        Ok(Some(profile)) => Ok(Json(profile)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("tenant `{}` has no profile", tag),
+        )),
+        Err(e) => Err(profile_store_failed(&tag, e)),
+    }
+}
+
+/// `PUT /admin/tenants/{tag}`
+pub async fn put_tenant(
+    State(state): State<AppState>,
+    Path(tag): Path<String>,
+    Json(profile): Json<TenantProfile>,
+) -> Result<Json<TenantProfile>, AdminError> {
+    let tag = tag.to_lowercase();
+    let store = profile_store_for(&state, &tag)?;
+    let errors = profile.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, errors.join("; ")));
+    }
+    store
+        .save(&tag, &profile)
+        .await
+        .map_err(|e| profile_store_failed(&tag, e))?;
+
+    info!(customer_tag = %tag, "tenant_profile_saved");
+    Ok(Json(profile))
+}
+
+/// `DELETE /admin/tenants/{tag}`
+pub async fn delete_tenant(
+    State(state): State<AppState>,
+    Path(tag): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    let tag = tag.to_lowercase();
+    let store = profile_store_for(&state, &tag)?;
+    match store.delete(&tag).await {
+        Ok(true) => {
+            info!(customer_tag = %tag, "tenant_profile_deleted");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            format!("tenant `{}` has no profile", tag),
+        )),
+        Err(e) => Err(profile_store_failed(&tag, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::overrides::MemoryOverrides;
+    use crate::tenant::MemoryProfiles;
+    use crate::web::router;
+    use crate::{Publisher, WebConfig};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn send(app: &axum::Router, method: &str, uri: &str, body: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_campaign_override_routes() {
+        let config = WebConfig::default();
+        let publisher = Publisher::new(config.core.cloudamqp_url.clone());
+        let state = AppState::new(config, publisher);
+        let unconfigured = router(state.clone());
+        let store = Arc::new(MemoryOverrides::default());
+        let app = router(state.with_campaign_overrides(store.clone()));
+        let uri = "/admin/campaigns/12345/overrides";
+        let cta_only = r#"{"open_probability": 1.0, "click_only": ["/cta"]}"#;
+
+        assert_eq!(
+            send(&unconfigured, "GET", uri, "").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(send(&app, "GET", uri, "").await, StatusCode::NOT_FOUND);
+        assert_eq!(send(&app, "PUT", uri, cta_only).await, StatusCode::OK);
+        assert!(store.load("12345").await.unwrap().is_some());
+        assert_eq!(send(&app, "GET", uri, "").await, StatusCode::OK);
+
+        assert_eq!(
+            send(&app, "PUT", uri, r#"{"open_probability": 2.0}"#).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            send(&app, "PUT", "/admin/campaigns/a.b/overrides", cta_only).await,
+            StatusCode::BAD_REQUEST
+        );
+
+        assert_eq!(send(&app, "DELETE", uri, "").await, StatusCode::NO_CONTENT);
+        assert_eq!(send(&app, "DELETE", uri, "").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_routes() {
+        let config = WebConfig::default();
+        let publisher = Publisher::new(config.core.cloudamqp_url.clone());
+        let state = AppState::new(config, publisher);
+        let unconfigured = router(state.clone());
+        let store = Arc::new(MemoryProfiles::default());
+        let app = router(state.with_tenant_profiles(store.clone()));
+        let uri = "/admin/tenants/Acme";
+        let profile = r#"{"open_probability": 0.4, "quota_per_day": 5000, "deny_domains": ["unsubscribe.example.com"]}"#;
+
+        assert_eq!(
+            send(&unconfigured, "GET", uri, "").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(send(&app, "GET", uri, "").await, StatusCode::NOT_FOUND);
+        assert_eq!(send(&app, "PUT", uri, profile).await, StatusCode::OK);
+        let saved = store.load("acme").await.unwrap().unwrap();
+        assert_eq!(saved.quota_per_day, Some(5000));
+        assert_eq!(send(&app, "GET", uri, "").await, StatusCode::OK);
+
+        assert_eq!(
+            send(&app, "PUT", uri, r#"{"callback_url": "not a url"}"#).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            send(&app, "PUT", "/admin/tenants/a%2Fb", profile).await,
+            StatusCode::BAD_REQUEST
+        );
+
+        assert_eq!(send(&app, "DELETE", uri, "").await, StatusCode::NO_CONTENT);
+        assert_eq!(send(&app, "DELETE", uri, "").await, StatusCode::NOT_FOUND);
+    }
+}
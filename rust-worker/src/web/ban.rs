@@ -0,0 +1,309 @@
+//! Temporary bans for sources that keep failing webhook authentication.
+//!
+//! Every `401` from a webhook endpoint counts against the caller's IP. Once
+//! an IP reaches `AUTH_BAN_THRESHOLD` failures within `AUTH_BAN_DURATION_SECS`
+//! it is refused with `429` before any handler work until the ban lapses, so
+//! brute-force probing of tokens and signatures costs us almost nothing.
+//!
+//! The IP is the TCP peer unless `TRUSTED_PROXY_HOPS` says how many proxies
+//! in front of us append to `X-Forwarded-For`; see [`client_ip`].
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::{debug, warn};
+
+use super::handlers::AppState;
+
+/// Most addresses tracked at once; stale entries are pruned beyond this and
+/// new addresses go untracked if every entry is still live.
+const MAX_TRACKED: usize = 10_000;
+
+/// Per-IP authentication failure counts and active bans.
+#[derive(Debug)]
+pub struct AuthBans {
+    threshold: u32,
+    duration: Duration,
+    /// Proxies in front of us whose `X-Forwarded-For` hops are believed
+    trusted_proxy_hops: usize,
+    inner: Mutex<HashMap<IpAddr, Strikes>>,
+}
+
+#[derive(Debug)]
+struct Strikes {
+    failures: u32,
+    /// Start of the current counting window
+    since: Instant,
+    banned_until: Option<Instant>,
+}
+
+impl Strikes {
+    fn new(now: Instant) -> Self {
+        Self {
+            failures: 0,
+            since: now,
+            banned_until: None,
+        }
+    }
+
+    fn is_stale(&self, now: Instant, duration: Duration) -> bool {
+        match self.banned_until {
+            Some(until) => now >= until,
+            None => now.duration_since(self.since) > duration,
+        }
+    }
+}
+
+impl AuthBans {
+    /// Ban an IP for `duration` after `threshold` failures within `duration`.
+    ///
+    /// A threshold of 0 disables banning.
+    pub fn new(threshold: u32, duration: Duration) -> Self {
+        Self {
+            threshold,
+            duration,
+            trusted_proxy_hops: 0,
+            inner: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Identify callers by `X-Forwarded-For` as appended by `hops` proxies
+    /// in front of us, e.g. 1 behind the Heroku router. With 0, the default,
+    /// the header is ignored and callers are identified by the TCP peer.
+    pub fn with_trusted_proxy_hops(mut self, hops: usize) -> Self {
+        self.trusted_proxy_hops = hops;
+        self
+    }
+
+    /// Whether banning is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.threshold > 0
+    }
+
+    /// Whether `ip` is currently banned.
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(&ip).and_then(|s| s.banned_until) {
+            Some(until) if now < until => true,
+            Some(_) => {
+                entries.remove(&ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Count an authentication failure, returning `true` if it started a ban.
+    pub fn record_failure(&self, ip: IpAddr) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        if entries.len() >= MAX_TRACKED && !entries.contains_key(&ip) {
+            entries.retain(|_, s| !s.is_stale(now, self.duration));
+            if entries.len() >= MAX_TRACKED {
+                return false;
+            }
+        }
+
+        let strikes = entries.entry(ip).or_insert_with(|| Strikes::new(now));
+        if strikes.is_stale(now, self.duration) {
+            *strikes = Strikes::new(now);
+        }
+
+        strikes.failures += 1;
+        if strikes.failures >= self.threshold && strikes.banned_until.is_none() {
+            strikes.banned_until = Some(now + self.duration);
+            return true;
+        }
+        false
+    }
+
+    /// Clear an address's failures after it authenticated successfully.
+    pub fn record_success(&self, ip: IpAddr) {
+        let mut entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.get(&ip).is_some_and(|s| s.banned_until.is_none()) {
+            entries.remove(&ip);
+        }
+    }
+
+    /// Number of addresses currently banned.
+    pub fn banned_count(&self) -> usize {
+        let now = Instant::now();
+        let entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .values()
+            .filter(|s| s.banned_until.is_some_and(|until| now < until))
+            .count()
+    }
+}
+
+/// Middleware for webhook routes: refuse banned IPs and count `401`s.
+pub async fn guard_auth_failures(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = client_ip(&request, state.auth_bans.trusted_proxy_hops);
+    let bans = &state.auth_bans;
+
+    if let Some(ip) = ip.filter(|&ip| bans.is_banned(ip)) {
+        state.metrics.auth_banned_requests.inc();
+        debug!(ip = %ip, path = %request.uri().path(), "auth_ban_rejected");
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    let response = next.run(request).await;
+    let Some(ip) = ip else {
+        return response;
+    };
+
+    if response.status() == StatusCode::UNAUTHORIZED {
+        state.metrics.auth_failures.inc();
+        if bans.record_failure(ip) {
+            state.metrics.auth_bans.inc();
+            warn!(
+                ip = %ip,
+                failures = bans.threshold,
+                duration_secs = bans.duration.as_secs(),
+                "auth_ban_started"
+            );
+        }
+    } else if response.status().is_success() {
+        bans.record_success(ip);
+    }
+
+    response
+}
+
+/// Caller's address: the TCP peer, or behind `trusted_hops` proxies, the
+/// `X-Forwarded-For` hop appended by the outermost of them.
+///
+/// Each proxy appends the address it was connected from, so only the last
+/// `trusted_hops` hops are theirs; earlier ones come from the caller, who
+/// could rotate them to dodge a ban or claim a provider's address to get it
+/// banned. Without trusted proxies the header is ignored altogether.
+fn client_ip(request: &Request, trusted_hops: usize) -> Option<IpAddr> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    if trusted_hops == 0 {
+        return peer;
+    }
+
+    let hops: Vec<&str> = request
+        .headers()
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    // Fewer hops than proxies means the outermost added none; take the
+    // furthest there is
+    hops.len()
+        .checked_sub(trusted_hops)
+        .and_then(|i| hops.get(i))
+        .or_else(|| hops.first())
+        .and_then(|hop| hop.parse().ok())
+        .or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([203, 0, 113, last])
+    }
+
+    #[test]
+    fn test_bans_after_threshold() {
+        let bans = AuthBans::new(3, Duration::from_secs(300));
+        assert!(!bans.record_failure(ip(1)));
+        assert!(!bans.record_failure(ip(1)));
+        assert!(!bans.is_banned(ip(1)));
+        assert!(bans.record_failure(ip(1)));
+        assert!(bans.is_banned(ip(1)));
+        assert!(!bans.is_banned(ip(2)));
+        assert_eq!(bans.banned_count(), 1);
+
+        // Further failures don't restart the ban
+        assert!(!bans.record_failure(ip(1)));
+    }
+
+    #[test]
+    fn test_success_clears_failures() {
+        let bans = AuthBans::new(2, Duration::from_secs(300));
+        assert!(!bans.record_failure(ip(1)));
+        bans.record_success(ip(1));
+        assert!(!bans.record_failure(ip(1)));
+        assert!(!bans.is_banned(ip(1)));
+    }
+
+    #[test]
+    fn test_ban_lapses() {
+        let bans = AuthBans::new(1, Duration::ZERO);
+        assert!(bans.record_failure(ip(1)));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!bans.is_banned(ip(1)));
+        assert_eq!(bans.banned_count(), 0);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let bans = AuthBans::new(0, Duration::from_secs(300));
+        assert!(!bans.record_failure(ip(1)));
+        assert!(!bans.is_banned(ip(1)));
+    }
+
+    fn forwarded(hops: &str) -> Request {
+        let mut request = Request::builder()
+            .header("x-forwarded-for", hops)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 9], 4000))));
+        request
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let request = forwarded("10.0.0.1, 198.51.100.7");
+        assert_eq!(client_ip(&request, 1), "198.51.100.7".parse().ok());
+        assert_eq!(client_ip(&request, 2), "10.0.0.1".parse().ok());
+        assert_eq!(client_ip(&request, 3), "10.0.0.1".parse().ok());
+
+        let mut request = Request::new(axum::body::Body::empty());
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 0, 2, 9], 4000))));
+        assert_eq!(client_ip(&request, 0), Some(IpAddr::from([192, 0, 2, 9])));
+        assert_eq!(client_ip(&request, 1), Some(IpAddr::from([192, 0, 2, 9])));
+    }
+
+    #[test]
+    fn test_client_ip_ignores_spoofed_hops() {
+        // Connected directly, claiming to be a Mailgun egress address
+        let request = forwarded("34.107.0.1");
+        assert_eq!(client_ip(&request, 0), Some(IpAddr::from([192, 0, 2, 9])));
+
+        // Behind one proxy, hops the caller sent before its own are ignored
+        let request = forwarded("34.107.0.1, 198.51.100.7");
+        assert_eq!(client_ip(&request, 1), "198.51.100.7".parse().ok());
+    }
+}
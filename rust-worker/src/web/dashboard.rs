@@ -0,0 +1,323 @@
+//! Status page answering "is the simulator healthy?" without Grafana.
+//!
+//! `GET /dashboard` renders one self-refreshing HTML page from what the web
+//! server can see: the depth of the queues it publishes to, the outcome
+//! stats of the workers in `WORKER_SIDECAR_URLS` over the last
+//! [`WINDOW`] (throughput and realized open and click rates), and its own
+//! recent errors.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, response::Html};
+use futures::future::join_all;
+use reqwest::Client;
+
+use super::handlers::AppState;
+use crate::stats::{fetch_summary, StatsSummary};
+
+/// Window of worker outcomes shown.
+pub const WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Recent errors kept for the page.
+const MAX_ERRORS: usize = 20;
+
+/// Seconds between automatic reloads of the page.
+const REFRESH_SECS: u64 = 30;
+
+/// One error worth showing on the dashboard.
+#[derive(Debug, Clone)]
+struct RecentError {
+    at: Instant,
+    event: &'static str,
+    detail: String,
+}
+
+/// What the dashboard keeps between requests.
+pub struct Dashboard {
+    /// Fetches the worker sidecars' stats
+    client: Client,
+    errors: Mutex<VecDeque<RecentError>>,
+}
+
+impl Dashboard {
+    /// Create an empty dashboard.
+    pub fn new() -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            errors: Mutex::new(VecDeque::with_capacity(MAX_ERRORS)),
+        }
+    }
+
+    /// Remember an error under its log event name, dropping the oldest
+    /// beyond the last [`MAX_ERRORS`].
+    pub fn record_error(&self, event: &'static str, detail: impl Display) {
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        if errors.len() == MAX_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(RecentError {
+            at: Instant::now(),
+            event,
+            detail: detail.to_string(),
+        });
+    }
+
+    /// Recent errors, newest first.
+    fn recent_errors(&self) -> Vec<RecentError> {
+        let errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        errors.iter().rev().cloned().collect()
+    }
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Everything one rendering of the page shows.
+struct Snapshot {
+    /// Ready messages per queue, or why they could not be read
+    queues: Result<Vec<(&'static str, u32)>, String>,
+    /// Merged stats of the workers that answered
+    stats: StatsSummary,
+    workers: usize,
+    /// Each worker that did not answer, with the reason
+    unreachable: Vec<(String, String)>,
+    errors: Vec<RecentError>,
+}
+
+/// Status dashboard endpoint.
+pub async fn dashboard(State(state): State<AppState>) -> Html<String> {
+    let config = &state.config;
+    let api_key = config.worker_api_key.as_deref();
+    let client = &state.dashboard.client;
+    let fetches = config.worker_sidecar_urls.iter().map(|url| async move {
+        let result = fetch_summary(client, url, WINDOW, api_key).await;
+        (url, result)
+    });
+    let (queues, summaries) = tokio::join!(state.publisher.queue_depths(), join_all(fetches));
+
+    let mut stats = StatsSummary {
+        window_secs: WINDOW.as_secs(),
+        ..StatsSummary::default()
+    };
+    let mut unreachable = Vec::new();
+    for (url, result) in summaries {
+        match result {
+            Ok(summary) => stats.merge(&summary),
+            Err(e) => unreachable.push((url.clone(), format!("{:#}", e))),
+        }
+    }
+
+    Html(render(&Snapshot {
+        queues: queues.map_err(|e| e.to_string()),
+        stats,
+        workers: config.worker_sidecar_urls.len(),
+        unreachable,
+        errors: state.dashboard.recent_errors(),
+    }))
+}
+
+fn render(snapshot: &Snapshot) -> String {
+    let mut page = String::new();
+    // Writing to a String cannot fail
+    let _ = write_page(&mut page, snapshot);
+    page
+}
+
+fn write_page(out: &mut String, snapshot: &Snapshot) -> fmt::Result {
+    let stats = &snapshot.stats;
+    let minutes = (stats.window_secs / 60).max(1);
+
+    let mut problems = Vec::new();
+    if let Err(e) = &snapshot.queues {
+        problems.push(format!("RabbitMQ unavailable: {}", e));
+    }
+    for (url, e) in &snapshot.unreachable {
+        problems.push(format!("worker {} unreachable: {}", url, e));
+    }
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(out, "<meta http-equiv=\"refresh\" content=\"{}\">", REFRESH_SECS)?;
+    writeln!(out, "<title>BobNet status</title>")?;
+    writeln!(
+        out,
+        "<style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse;margin-bottom:1.5em}}\
+         td,th{{border:1px solid #ccc;padding:4px 10px;text-align:left}}.ok{{color:#080}}.bad{{color:#b00}}</style>"
+    )?;
+    writeln!(out, "</head><body>")?;
+    writeln!(out, "<h1>BobNet status</h1>")?;
+    if problems.is_empty() {
+        writeln!(out, "<p class=\"ok\">Healthy</p>")?;
+    } else {
+        writeln!(out, "<p class=\"bad\">Degraded</p><ul>")?;
+        for problem in &problems {
+            writeln!(out, "<li>{}</li>", Escaped(problem))?;
+        }
+        writeln!(out, "</ul>")?;
+    }
+
+    writeln!(out, "<h2>Queues</h2>")?;
+    if let Ok(queues) = &snapshot.queues {
+        writeln!(out, "<table><tr><th>Queue</th><th>Ready</th></tr>")?;
+        for (queue, depth) in queues {
+            writeln!(out, "<tr><td>{}</td><td>{}</td></tr>", queue, depth)?;
+        }
+        writeln!(out, "</table>")?;
+    } else {
+        writeln!(out, "<p>Unknown</p>")?;
+    }
+
+    writeln!(out, "<h2>Last {} minutes</h2>", minutes)?;
+    if snapshot.workers == 0 {
+        writeln!(out, "<p>No workers configured (WORKER_SIDECAR_URLS)</p>")?;
+    } else {
+        let answered = snapshot.workers - snapshot.unreachable.len();
+        writeln!(out, "<table>")?;
+        row(out, "Workers answering", format_args!("{} of {}", answered, snapshot.workers))?;
+        row(
+            out,
+            "Throughput",
+            format_args!("{:.1} jobs/min ({} jobs)", stats.jobs as f64 / minutes as f64, stats.jobs),
+        )?;
+        row(
+            out,
+            "Open rate",
+            format_args!("{} ({} opened)", Percent(stats.open_rate()), stats.opened),
+        )?;
+        row(
+            out,
+            "Click rate",
+            format_args!("{} ({} clicks)", Percent(stats.click_rate()), stats.clicks),
+        )?;
+        row(out, "Click errors", stats.click_errors)?;
+        row(out, "Rejected deliveries", stats.rejected)?;
+        for (status, count) in &stats.statuses {
+            row(out, &format!("Status {}", status), count)?;
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    writeln!(out, "<h2>Recent errors</h2>")?;
+    if snapshot.errors.is_empty() {
+        writeln!(out, "<p>None</p>")?;
+    } else {
+        writeln!(out, "<table><tr><th>Age</th><th>Event</th><th>Detail</th></tr>")?;
+        for error in &snapshot.errors {
+            writeln!(
+                out,
+                "<tr><td>{}s ago</td><td>{}</td><td>{}</td></tr>",
+                error.at.elapsed().as_secs(),
+                error.event,
+                Escaped(&error.detail)
+            )?;
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    writeln!(out, "</body></html>")
+}
+
+fn row(out: &mut String, label: &str, value: impl Display) -> fmt::Result {
+    writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", Escaped(label), Escaped(&value.to_string()))
+}
+
+/// A rate as a percentage, or `-` without jobs.
+struct Percent(Option<f64>);
+
+impl Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(rate) => write!(f, "{:.1}%", rate * 100.0),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+/// Text escaped for HTML.
+struct Escaped<'a>(&'a str);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&#39;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_healthy() {
+        let mut stats = StatsSummary {
+            window_secs: 900,
+            jobs: 30,
+            opened: 15,
+            clicked: 3,
+            clicks: 4,
+            ..StatsSummary::default()
+        };
+        stats.statuses.insert("completed".to_string(), 30);
+        let page = render(&Snapshot {
+            queues: Ok(vec![("inbound_webhooks", 2), ("email_simulator", 17)]),
+            stats,
+            workers: 2,
+            unreachable: Vec::new(),
+            errors: Vec::new(),
+        });
+
+        assert!(page.contains("Healthy"));
+        assert!(page.contains("<tr><td>email_simulator</td><td>17</td></tr>"));
+        assert!(page.contains("2.0 jobs/min (30 jobs)"));
+        assert!(page.contains("50.0% (15 opened)"));
+        assert!(page.contains("10.0% (4 clicks)"));
+    }
+
+    #[test]
+    fn test_render_degraded() {
+        let dashboard = Dashboard::new();
+        dashboard.record_error("cloudflare_publish_failed", "<broker> gone");
+        let page = render(&Snapshot {
+            queues: Err("connect timed out".to_string()),
+            stats: StatsSummary::default(),
+            workers: 1,
+            unreachable: vec![("http://worker:9090".to_string(), "unreachable".to_string())],
+            errors: dashboard.recent_errors(),
+        });
+
+        assert!(page.contains("Degraded"));
+        assert!(page.contains("worker http://worker:9090 unreachable"));
+        assert!(page.contains("Workers answering</th><td>0 of 1"));
+        assert!(page.contains("cloudflare_publish_failed</td><td>&lt;broker&gt; gone"));
+    }
+
+    #[test]
+    fn test_recent_errors_are_capped() {
+        let dashboard = Dashboard::new();
+        for i in 0..MAX_ERRORS + 5 {
+            dashboard.record_error("mailgun_publish_failed", i);
+        }
+        let errors = dashboard.recent_errors();
+        assert_eq!(errors.len(), MAX_ERRORS);
+        assert_eq!(errors[0].detail, (MAX_ERRORS + 4).to_string());
+    }
+}
@@ -2,42 +2,146 @@
 //!
 //! These handlers are designed to be extremely fast - they only:
 //! 1. Verify authentication
-//! 2. Enqueue the raw payload to RabbitMQ
+//! 2. Enqueue the raw payload to RabbitMQ, stamped with the request's
+//!    `X-Request-Id`
 //! 3. Return immediately
 //!
 //! All parsing and processing happens in the background processor.
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
-    extract::{Form, State},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    extract::{Form, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
 use tracing::{error, info, warn};
 
-use crate::queue::{CloudflareRawPayload, InboundWebhook, MailgunRawPayload, Publisher};
-use crate::web::signature::{is_signature_verification_enabled, verify_mailgun_signature};
-use crate::Config;
+use crate::campaign::overrides::OverrideStore;
+use crate::error::BobNetError;
+use crate::metrics::{Counter, Gauge, Registry};
+use crate::queue::{
+    CloudflareRawPayload, GenericRawPayload, InboundWebhook, MailgunRawPayload, Publisher,
+};
+use crate::simulate::latency::unix_millis;
+use crate::tenant::ProfileStore;
+use crate::web::ban::AuthBans;
+use crate::web::dashboard::Dashboard;
+use crate::web::replay::ReplayCache;
+use crate::web::request_id::request_id_of;
+use crate::web::signature::{
+    is_signature_verification_enabled, match_auth_token, verify_mailgun_signature,
+};
+use crate::WebConfig;
 
 /// Shared application state.
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Arc<Config>,
+    pub config: Arc<WebConfig>,
     pub publisher: Publisher,
+    /// Recently seen Mailgun (timestamp, token) pairs
+    pub replay_cache: Arc<ReplayCache>,
+    /// Per-IP authentication failures and bans
+    pub auth_bans: Arc<AuthBans>,
+    pub metrics: Arc<WebMetrics>,
+    /// Store behind the campaign override admin API, when configured
+    pub campaign_overrides: Option<Arc<dyn OverrideStore>>,
+    /// Store behind the tenant admin API, when configured
+    pub tenant_profiles: Option<Arc<dyn ProfileStore>>,
+    /// Recent errors for the status dashboard
+    pub dashboard: Arc<Dashboard>,
 }
 
 impl AppState {
-    pub fn new(config: Config, publisher: Publisher) -> Self {
+    pub fn new(config: WebConfig, publisher: Publisher) -> Self {
+        let replay_cache = ReplayCache::new(
+            config.mailgun_replay_cache_size,
+            Duration::from_secs(config.mailgun_signature_max_age),
+        );
+        let auth_bans = AuthBans::new(
+            config.auth_ban_threshold,
+            Duration::from_secs(config.auth_ban_duration_secs),
+        )
+        .with_trusted_proxy_hops(config.trusted_proxy_hops);
         Self {
             config: Arc::new(config),
             publisher,
+            replay_cache: Arc::new(replay_cache),
+            auth_bans: Arc::new(auth_bans),
+            metrics: Arc::new(WebMetrics::new()),
+            campaign_overrides: None,
+            tenant_profiles: None,
+            dashboard: Arc::new(Dashboard::new()),
+        }
+    }
+
+    /// Serve the campaign override admin API from `store`.
+    pub fn with_campaign_overrides(mut self, store: Arc<dyn OverrideStore>) -> Self {
+        self.campaign_overrides = Some(store);
+        self
+    }
+
+    /// Serve the tenant admin API from `store`.
+    pub fn with_tenant_profiles(mut self, store: Arc<dyn ProfileStore>) -> Self {
+        self.tenant_profiles = Some(store);
+        self
+    }
+}
+
+/// Metrics tracked by the web server.
+pub struct WebMetrics {
+    pub registry: Registry,
+    pub auth_failures: Counter,
+    pub auth_bans: Counter,
+    pub auth_banned_requests: Counter,
+    pub banned_ips: Gauge,
+    pub outbox_bytes: Gauge,
+    pub outbox_rejected: Gauge,
+}
+
+impl WebMetrics {
+    /// Create the web server metrics.
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        Self {
+            auth_failures: registry.counter(
+                "bobnet_web_auth_failures_total",
+                "Webhook requests rejected with 401",
+            ),
+            auth_bans: registry.counter(
+                "bobnet_web_auth_bans_total",
+                "Source IPs banned for repeated authentication failures",
+            ),
+            auth_banned_requests: registry.counter(
+                "bobnet_web_auth_banned_requests_total",
+                "Webhook requests refused because the source IP is banned",
+            ),
+            banned_ips: registry.gauge(
+                "bobnet_web_banned_ips",
+                "Source IPs currently banned",
+            ),
+            outbox_bytes: registry.gauge(
+                "bobnet_web_outbox_bytes",
+                "Bytes of webhooks journaled in the outbox awaiting the broker",
+            ),
+            outbox_rejected: registry.gauge(
+                "bobnet_web_outbox_rejected",
+                "Webhooks not journaled since startup because the outbox was full",
+            ),
+            registry,
         }
     }
 }
 
+impl Default for WebMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // =============================================================================
 // Health Check
 // =============================================================================
@@ -53,6 +157,22 @@ pub async fn health() -> Json<HealthResponse> {
     Json(HealthResponse { status: "ok" })
 }
 
+/// Prometheus metrics endpoint.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    state
+        .metrics
+        .banned_ips
+        .set(state.auth_bans.banned_count() as i64);
+    if let Some(outbox) = state.publisher.outbox() {
+        state.metrics.outbox_bytes.set(outbox.bytes() as i64);
+        state.metrics.outbox_rejected.set(outbox.rejected() as i64);
+    }
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.registry.render(),
+    )
+}
+
 // =============================================================================
 // Mailgun Webhook
 // =============================================================================
@@ -86,6 +206,8 @@ pub struct MailgunForm {
     pub token: String,
     #[serde(default)]
     pub signature: String,
+    #[serde(default, rename = "message-url")]
+    pub message_url: Option<String>,
 }
 
 /// Webhook response.
@@ -96,18 +218,48 @@ pub struct WebhookResponse {
     pub message_id: Option<String>,
 }
 
+/// Response to a webhook that could not be enqueued.
+///
+/// While the broker is unreachable this is `503` with `Retry-After`, so
+/// Mailgun and Cloudflare back off and retry instead of waiting on us;
+/// other publish failures are `500`.
+fn publish_failed(state: &AppState, e: &BobNetError) -> Response {
+    if !e.is_unavailable() {
+        let body = Json(WebhookResponse {
+            status: "error",
+            message_id: None,
+        });
+        return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+    }
+    let retry_after = state.config.publish_retry_after_secs.to_string();
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, retry_after)],
+        Json(WebhookResponse {
+            status: "unavailable",
+            message_id: None,
+        }),
+    )
+        .into_response()
+}
+
 /// Mailgun webhook endpoint.
 ///
 /// This endpoint:
-/// 1. Verifies the HMAC signature (if configured)
+/// 1. Verifies the HMAC signature (if configured) and rejects replayed tokens
 /// 2. Enqueues the raw payload immediately
 /// 3. Returns 200 OK
+///
+/// Replays get 406 Not Acceptable, which tells Mailgun not to retry.
 pub async fn mailgun_webhook(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Form(form): Form<MailgunForm>,
-) -> impl IntoResponse {
+) -> Response {
+    let request_id = request_id_of(&headers);
     info!(
         recipient = %form.recipient,
+        request_id = %request_id,
         has_body_html = form.body_html.is_some(),
         body_html_length = form.body_html.as_ref().map(|s| s.len()).unwrap_or(0),
         has_signature = !form.signature.is_empty(),
@@ -115,7 +267,8 @@ pub async fn mailgun_webhook(
     );
 
     // Verify signature if signing key is configured
-    if is_signature_verification_enabled(&state.config.mailgun_signing_key) {
+    let signing_enabled = is_signature_verification_enabled(&state.config.mailgun_signing_key);
+    if signing_enabled {
         let signing_key = state.config.mailgun_signing_key.as_ref().unwrap();
         if !verify_mailgun_signature(
             signing_key,
@@ -131,7 +284,20 @@ pub async fn mailgun_webhook(
                     status: "unauthorized",
                     message_id: None,
                 }),
-            );
+            )
+                .into_response();
+        }
+
+        if !state.replay_cache.check(&form.timestamp, &form.token) {
+            warn!(recipient = %form.recipient, "mailgun_token_replayed");
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                Json(WebhookResponse {
+                    status: "replayed",
+                    message_id: None,
+                }),
+            )
+                .into_response();
         }
     }
 
@@ -149,11 +315,13 @@ pub async fn mailgun_webhook(
                     status: "invalid_domain",
                     message_id: None,
                 }),
-            );
+            )
+                .into_response();
         }
     }
 
     // Convert form to raw payload and enqueue immediately
+    let replay_key = (form.timestamp.clone(), form.token.clone());
     let payload = InboundWebhook::Mailgun(MailgunRawPayload {
         recipient: form.recipient.clone(),
         sender: form.sender,
@@ -165,20 +333,26 @@ pub async fn mailgun_webhook(
         from_field: form.from_field,
         timestamp: form.timestamp,
         token: form.token,
+        received_at_ms: Some(unix_millis()),
+        message_url: form.message_url,
+        body_mime: None,
     });
 
-    if let Err(e) = state.publisher.publish_inbound(&payload).await {
+    if let Err(e) = state
+        .publisher
+        .publish_inbound(&payload, Some(&request_id))
+        .await
+    {
         error!(error = %e, "mailgun_publish_failed");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(WebhookResponse {
-                status: "error",
-                message_id: None,
-            }),
-        );
+        state.dashboard.record_error("mailgun_publish_failed", &e);
+        // Let Mailgun's retry of this webhook through
+        if signing_enabled {
+            state.replay_cache.forget(&replay_key.0, &replay_key.1);
+        }
+        return publish_failed(&state, &e);
     }
 
-    info!(recipient = %form.recipient, "mailgun_enqueued");
+    info!(recipient = %form.recipient, request_id = %request_id, "mailgun_enqueued");
 
     (
         StatusCode::OK,
@@ -187,6 +361,7 @@ pub async fn mailgun_webhook(
             message_id: Some(form.recipient),
         }),
     )
+        .into_response()
 }
 
 // =============================================================================
@@ -214,9 +389,11 @@ pub async fn cloudflare_webhook(
     State(state): State<AppState>,
     headers: HeaderMap,
     Json(payload): Json<CloudflarePayload>,
-) -> impl IntoResponse {
+) -> Response {
+    let request_id = request_id_of(&headers);
     info!(
         from = %payload.from_field,
+        request_id = %request_id,
         to = %payload.to,
         subject = %payload.subject,
         raw_content_length = payload.raw_content.len(),
@@ -228,37 +405,40 @@ pub async fn cloudflare_webhook(
         .get("X-Custom-Auth")
         .and_then(|v| v.to_str().ok());
 
-    let expected_token = state.config.cloudflare_auth_token.as_deref();
-
-    match (auth_header, expected_token) {
-        (Some(provided), Some(expected)) if provided == expected => {
-            // Auth passes
-        }
-        (None, Some(_)) => {
-            warn!(to = %payload.to, "cloudflare_auth_missing");
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(WebhookResponse {
-                    status: "unauthorized",
-                    message_id: None,
-                }),
-            );
-        }
-        (Some(_), Some(_)) => {
-            warn!(to = %payload.to, "cloudflare_auth_invalid");
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(WebhookResponse {
-                    status: "unauthorized",
-                    message_id: None,
-                }),
-            );
-        }
-        (_, None) => {
-            // No auth configured, allow through
-            warn!("cloudflare_auth_not_configured");
+    // Several tokens may be accepted while the Worker secret is rotated;
+    // `auth_token` is the position of the one presented
+    let tokens = &state.config.cloudflare_auth_tokens;
+    let auth_token = if tokens.is_empty() {
+        // No auth configured, allow through
+        warn!("cloudflare_auth_not_configured");
+        None
+    } else {
+        match auth_header.map(|provided| match_auth_token(tokens, provided)) {
+            Some(Some(index)) => Some(index),
+            Some(None) => {
+                warn!(to = %payload.to, "cloudflare_auth_invalid");
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(WebhookResponse {
+                        status: "unauthorized",
+                        message_id: None,
+                    }),
+                )
+                    .into_response();
+            }
+            None => {
+                warn!(to = %payload.to, "cloudflare_auth_missing");
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(WebhookResponse {
+                        status: "unauthorized",
+                        message_id: None,
+                    }),
+                )
+                    .into_response();
+            }
         }
-    }
+    };
 
     // Convert payload to raw payload and enqueue immediately
     let webhook = InboundWebhook::Cloudflare(CloudflareRawPayload {
@@ -266,21 +446,26 @@ pub async fn cloudflare_webhook(
         to: payload.to.clone(),
         subject: payload.subject,
         timestamp: payload.timestamp,
+        received_at_ms: Some(unix_millis()),
         raw_content: payload.raw_content,
     });
 
-    if let Err(e) = state.publisher.publish_inbound(&webhook).await {
+    if let Err(e) = state
+        .publisher
+        .publish_inbound(&webhook, Some(&request_id))
+        .await
+    {
         error!(error = %e, "cloudflare_publish_failed");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(WebhookResponse {
-                status: "error",
-                message_id: None,
-            }),
-        );
+        state.dashboard.record_error("cloudflare_publish_failed", &e);
+        return publish_failed(&state, &e);
     }
 
-    info!(to = %payload.to, "cloudflare_enqueued");
+    info!(
+        to = %payload.to,
+        auth_token = ?auth_token,
+        request_id = %request_id,
+        "cloudflare_enqueued"
+    );
 
     (
         StatusCode::OK,
@@ -289,4 +474,111 @@ pub async fn cloudflare_webhook(
             message_id: Some(payload.to),
         }),
     )
+        .into_response()
+}
+
+// =============================================================================
+// Generic Webhook
+// =============================================================================
+
+/// Generic JSON webhook endpoint for the `GENERIC_WEBHOOKS` source `name`.
+///
+/// This endpoint:
+/// 1. Verifies the source's auth header against `GENERIC_WEBHOOK_TOKENS`,
+///    when the source names one
+/// 2. Maps the recipient, HTML and Message-Id out of the body
+/// 3. Enqueues them and returns 200 OK
+///
+/// Unknown sources get 404 and bodies without a recipient 400.
+pub async fn generic_webhook(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let request_id = request_id_of(&headers);
+    let name = name.to_lowercase();
+    let Some(source) = state.config.generic_webhooks.iter().find(|s| s.name == name) else {
+        warn!(source = %name, request_id = %request_id, "generic_source_unknown");
+        return generic_rejected(StatusCode::NOT_FOUND, "unknown_source");
+    };
+    info!(source = %name, request_id = %request_id, "generic_webhook_received");
+
+    // Several tokens may be accepted while a source's secret is rotated
+    let auth_token = match &source.auth_header {
+        None => None,
+        Some(header) => {
+            let tokens: Vec<String> = state
+                .config
+                .generic_webhook_tokens
+                .iter()
+                .filter(|t| t.source == name)
+                .map(|t| t.token.clone())
+                .collect();
+            let provided = headers.get(header.as_str()).and_then(|v| v.to_str().ok());
+            match provided.map(|provided| match_auth_token(&tokens, provided)) {
+                Some(Some(index)) => Some(index),
+                Some(None) => {
+                    warn!(source = %name, "generic_auth_invalid");
+                    return generic_rejected(StatusCode::UNAUTHORIZED, "unauthorized");
+                }
+                None => {
+                    warn!(source = %name, "generic_auth_missing");
+                    return generic_rejected(StatusCode::UNAUTHORIZED, "unauthorized");
+                }
+            }
+        }
+    };
+
+    let extracted = match source.extract(&body) {
+        Ok(extracted) => extracted,
+        Err(reason) => {
+            warn!(source = %name, reason = %reason, "generic_payload_invalid");
+            return generic_rejected(StatusCode::BAD_REQUEST, "invalid");
+        }
+    };
+
+    let to = extracted.to.clone();
+    let webhook = InboundWebhook::Generic(GenericRawPayload {
+        source: name.clone(),
+        to: extracted.to,
+        html: extracted.html,
+        message_id: extracted.message_id,
+        received_at_ms: Some(unix_millis()),
+    });
+
+    if let Err(e) = state
+        .publisher
+        .publish_inbound(&webhook, Some(&request_id))
+        .await
+    {
+        error!(source = %name, error = %e, "generic_publish_failed");
+        state.dashboard.record_error("generic_publish_failed", &e);
+        return publish_failed(&state, &e);
+    }
+
+    info!(
+        source = %name,
+        to = %to,
+        auth_token = ?auth_token,
+        request_id = %request_id,
+        "generic_enqueued"
+    );
+
+    (
+        StatusCode::OK,
+        Json(WebhookResponse {
+            status: "enqueued",
+            message_id: Some(to),
+        }),
+    )
+        .into_response()
+}
+
+fn generic_rejected(code: StatusCode, status: &'static str) -> Response {
+    let body = Json(WebhookResponse {
+        status,
+        message_id: None,
+    });
+    (code, body).into_response()
 }
@@ -1,18 +1,34 @@
 //! Web server module for handling inbound webhooks.
 //!
 //! This module provides a thin, fast web server that:
-//! - Receives webhooks from Mailgun and Cloudflare
+//! - Receives webhooks from Mailgun, Cloudflare and configured generic JSON
+//!   sources
 //! - Verifies authentication
 //! - Immediately enqueues raw payloads to RabbitMQ
 //! - Returns 200 OK in microseconds
 //!
 //! All parsing and processing happens in the background processor.
 
+pub mod admin;
+pub mod ban;
+pub mod dashboard;
 pub mod handlers;
+pub mod replay;
+pub mod request_id;
+pub mod router;
 pub mod signature;
+pub mod tls;
 
+pub use crate::auth::{require_scope, ApiKey, ApiKeys};
+pub use ban::{guard_auth_failures, AuthBans};
+pub use dashboard::{dashboard, Dashboard};
 pub use handlers::{
-    cloudflare_webhook, health, mailgun_webhook, AppState, CloudflarePayload,
-    HealthResponse, MailgunForm, WebhookResponse,
+    cloudflare_webhook, generic_webhook, health, mailgun_webhook, metrics, AppState, CloudflarePayload,
+    HealthResponse, MailgunForm, WebMetrics, WebhookResponse,
+};
+pub use replay::ReplayCache;
+pub use request_id::{assign_request_id, request_id_of};
+pub use router::router;
+pub use signature::{
+    is_signature_verification_enabled, match_auth_token, sign_mailgun, verify_mailgun_signature,
 };
-pub use signature::{is_signature_verification_enabled, verify_mailgun_signature};
@@ -0,0 +1,134 @@
+//! Replay protection for Mailgun webhooks.
+//!
+//! Signature verification only proves a webhook is recent, so a captured
+//! request could be replayed until its timestamp goes stale. Mailgun
+//! recommends also rejecting tokens that have already been seen; this cache
+//! remembers verified (timestamp, token) pairs for the max-age window.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Bounded set of recently seen (timestamp, token) pairs.
+#[derive(Debug)]
+pub struct ReplayCache {
+    capacity: usize,
+    max_age: Duration,
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    seen: HashSet<String>,
+    /// Keys in insertion order, for expiry and eviction
+    order: VecDeque<(Instant, String)>,
+}
+
+impl ReplayCache {
+    /// Create a cache holding at most `capacity` pairs for `max_age`.
+    ///
+    /// A capacity of 0 disables the cache.
+    pub fn new(capacity: usize, max_age: Duration) -> Self {
+        Self {
+            capacity,
+            max_age,
+            inner: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Record a pair, returning `false` if it was already seen.
+    pub fn check(&self, timestamp: &str, token: &str) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+
+        let key = Self::key(timestamp, token);
+        let now = Instant::now();
+        let mut entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Drop pairs old enough that the freshness check rejects them anyway
+        while let Some((added, _)) = entries.order.front() {
+            if now.duration_since(*added) <= self.max_age {
+                break;
+            }
+            if let Some((_, old)) = entries.order.pop_front() {
+                entries.seen.remove(&old);
+            }
+        }
+
+        if entries.seen.contains(&key) {
+            return false;
+        }
+
+        while entries.order.len() >= self.capacity {
+            match entries.order.pop_front() {
+                Some((_, old)) => {
+                    entries.seen.remove(&old);
+                }
+                None => break,
+            }
+        }
+
+        entries.seen.insert(key.clone());
+        entries.order.push_back((now, key));
+        true
+    }
+
+    /// Forget a pair so a retry of the same webhook is accepted, e.g. after
+    /// the first delivery failed to enqueue.
+    pub fn forget(&self, timestamp: &str, token: &str) {
+        let key = Self::key(timestamp, token);
+        let mut entries = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.seen.remove(&key) {
+            entries.order.retain(|(_, k)| *k != key);
+        }
+    }
+
+    fn key(timestamp: &str, token: &str) -> String {
+        format!("{}:{}", timestamp, token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_repeats() {
+        let cache = ReplayCache::new(10, Duration::from_secs(300));
+        assert!(cache.check("1700000000", "token-a"));
+        assert!(!cache.check("1700000000", "token-a"));
+        assert!(cache.check("1700000000", "token-b"));
+        assert!(cache.check("1700000001", "token-a"));
+    }
+
+    #[test]
+    fn test_evicts_oldest_when_full() {
+        let cache = ReplayCache::new(2, Duration::from_secs(300));
+        assert!(cache.check("1", "a"));
+        assert!(cache.check("2", "b"));
+        assert!(cache.check("3", "c"));
+        assert!(cache.check("1", "a"));
+        assert!(!cache.check("3", "c"));
+    }
+
+    #[test]
+    fn test_expires_after_max_age() {
+        let cache = ReplayCache::new(10, Duration::ZERO);
+        assert!(cache.check("1", "a"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(cache.check("1", "a"));
+    }
+
+    #[test]
+    fn test_forget_and_disabled() {
+        let cache = ReplayCache::new(10, Duration::from_secs(300));
+        assert!(cache.check("1", "a"));
+        cache.forget("1", "a");
+        assert!(cache.check("1", "a"));
+
+        let disabled = ReplayCache::new(0, Duration::from_secs(300));
+        assert!(disabled.check("1", "a"));
+        assert!(disabled.check("1", "a"));
+    }
+}
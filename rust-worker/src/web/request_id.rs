@@ -0,0 +1,90 @@
+//! `X-Request-Id` for every request the web server answers.
+//!
+//! [`assign_request_id`] keeps a caller's usable `X-Request-Id` or generates
+//! one, so the webhook handlers can stamp it into the inbound message (see
+//! [`crate::queue::request_id`]). Logs written while handling the request
+//! carry it through a `request` span, and it is echoed in the response so
+//! Mailgun and Cloudflare logs can be matched to ours.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+use crate::queue::request_id::{self, REQUEST_ID_HEADER};
+
+/// Middleware: make sure the request has an `X-Request-Id` and echo it back.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = request_id_of(request.headers());
+    // Accepted and generated IDs are plain ASCII, so always valid values
+    let value = HeaderValue::from_str(&id).expect("request IDs are valid header values");
+    request.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut response = next.run(request).instrument(span).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
+
+/// The request's ID: its `X-Request-Id` if usable, otherwise a new one.
+///
+/// Behind [`assign_request_id`] this is always the ID that middleware set.
+pub fn request_id_of(headers: &HeaderMap) -> String {
+    request_id::accept(headers.get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::{middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|headers: HeaderMap| async move { request_id_of(&headers) }),
+            )
+            .layer(middleware::from_fn(assign_request_id))
+    }
+
+    async fn call(request_id: Option<&str>) -> (String, String) {
+        let mut request = Request::builder().uri("/");
+        if let Some(id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let echoed = response.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        (echoed, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_caller_request_id_is_kept() {
+        let (echoed, seen) = call(Some("req-42")).await;
+        assert_eq!(echoed, "req-42");
+        assert_eq!(seen, "req-42");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_generated() {
+        let (echoed, seen) = call(None).await;
+        assert_eq!(echoed.len(), 32);
+        assert_eq!(seen, echoed);
+
+        let (echoed, _) = call(Some("not valid!")).await;
+        assert_ne!(echoed, "not valid!");
+    }
+}
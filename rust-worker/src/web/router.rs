@@ -0,0 +1,274 @@
+//! The webhook server's routes, for serving directly or mounting elsewhere.
+//!
+//! [`router`] is what `bobnet-web` serves. Other axum applications can nest or
+//! merge it next to their own routes and layer their own middleware on top:
+//!
+//! ```no_run
+//! use axum::Router;
+//! use bobnet::web::{router, AppState};
+//! use bobnet::{Publisher, WebConfig};
+//!
+//! let config = WebConfig::from_env();
+//! let publisher = Publisher::new(config.core.cloudamqp_url.clone());
+//! let app: Router = Router::new().nest("/bobnet", router(AppState::new(config, publisher)));
+//! ```
+
+use std::sync::Arc;
+
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use tower_http::decompression::RequestDecompressionLayer;
+
+use super::admin::{
+    delete_campaign_override, delete_tenant, get_campaign_override, get_tenant,
+    put_campaign_override, put_tenant,
+};
+use super::ban::guard_auth_failures;
+use super::dashboard::dashboard;
+use super::handlers::{
+    cloudflare_webhook, generic_webhook, health, mailgun_webhook, metrics, AppState,
+};
+use super::request_id::assign_request_id;
+use super::tls::{require_client_cert, MtlsRoutes};
+use crate::auth::{require_scope, SCOPE_ADMIN, SCOPE_METRICS};
+
+/// Build the webhook server's routes with their authentication.
+///
+/// Serves `/health`, the Mailgun, Cloudflare and `GENERIC_WEBHOOKS` webhooks
+/// (guarded by `AUTH_BAN_THRESHOLD`), `/metrics`, the `/dashboard` status
+/// page and the campaign override and tenant admin APIs under `/admin`
+/// (guarded by `API_KEYS`). Webhook bodies may be gzip, deflate or brotli
+/// compressed (`Content-Encoding`) and are capped at
+/// `WEBHOOK_MAX_BODY_BYTES` once decompressed. With
+/// `TLS_CLIENT_CA_PATH` set, the `MTLS_ROUTES` also require the client
+/// certificate that [`serve_tls`](super::tls::serve_tls) attaches, so serve
+/// the router with it. Every response carries the request's `X-Request-Id`
+/// (see [`assign_request_id`]); request tracing is left to the caller.
+pub fn router(state: AppState) -> Router {
+    let config = Arc::clone(&state.config);
+
+    let webhooks = Router::new()
+        .route("/webhooks/mailgun", post(mailgun_webhook))
+        .route("/webhooks/cloudflare", post(cloudflare_webhook))
+        .route("/webhooks/generic/:name", post(generic_webhook))
+        // The limit applies to the body the handlers read, which the
+        // decompression layer has already inflated
+        .route_layer(DefaultBodyLimit::max(config.webhook_max_body_bytes))
+        .route_layer(RequestDecompressionLayer::new())
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            guard_auth_failures,
+        ));
+    let api_keys = Arc::new(config.core.api_keys.clone());
+    let operational = require_scope(
+        Router::new()
+            .route("/metrics", get(metrics))
+            .route("/dashboard", get(dashboard)),
+        &api_keys,
+        SCOPE_METRICS,
+    );
+    let admin = require_scope(
+        Router::new()
+            .route(
+                "/admin/campaigns/:id/overrides",
+                get(get_campaign_override)
+                    .put(put_campaign_override)
+                    .delete(delete_campaign_override),
+            )
+            .route(
+                "/admin/tenants/:tag",
+                get(get_tenant).put(put_tenant).delete(delete_tenant),
+            ),
+        &api_keys,
+        SCOPE_ADMIN,
+    );
+
+    let mut app = Router::new()
+        .route("/health", get(health))
+        .merge(webhooks)
+        .merge(operational)
+        .merge(admin);
+    if config.tls_client_ca_path.is_some() {
+        app = require_client_cert(app, MtlsRoutes(config.mtls_routes.clone()));
+    }
+    app.layer(middleware::from_fn(assign_request_id)).with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::auth::{ApiKey, ApiKeys};
+    use crate::queue::{GenericSource, GenericToken};
+    use crate::{Publisher, WebConfig};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    async fn status(app: &Router, method: &str, uri: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_router_mounts_under_prefix() {
+        let mut config = WebConfig::default();
+        config.core.api_keys = ApiKeys(vec![ApiKey::parse("grafana:abc123:metrics").unwrap()]);
+        let publisher = Publisher::new(config.core.cloudamqp_url.clone());
+        let app = Router::new().nest("/bobnet", router(AppState::new(config, publisher)));
+
+        assert_eq!(status(&app, "GET", "/bobnet/health").await, StatusCode::OK);
+        assert_eq!(
+            status(&app, "GET", "/bobnet/metrics").await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&app, "GET", "/bobnet/webhooks/mailgun").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(status(&app, "GET", "/health").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_answers_503_while_broker_is_down() {
+        let config = WebConfig {
+            publish_retry_after_secs: 45,
+            ..WebConfig::default()
+        };
+        // Nothing listens on port 1
+        let publisher = Publisher::new("amqp://127.0.0.1:1".to_string())
+            .with_connect_timeout(Duration::from_millis(500));
+        let app = router(AppState::new(config, publisher));
+        let webhook = Request::builder()
+            .method("POST")
+            .uri("/webhooks/cloudflare")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"from":"a@example.com","to":"b@example.com","subject":"Hi","timestamp":"","raw_content":""}"#,
+            ))
+            .unwrap();
+
+        let response = app.clone().oneshot(webhook).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "45");
+        assert_eq!(response.headers()["x-request-id"].len(), 32);
+
+        // The dashboard still renders, showing the outage and the failure
+        let request = Request::builder()
+            .uri("/dashboard")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), 64 * 1024)
+            .await
+            .unwrap();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("Degraded"));
+        assert!(page.contains("cloudflare_publish_failed"));
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_compressed_webhook_body_is_limited_after_decompression() {
+        let config = WebConfig {
+            cloudflare_auth_tokens: vec!["secret".to_string()],
+            webhook_max_body_bytes: 64 * 1024,
+            ..WebConfig::default()
+        };
+        let publisher = Publisher::new(config.core.cloudamqp_url.clone());
+        let app = router(AppState::new(config, publisher));
+        let post = |body: Vec<u8>| {
+            Request::builder()
+                .method("POST")
+                .uri("/webhooks/cloudflare")
+                .header("content-type", "application/json")
+                .header("content-encoding", "gzip")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        // The payload was decoded: the handler got as far as checking auth
+        let payload = r#"{"from":"a@example.com","to":"b@example.com","subject":"Hi","timestamp":"","raw_content":""}"#;
+        let response = app.clone().oneshot(post(gzip(payload.as_bytes()))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // A small body that inflates past the limit is refused
+        let raw_content = "a".repeat(128 * 1024);
+        let payload = format!(
+            r#"{{"from":"a@example.com","to":"b@example.com","subject":"Hi","timestamp":"","raw_content":"{}"}}"#,
+            raw_content
+        );
+        let body = gzip(payload.as_bytes());
+        assert!(body.len() < 64 * 1024);
+        let response = app.clone().oneshot(post(body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_generic_webhook_auth_and_mapping() {
+        let config = WebConfig {
+            generic_webhooks: vec![
+                GenericSource::parse("acme:data.to:data.html:data.id:X-Acme-Token").unwrap()
+            ],
+            generic_webhook_tokens: vec![GenericToken::parse("acme:s3cret").unwrap()],
+            ..WebConfig::default()
+        };
+        // Nothing listens on port 1, so accepted webhooks fail to publish
+        let publisher = Publisher::new("amqp://127.0.0.1:1".to_string())
+            .with_connect_timeout(Duration::from_millis(500));
+        let app = router(AppState::new(config, publisher));
+        let post = |uri: &str, token: Option<&str>, body: &str| {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json");
+            if let Some(token) = token {
+                request = request.header("x-acme-token", token);
+            }
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+        let body = r#"{"data":{"to":"user@example.com","html":"<p>Hi</p>","id":"abc"}}"#;
+        let call = |request: Request<Body>| async {
+            app.clone().oneshot(request).await.unwrap().status()
+        };
+
+        assert_eq!(
+            call(post("/webhooks/generic/other", Some("s3cret"), body)).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            call(post("/webhooks/generic/acme", None, body)).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call(post("/webhooks/generic/acme", Some("wrong"), body)).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call(post("/webhooks/generic/acme", Some("s3cret"), r#"{"data":{}}"#)).await,
+            StatusCode::BAD_REQUEST
+        );
+        // Authenticated and mapped, it reaches the (unreachable) broker
+        assert_eq!(
+            call(post("/webhooks/generic/ACME", Some("s3cret"), body)).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -8,6 +8,8 @@ use sha2::Sha256;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::warn;
 
+use crate::auth::constant_time_compare;
+
 type HmacSha256 = Hmac<Sha256>;
 
 /// Verify a Mailgun webhook signature.
@@ -61,11 +63,7 @@ pub fn verify_mailgun_signature(
         .unwrap_or_default()
         .as_secs();
 
-    let age = if current_time > webhook_time {
-        current_time - webhook_time
-    } else {
-        webhook_time - current_time
-    };
+    let age = current_time.abs_diff(webhook_time);
 
     if age > max_age_seconds {
         warn!(
@@ -78,19 +76,11 @@ pub fn verify_mailgun_signature(
         return false;
     }
 
-    // Compute expected signature: HMAC-SHA256(signing_key, timestamp + token)
-    let mut mac = match HmacSha256::new_from_slice(signing_key.as_bytes()) {
-        Ok(m) => m,
-        Err(_) => {
-            warn!("mailgun_signature_invalid_key");
-            return false;
-        }
+    let Some(expected_signature) = sign_mailgun(signing_key, timestamp, token) else {
+        warn!("mailgun_signature_invalid_key");
+        return false;
     };
 
-    mac.update(format!("{}{}", timestamp, token).as_bytes());
-
-    let expected_signature = hex::encode(mac.finalize().into_bytes());
-
     // Constant-time comparison to prevent timing attacks
     let valid = constant_time_compare(&expected_signature, signature);
 
@@ -105,17 +95,26 @@ pub fn verify_mailgun_signature(
     valid
 }
 
-/// Constant-time string comparison to prevent timing attacks.
-fn constant_time_compare(a: &str, b: &str) -> bool {
-    if a.len() != b.len() {
-        return false;
-    }
+/// Sign a Mailgun webhook as Mailgun does: the hex HMAC-SHA256 of
+/// `timestamp + token` under `signing_key`.
+///
+/// Returns `None` if the key can't be used as an HMAC key.
+pub fn sign_mailgun(signing_key: &str, timestamp: &str, token: &str) -> Option<String> {
+    let mut mac = HmacSha256::new_from_slice(signing_key.as_bytes()).ok()?;
+    mac.update(format!("{}{}", timestamp, token).as_bytes());
+    Some(hex::encode(mac.finalize().into_bytes()))
+}
 
-    let mut result = 0u8;
-    for (x, y) in a.bytes().zip(b.bytes()) {
-        result |= x ^ y;
-    }
-    result == 0
+/// Find which of the configured Cloudflare tokens a request presented.
+///
+/// Returns the token's position in `CLOUDFLARE_AUTH_TOKEN`, so logs can show
+/// which token clients still use during a rotation without revealing it.
+/// Every token is compared, keeping the check constant-time.
+pub fn match_auth_token(tokens: &[String], provided: &str) -> Option<usize> {
+    tokens.iter().enumerate().fold(None, |found, (index, token)| {
+        let matched = constant_time_compare(token, provided);
+        found.or(matched.then_some(index))
+    })
 }
 
 /// Check if Mailgun signature verification is enabled.
@@ -161,6 +160,19 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_sign_mailgun_round_trip() {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+        let signature = sign_mailgun("key", &timestamp, "token").unwrap();
+        assert_eq!(signature.len(), 64);
+        assert!(verify_mailgun_signature("key", &timestamp, "token", &signature, 300));
+        assert!(!verify_mailgun_signature("other-key", &timestamp, "token", &signature, 300));
+    }
+
     #[test]
     fn test_verify_signature_valid() {
         use std::time::{SystemTime, UNIX_EPOCH};
@@ -188,10 +200,12 @@ mod tests {
     }
 
     #[test]
-    fn test_constant_time_compare() {
-        assert!(constant_time_compare("abc", "abc"));
-        assert!(!constant_time_compare("abc", "abd"));
-        assert!(!constant_time_compare("abc", "abcd"));
+    fn test_match_auth_token() {
+        let tokens = vec!["old-secret".to_string(), "new-secret".to_string()];
+        assert_eq!(match_auth_token(&tokens, "old-secret"), Some(0));
+        assert_eq!(match_auth_token(&tokens, "new-secret"), Some(1));
+        assert_eq!(match_auth_token(&tokens, "other"), None);
+        assert_eq!(match_auth_token(&[], "old-secret"), None);
     }
 
     #[test]
@@ -0,0 +1,267 @@
+//! TLS termination and mutual-TLS client certificates for bobnet-web.
+//!
+//! For deployments where Cloudflare Workers and Mailgun reach us through our
+//! own edge, the web server can terminate TLS itself (`WEB_TLS_CERT_PATH`,
+//! `WEB_TLS_KEY_PATH`) and verify client certificates against a CA
+//! (`WEB_TLS_CLIENT_CA_PATH`). Certificates are optional at the handshake so
+//! that only the routes listed in `WEB_MTLS_ROUTES` insist on one.
+
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::GracefulShutdown;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use tracing::{debug, info, warn};
+
+/// How long open connections get to finish after a shutdown signal.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Longest a client may take to complete the TLS handshake; connections
+/// that stay idle are closed rather than hold a task forever.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after a failed accept, e.g. when out of file descriptors, which
+/// would otherwise fail again at once in a busy loop.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The verified client certificate of a connection, added to each request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// Lowercase hex SHA-256 of the DER-encoded certificate
+    pub sha256: String,
+}
+
+impl ClientCertificate {
+    fn from_der(der: &[u8]) -> Self {
+        Self {
+            sha256: hex::encode(Sha256::digest(der)),
+        }
+    }
+}
+
+/// Build the server TLS config, verifying client certificates against
+/// `client_ca` when set.
+pub fn server_config(cert: &str, key: &str, client_ca: Option<&str>) -> Result<Arc<ServerConfig>> {
+    let certs = load_certs(cert)?;
+    let key = load_key(key)?;
+
+    let builder = match client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for ca in load_certs(path)? {
+                roots
+                    .add(ca)
+                    .with_context(|| format!("Invalid client CA certificate in {}", path))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .allow_unauthenticated()
+                .build()
+                .context("Failed to build client certificate verifier")?;
+            ServerConfig::builder().with_client_cert_verifier(verifier)
+        }
+        None => ServerConfig::builder().with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to read certificates from {}", path))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", path);
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to read private key from {}", path))?
+        .with_context(|| format!("No private key found in {}", path))
+}
+
+/// Serve `app` over TLS until `shutdown` completes.
+///
+/// Each request carries the peer address as [`ConnectInfo`] and, when the
+/// client presented one, its [`ClientCertificate`].
+pub async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    config: Arc<ServerConfig>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let acceptor = TlsAcceptor::from(config);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = %e, "tls_accept_failed");
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        let watcher = graceful.watcher();
+
+        tokio::spawn(async move {
+            let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream));
+            let stream = match handshake.await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    debug!(peer = %peer, error = %e, "tls_handshake_failed");
+                    return;
+                }
+                Err(_) => {
+                    debug!(
+                        peer = %peer,
+                        timeout_secs = HANDSHAKE_TIMEOUT.as_secs(),
+                        "tls_handshake_timed_out"
+                    );
+                    return;
+                }
+            };
+
+            let client_cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|cert| ClientCertificate::from_der(cert.as_ref()));
+
+            let service = hyper::service::service_fn(move |mut request: Request<hyper::body::Incoming>| {
+                request.extensions_mut().insert(ConnectInfo::<SocketAddr>(peer));
+                if let Some(cert) = &client_cert {
+                    request.extensions_mut().insert(cert.clone());
+                }
+                app.clone().oneshot(request)
+            });
+
+            let builder = Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection.into_owned()).await {
+                debug!(peer = %peer, error = %e, "tls_connection_error");
+            }
+        });
+    }
+
+    if tokio::time::timeout(SHUTDOWN_GRACE, graceful.shutdown()).await.is_err() {
+        warn!(grace_secs = SHUTDOWN_GRACE.as_secs(), "tls_connections_still_open");
+    }
+    Ok(())
+}
+
+/// Routes that require a client certificate.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MtlsRoutes(pub Vec<String>);
+
+impl MtlsRoutes {
+    /// Whether `path` requires a client certificate.
+    ///
+    /// With no prefixes configured, every route except `/health` does.
+    pub fn requires(&self, path: &str) -> bool {
+        if self.0.is_empty() {
+            return path != "/health";
+        }
+        self.0.iter().any(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+/// Reject requests to routes in `routes` that arrived without a verified
+/// client certificate.
+pub fn require_client_cert<S>(router: Router<S>, routes: MtlsRoutes) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    info!(routes = ?routes.0, "mtls_enabled");
+    router.layer(middleware::from_fn_with_state(Arc::new(routes), check_client_cert))
+}
+
+async fn check_client_cert(
+    State(routes): State<Arc<MtlsRoutes>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if !routes.requires(path) {
+        return next.run(request).await;
+    }
+
+    match request.extensions().get::<ClientCertificate>() {
+        Some(cert) => {
+            debug!(path = %path, client_cert = %cert.sha256, "mtls_client_authenticated");
+            next.run(request).await
+        }
+        None => {
+            warn!(path = %path, "mtls_client_cert_missing");
+            StatusCode::FORBIDDEN.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mtls_routes() {
+        let all = MtlsRoutes::default();
+        assert!(all.requires("/webhooks/mailgun"));
+        assert!(all.requires("/metrics"));
+        assert!(!all.requires("/health"));
+
+        let some = MtlsRoutes(vec!["/webhooks/cloudflare".to_string()]);
+        assert!(some.requires("/webhooks/cloudflare"));
+        assert!(!some.requires("/webhooks/mailgun"));
+        assert!(!some.requires("/health"));
+    }
+
+    #[test]
+    fn test_client_certificate_fingerprint() {
+        let cert = ClientCertificate::from_der(b"test");
+        assert_eq!(
+            cert.sha256,
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn test_server_config_reports_missing_files() {
+        let err = server_config("/nonexistent/cert.pem", "/nonexistent/key.pem", None).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/cert.pem"));
+    }
+}